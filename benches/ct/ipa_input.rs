@@ -0,0 +1,52 @@
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use generic_array::GenericArray;
+use ipa::{
+    ff::{Fp32BitPrime, Serializable},
+    protocol::{ipa::IPAInputRow, BreakdownKey, MatchKey},
+    secret_sharing::replicated::{
+        semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing,
+    },
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use typenum::Unsigned;
+
+type Row = IPAInputRow<Fp32BitPrime, MatchKey, BreakdownKey>;
+
+/// Serialized input of `rows` random rows.
+fn input(rows: usize) -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let row_sz = <Row as Serializable>::Size::USIZE;
+    let mut buf = vec![0u8; rows * row_sz];
+    for chunk in buf.chunks_mut(row_sz) {
+        let row = Row {
+            timestamp: Replicated::new(rng.gen(), rng.gen()),
+            mk_shares: Replicated::new(rng.gen(), rng.gen()),
+            is_trigger_bit: Replicated::new(rng.gen(), rng.gen()),
+            breakdown_key: Replicated::new(rng.gen(), rng.gen()),
+            trigger_value: Replicated::new(rng.gen(), rng.gen()),
+        };
+        row.serialize(GenericArray::from_mut_slice(chunk));
+    }
+
+    buf
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ipa_input");
+
+    for rows in [10_000, 1_000_000] {
+        let buf = input(rows);
+        let bytes = Bytes::from(buf.clone());
+        group.throughput(Throughput::Bytes(buf.len() as u64));
+        group.bench_with_input(BenchmarkId::new("from_byte_slice", rows), &buf, |b, buf| {
+            b.iter(|| Row::from_byte_slice(black_box(buf)).for_each(|row| drop(black_box(row))));
+        });
+        group.bench_with_input(BenchmarkId::new("from_bytes", rows), &bytes, |b, bytes| {
+            b.iter(|| Row::from_bytes(black_box(bytes)).for_each(|row| drop(black_box(row))));
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     num::{NonZeroU32, NonZeroUsize},
     time::Instant,
 };
@@ -9,7 +10,7 @@ use ipa::{
     ff::Fp32BitPrime,
     helpers::{query::IpaQueryConfig, GatewayConfig},
     test_fixture::{
-        ipa::{ipa_in_the_clear, test_ipa, IpaSecurityModel},
+        ipa::{ipa_in_the_clear, test_ipa, IpaSecurityModel, TestRawDataRecord},
         EventGenerator, EventGeneratorConfig, TestWorld, TestWorldConfig,
     },
 };
@@ -67,6 +68,18 @@ struct Args {
     /// Desired security model for IPA protocol
     #[arg(short = 'm', long, value_enum, default_value_t=IpaSecurityModel::Malicious)]
     mode: IpaSecurityModel,
+    /// Replace breakdown keys that are out of range with the largest valid key.
+    #[arg(long)]
+    clamp_breakdown_keys: bool,
+    /// Zero the trigger value of trigger events that duplicate the one right before them.
+    #[arg(long)]
+    dedup: bool,
+    /// Only count breakdown keys up to the largest one that appears in the input.
+    #[arg(long)]
+    detect_max_breakdown_key: bool,
+    /// Check that the output histogram adds up to the total of capped credits.
+    #[arg(long)]
+    check_histogram_total: bool,
     /// Needed for benches.
     #[arg(long, hide = true)]
     bench: bool,
@@ -83,6 +96,30 @@ impl Args {
         NonZeroU32::new(self.attribution_window)
     }
 
+    /// The input that [`ipa_in_the_clear`] sees, with every option that changes the input of
+    /// attribution applied to `raw_data`.
+    fn expected_input(&self, raw_data: &[TestRawDataRecord]) -> Vec<TestRawDataRecord> {
+        let mut records = raw_data.to_vec();
+        if self.dedup {
+            // A trigger event loses its value if the previous event of the same user is a trigger
+            // event with the same value.
+            let mut last_event = HashMap::<u64, &TestRawDataRecord>::new();
+            for (record, original) in records.iter_mut().zip(raw_data) {
+                if let Some(prev) = last_event.insert(original.user_id, original) {
+                    if prev.is_trigger_report
+                        && original.is_trigger_report
+                        && prev.breakdown_key == original.breakdown_key
+                        && prev.trigger_value == original.trigger_value
+                    {
+                        record.trigger_value = 0;
+                    }
+                }
+            }
+        }
+
+        records
+    }
+
     fn config(&self) -> IpaQueryConfig {
         IpaQueryConfig {
             per_user_credit_cap: self.per_user_cap,
@@ -90,6 +127,17 @@ impl Args {
             attribution_window_seconds: self.attribution_window(),
             num_multi_bits: self.num_multi_bits,
             plaintext_match_keys: true,
+            clamp_breakdown_keys: self.clamp_breakdown_keys,
+            detect_max_breakdown_key: self.detect_max_breakdown_key,
+            dedup: self.dedup,
+            drop_zero_match_keys: false,
+            signed_trigger_values: false,
+            max_trigger_value: None,
+            check_histogram_total: self.check_histogram_total,
+            first_row_continues_previous: false,
+            max_input_rows: None,
+            callback_url: None,
+            log_level: None,
         }
     }
 }
@@ -121,12 +169,21 @@ async fn run(args: Args) -> Result<(), Error> {
     .take(args.query_size)
     .collect::<Vec<_>>();
 
-    let expected_results = ipa_in_the_clear(
-        &raw_data,
+    let mut expected_results = ipa_in_the_clear(
+        &args.expected_input(&raw_data),
         args.per_user_cap,
         args.attribution_window(),
         args.breakdown_keys,
     );
+    if args.detect_max_breakdown_key {
+        // Buckets above the largest key in the input are not part of the output.
+        let buckets = raw_data
+            .iter()
+            .map(|r| r.breakdown_key + 1)
+            .max()
+            .unwrap_or(0);
+        expected_results.truncate(usize::try_from(buckets).unwrap());
+    }
 
     let world = TestWorld::new_with(config.clone());
     tracing::trace!("Preparation complete in {:?}", _prep_time.elapsed());
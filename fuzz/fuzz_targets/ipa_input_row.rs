@@ -0,0 +1,27 @@
+//! Feeds arbitrary bytes into the IPA input parser. Malformed input must be rejected with an
+//! error, never with a panic, and input that is accepted must serialize back to the same bytes.
+//!
+//! Run with `cargo fuzz run ipa_input_row fuzz/corpus/ipa_input_row`. The seed corpus contains
+//! valid serialized rows for the field and key types that IPA queries use.
+#![no_main]
+
+use std::iter::zip;
+
+use generic_array::GenericArray;
+use ipa::{
+    ff::{Fp32BitPrime, Serializable},
+    protocol::{ipa::IPAInputRow, BreakdownKey, MatchKey},
+};
+use libfuzzer_sys::fuzz_target;
+
+type Row = IPAInputRow<Fp32BitPrime, MatchKey, BreakdownKey>;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(rows) = Row::try_from_byte_slice(data) {
+        let mut buf = vec![0u8; data.len()];
+        for (row, chunk) in zip(rows, buf.chunks_mut(Row::SIZE_IN_BYTES)) {
+            row.serialize(GenericArray::from_mut_slice(chunk));
+        }
+        assert_eq!(data, buf);
+    }
+});
@@ -0,0 +1,135 @@
+// Procedural macro to derive `Serializable` for structs whose fields are all `Serializable`.
+//
+// Fields are laid out one after another, in the order they are declared. For a struct
+//
+//     struct Row<F: Field> {
+//         a: Replicated<F>,
+//         b: Replicated<F>,
+//     }
+//
+// the generated code is equivalent to
+//
+//     impl<F: Field> Serializable for Row<F>
+//     where
+//         Replicated<F>: Serializable,
+//         <Replicated<F> as Serializable>::Size: Add<<Replicated<F> as Serializable>::Size>,
+//         <<Replicated<F> as Serializable>::Size as Add<..>>::Output: ArrayLength<u8>,
+//     {
+//         type Size = <<Replicated<F> as Serializable>::Size as Add<..>>::Output;
+//
+//         fn serialize(&self, buf: &mut GenericArray<u8, Self::Size>) {
+//             self.a.serialize(GenericArray::from_mut_slice(&mut buf[0..SIZE_A]));
+//             self.b.serialize(GenericArray::from_mut_slice(&mut buf[SIZE_A..SIZE_A + SIZE_B]));
+//         }
+//
+//         fn deserialize(buf: &GenericArray<u8, Self::Size>) -> Self { .. }
+//     }
+//
+// The generated code refers to `crate::ff::Serializable`, so this macro can only be used inside
+// the `ipa` crate.
+
+use proc_macro::TokenStream;
+use quote::{__private::TokenStream as TokenStream2, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Index, Member, Type};
+
+pub fn expand(item: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(item as DeriveInput);
+    match expand_struct(&ast) {
+        Ok(expanded) => expanded.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_struct(ast: &DeriveInput) -> Result<TokenStream2, syn::Error> {
+    let data = match &ast.data {
+        Data::Struct(data) => data,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ast,
+                "derive Serializable expects a struct",
+            ))
+        }
+    };
+    let (members, types): (Vec<Member>, Vec<&Type>) = match &data.fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|f| (Member::Named(f.ident.clone().unwrap()), &f.ty))
+            .unzip(),
+        Fields::Unnamed(fields) => fields
+            .unnamed
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (Member::Unnamed(Index::from(i)), &f.ty))
+            .unzip(),
+        Fields::Unit => (Vec::new(), Vec::new()),
+    };
+
+    let sizes = types
+        .iter()
+        .map(|ty| quote!(<#ty as crate::ff::Serializable>::Size))
+        .collect::<Vec<_>>();
+
+    // The size of the struct is the sum of the field sizes, folded from the right:
+    // `A + (B + (C + D))`. Every partial sum needs its own `Add` bound.
+    let mut add_bounds = Vec::new();
+    let size = match sizes.split_last() {
+        None => quote!(::typenum::U0),
+        Some((last, rest)) => rest.iter().rev().fold(last.clone(), |acc, size| {
+            add_bounds.push(quote!(#size: ::std::ops::Add<#acc>));
+            quote!(<#size as ::std::ops::Add<#acc>>::Output)
+        }),
+    };
+
+    // Byte ranges of the fields within the serialized struct.
+    let ranges = sizes
+        .iter()
+        .enumerate()
+        .map(|(i, size)| {
+            if i == 0 {
+                return quote!(..<#size as ::typenum::Unsigned>::USIZE);
+            }
+            let preceding = &sizes[..i];
+            let start = quote!(#(<#preceding as ::typenum::Unsigned>::USIZE)+*);
+            quote!((#start)..(#start + <#size as ::typenum::Unsigned>::USIZE))
+        })
+        .collect::<Vec<_>>();
+
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let predicates = where_clause
+        .map(|w| w.predicates.iter().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    Ok(quote!(
+        #[automatically_derived]
+        impl #impl_generics crate::ff::Serializable for #name #ty_generics
+        where
+            #(#predicates,)*
+            #(#types: crate::ff::Serializable,)*
+            #(#add_bounds,)*
+            #size: ::generic_array::ArrayLength<u8>,
+        {
+            type Size = #size;
+
+            fn serialize(&self, buf: &mut ::generic_array::GenericArray<u8, Self::Size>) {
+                #(
+                    crate::ff::Serializable::serialize(
+                        &self.#members,
+                        ::generic_array::GenericArray::from_mut_slice(&mut buf[#ranges]),
+                    );
+                )*
+            }
+
+            fn deserialize(buf: &::generic_array::GenericArray<u8, Self::Size>) -> Self {
+                Self {
+                    #(
+                        #members: <#types as crate::ff::Serializable>::deserialize(
+                            ::generic_array::GenericArray::from_slice(&buf[#ranges]),
+                        ),
+                    )*
+                }
+            }
+        }
+    ))
+}
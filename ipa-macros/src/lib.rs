@@ -1,4 +1,5 @@
 mod derive_gate;
+mod derive_serializable;
 mod derive_step;
 mod parser;
 mod tree;
@@ -13,3 +14,10 @@ pub fn derive_gate(item: TokenStream) -> TokenStream {
 pub fn derive_step(input: TokenStream) -> TokenStream {
     derive_step::expand(input)
 }
+
+/// Derives `Serializable` for a struct with `Serializable` fields. Fields are written one after
+/// another, in the order of declaration.
+#[proc_macro_derive(Serializable)]
+pub fn derive_serializable(input: TokenStream) -> TokenStream {
+    derive_serializable::expand(input)
+}
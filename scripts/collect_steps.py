@@ -29,6 +29,15 @@ ATTRIBUTION_WINDOW = [0, 86400]
 # As of July 2023, we are limiting the number of breakdown keys to 32.
 BREAKDOWN_KEYS = [32]
 SECURITY_MODEL = ["malicious", "semi-honest"]
+# Opt-in parts of the protocol. Each of them is collected on its own, on top of every
+# configuration above.
+OPTIONAL_FLAGS = [
+    [],
+    ["--clamp-breakdown-keys"],
+    ["--dedup"],
+    ["--detect-max-breakdown-key"],
+    ["--check-histogram-total"],
+]
 ROOT_STEP_PREFIX = "protocol/alloc::string::String::run-0"
 
 # TODO(taikiy): #771 allows us to remove this synthetic step generation code
@@ -47,11 +56,20 @@ ROOT_STEP_PREFIX = "protocol/alloc::string::String::run-0"
 # transitions that are not actually executed. This is not optimal, but not a big deal.
 # It's impossible to generate the exact set of steps that are executed in the actual
 # protocol without executing the protocol or analyzing the code statically.
-DEPTH_DYNAMIC_STEPS = [
-    "ipa::protocol::attribution::InteractionPatternStep",
-]
+# The same goes for the tournament that finds the largest breakdown key, which runs log2(N)
+# rounds. Each entry maps the step enum to the name of its dynamic variant.
+DEPTH_DYNAMIC_STEPS = {
+    "ipa::protocol::attribution::InteractionPatternStep": "depth",
+    "ipa::protocol::ipa::MaxBreakdownKeyStep": "round",
+}
 MAXIMUM_DEPTH = 32
 
+# `Context::scoped` is not used by IPA itself, but protocols running under the compact gate
+# may split their root context into scopes. All of them are added to the output.
+ROOT_DYNAMIC_STEPS = {
+    "ipa::protocol::step::ScopeStep::scope": 64,
+}
+
 
 def set_env():
     env = os.environ.copy()
@@ -89,8 +107,10 @@ def collect_steps(args):
 
         count += 1
 
-        if any(s in line for s in DEPTH_DYNAMIC_STEPS):
-            line = re.sub(r"depth\d+", "depthX", line)
+        dynamic = [(s, v) for s, v in DEPTH_DYNAMIC_STEPS.items() if s in line]
+        if dynamic:
+            for s, v in dynamic:
+                line = re.sub(re.escape(s + "::" + v) + r"\d+", s + "::" + v + "X", line)
             depth_dynamic_steps.add(remove_root_step_name_from_line(line))
             # continue without adding to the `output`. we'll generate the dynamic steps later
             continue
@@ -105,7 +125,9 @@ def collect_steps(args):
     # generate dynamic steps
     for i in range(MAXIMUM_DEPTH):
         for s in depth_dynamic_steps:
-            line = re.sub(r"depthX", "depth" + str(i), s)
+            line = s
+            for v in DEPTH_DYNAMIC_STEPS.values():
+                line = line.replace("::" + v + "X", "::" + v + str(i))
             output.add(line)
 
     return output
@@ -156,20 +178,24 @@ if __name__ == "__main__":
         for w in ATTRIBUTION_WINDOW:
             for b in BREAKDOWN_KEYS:
                 for m in SECURITY_MODEL:
-                    args = ARGS + [
-                        "-n",
-                        str(QUERY_SIZE),
-                        "-c",
-                        str(c),
-                        "-w",
-                        str(w),
-                        "-b",
-                        str(b),
-                        "-m",
-                        m,
-                    ]
-                    print(" ".join(args), file=sys.stderr)
-                    steps.update(collect_steps(args))
+                    for f in OPTIONAL_FLAGS:
+                        args = ARGS + [
+                            "-n",
+                            str(QUERY_SIZE),
+                            "-c",
+                            str(c),
+                            "-w",
+                            str(w),
+                            "-b",
+                            str(b),
+                            "-m",
+                            m,
+                        ] + f
+                        print(" ".join(args), file=sys.stderr)
+                        steps.update(collect_steps(args))
+
+    for s, n in ROOT_DYNAMIC_STEPS.items():
+        steps.update(s + str(i) for i in range(n))
 
     full_steps = extract_intermediate_steps(steps)
     sorted_steps = sorted(full_steps)
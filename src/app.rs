@@ -1,13 +1,13 @@
 use crate::{
     helpers::{
-        query::{QueryConfig, QueryInput},
-        Transport, TransportCallbacks, TransportImpl,
+        query::{QueryConfig, QueryInput, ResultPage},
+        HelperIdentity, Transport, TransportCallbacks, TransportImpl,
     },
     hpke::{KeyPair, KeyRegistry},
     protocol::QueryId,
     query::{
-        NewQueryError, QueryCompletionError, QueryInputError, QueryProcessor, QueryStatus,
-        QueryStatusError,
+        CompletionCallbacks, NewQueryError, QueryCompletionError, QueryInputError, QueryProcessor,
+        QueryStatus, QueryStatusError, WarmupError,
     },
     sync::Arc,
 };
@@ -33,7 +33,28 @@ impl Setup {
     pub fn with_key_registry(
         key_registry: KeyRegistry<KeyPair>,
     ) -> (Self, TransportCallbacks<TransportImpl>) {
-        let query_processor = Arc::new(QueryProcessor::new(key_registry));
+        Self::with_processor(QueryProcessor::new(key_registry))
+    }
+
+    /// Same as [`Self::with_key_registry`], but the helper notifies clients about completed
+    /// queries through `completion_callbacks`.
+    #[must_use]
+    pub fn with_completion_callbacks(
+        key_registry: KeyRegistry<KeyPair>,
+        completion_callbacks: CompletionCallbacks,
+    ) -> (Self, TransportCallbacks<TransportImpl>) {
+        Self::with_processor(
+            QueryProcessor::new(key_registry).with_completion_callbacks(completion_callbacks),
+        )
+    }
+
+    /// Sets up a helper that runs queries with `query_processor`. Use this when the processor
+    /// needs settings the other constructors don't cover.
+    #[must_use]
+    pub fn with_processor(
+        query_processor: QueryProcessor,
+    ) -> (Self, TransportCallbacks<TransportImpl>) {
+        let query_processor = Arc::new(query_processor);
         let this = Self {
             query_processor: Arc::clone(&query_processor),
         };
@@ -53,7 +74,9 @@ impl Setup {
         let pqp = Arc::clone(query_processor);
         let iqp = Arc::clone(query_processor);
         let sqp = Arc::clone(query_processor);
+        let aqp = Arc::clone(query_processor);
         let cqp = Arc::clone(query_processor);
+        let pgqp = Arc::clone(query_processor);
 
         TransportCallbacks {
             receive_query: Box::new(move |transport: TransportImpl, receive_query| {
@@ -76,10 +99,18 @@ impl Setup {
                 let processor = Arc::clone(&sqp);
                 Box::pin(async move { processor.query_status(query_id) })
             }),
+            active_queries: Box::new(move |_transport: TransportImpl| {
+                let processor = Arc::clone(&aqp);
+                Box::pin(async move { processor.active_queries() })
+            }),
             complete_query: Box::new(move |_transport: TransportImpl, query_id| {
                 let processor = Arc::clone(&cqp);
                 Box::pin(async move { processor.complete(query_id).await })
             }),
+            result_page: Box::new(move |_transport: TransportImpl, page| {
+                let processor = Arc::clone(&pgqp);
+                Box::pin(async move { processor.result_page(page).await })
+            }),
         }
     }
 }
@@ -92,6 +123,14 @@ impl HelperApp {
         }
     }
 
+    /// Connects to the other helpers ahead of the first query. See [`QueryProcessor::warmup`].
+    ///
+    /// ## Errors
+    /// If another helper cannot be reached.
+    pub async fn warmup(&self) -> Result<(), Error> {
+        Ok(self.query_processor.warmup(&self.transport).await?)
+    }
+
     /// Initiates a new query on this helper. In case if query is accepted, the unique [`QueryId`]
     /// identifier is returned, otherwise an error indicating what went wrong is reported back.
     ///
@@ -105,6 +144,27 @@ impl HelperApp {
             .query_id)
     }
 
+    /// Same as [`Self::start_query`], but helper `assignment[i]` plays role `Role::all()[i]` in the
+    /// new query.
+    ///
+    /// ## Errors
+    /// If query is rejected for any reason.
+    pub async fn start_query_with_role_assignment(
+        &self,
+        query_config: QueryConfig,
+        assignment: [HelperIdentity; 3],
+    ) -> Result<QueryId, NewQueryError> {
+        Ok(self
+            .query_processor
+            .new_query_with_role_assignment(
+                Transport::clone_ref(&self.transport),
+                query_config,
+                assignment,
+            )
+            .await?
+            .query_id)
+    }
+
     /// Sends query input to a helper.
     ///
     /// ## Errors
@@ -130,6 +190,15 @@ impl HelperApp {
     pub async fn complete_query(&self, query_id: QueryId) -> Result<Vec<u8>, Error> {
         Ok(self.query_processor.complete(query_id).await?.into_bytes())
     }
+
+    /// Returns a page of the query result, waiting for the query to complete if needed. See
+    /// [`QueryProcessor::result_page`].
+    ///
+    /// ## Errors
+    /// Propagates errors from the helper.
+    pub async fn query_result_page(&self, page: ResultPage) -> Result<Vec<u8>, Error> {
+        Ok(self.query_processor.result_page(page).await?)
+    }
 }
 
 /// Union of error types returned by API operations.
@@ -143,4 +212,6 @@ pub enum Error {
     QueryCompletion(#[from] QueryCompletionError),
     #[error(transparent)]
     QueryStatus(#[from] QueryStatusError),
+    #[error(transparent)]
+    Warmup(#[from] WarmupError),
 }
@@ -1,9 +1,12 @@
 use std::{
     fs,
     net::TcpListener,
+    num::NonZeroUsize,
     os::fd::{FromRawFd, RawFd},
     path::{Path, PathBuf},
     process,
+    sync::Arc,
+    time::Duration,
 };
 
 use clap::{self, Parser, Subcommand};
@@ -15,10 +18,11 @@ use ipa::{
     config::{hpke_registry, HpkeServerConfig, NetworkConfig, ServerConfig, TlsConfig},
     error::BoxError,
     helpers::HelperIdentity,
-    net::{ClientIdentity, HttpTransport, MpcHelperClient},
+    net::{ClientIdentity, HttpCompletionNotifier, HttpTransport, MpcHelperClient},
+    query::{CompletionCallbacks, QueryProcessor},
     AppSetup,
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[cfg(not(target_env = "msvc"))]
 #[global_allocator]
@@ -88,6 +92,30 @@ struct ServerArgs {
     /// Private key for decrypting match keys
     #[arg(long, requires = "mk_public_key")]
     mk_private_key: Option<PathBuf>,
+
+    /// Interval, in seconds, between liveness probes of the other helpers
+    #[arg(long, default_value = "10")]
+    peer_probe_interval: u64,
+
+    /// Number of records coalesced into a single message sent to other helpers. Smaller batches
+    /// reduce latency, larger ones reduce per-message overhead.
+    #[arg(long)]
+    batch_records: Option<NonZeroUsize>,
+
+    /// Host that clients may ask this helper to notify when their queries complete. Can be
+    /// repeated. Queries that request a callback to any other host are rejected.
+    #[arg(long)]
+    callback_allowed_host: Vec<String>,
+
+    /// Time, in seconds, this helper keeps the results of queries that clients fetch in pages.
+    /// The time starts when the first page is requested.
+    #[arg(long, default_value = "600")]
+    result_ttl: u64,
+
+    /// Start every log line with the identity of this helper, e.g. `[H2]`. Useful when several
+    /// helpers log to the same terminal.
+    #[arg(long)]
+    log_identity_prefix: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -131,13 +159,22 @@ async fn server(args: ServerArgs) -> Result<(), BoxError> {
         });
 
     let key_registry = hpke_registry(mk_encryption.as_ref()).await?;
-    let (setup, callbacks) = AppSetup::with_key_registry(key_registry);
+    let mut query_processor =
+        QueryProcessor::new(key_registry).with_result_ttl(Duration::from_secs(args.result_ttl));
+    if !args.callback_allowed_host.is_empty() {
+        query_processor = query_processor.with_completion_callbacks(CompletionCallbacks::new(
+            args.callback_allowed_host,
+            Arc::new(HttpCompletionNotifier::default()),
+        ));
+    }
+    let (setup, callbacks) = AppSetup::with_processor(query_processor);
 
     let server_config = ServerConfig {
         port: args.port,
         disable_https: args.disable_https,
         tls: server_tls,
         hpke_config: mk_encryption,
+        batch_records: args.batch_records,
     };
 
     let scheme = if args.disable_https {
@@ -158,7 +195,7 @@ async fn server(args: ServerArgs) -> Result<(), BoxError> {
         callbacks,
     );
 
-    let _app = setup.connect(transport.clone());
+    let app = setup.connect(transport.clone());
 
     let listener = args.server_socket_fd
         .map(|fd| {
@@ -184,6 +221,15 @@ async fn server(args: ServerArgs) -> Result<(), BoxError> {
         )
         .await;
 
+    // Other helpers may still be starting, in which case connections to them are made when the
+    // first query needs them.
+    if let Err(e) = app.warmup().await {
+        warn!("warmup incomplete: {e}");
+    }
+    info!("helper is ready to accept queries");
+
+    let _probe = transport.start_liveness_probe(Duration::from_secs(args.peer_probe_interval));
+
     server_handle.await?;
 
     Ok(())
@@ -192,7 +238,12 @@ async fn server(args: ServerArgs) -> Result<(), BoxError> {
 #[tokio::main]
 pub async fn main() {
     let args = Args::parse();
-    let _handle = args.logging.setup_logging();
+    let log_prefix = args
+        .server
+        .identity
+        .filter(|_| args.server.log_identity_prefix)
+        .map(|id| format!("[H{id}]"));
+    let _handle = args.logging.setup_logging_with_prefix(log_prefix);
 
     let res = match args.command {
         None => server(args.server).await,
@@ -5,6 +5,7 @@ use std::{
     fs::{File, OpenOptions},
     io,
     io::{stdout, Write},
+    num::NonZeroU32,
     ops::Deref,
     path::{Path, PathBuf},
 };
@@ -16,11 +17,14 @@ use ipa::{
     cli::{
         noise::{apply, ApplyDpArgs},
         playbook::{make_clients, playbook_ipa, validate, InputSource},
-        CsvSerializer, IpaQueryResult, Verbosity,
+        CsvSerializer, IpaQueryResult, ResultFormat, Verbosity,
     },
     config::NetworkConfig,
     ff::{FieldType, Fp32BitPrime},
-    helpers::query::{IpaQueryConfig, QueryConfig, QuerySize, QueryType},
+    helpers::{
+        query::{IpaQueryConfig, QueryConfig, QueryPriority, QuerySize, QueryType},
+        WireCompression,
+    },
     hpke::{KeyRegistry, PublicKeyOnly},
     net::MpcHelperClient,
     protocol::{BreakdownKey, MatchKey},
@@ -59,6 +63,22 @@ struct Args {
     #[arg(long, value_name = "FILE")]
     output_file: Option<PathBuf>,
 
+    #[clap(flatten)]
+    result_format: ResultFormat,
+
+    /// Priority of the query relative to other queries running on the helpers
+    #[arg(long, value_enum, default_value_t = QueryPriority::Normal)]
+    priority: QueryPriority,
+
+    /// Compression of the records that helpers send to each other. Compression trades CPU for
+    /// bandwidth.
+    #[arg(long, value_enum, default_value_t = WireCompression::None)]
+    wire_compression: WireCompression,
+
+    /// Time in seconds that helpers give the query to run once they have its input
+    #[arg(long)]
+    timeout_seconds: Option<NonZeroU32>,
+
     #[command(subcommand)]
     action: ReportCollectorCommand,
 }
@@ -127,22 +147,22 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let (clients, network) = make_clients(args.network.as_deref(), scheme, args.wait).await;
     match args.action {
-        ReportCollectorCommand::SemiHonestIpa(config) => {
+        ReportCollectorCommand::SemiHonestIpa(ref config) => {
             ipa(
                 &args,
                 &network,
                 IpaSecurityModel::SemiHonest,
-                config,
+                config.clone(),
                 &clients,
             )
             .await?
         }
-        ReportCollectorCommand::MaliciousIpa(config) => {
+        ReportCollectorCommand::MaliciousIpa(ref config) => {
             ipa(
                 &args,
                 &network,
                 IpaSecurityModel::Malicious,
-                config,
+                config.clone(),
                 &clients,
             )
             .await?
@@ -238,6 +258,9 @@ async fn ipa(
         size: QuerySize::try_from(input_rows.len()).unwrap(),
         field_type: FieldType::Fp32BitPrime,
         query_type,
+        priority: args.priority,
+        wire_compression: args.wire_compression,
+        timeout_seconds: args.timeout_seconds,
     };
     let query_id = helper_clients[0].create_query(query_config).await.unwrap();
 
@@ -263,8 +286,9 @@ async fn ipa(
         &input_rows,
         &helper_clients,
         query_id,
-        ipa_query_config,
+        ipa_query_config.clone(),
         key_registries.init_from(network),
+        args.result_format,
     )
     .await;
 
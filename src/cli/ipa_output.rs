@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{cmp::Reverse, num::NonZeroU32, time::Duration};
 
 use crate::helpers::query::{IpaQueryConfig, QuerySize};
 
@@ -13,4 +13,220 @@ pub struct QueryResult {
     )]
     pub latency: Duration,
     pub breakdowns: Vec<u32>,
+    /// Breakdown keys in the order requested by [`ResultFormat::order_by`].
+    #[serde(default)]
+    pub order: Vec<u32>,
+}
+
+/// Rounding applied to the revealed histogram before it is reported to the client.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum ResultRounding {
+    /// Report revealed values as they are, without removing the scale.
+    #[default]
+    None,
+    /// Divide by the scale and round to the closest whole number, halves are rounded up.
+    Nearest,
+    /// Divide by the scale and round down.
+    Floor,
+}
+
+impl ResultRounding {
+    #[must_use]
+    pub fn apply(self, value: u128, scale: NonZeroU32) -> u128 {
+        let scale = u128::from(scale.get());
+        match self {
+            Self::None => value,
+            // adding `scale / 2` before dividing could overflow
+            Self::Nearest => value / scale + u128::from(value % scale >= scale - scale / 2),
+            Self::Floor => value / scale,
+        }
+    }
+}
+
+/// Order in which breakdowns are presented to the client.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OutputOrder {
+    /// Ascending breakdown key, the order in which the histogram is revealed.
+    #[default]
+    BreakdownKey,
+    /// Largest credit first. Breakdowns with equal credit are ordered by breakdown key.
+    CreditDescending,
+}
+
+/// Controls how revealed breakdown values are turned into whole-number credits.
+///
+/// When fixed-point arithmetic or DP noise is involved, revealed values are not necessarily clean
+/// integers. This transform is applied to public values after the reveal, so it does not leak
+/// anything helpers didn't already agree to disclose.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct ResultFormat {
+    /// Fixed-point scale of the revealed values.
+    #[cfg_attr(feature = "clap", arg(long, default_value = "1"))]
+    pub output_scale: NonZeroU32,
+
+    /// Rounding applied after the scale is removed.
+    #[cfg_attr(feature = "clap", arg(long, value_enum, default_value_t = ResultRounding::None))]
+    pub rounding: ResultRounding,
+
+    /// Order in which breakdowns are listed in the result.
+    #[cfg_attr(feature = "clap", arg(long, value_enum, default_value_t = OutputOrder::BreakdownKey))]
+    pub order_by: OutputOrder,
+
+    /// Credits below this value are reported as zero, to keep tiny counts from cluttering
+    /// dashboards. The floor applies to credits after scaling and rounding.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub reveal_floor: Option<u128>,
+}
+
+impl Default for ResultFormat {
+    fn default() -> Self {
+        Self {
+            output_scale: NonZeroU32::new(1).unwrap(),
+            rounding: ResultRounding::None,
+            order_by: OutputOrder::BreakdownKey,
+            reveal_floor: None,
+        }
+    }
+}
+
+impl ResultFormat {
+    /// Formats revealed values as a histogram with `len` breakdowns, where breakdown key is the
+    /// index of the value inside `values`. Credits below `reveal_floor` are set to zero.
+    ///
+    /// ## Panics
+    /// If there are more than `len` values or a formatted value does not fit into `u32`.
+    #[must_use]
+    pub fn breakdowns<I: IntoIterator<Item = u128>>(&self, values: I, len: usize) -> Vec<u32> {
+        let mut breakdowns = vec![0; len];
+        for (breakdown_key, value) in values.into_iter().enumerate() {
+            // TODO: make the data type used consistent with `ipa_in_the_clear`
+            // I think using u32 is wrong, we should move to u128
+            let credit = self.rounding.apply(value, self.output_scale);
+            if self.reveal_floor.map_or(true, |floor| credit >= floor) {
+                breakdowns[breakdown_key] += u32::try_from(credit).unwrap();
+            }
+        }
+
+        breakdowns
+    }
+
+    /// Returns breakdown keys of a histogram produced by [`breakdowns`] in the order set by
+    /// `order_by`. Credits are already public at this point, so this is a plain sort.
+    ///
+    /// ## Panics
+    /// If there are more than `u32::MAX` breakdowns.
+    ///
+    /// [`breakdowns`]: Self::breakdowns
+    #[must_use]
+    pub fn order(&self, breakdowns: &[u32]) -> Vec<u32> {
+        let mut order = (0..u32::try_from(breakdowns.len()).unwrap()).collect::<Vec<_>>();
+        match self.order_by {
+            OutputOrder::BreakdownKey => {}
+            OutputOrder::CreditDescending => {
+                // stable sort keeps ties ordered by breakdown key
+                order.sort_by_key(|&key| Reverse(breakdowns[key as usize]));
+            }
+        }
+
+        order
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::{OutputOrder, ResultFormat, ResultRounding};
+
+    #[test]
+    fn rounds_scaled_breakdowns() {
+        // credits 0, 1.25, 1.5, 1.75 and 3 scaled by 4
+        const SCALED: [u128; 5] = [0, 5, 6, 7, 12];
+
+        for (rounding, expected) in [
+            (ResultRounding::None, [0, 5, 6, 7, 12, 0]),
+            (ResultRounding::Nearest, [0, 1, 2, 2, 3, 0]),
+            (ResultRounding::Floor, [0, 1, 1, 1, 3, 0]),
+        ] {
+            let format = ResultFormat {
+                output_scale: NonZeroU32::new(4).unwrap(),
+                rounding,
+                order_by: OutputOrder::BreakdownKey,
+                reveal_floor: None,
+            };
+            assert_eq!(
+                expected.to_vec(),
+                format.breakdowns(SCALED, expected.len()),
+                "{rounding:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rounds_largest_values() {
+        let scale = NonZeroU32::new(4).unwrap();
+        assert_eq!(
+            u128::MAX / 4 + 1,
+            ResultRounding::Nearest.apply(u128::MAX, scale)
+        );
+        assert_eq!(u128::MAX / 4, ResultRounding::Floor.apply(u128::MAX, scale));
+        assert_eq!(
+            u128::MAX,
+            ResultRounding::Nearest.apply(u128::MAX, NonZeroU32::new(1).unwrap())
+        );
+    }
+
+    #[test]
+    fn default_format_is_identity() {
+        let values = [0, 3, 17];
+        assert_eq!(
+            vec![0, 3, 17],
+            ResultFormat::default().breakdowns(values, values.len())
+        );
+    }
+
+    #[test]
+    fn zeroes_credits_below_floor() {
+        let values = [0, 1, 4, 5, 9, 2];
+        let format = ResultFormat {
+            reveal_floor: Some(5),
+            ..ResultFormat::default()
+        };
+        assert_eq!(
+            vec![0, 0, 0, 5, 9, 0],
+            format.breakdowns(values, values.len())
+        );
+    }
+
+    #[test]
+    fn floor_applies_after_rounding() {
+        // credits 1.25, 1.5 and 2.25 scaled by 4
+        let values = [5, 6, 9];
+        let format = ResultFormat {
+            output_scale: NonZeroU32::new(4).unwrap(),
+            rounding: ResultRounding::Nearest,
+            reveal_floor: Some(2),
+            ..ResultFormat::default()
+        };
+        assert_eq!(vec![0, 2, 2], format.breakdowns(values, values.len()));
+    }
+
+    #[test]
+    fn orders_by_credit_descending() {
+        let breakdowns = [0, 2, 3, 0, 3, 1];
+
+        assert_eq!(
+            vec![0, 1, 2, 3, 4, 5],
+            ResultFormat::default().order(&breakdowns)
+        );
+
+        let format = ResultFormat {
+            order_by: OutputOrder::CreditDescending,
+            ..ResultFormat::default()
+        };
+        assert_eq!(vec![2, 4, 1, 5, 0, 3], format.order(&breakdowns));
+    }
 }
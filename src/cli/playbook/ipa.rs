@@ -2,9 +2,15 @@
 use std::{
     cmp::min,
     iter::zip,
+    num::NonZeroUsize,
     time::{Duration, Instant},
 };
 
+use bytes::Bytes;
+use futures::{
+    future::{join3, ready},
+    stream, Stream, StreamExt,
+};
 use futures_util::future::try_join_all;
 use generic_array::GenericArray;
 use rand::{distributions::Standard, prelude::Distribution, rngs::StdRng};
@@ -13,7 +19,7 @@ use tokio::time::sleep;
 use typenum::Unsigned;
 
 use crate::{
-    cli::IpaQueryResult,
+    cli::{IpaQueryResult, ResultFormat},
     ff::{PrimeField, Serializable},
     helpers::{
         query::{IpaQueryConfig, QueryInput, QuerySize},
@@ -25,7 +31,7 @@ use crate::{
     protocol::{ipa::IPAInputRow, BreakdownKey, MatchKey, QueryId},
     query::QueryStatus,
     report::{KeyIdentifier, Report},
-    secret_sharing::{replicated::semi_honest::AdditiveShare, IntoShares},
+    secret_sharing::{replicated::semi_honest::AdditiveShare, share_stream, IntoShares},
     test_fixture::{input::GenericReportTestInput, ipa::TestRawDataRecord, Reconstruct},
 };
 
@@ -38,6 +44,7 @@ pub async fn playbook_ipa<F, MK, BK, KR>(
     query_id: QueryId,
     query_config: IpaQueryConfig,
     encryption: Option<(KeyIdentifier, [&KR; 3])>,
+    result_format: ResultFormat,
 ) -> IpaQueryResult
 where
     F: PrimeField + IntoShares<AdditiveShare<F>>,
@@ -47,57 +54,17 @@ where
     AdditiveShare<F>: Serializable,
     KR: PublicKeyRegistry,
 {
-    let mut buffers: [_; 3] = std::array::from_fn(|_| Vec::new());
     let query_size = records.len();
-
-    if !query_config.plaintext_match_keys {
-        if let Some((key_id, key_registries)) = encryption {
-            const ESTIMATED_AVERAGE_REPORT_SIZE: usize = 80; // TODO: confirm/adjust
-            for buffer in &mut buffers {
-                buffer.reserve(query_size * ESTIMATED_AVERAGE_REPORT_SIZE);
-            }
-
-            let mut rng = StdRng::from_entropy();
-            let shares: [Vec<Report<_, _, _>>; 3] = records.iter().cloned().share();
-            zip(&mut buffers, shares).zip(key_registries).for_each(
-                |((buf, shares), key_registry)| {
-                    for share in shares {
-                        share
-                            .delimited_encrypt_to(key_id, key_registry, &mut rng, buf)
-                            .unwrap();
-                    }
-                },
-            );
-        } else {
-            panic!("match key encryption was requested, but one or more helpers is missing a public key")
-        }
+    let inputs = if query_config.plaintext_match_keys {
+        plaintext_inputs::<F>(records)
+    } else if let Some((key_id, key_registries)) = encryption {
+        encrypted_inputs::<F, _>(records, key_id, key_registries).await
     } else {
-        let sz = <IPAInputRow<F, MatchKey, BreakdownKey> as Serializable>::Size::USIZE;
-        for buffer in &mut buffers {
-            buffer.resize(query_size * sz, 0u8);
-        }
+        panic!(
+            "match key encryption was requested, but one or more helpers is missing a public key"
+        )
+    };
 
-        let inputs = records.iter().map(|x| {
-            ipa_test_input!(
-                {
-                    timestamp: x.timestamp,
-                    match_key: x.user_id,
-                    is_trigger_report: x.is_trigger_report,
-                    breakdown_key: x.breakdown_key,
-                    trigger_value: x.trigger_value,
-                };
-                (F, MatchKey, BreakdownKey)
-            )
-        });
-        let shares: [Vec<IPAInputRow<_, _, _>>; 3] = inputs.share();
-        zip(&mut buffers, shares).for_each(|(buf, shares)| {
-            for (share, chunk) in zip(shares, buf.chunks_mut(sz)) {
-                share.serialize(GenericArray::from_mut_slice(chunk));
-            }
-        });
-    }
-
-    let inputs = buffers.map(BodyStream::from);
     tracing::info!("Starting query after finishing encryption");
     let mpc_time = Instant::now();
     try_join_all(
@@ -144,17 +111,115 @@ where
 
     let lat = mpc_time.elapsed();
     tracing::info!("Running IPA for {query_size:?} records took {t:?}", t = lat);
-    let mut breakdowns = vec![0; usize::try_from(query_config.max_breakdown_key).unwrap()];
-    for (breakdown_key, trigger_value) in results.into_iter().enumerate() {
-        // TODO: make the data type used consistent with `ipa_in_the_clear`
-        // I think using u32 is wrong, we should move to u128
-        breakdowns[breakdown_key] += u32::try_from(trigger_value.as_u128()).unwrap();
-    }
+    let breakdowns = result_format.breakdowns(
+        results.into_iter().map(|v| v.as_u128()),
+        usize::try_from(query_config.max_breakdown_key).unwrap(),
+    );
+    let order = result_format.order(&breakdowns);
 
     IpaQueryResult {
         input_size: QuerySize::try_from(query_size).unwrap(),
         config: query_config,
         latency: lat,
         breakdowns,
+        order,
     }
 }
+
+/// Number of rows shared ahead for a helper that is slower to accept its input.
+const SHARE_CAPACITY: usize = 4096;
+
+/// Shares plaintext rows while they are being sent, so helper inputs are never held in memory in
+/// full.
+fn plaintext_inputs<F>(records: &[TestRawDataRecord]) -> [BodyStream; 3]
+where
+    F: PrimeField,
+    Standard: Distribution<F>,
+    IPAInputRow<F, MatchKey, BreakdownKey>: Serializable,
+{
+    const ROWS_PER_CHUNK: usize = 1024;
+    let sz = <IPAInputRow<F, MatchKey, BreakdownKey> as Serializable>::Size::USIZE;
+    let inputs = records
+        .iter()
+        .map(|x| {
+            ipa_test_input!(
+                {
+                    timestamp: x.timestamp,
+                    match_key: x.user_id,
+                    is_trigger_report: x.is_trigger_report,
+                    breakdown_key: x.breakdown_key,
+                    trigger_value: x.trigger_value,
+                };
+                (F, MatchKey, BreakdownKey)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    share_stream::<_, _, IPAInputRow<F, MatchKey, BreakdownKey>, _>(
+        stream::iter(inputs),
+        StdRng::from_entropy(),
+        NonZeroUsize::new(SHARE_CAPACITY).unwrap(),
+    )
+    .map(|shares| {
+        BodyStream::from_bytes_stream(shares.ready_chunks(ROWS_PER_CHUNK).map(move |shares| {
+            let mut buf = vec![0u8; shares.len() * sz];
+            for (share, chunk) in zip(shares, buf.chunks_mut(sz)) {
+                share.serialize(GenericArray::from_mut_slice(chunk));
+            }
+            Ok(Bytes::from(buf))
+        }))
+    })
+}
+
+/// Shares reports and encrypts each share for the helper that receives it.
+async fn encrypted_inputs<F, KR>(
+    records: &[TestRawDataRecord],
+    key_id: KeyIdentifier,
+    key_registries: [&KR; 3],
+) -> [BodyStream; 3]
+where
+    F: PrimeField,
+    TestRawDataRecord: IntoShares<Report<F, MatchKey, BreakdownKey>>,
+    AdditiveShare<F>: Serializable,
+    KR: PublicKeyRegistry,
+{
+    const ESTIMATED_AVERAGE_REPORT_SIZE: usize = 80; // TODO: confirm/adjust
+    let [s1, s2, s3] = share_stream::<_, _, Report<F, MatchKey, BreakdownKey>, _>(
+        stream::iter(records.iter().cloned()),
+        StdRng::from_entropy(),
+        NonZeroUsize::new(SHARE_CAPACITY).unwrap(),
+    );
+    let [k1, k2, k3] = key_registries;
+    let capacity = records.len() * ESTIMATED_AVERAGE_REPORT_SIZE;
+    let (b1, b2, b3) = join3(
+        encrypt_shares(s1, key_id, k1, capacity),
+        encrypt_shares(s2, key_id, k2, capacity),
+        encrypt_shares(s3, key_id, k3, capacity),
+    )
+    .await;
+
+    [b1, b2, b3].map(BodyStream::from)
+}
+
+async fn encrypt_shares<S, F, KR>(
+    shares: S,
+    key_id: KeyIdentifier,
+    key_registry: &KR,
+    capacity: usize,
+) -> Vec<u8>
+where
+    S: Stream<Item = Report<F, MatchKey, BreakdownKey>>,
+    F: PrimeField,
+    AdditiveShare<F>: Serializable,
+    KR: PublicKeyRegistry,
+{
+    let mut rng = StdRng::from_entropy();
+    shares
+        .fold(Vec::with_capacity(capacity), |mut buf, share| {
+            share
+                .delimited_encrypt_to(key_id, key_registry, &mut rng, &mut buf)
+                .unwrap();
+            ready(buf)
+        })
+        .await
+}
@@ -2,14 +2,23 @@ use std::io::{stderr, IsTerminal};
 
 use clap::Parser;
 use metrics_tracing_context::MetricsLayer;
-use tracing::{info, metadata::LevelFilter, Level};
+use tracing::{info, metadata::LevelFilter, Event, Level, Subscriber};
 use tracing_subscriber::{
-    fmt, fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt,
+    fmt,
+    fmt::{
+        format::{FmtSpan, Writer},
+        FmtContext, FormatEvent, FormatFields,
+    },
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    util::SubscriberInitExt,
 };
 
 use crate::{
     cli::{install_collector, metric_collector::CollectorHandle},
     error::set_global_panic_hook,
+    helpers::query::QueryLogLevel,
+    query::QueryLevelFilter,
 };
 
 #[derive(Debug, Parser)]
@@ -21,6 +30,10 @@ pub struct Verbosity {
     /// Verbose mode (-v, or -vv for even more verbose)
     #[arg(short, long, action = clap::ArgAction::Count, global = true)]
     verbose: u8,
+
+    /// Most verbose log level that clients may request for their queries
+    #[arg(long, value_enum, default_value_t = QueryLogLevel::Debug, global = true)]
+    max_query_log_level: QueryLogLevel,
 }
 
 pub struct LoggingHandle {
@@ -31,14 +44,25 @@ pub struct LoggingHandle {
 impl Verbosity {
     #[must_use]
     pub fn setup_logging(&self) -> LoggingHandle {
+        self.setup_logging_with_prefix(None)
+    }
+
+    /// Same as [`Self::setup_logging`], but every log line starts with `prefix`. This makes it
+    /// possible to tell apart the logs of several processes that write to the same terminal.
+    #[must_use]
+    pub fn setup_logging_with_prefix(&self, prefix: Option<String>) -> LoggingHandle {
         let filter_layer = self.level_filter();
         let fmt_layer = fmt::layer()
             .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
+            .event_format(PrefixedFormat::new(prefix, fmt::format()))
             .with_ansi(std::io::stderr().is_terminal())
             .with_writer(stderr);
 
         tracing_subscriber::registry()
-            .with(self.level_filter())
+            .with(QueryLevelFilter::new(
+                filter_layer,
+                LevelFilter::from_level(self.max_query_log_level.into()),
+            ))
             .with(fmt_layer)
             .with(MetricsLayer::new())
             .init();
@@ -65,3 +89,84 @@ impl Verbosity {
         }
     }
 }
+
+/// Event formatter that writes an optional prefix before every log line and formats the rest of
+/// it with `inner`.
+pub struct PrefixedFormat<F> {
+    prefix: Option<String>,
+    inner: F,
+}
+
+impl<F> PrefixedFormat<F> {
+    #[must_use]
+    pub fn new(prefix: Option<String>, inner: F) -> Self {
+        Self { prefix, inner }
+    }
+}
+
+impl<S, N, F> FormatEvent<S, N> for PrefixedFormat<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        if let Some(prefix) = &self.prefix {
+            write!(writer, "{prefix} ")?;
+        }
+        self.inner.format_event(ctx, writer, event)
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing_subscriber::fmt;
+
+    use super::PrefixedFormat;
+
+    #[test]
+    fn prefixes_every_line() {
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = fmt()
+            .event_format(PrefixedFormat::new(
+                Some("[H2]".to_string()),
+                fmt::format().with_ansi(false),
+            ))
+            .with_writer({
+                let logs = Arc::clone(&logs);
+                move || CapturedLogs(Arc::clone(&logs))
+            })
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("first");
+            tracing::warn!("second");
+        });
+
+        let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+        let lines = logs.lines().collect::<Vec<_>>();
+        assert_eq!(2, lines.len(), "{logs}");
+        for line in lines {
+            assert!(line.starts_with("[H2] "), "{line}");
+        }
+    }
+
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -3,6 +3,7 @@ use std::{
     borrow::{Borrow, Cow},
     fmt::{Debug, Formatter},
     iter::Zip,
+    num::NonZeroUsize,
     path::PathBuf,
     slice,
     time::Duration,
@@ -271,6 +272,10 @@ pub struct ServerConfig {
 
     /// Configuration needed for encrypting and decrypting match keys
     pub hpke_config: Option<HpkeServerConfig>,
+
+    /// Number of records coalesced into a single message sent to other helpers. If not set, it is
+    /// picked by the query.
+    pub batch_records: Option<NonZeroUsize>,
 }
 
 pub trait HyperClientConfigurator {
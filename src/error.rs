@@ -2,7 +2,11 @@ use std::{backtrace::Backtrace, fmt::Debug};
 
 use thiserror::Error;
 
-use crate::{report::InvalidReportError, task::JoinError};
+use crate::{
+    helpers::{AbortReason, Role},
+    report::InvalidReportError,
+    task::JoinError,
+};
 
 /// An error raised by the IPA protocol.
 ///
@@ -24,6 +28,8 @@ pub enum Error {
     InvalidId(String),
     #[error("invalid role")]
     InvalidRole,
+    #[error("shares are not consistent with role {0:?}")]
+    RoleShareMismatch(Role),
     #[error("not enough helpers")]
     NotEnoughHelpers,
     #[error("not found")]
@@ -36,6 +42,8 @@ pub enum Error {
     MaliciousSecurityCheckFailed,
     #[error("malicious reveal failed")]
     MaliciousRevealFailed,
+    #[error("reveal budget of {budget} values is exhausted")]
+    RevealBudgetExceeded { budget: usize },
     #[error("problem during IO: {0}")]
     Io(#[from] std::io::Error),
     // TODO remove if this https://github.com/awslabs/shuttle/pull/109 gets approved
@@ -49,15 +57,46 @@ pub enum Error {
     #[cfg(feature = "enable-serde")]
     Serde(#[from] serde_json::Error),
     #[error("Infrastructure error: {0}")]
-    InfraError(#[from] crate::helpers::Error),
+    InfraError(#[source] crate::helpers::Error),
     #[error("Value truncation error: {0}")]
     FieldValueTruncation(String),
+    #[error("Field overflow: {0}")]
+    FieldOverflow(String),
+    #[error("invalid permutation: {0}")]
+    InvalidPermutation(String),
+    /// Unlike most other errors, a timeout does not indicate that the request was malformed, so
+    /// it may succeed if retried.
+    #[error("timeout: {stage}")]
+    Timeout { stage: String },
     #[error("Invalid query parameter: {0}")]
     InvalidQueryParameter(String),
     #[error("invalid report: {0}")]
     InvalidReport(#[from] InvalidReportError),
     #[error("unsupported: {0}")]
     Unsupported(String),
+    #[error("unsupported wire format version {0}")]
+    UnsupportedWireVersion(u8),
+    #[error("input has {rows} rows, more than the limit of {limit}")]
+    InputTooLarge { rows: usize, limit: usize },
+    #[error("histogram total does not match the total of capped credits")]
+    HistogramTotalMismatch,
+}
+
+impl From<crate::helpers::Error> for Error {
+    fn from(err: crate::helpers::Error) -> Self {
+        match err {
+            // Running out of time is not an infrastructure failure, so report it the same way as
+            // other timeouts.
+            crate::helpers::Error::DeadlineExceeded { channel_id } => {
+                Error::timeout(format!("deadline exceeded at {}", channel_id.gate.as_ref()))
+            }
+            crate::helpers::Error::Aborted {
+                channel_id,
+                reason: AbortReason::Timeout,
+            } => Error::timeout(format!("receiving aborted at {}", channel_id.gate.as_ref())),
+            err => Error::InfraError(err),
+        }
+    }
 }
 
 impl Default for Error {
@@ -71,6 +110,19 @@ impl Error {
     pub fn path_parse_error(source: &str) -> Error {
         Error::ParseError(format!("unexpected value \"{source}\" in path").into())
     }
+
+    #[must_use]
+    pub fn timeout<S: Into<String>>(stage: S) -> Error {
+        Error::Timeout {
+            stage: stage.into(),
+        }
+    }
+
+    /// Returns `true` if this error is caused by some stage not completing in time.
+    #[must_use]
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Timeout { .. })
+    }
 }
 
 impl From<std::num::ParseIntError> for Error {
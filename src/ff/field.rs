@@ -1,5 +1,6 @@
 use std::fmt::Debug;
 
+use subtle::{Choice, ConstantTimeEq};
 use typenum::{U1, U4};
 
 use crate::{
@@ -27,6 +28,68 @@ pub trait Field: SharedValue + TryFrom<u128, Error = error::Error> + Into<Self::
     /// Blanket implementation to represent the instance of this trait as 16 byte integer.
     /// Uses the fact that such conversion already exists via `Self` -> `Self::Integer` -> `Into<u128>`
     fn as_u128(&self) -> u128;
+
+    /// Compares two field values in constant time. Use this instead of `==` whenever either
+    /// side is derived from secret data, for example when checking that a revealed value is zero
+    /// or that the shares received from two peers agree.
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.as_u128().ct_eq(&other.as_u128())
+    }
+
+    /// Multiplicative inverse of this value, or `None` if it has none. In a field, that is only
+    /// the case for zero. [`Gf40Bit`](crate::ff::Gf40Bit) is not a field because its polynomial
+    /// is reducible, so some of its non-zero values have no inverse either.
+    ///
+    /// This is not constant time: it branches on the value.
+    fn invert(&self) -> Option<Self>;
+
+    /// Replaces every value of the slice with its multiplicative inverse using Montgomery's trick,
+    /// which costs a single inversion and three multiplications per value instead of one inversion
+    /// per value.
+    ///
+    /// Zero has no inverse. Zeros are skipped and left in place, so they do not affect the
+    /// inverses computed for the other values. If other values have no inverse, which can only
+    /// happen for [`Gf40Bit`](crate::ff::Gf40Bit), every value is inverted on its own and the
+    /// ones without an inverse are left in place as well.
+    fn batch_invert(values: &mut [Self]) {
+        // prefix[i] is the product of the non-zero values before position i
+        let mut prefix = Vec::with_capacity(values.len());
+        let mut acc = Self::ONE;
+        for &v in values.iter() {
+            prefix.push(acc);
+            if v != Self::ZERO {
+                acc *= v;
+            }
+        }
+
+        let Some(mut inv) = acc.invert() else {
+            for v in values.iter_mut() {
+                *v = v.invert().unwrap_or(*v);
+            }
+            return;
+        };
+        for (v, prefix) in values.iter_mut().zip(prefix).rev() {
+            if *v != Self::ZERO {
+                let v_inv = inv * prefix;
+                inv *= *v;
+                *v = v_inv;
+            }
+        }
+    }
+}
+
+/// Raises `base` to the power of `exp` by repeated squaring. Only the exponent decides which
+/// multiplications are done, so this is fine to use with a public exponent.
+pub(super) fn pow<F: Field>(base: F, mut exp: u128) -> F {
+    let (mut base, mut acc) = (base, F::ONE);
+    while exp > 0 {
+        if exp & 1 == 1 {
+            acc *= base;
+        }
+        base *= base;
+        exp >>= 1;
+    }
+    acc
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -37,3 +100,117 @@ pub enum FieldType {
     Fp31,
     Fp32BitPrime,
 }
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use rand::{distributions::Standard, prelude::Distribution, thread_rng, Rng};
+
+    use super::Field;
+    use crate::{
+        ff::{Fp31, Fp32BitPrime, Gf2, Gf20Bit, Gf32Bit, Gf3Bit, Gf40Bit, Gf5Bit, Gf8Bit},
+        secret_sharing::{replicated::semi_honest::AdditiveShare as Replicated, SharedValue},
+        test_fixture::Reconstruct,
+    };
+
+    #[test]
+    fn ct_eq_agrees_with_eq() {
+        for a in 0..31_u32 {
+            for b in 0..31_u32 {
+                let (a, b) = (Fp31::truncate_from(a), Fp31::truncate_from(b));
+                assert_eq!(a == b, bool::from(a.ct_eq(&b)));
+            }
+        }
+
+        let values =
+            [0, 1, 2, 1 << 31, u32::MAX - 5, u32::MAX - 6].map(Fp32BitPrime::truncate_from);
+        for a in values {
+            for b in values {
+                assert_eq!(a == b, bool::from(a.ct_eq(&b)));
+            }
+        }
+    }
+
+    #[test]
+    fn invert() {
+        assert_eq!(None, Fp31::ZERO.invert());
+        for a in 1..31_u32 {
+            let a = Fp31::truncate_from(a);
+            assert_eq!(Fp31::ONE, a * a.invert().unwrap());
+        }
+
+        let a = Fp32BitPrime::truncate_from(u32::MAX - 6);
+        assert_eq!(Fp32BitPrime::ONE, a * a.invert().unwrap());
+        assert_eq!(None, Gf32Bit::ZERO.invert());
+        let a = Gf32Bit::truncate_from(0x1234_5678_u32);
+        assert_eq!(Gf32Bit::ONE, a * a.invert().unwrap());
+
+        // x^3 + x^2 + 1 divides x^40 + x^5 + x^3 + x^2 + 1, so it has no inverse
+        assert_eq!(None, Gf40Bit::truncate_from(0b1101_u32).invert());
+        let a = Gf40Bit::truncate_from(0x12_3456_789a_u64);
+        assert_eq!(Gf40Bit::ONE, a * a.invert().unwrap());
+    }
+
+    fn batch_invert_matches_invert<F: Field>(mut values: Vec<F>) {
+        let expected = values
+            .iter()
+            .map(|v| v.invert().unwrap_or(*v))
+            .collect::<Vec<_>>();
+        F::batch_invert(&mut values);
+        assert_eq!(expected, values);
+    }
+
+    #[test]
+    fn batch_invert() {
+        let mut rng = thread_rng();
+        batch_invert_matches_invert((0..100).map(|_| rng.gen::<Fp32BitPrime>()).collect());
+        batch_invert_matches_invert((0..100).map(|_| rng.gen::<Gf32Bit>()).collect());
+        // values without an inverse are left as they are
+        batch_invert_matches_invert((0..100).map(|_| rng.gen::<Gf40Bit>()).collect());
+        // zeros are left as they are
+        batch_invert_matches_invert((0..100).map(|_| rng.gen::<Fp31>()).collect());
+        batch_invert_matches_invert([0_u32, 3, 0, 0, 7, 30, 0].map(Fp31::truncate_from).to_vec());
+        batch_invert_matches_invert(vec![Fp32BitPrime::ZERO; 3]);
+        batch_invert_matches_invert(Vec::<Fp31>::new());
+    }
+
+    /// Helper bits and attribution rely on `ZERO` and `ONE` being the additive and multiplicative
+    /// identities, and on the replicated sharing of zero being a sharing of `ZERO`.
+    fn zero_and_one_are_identities<F: Field>()
+    where
+        Standard: Distribution<F>,
+    {
+        assert_ne!(F::ZERO, F::ONE);
+        assert_eq!(0, F::ZERO.as_u128());
+        assert_eq!(1, F::ONE.as_u128());
+        assert_eq!(F::ZERO, F::truncate_from(0_u128));
+        assert_eq!(F::ONE, F::truncate_from(1_u128));
+
+        let mut rng = thread_rng();
+        for _ in 0..100 {
+            let x = rng.gen::<F>();
+            assert_eq!(x, F::ZERO + x);
+            assert_eq!(x, x + F::ZERO);
+            assert_eq!(x, F::ONE * x);
+            assert_eq!(x, x * F::ONE);
+            assert_eq!(F::ZERO, F::ZERO * x);
+        }
+
+        assert_eq!(
+            F::ZERO,
+            [Replicated::<F>::ZERO, Replicated::ZERO, Replicated::ZERO].reconstruct()
+        );
+    }
+
+    #[test]
+    fn zero_and_one() {
+        zero_and_one_are_identities::<Fp31>();
+        zero_and_one_are_identities::<Fp32BitPrime>();
+        zero_and_one_are_identities::<Gf2>();
+        zero_and_one_are_identities::<Gf3Bit>();
+        zero_and_one_are_identities::<Gf5Bit>();
+        zero_and_one_are_identities::<Gf8Bit>();
+        zero_and_one_are_identities::<Gf20Bit>();
+        zero_and_one_are_identities::<Gf32Bit>();
+        zero_and_one_are_identities::<Gf40Bit>();
+    }
+}
@@ -5,7 +5,7 @@ use std::{
 
 use bitvec::prelude::{bitarr, BitArr, Lsb0};
 use generic_array::GenericArray;
-use typenum::{Unsigned, U1, U4, U5};
+use typenum::{Unsigned, U1, U3, U4, U5};
 
 use crate::{
     ff::{Field, Serializable},
@@ -25,6 +25,7 @@ pub trait GaloisField:
 
 // Bit store type definitions
 type U8_1 = BitArr!(for 8, in u8, Lsb0);
+type U8_3 = BitArr!(for 24, in u8, Lsb0);
 type U8_4 = BitArr!(for 32, in u8, Lsb0);
 type U8_5 = BitArr!(for 40, in u8, Lsb0);
 
@@ -32,6 +33,10 @@ impl Block for U8_1 {
     type Size = U1;
 }
 
+impl Block for U8_3 {
+    type Size = U3;
+}
+
 impl Block for U8_4 {
     type Size = U4;
 }
@@ -120,6 +125,36 @@ fn clmul<GF: GaloisField>(a: GF, b: GF) -> u128 {
     product
 }
 
+/// Inverse of `a` modulo `polynomial` in GF(2)\[x\], found with the extended Euclidean
+/// algorithm. Returns `None` if `a` and `polynomial` have a common factor. For an irreducible
+/// polynomial that only happens for zero, but the polynomial of [`Gf40Bit`] is reducible, so some
+/// of its non-zero values have no inverse either.
+fn invert_mod(a: u128, polynomial: u128) -> Option<u128> {
+    // degree of a non-zero polynomial
+    let degree = |v: u128| u128::BITS - 1 - v.leading_zeros();
+    let (mut r0, mut r1) = (polynomial, a);
+    let (mut s0, mut s1) = (0_u128, 1_u128);
+    while r1 != 0 {
+        let (mut q, mut r) = (0_u128, r0);
+        while r != 0 && degree(r) >= degree(r1) {
+            let shift = degree(r) - degree(r1);
+            q ^= 1 << shift;
+            r ^= r1 << shift;
+        }
+        // both `q` and `s1` have a lower degree than `polynomial`, so the product fits
+        let mut qs = 0;
+        for i in 0..u128::BITS - q.leading_zeros() {
+            if q >> i & 1 == 1 {
+                qs ^= s1 << i;
+            }
+        }
+        (r0, r1) = (r1, r);
+        (s0, s1) = (s1, s0 ^ qs);
+    }
+
+    (r0 == 1).then_some(s0)
+}
+
 macro_rules! bit_array_impl {
     ( $modname:ident, $name:ident, $store:ty, $bits:expr, $one:expr, $polynomial:expr ) => {
         #[allow(clippy::suspicious_arithmetic_impl)]
@@ -135,6 +170,34 @@ macro_rules! bit_array_impl {
             #[derive(Clone, Copy, PartialEq, Eq)]
             pub struct $name($store);
 
+            impl $name {
+                /// Assembles a value from its bits, starting from the LSB. This is the order in
+                /// which [`Self::bits`] yields them, so match keys can be built from a stream of
+                /// bits, e.g. the output of a hash, without assembling an integer first.
+                ///
+                /// ## Panics
+                /// If `bits` does not yield exactly `Self::BITS` bits.
+                #[must_use]
+                pub fn from_bits<I: IntoIterator<Item = bool>>(bits: I) -> Self {
+                    let len = usize::try_from(<$name>::BITS).unwrap();
+                    let mut store = <$store>::ZERO;
+                    let mut count = 0;
+                    for bit in bits {
+                        assert!(count < len, "more than {len} bits provided");
+                        store.set(count, bit);
+                        count += 1;
+                    }
+                    assert_eq!(len, count, "expected {len} bits, got {count}");
+
+                    Self(store)
+                }
+
+                /// Returns an iterator over the bits of this value, starting from the LSB.
+                pub fn bits(self) -> impl Iterator<Item = bool> {
+                    (0..<$name>::BITS).map(move |i| self[i])
+                }
+            }
+
             impl SharedValue for $name {
                 type Storage = $store;
                 const BITS: u32 = $bits;
@@ -153,6 +216,10 @@ macro_rules! bit_array_impl {
                     let v = &(v.into() & MASK).to_le_bytes()[..<Self as Serializable>::Size::to_usize()];
                     Self(<$store>::new(v.try_into().unwrap()))
                 }
+
+                fn invert(&self) -> Option<Self> {
+                    invert_mod(self.as_u128(), Self::POLYNOMIAL).map(Self::truncate_from)
+                }
             }
 
             impl GaloisField for $name {
@@ -512,6 +579,32 @@ macro_rules! bit_array_impl {
                     assert_eq!(a < b, $name::truncate_from(a) < $name::truncate_from(b));
                 }
 
+                #[test]
+                pub fn bits_round_trip() {
+                    let mut rng = thread_rng();
+                    let a = $name::truncate_from(rng.gen::<u128>());
+
+                    assert_eq!(a, $name::from_bits(a.bits()));
+                    assert_eq!(
+                        a.as_u128(),
+                        a.bits()
+                            .enumerate()
+                            .fold(0, |acc, (i, bit)| acc | (u128::from(bit) << i))
+                    );
+                }
+
+                #[test]
+                #[should_panic(expected = "bits")]
+                pub fn from_too_few_bits() {
+                    let _ = $name::from_bits($name::ONE.bits().skip(1));
+                }
+
+                #[test]
+                #[should_panic(expected = "bits")]
+                pub fn from_too_many_bits() {
+                    let _ = $name::from_bits($name::ONE.bits().chain(std::iter::once(false)));
+                }
+
                 #[test]
                 pub fn serde() {
                     let mut rng = thread_rng();
@@ -550,6 +643,16 @@ bit_array_impl!(
     0b1_0000_0000_0000_0000_0000_0000_1000_1101_u128
 );
 
+bit_array_impl!(
+    bit_array_20,
+    Gf20Bit,
+    U8_3,
+    20,
+    bitarr!(const u8, Lsb0; 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0),
+    // x^20 + x^3 + 1
+    0b1_0000_0000_0000_0000_1001_u128
+);
+
 bit_array_impl!(
     bit_array_8,
     Gf8Bit,
@@ -10,6 +10,23 @@ pub trait PrimeField: Field {
     type PrimeInteger: Into<u128>;
 
     const PRIME: Self::PrimeInteger;
+
+    /// Returns the largest sum of values that can be computed in this field before it wraps
+    /// around the modulus. Protocols that add values up, such as credit accumulation in
+    /// attribution, silently produce wrong results past that point, so the field must be chosen
+    /// large enough for the expected totals, e.g. the sum of all trigger values of a single user.
+    #[must_use]
+    fn max_safe_sum() -> u128 {
+        let prime: u128 = Self::PRIME.into();
+        prime - 1
+    }
+
+    /// Returns the largest value that each of `summands` values can take, so that their sum does
+    /// not exceed [`Self::max_safe_sum`].
+    #[must_use]
+    fn max_safe_summand(summands: usize) -> u128 {
+        Self::max_safe_sum() / u128::try_from(summands.max(1)).unwrap()
+    }
 }
 
 impl<F: PrimeField> Serializable for F {
@@ -60,6 +77,15 @@ macro_rules! field_impl {
                 #[allow(clippy::cast_possible_truncation)]
                 Self((v.into() % u128::from(Self::PRIME)) as <Self as SharedValue>::Storage)
             }
+
+            /// Uses Fermat's little theorem: `a^(p - 2) = a^-1` for any non-zero `a`.
+            fn invert(&self) -> Option<Self> {
+                if *self == Self::ZERO {
+                    None
+                } else {
+                    Some(crate::ff::field::pow(*self, u128::from(Self::PRIME) - 2))
+                }
+            }
         }
 
         impl PrimeField for $field {
@@ -244,6 +270,41 @@ mod fp31 {
             x += Fp31(2);
             assert_eq!(Fp31(3), x);
         }
+
+        /// The field is small enough to check every pair of elements against plain modular
+        /// arithmetic, which catches off-by-one reductions at the edges that random inputs rarely
+        /// hit.
+        #[test]
+        fn exhaustive_arithmetic() {
+            let p = u128::from(Fp31::PRIME);
+            for a in 0..p {
+                let x = Fp31::truncate_from(a);
+                assert_eq!((p - a) % p, (-x).as_u128(), "-{a}");
+                for b in 0..p {
+                    let y = Fp31::truncate_from(b);
+                    assert_eq!((a + b) % p, (x + y).as_u128(), "{a} + {b}");
+                    assert_eq!((a + p - b) % p, (x - y).as_u128(), "{a} - {b}");
+                    assert_eq!(a * b % p, (x * y).as_u128(), "{a} * {b}");
+
+                    let (mut sum, mut diff, mut product) = (x, x, x);
+                    sum += y;
+                    diff -= y;
+                    product *= y;
+                    assert_eq!((x + y, x - y, x * y), (sum, diff, product));
+                }
+            }
+        }
+
+        /// Every value of the storage type is reduced when converted into the field.
+        #[test]
+        fn conversion_reduces_all_bytes() {
+            let p = u128::from(Fp31::PRIME);
+            for v in 0..=u128::from(u8::MAX) {
+                assert_eq!(v % p, Fp31::try_from(v).unwrap().as_u128(), "{v}");
+                assert_eq!(v % p, Fp31::truncate_from(v).as_u128(), "{v}");
+            }
+            assert!(Fp31::try_from(u128::from(u8::MAX) + 1).is_err());
+        }
     }
 }
 
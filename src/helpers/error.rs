@@ -3,7 +3,7 @@ use tokio::sync::mpsc::error::SendError;
 
 use crate::{
     error::BoxError,
-    helpers::{ChannelId, HelperIdentity, Message, Role, TotalRecords},
+    helpers::{AbortReason, ChannelId, HelperIdentity, Message, Role, TotalRecords},
     protocol::{step::Gate, RecordId},
 };
 
@@ -54,6 +54,13 @@ pub enum Error {
         channel_id: ChannelId,
         total_records: TotalRecords,
     },
+    #[error("query deadline exceeded while communicating on {channel_id:?}")]
+    DeadlineExceeded { channel_id: ChannelId },
+    #[error("receiving on {channel_id:?} was aborted: {reason}")]
+    Aborted {
+        channel_id: ChannelId,
+        reason: AbortReason,
+    },
 }
 
 impl Error {
@@ -2,7 +2,7 @@ mod receive;
 mod send;
 mod transport;
 
-use std::{fmt::Debug, num::NonZeroUsize};
+use std::{fmt::Debug, future::Future, num::NonZeroUsize, time::Instant};
 
 pub use send::SendingEnd;
 #[cfg(all(feature = "shuttle", test))]
@@ -15,7 +15,8 @@ use crate::{
             send::GatewaySenders,
             transport::RoleResolvingTransport,
         },
-        ChannelId, Message, Role, RoleAssignment, TotalRecords, Transport,
+        AbortReason, ChannelId, Message, Role, RoleAssignment, TotalRecords, Transport,
+        WireCompression,
     },
     protocol::QueryId,
 };
@@ -50,6 +51,14 @@ pub struct GatewayConfig {
     /// The number of items that can be active at the one time.
     /// This is used to determine the size of sending and receiving buffers.
     active: NonZeroUsize,
+    /// Point in time after which sending and receiving fails with a timeout, so that slow peers
+    /// cannot stretch a query beyond its time budget.
+    deadline: Option<Instant>,
+    /// The number of records coalesced into a single message sent to a peer. If not set, a
+    /// message holds up to `active` records.
+    batch_records: Option<NonZeroUsize>,
+    /// Compression applied to messages sent to peers and expected on messages received from them.
+    wire_compression: WireCompression,
 }
 
 impl<T: Transport> Gateway<T> {
@@ -94,7 +103,7 @@ impl<T: Transport> Gateway<T> {
     ) -> SendingEnd<M> {
         let (tx, maybe_stream) =
             self.senders
-                .get_or_create::<M>(channel_id, self.config.active_work(), total_records);
+                .get_or_create::<M>(channel_id, self.config.batch_records(), total_records);
         if let Some(stream) = maybe_stream {
             tokio::spawn({
                 let channel_id = channel_id.clone();
@@ -109,7 +118,7 @@ impl<T: Transport> Gateway<T> {
             });
         }
 
-        SendingEnd::new(tx, self.role(), channel_id)
+        SendingEnd::new(tx, self.role(), channel_id, self.config.deadline)
     }
 
     #[must_use]
@@ -117,9 +126,18 @@ impl<T: Transport> Gateway<T> {
         ReceivingEndBase::new(
             channel_id.clone(),
             self.receivers
-                .get_or_create(channel_id, || self.transport.receive(channel_id)),
+                .get_or_create(channel_id, || self.transport.receive::<M>(channel_id)),
+            self.config.deadline,
+            self.transport.clone(),
         )
     }
+
+    /// Aborts every stream that peers send for this query and that has not been received yet.
+    /// Receivers that wait for one of them fail with `reason` rather than hang, and streams that
+    /// arrive later are dropped instead of kept around for a query that is gone.
+    pub fn abort(&self, reason: AbortReason) {
+        self.transport.abort(reason);
+    }
 }
 
 impl Default for GatewayConfig {
@@ -137,9 +155,57 @@ impl GatewayConfig {
     pub fn new(active: usize) -> Self {
         Self {
             active: NonZeroUsize::new(active).unwrap(),
+            deadline: None,
+            batch_records: None,
+            wire_compression: WireCompression::None,
+        }
+    }
+
+    /// Makes every send and receive that is still waiting at `deadline` fail with a timeout. All
+    /// contexts created for a query share its gateway, so the deadline applies to every narrowed
+    /// context and bounds the whole query, even if peers are slow rather than unresponsive.
+    #[must_use]
+    pub fn with_deadline(self, deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..self
         }
     }
 
+    /// Sets the number of records that are coalesced into a single message sent to a peer. Small
+    /// batches let peers start working on records sooner, large batches reduce the per-message
+    /// overhead. Batches never hold more than the active work, so larger values have no effect.
+    #[must_use]
+    pub fn with_batch_records(self, batch_records: NonZeroUsize) -> Self {
+        Self {
+            batch_records: Some(batch_records),
+            ..self
+        }
+    }
+
+    /// Sets the compression of messages exchanged with peers. All helpers must use the same
+    /// setting, otherwise they cannot read each other's messages.
+    #[must_use]
+    pub fn with_wire_compression(self, wire_compression: WireCompression) -> Self {
+        Self {
+            wire_compression,
+            ..self
+        }
+    }
+
+    /// Compression of messages exchanged with peers.
+    #[must_use]
+    pub fn wire_compression(&self) -> WireCompression {
+        self.wire_compression
+    }
+
+    /// The number of records sent to a peer in a single message.
+    #[must_use]
+    pub fn batch_records(&self) -> NonZeroUsize {
+        self.batch_records
+            .map_or(self.active, |batch_records| batch_records.min(self.active))
+    }
+
     /// The configured amount of active work.
     #[must_use]
     pub fn active_work(&self) -> NonZeroUsize {
@@ -147,16 +213,52 @@ impl GatewayConfig {
     }
 }
 
+/// Waits for `fut` to complete, unless `deadline` passes first, in which case it fails with
+/// [`Error::DeadlineExceeded`]. It also fails if `deadline` has passed before the wait started,
+/// even if `fut` would complete immediately.
+///
+/// [`Error::DeadlineExceeded`]: crate::helpers::Error::DeadlineExceeded
+async fn until_deadline<F, T>(
+    deadline: Option<Instant>,
+    channel_id: &ChannelId,
+    fut: F,
+) -> Result<T, crate::helpers::Error>
+where
+    F: Future<Output = Result<T, crate::helpers::Error>>,
+{
+    let Some(deadline) = deadline else {
+        return fut.await;
+    };
+    let exceeded = || crate::helpers::Error::DeadlineExceeded {
+        channel_id: channel_id.clone(),
+    };
+    if Instant::now() >= deadline {
+        return Err(exceeded());
+    }
+
+    ::tokio::time::timeout_at(deadline.into(), fut)
+        .await
+        .map_err(|_| exceeded())?
+}
+
 #[cfg(all(test, unit_test))]
 mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
     use futures_util::future::{join, try_join};
 
     use super::*;
     use crate::{
+        error::Error,
         ff::{Field, Fp31, Fp32BitPrime, Gf2},
-        helpers::{Direction, GatewayConfig, SendingEnd},
-        protocol::{context::Context, RecordId},
-        test_fixture::{Runner, TestWorld, TestWorldConfig},
+        helpers::{Direction, GatewayConfig, HelperIdentity, SendingEnd, StreamInterceptor},
+        protocol::{basics::SecureMul, context::Context, step::Gate, RecordId},
+        seq_join::SeqJoin,
+        sync::Arc,
+        test_fixture::{Reconstruct, Runner, TestWorld, TestWorldConfig},
     };
 
     /// Verifies that [`Gateway`] send buffer capacity is adjusted to the message size.
@@ -237,4 +339,225 @@ mod tests {
         spawned.await.unwrap();
         let _world = unsafe { Box::from_raw(world_ptr) };
     }
+
+    #[tokio::test]
+    async fn deadline_aborts_protocol() {
+        let config = TestWorldConfig {
+            gateway_config: GatewayConfig::default().with_deadline(Instant::now()),
+            ..Default::default()
+        };
+        let world = TestWorld::new_with(config);
+
+        let results = world
+            .semi_honest(
+                (Fp31::truncate_from(3_u128), Fp31::truncate_from(5_u128)),
+                |ctx, (a, b)| async move {
+                    a.multiply(&b, ctx.narrow("mul").set_total_records(1), RecordId::FIRST)
+                        .await
+                },
+            )
+            .await;
+
+        for result in results {
+            assert!(matches!(result, Err(Error::Timeout { .. })), "{result:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn deadline_interrupts_waiting_receive() {
+        let config = TestWorldConfig {
+            gateway_config: GatewayConfig::default()
+                .with_deadline(Instant::now() + Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let world = TestWorld::new_with(config);
+
+        // nobody sends anything, so every helper would wait forever without the deadline
+        let results = world
+            .semi_honest((), |ctx, _| async move {
+                ctx.narrow("recv")
+                    .recv_channel::<Fp31>(ctx.role().peer(Direction::Left))
+                    .receive(RecordId::FIRST)
+                    .await
+                    .map_err(Error::from)
+            })
+            .await;
+
+        for result in results {
+            assert!(matches!(result, Err(Error::Timeout { .. })), "{result:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn abort_surfaces_reason() {
+        let world = TestWorld::default();
+        let ctx = world.contexts()[0].narrow("abort");
+        world.gateway(Role::H1).abort(AbortReason::PeerFailed);
+
+        let err = ctx
+            .recv_channel::<Fp31>(Role::H2)
+            .receive(RecordId::FIRST)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                crate::helpers::Error::Aborted {
+                    reason: AbortReason::PeerFailed,
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    /// Records the offset and the size of the messages that helpers send to each other.
+    #[derive(Default)]
+    struct RecordMessages(std::sync::Mutex<Vec<(usize, usize)>>);
+
+    impl StreamInterceptor for RecordMessages {
+        fn intercept(
+            &self,
+            _from: HelperIdentity,
+            _to: HelperIdentity,
+            _gate: &Gate,
+            offset: usize,
+            chunk: &mut Vec<u8>,
+        ) {
+            self.0.lock().unwrap().push((offset, chunk.len()));
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_records_sets_message_size() {
+        const RECORDS: usize = 64;
+
+        /// Multiplies `RECORDS` pairs of values and returns the offset and the size of the
+        /// messages sent together with the products.
+        async fn multiply(batch_records: usize) -> (Vec<(usize, usize)>, Vec<Fp31>) {
+            let messages = Arc::new(RecordMessages::default());
+            let config = TestWorldConfig {
+                gateway_config: GatewayConfig::default()
+                    .with_batch_records(NonZeroUsize::new(batch_records).unwrap()),
+                ..Default::default()
+            }
+            .with_stream_interceptor(Arc::clone(&messages) as Arc<dyn StreamInterceptor>);
+            let world = TestWorld::new_with(config);
+
+            let input = (0..RECORDS).map(|i| {
+                (
+                    Fp31::truncate_from(u128::try_from(i).unwrap()),
+                    Fp31::truncate_from(3_u128),
+                )
+            });
+            let products = world
+                .semi_honest(input, |ctx, pairs| async move {
+                    let ctx = ctx.narrow("mul").set_total_records(RECORDS);
+                    ctx.try_join(pairs.into_iter().enumerate().map(|(i, (a, b))| {
+                        let ctx = ctx.clone();
+                        async move { a.multiply(&b, ctx, RecordId::from(i)).await }
+                    }))
+                    .await
+                    .unwrap()
+                })
+                .await
+                .reconstruct();
+
+            let messages = messages.0.lock().unwrap().clone();
+            (messages, products)
+        }
+
+        // Senders may buffer more than a batch if the stream is not read fast enough, so only the
+        // lower bound on the message size is deterministic. Only the last message of a stream
+        // may be smaller than a batch.
+        let mut products = Vec::new();
+        for batch_records in [4, 16] {
+            let (messages, batch_products) = multiply(batch_records).await;
+            // every helper sends all of its records to one peer
+            assert_eq!(
+                3 * RECORDS,
+                messages.iter().map(|(_, len)| len).sum::<usize>()
+            );
+            assert!(
+                messages
+                    .iter()
+                    .all(|&(offset, len)| offset + len == RECORDS || len >= batch_records),
+                "{messages:?}"
+            );
+            products.push(batch_products);
+        }
+        assert_eq!(products[0], products[1]);
+    }
+
+    /// Counts the bytes that helpers send to each other.
+    #[derive(Default)]
+    struct CountBytes(AtomicUsize);
+
+    impl StreamInterceptor for CountBytes {
+        fn intercept(
+            &self,
+            _from: HelperIdentity,
+            _to: HelperIdentity,
+            _gate: &Gate,
+            _offset: usize,
+            chunk: &mut Vec<u8>,
+        ) {
+            self.0.fetch_add(chunk.len(), Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn wire_compression_shrinks_sparse_batches() {
+        const RECORDS: usize = 256;
+
+        /// Every helper sends `RECORDS` zeros to its right peer. Returns the number of bytes
+        /// sent on the wire together with the values received by all helpers.
+        async fn send_zeros(wire_compression: WireCompression) -> (usize, Vec<Fp32BitPrime>) {
+            let bytes = Arc::new(CountBytes::default());
+            let config = TestWorldConfig {
+                gateway_config: GatewayConfig::default().with_wire_compression(wire_compression),
+                ..Default::default()
+            }
+            .with_stream_interceptor(Arc::clone(&bytes) as Arc<dyn StreamInterceptor>);
+            let world = TestWorld::new_with(config);
+
+            let received = world
+                .semi_honest((), |ctx, _| async move {
+                    let ctx = ctx.narrow("zeros").set_total_records(RECORDS);
+                    ctx.try_join((0..RECORDS).map(|i| {
+                        let ctx = ctx.clone();
+                        async move {
+                            let role = ctx.role();
+                            let record_id = RecordId::from(i);
+                            let ((), received) = try_join(
+                                ctx.send_channel::<Fp32BitPrime>(role.peer(Direction::Right))
+                                    .send(record_id, Fp32BitPrime::truncate_from(0_u128)),
+                                ctx.recv_channel::<Fp32BitPrime>(role.peer(Direction::Left))
+                                    .receive(record_id),
+                            )
+                            .await?;
+                            Ok::<_, Error>(received)
+                        }
+                    }))
+                    .await
+                    .unwrap()
+                })
+                .await;
+
+            (bytes.0.load(Ordering::Relaxed), received.concat())
+        }
+
+        let (uncompressed_bytes, uncompressed) = send_zeros(WireCompression::None).await;
+        let (compressed_bytes, compressed) = send_zeros(WireCompression::Zstd).await;
+
+        assert_eq!(
+            vec![Fp32BitPrime::truncate_from(0_u128); 3 * RECORDS],
+            compressed
+        );
+        assert_eq!(uncompressed, compressed);
+        assert!(
+            compressed_bytes * 10 < uncompressed_bytes,
+            "{compressed_bytes} bytes compressed, {uncompressed_bytes} bytes uncompressed"
+        );
+    }
 }
@@ -1,10 +1,12 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, time::Instant};
 
 use dashmap::DashMap;
-use futures::Stream;
 
+use super::{transport::RoleResolvingTransport, until_deadline};
 use crate::{
-    helpers::{buffers::UnorderedReceiver, ChannelId, Error, Message, Transport},
+    helpers::{
+        buffers::UnorderedReceiver, ChannelId, DecompressedStream, Error, Message, Transport,
+    },
     protocol::RecordId,
 };
 
@@ -12,6 +14,9 @@ use crate::{
 pub struct ReceivingEnd<T: Transport, M: Message> {
     channel_id: ChannelId,
     unordered_rx: UR<T>,
+    deadline: Option<Instant>,
+    /// Tells why the stream ended early, if it was aborted.
+    transport: RoleResolvingTransport<T>,
     _phantom: PhantomData<M>,
 }
 
@@ -20,16 +25,21 @@ pub(super) struct GatewayReceivers<T: Transport> {
     inner: DashMap<ChannelId, UR<T>>,
 }
 
-pub(super) type UR<T> = UnorderedReceiver<
-    <T as Transport>::RecordsStream,
-    <<T as Transport>::RecordsStream as Stream>::Item,
->;
+pub(super) type UR<T> =
+    UnorderedReceiver<DecompressedStream<<T as Transport>::RecordsStream>, Vec<u8>>;
 
 impl<T: Transport, M: Message> ReceivingEnd<T, M> {
-    pub(super) fn new(channel_id: ChannelId, rx: UR<T>) -> Self {
+    pub(super) fn new(
+        channel_id: ChannelId,
+        rx: UR<T>,
+        deadline: Option<Instant>,
+        transport: RoleResolvingTransport<T>,
+    ) -> Self {
         Self {
             channel_id,
             unordered_rx: rx,
+            deadline,
+            transport,
             _phantom: PhantomData,
         }
     }
@@ -38,20 +48,30 @@ impl<T: Transport, M: Message> ReceivingEnd<T, M> {
     /// message is actually received and deserialized.
     ///
     /// ## Errors
-    /// Returns an error if receiving fails
+    /// Returns an error if receiving fails, the query deadline passes before the message
+    /// arrives or the stream was aborted, in which case the error carries the reason.
     ///
     /// ## Panics
     /// This will panic if message size does not fit into 8 bytes and it somehow got serialized
     /// and sent to this helper.
     pub async fn receive(&self, record_id: RecordId) -> Result<M, Error> {
-        self.unordered_rx
-            .recv::<M, _>(record_id)
-            .await
-            .map_err(|e| Error::ReceiveError {
-                source: self.channel_id.role,
-                step: self.channel_id.gate.to_string(),
-                inner: Box::new(e),
-            })
+        until_deadline(self.deadline, &self.channel_id, async {
+            self.unordered_rx
+                .recv::<M, _>(record_id)
+                .await
+                .map_err(|e| match self.transport.abort_reason() {
+                    Some(reason) => Error::Aborted {
+                        channel_id: self.channel_id.clone(),
+                        reason,
+                    },
+                    None => Error::ReceiveError {
+                        source: self.channel_id.role,
+                        step: self.channel_id.gate.to_string(),
+                        inner: Box::new(e),
+                    },
+                })
+        })
+        .await
     }
 }
 
@@ -3,12 +3,14 @@ use std::{
     num::NonZeroUsize,
     pin::Pin,
     task::{Context, Poll},
+    time::Instant,
 };
 
 use dashmap::DashMap;
 use futures::Stream;
 use typenum::Unsigned;
 
+use super::until_deadline;
 use crate::{
     helpers::{buffers::OrderingSender, ChannelId, Error, Message, Role, TotalRecords},
     protocol::RecordId,
@@ -24,6 +26,7 @@ pub struct SendingEnd<M: Message> {
     sender_role: Role,
     channel_id: ChannelId,
     inner: Arc<GatewaySender>,
+    deadline: Option<Instant>,
     _phantom: PhantomData<M>,
 }
 
@@ -80,11 +83,17 @@ impl GatewaySender {
 }
 
 impl<M: Message> SendingEnd<M> {
-    pub(super) fn new(sender: Arc<GatewaySender>, role: Role, channel_id: &ChannelId) -> Self {
+    pub(super) fn new(
+        sender: Arc<GatewaySender>,
+        role: Role,
+        channel_id: &ChannelId,
+        deadline: Option<Instant>,
+    ) -> Self {
         Self {
             sender_role: role,
             channel_id: channel_id.clone(),
             inner: sender,
+            deadline,
             _phantom: PhantomData,
         }
     }
@@ -94,12 +103,17 @@ impl<M: Message> SendingEnd<M> {
     /// for sending.
     ///
     /// ## Errors
-    /// If send operation fails or `record_id` exceeds the channel limit set by [`set_total_records`]
-    /// call.
+    /// If send operation fails, `record_id` exceeds the channel limit set by [`set_total_records`]
+    /// call or the query deadline passes before there is capacity for the message.
     ///
     /// [`set_total_records`]: crate::protocol::context::Context::set_total_records
     pub async fn send(&self, record_id: RecordId, msg: M) -> Result<(), Error> {
-        let r = self.inner.send(record_id, msg).await;
+        let r = until_deadline(
+            self.deadline,
+            &self.channel_id,
+            self.inner.send(record_id, msg),
+        )
+        .await;
         metrics::increment_counter!(RECORDS_SENT,
             STEP => self.channel_id.gate.as_ref().to_string(),
             ROLE => self.sender_role.as_static_str()
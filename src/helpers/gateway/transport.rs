@@ -1,8 +1,12 @@
+use typenum::Unsigned;
+
 use crate::{
     helpers::{
         buffers::UnorderedReceiver,
+        compress,
         gateway::{receive::UR, send::GatewaySendStream},
-        ChannelId, GatewayConfig, Role, RoleAssignment, RouteId, Transport,
+        AbortReason, ChannelId, DecompressedStream, GatewayConfig, Message, Role, RoleAssignment,
+        RouteId, Transport,
     },
     protocol::QueryId,
 };
@@ -36,12 +40,12 @@ impl<T: Transport> RoleResolvingTransport<T> {
             .send(
                 dest_identity,
                 (RouteId::Records, self.query_id, channel_id.gate.clone()),
-                data,
+                compress(data, self.config.wire_compression()),
             )
             .await
     }
 
-    pub(crate) fn receive(&self, channel_id: &ChannelId) -> UR<T> {
+    pub(crate) fn receive<M: Message>(&self, channel_id: &ChannelId) -> UR<T> {
         let peer = self.roles.identity(channel_id.role);
         assert_ne!(
             peer,
@@ -49,15 +53,27 @@ impl<T: Transport> RoleResolvingTransport<T> {
             "can't receive message from itself"
         );
 
+        // Senders never put more than `active_work` records into a single message.
+        let max_message_size = self.config.active_work().get() * M::Size::USIZE;
         UnorderedReceiver::new(
-            Box::pin(
+            Box::pin(DecompressedStream::new(
                 self.inner
                     .receive(peer, (self.query_id, channel_id.gate.clone())),
-            ),
+                self.config.wire_compression(),
+                max_message_size,
+            )),
             self.config.active_work(),
         )
     }
 
+    pub(crate) fn abort(&self, reason: AbortReason) {
+        self.inner.abort(self.query_id, reason);
+    }
+
+    pub(crate) fn abort_reason(&self) -> Option<AbortReason> {
+        self.inner.abort_reason(self.query_id)
+    }
+
     pub(crate) fn role(&self) -> Role {
         self.roles.role(self.inner.identity())
     }
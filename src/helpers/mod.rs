@@ -24,12 +24,14 @@ pub use prss_protocol::negotiate as negotiate_prss;
 #[cfg(feature = "web-app")]
 pub use transport::WrappedAxumBodyStream;
 pub use transport::{
-    callbacks::*, query, BodyStream, BytesStream, LengthDelimitedStream, LogErrors,
-    NoResourceIdentifier, QueryIdBinding, ReceiveRecords, RecordsStream, RouteId, RouteParams,
-    StepBinding, StreamCollection, StreamKey, Transport, WrappedBoxBodyStream,
+    callbacks::*, compress, query, sequence, AbortReason, BodyStream, BufferedBytesStream,
+    BytesStream, DecompressedStream, LengthDelimitedStream, LogErrors, NoResourceIdentifier,
+    PeerState, QueryIdBinding, ReceiveRecords, RecordsStream, Redeliveries, ResumableStream,
+    RouteId, RouteParams, SequenceError, StepBinding, StreamCollection, StreamKey, TeeStream,
+    Transport, UnsequencedStream, WireCompression, WrappedBoxBodyStream,
 };
 #[cfg(feature = "in-memory-infra")]
-pub use transport::{InMemoryNetwork, InMemoryTransport};
+pub use transport::{InMemoryNetwork, InMemoryTransport, StreamInterceptor};
 use typenum::{Unsigned, U8};
 use x25519_dalek::PublicKey;
 
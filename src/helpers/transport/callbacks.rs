@@ -1,7 +1,7 @@
 use std::{future::Future, pin::Pin};
 
 use crate::{
-    helpers::query::{PrepareQuery, QueryConfig, QueryInput},
+    helpers::query::{PrepareQuery, QueryConfig, QueryInput, ResultPage},
     protocol::QueryId,
     query::{
         NewQueryError, PrepareQueryError, ProtocolResult, QueryCompletionError, QueryInputError,
@@ -27,14 +27,14 @@ use crate::{
 macro_rules! callbacks {
     {
         $(
-            $(#[$($attr:meta),+ ])?
+            $(#[$attr:meta])*
             ($cb_name:ident, $res_name:ident): async fn($($args:ident),*) -> $result:ty;
         )*
     } => {
         $(
             pub type $res_name = Pin<Box<dyn Future<Output = $result> + Send>>;
 
-            $(#[$($attr),+ ])?
+            $(#[$attr])*
             pub trait $cb_name<T>: Fn($($args),*) -> $res_name + Send + Sync {}
 
             impl<T, F> $cb_name<T> for F where
@@ -60,9 +60,18 @@ callbacks! {
     (QueryStatusCallback, QueryStatusResult):
         async fn(T, QueryId) -> Result<QueryStatus, QueryStatusError>;
 
+    /// Called by operators to list queries this helper is currently tracking.
+    (ActiveQueriesCallback, ActiveQueriesResult):
+        async fn(T) -> Vec<(QueryId, QueryStatus)>;
+
     /// Called by clients to drive query to completion and retrieve results.
     (CompleteQueryCallback, CompleteQueryResult):
         async fn(T, QueryId) -> Result<Box<dyn ProtocolResult>, QueryCompletionError>;
+
+    /// Called by clients to retrieve a page of query results. Unlike `CompleteQueryCallback`,
+    /// this can be called again for the same query while its results are retained.
+    (QueryResultPageCallback, QueryResultPageResult):
+        async fn(T, ResultPage) -> Result<Vec<u8>, QueryCompletionError>;
 }
 
 pub struct TransportCallbacks<T> {
@@ -70,7 +79,9 @@ pub struct TransportCallbacks<T> {
     pub prepare_query: Box<dyn PrepareQueryCallback<T>>,
     pub query_input: Box<dyn QueryInputCallback<T>>,
     pub query_status: Box<dyn QueryStatusCallback<T>>,
+    pub active_queries: Box<dyn ActiveQueriesCallback<T>>,
     pub complete_query: Box<dyn CompleteQueryCallback<T>>,
+    pub result_page: Box<dyn QueryResultPageCallback<T>>,
 }
 
 #[cfg(any(test, feature = "in-memory-infra"))]
@@ -93,9 +104,15 @@ impl<T> Default for TransportCallbacks<T> {
             query_status: Box::new(move |_, _| {
                 Box::pin(async { panic!("unexpected call to query_status") })
             }),
+            active_queries: Box::new(move |_| {
+                Box::pin(async { panic!("unexpected call to active_queries") })
+            }),
             complete_query: Box::new(move |_, _| {
                 Box::pin(async { panic!("unexpected call to complete_query") })
             }),
+            result_page: Box::new(move |_, _| {
+                Box::pin(async { panic!("unexpected call to result_page") })
+            }),
         }
     }
 }
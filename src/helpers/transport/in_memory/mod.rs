@@ -1,6 +1,8 @@
 mod transport;
 
-pub use transport::Setup;
+use std::num::NonZeroUsize;
+
+pub use transport::{Setup, StreamInterceptor};
 
 use crate::{
     helpers::{HelperIdentity, TransportCallbacks},
@@ -9,6 +11,10 @@ use crate::{
 
 pub type InMemoryTransport = Weak<transport::InMemoryTransport>;
 
+/// Size of the frames that [`InMemoryNetwork::framed`] cuts record streams into. It is chosen to
+/// not line up with the size of any message or message header.
+const FRAME_SIZE: usize = 7;
+
 /// Container for all active transports
 #[derive(Clone)]
 pub struct InMemoryNetwork {
@@ -29,8 +35,57 @@ impl Default for InMemoryNetwork {
 impl InMemoryNetwork {
     #[must_use]
     pub fn new(callbacks: [TransportCallbacks<InMemoryTransport>; 3]) -> Self {
+        Self::build(callbacks, None, None)
+    }
+
+    /// Creates a network that delivers record streams the way a byte stream transport does: the
+    /// chunks that helpers send arrive split into small frames instead of one piece. Query requests
+    /// already go through their serialized form on every in-memory network. Use this to catch
+    /// serialization and framing bugs without running helpers over HTTP.
+    ///
+    /// This is not a separate [`Transport`] implementation: helpers still talk over
+    /// [`InMemoryTransport`], which is the only transport they can use in this build configuration.
+    ///
+    /// ## Panics
+    /// Never.
+    ///
+    /// [`Transport`]: crate::helpers::Transport
+    #[must_use]
+    pub fn framed(callbacks: [TransportCallbacks<InMemoryTransport>; 3]) -> Self {
+        Self::build(
+            callbacks,
+            None,
+            Some(NonZeroUsize::new(FRAME_SIZE).unwrap()),
+        )
+    }
+
+    /// Creates a network where every record stream sent between helpers passes through
+    /// `interceptor`, which may modify it.
+    #[must_use]
+    pub fn with_interceptor(
+        callbacks: [TransportCallbacks<InMemoryTransport>; 3],
+        interceptor: &Arc<dyn StreamInterceptor>,
+    ) -> Self {
+        Self::build(callbacks, Some(interceptor), None)
+    }
+
+    fn build(
+        callbacks: [TransportCallbacks<InMemoryTransport>; 3],
+        interceptor: Option<&Arc<dyn StreamInterceptor>>,
+        frame_size: Option<NonZeroUsize>,
+    ) -> Self {
         let [mut first, mut second, mut third]: [_; 3] =
-            HelperIdentity::make_three().map(Setup::new);
+            HelperIdentity::make_three().map(|identity| {
+                let setup = Setup::new(identity);
+                let setup = match interceptor {
+                    Some(interceptor) => setup.with_interceptor(Arc::clone(interceptor)),
+                    None => setup,
+                };
+                match frame_size {
+                    Some(frame_size) => setup.with_frame_size(frame_size),
+                    None => setup,
+                }
+            });
 
         first.connect(&mut second);
         second.connect(&mut third);
@@ -4,6 +4,7 @@ use std::{
     convert,
     fmt::{Debug, Formatter},
     io,
+    num::NonZeroUsize,
     pin::Pin,
     sync::{Arc, Weak},
     task::{Context, Poll},
@@ -14,7 +15,7 @@ use ::tokio::sync::{
     oneshot,
 };
 use async_trait::async_trait;
-use futures::{Stream, StreamExt};
+use futures::{future::ready, stream, Stream, StreamExt};
 use serde::de::DeserializeOwned;
 #[cfg(all(feature = "shuttle", test))]
 use shuttle::future as tokio;
@@ -25,8 +26,11 @@ use crate::{
     error::BoxError,
     helpers::{
         query::{PrepareQuery, QueryConfig},
-        HelperIdentity, NoResourceIdentifier, QueryIdBinding, ReceiveRecords, RouteId, RouteParams,
-        StepBinding, StreamCollection, Transport, TransportCallbacks,
+        sequence,
+        transport::stream::unsequence_message,
+        AbortReason, HelperIdentity, LogErrors, NoResourceIdentifier, QueryIdBinding,
+        ReceiveRecords, RouteId, RouteParams, StepBinding, StreamCollection, StreamKey, Transport,
+        TransportCallbacks,
     },
     protocol::{step::Gate, QueryId},
 };
@@ -51,6 +55,22 @@ pub enum Error {
     },
 }
 
+/// Lets tests tamper with the records that helpers send to each other, for example to check that
+/// malicious protocols detect it.
+pub trait StreamInterceptor: Send + Sync {
+    /// Called for every chunk of data that `from` sends to `to` on `gate`. `offset` is the number of
+    /// bytes the sender put on this channel before `chunk`, not counting any changes made by
+    /// earlier calls.
+    fn intercept(
+        &self,
+        from: HelperIdentity,
+        to: HelperIdentity,
+        gate: &Gate,
+        offset: usize,
+        chunk: &mut Vec<u8>,
+    );
+}
+
 /// In-memory implementation of [`Transport`] backed by Tokio mpsc channels.
 /// Use [`Setup`] to initialize it and call [`Setup::start`] to make it actively listen for
 /// incoming messages.
@@ -58,15 +78,24 @@ pub struct InMemoryTransport {
     identity: HelperIdentity,
     connections: HashMap<HelperIdentity, ConnectionTx>,
     record_streams: StreamCollection<InMemoryStream>,
+    interceptor: Option<Arc<dyn StreamInterceptor>>,
+    frame_size: Option<NonZeroUsize>,
 }
 
 impl InMemoryTransport {
     #[must_use]
-    fn new(identity: HelperIdentity, connections: HashMap<HelperIdentity, ConnectionTx>) -> Self {
+    fn new(
+        identity: HelperIdentity,
+        connections: HashMap<HelperIdentity, ConnectionTx>,
+        interceptor: Option<Arc<dyn StreamInterceptor>>,
+        frame_size: Option<NonZeroUsize>,
+    ) -> Self {
         Self {
             identity,
             connections,
             record_streams: StreamCollection::default(),
+            interceptor,
+            frame_size,
         }
     }
 
@@ -110,7 +139,9 @@ impl InMemoryTransport {
                                 let query_id = addr.query_id.unwrap();
                                 let gate = addr.gate.unwrap();
                                 let from = addr.origin.unwrap();
-                                streams.add_stream((query_id, from, gate), stream);
+                                let key = (query_id, from, gate);
+                                let stream = InMemoryStream::wrap(check_order(key.clone(), stream));
+                                streams.add_stream(key, stream);
                                 Ok(())
                             }
                             RouteId::PrepareQuery => {
@@ -152,7 +183,7 @@ impl InMemoryTransport {
 
 #[async_trait]
 impl Transport for Weak<InMemoryTransport> {
-    type RecordsStream = ReceiveRecords<InMemoryStream>;
+    type RecordsStream = LogErrors<ReceiveRecords<InMemoryStream>, StreamItem, AbortReason>;
     type Error = Error;
 
     fn identity(&self) -> HelperIdentity {
@@ -179,12 +210,31 @@ impl Transport for Weak<InMemoryTransport> {
         let addr = Addr::from_route(this.identity, route);
         let (ack_tx, ack_rx) = oneshot::channel();
 
-        channel
-            .send((addr, InMemoryStream::wrap(data), ack_tx))
-            .await
-            .map_err(|_e| {
-                io::Error::new::<String>(io::ErrorKind::ConnectionAborted, "channel closed".into())
-            })?;
+        let stream = match (&this.interceptor, &addr.gate) {
+            (Some(interceptor), Some(gate)) => {
+                let (interceptor, gate, origin) =
+                    (Arc::clone(interceptor), gate.clone(), this.identity);
+                InMemoryStream::wrap(data.scan(0, move |offset, mut chunk| {
+                    let len = chunk.len();
+                    interceptor.intercept(origin, dest, &gate, *offset, &mut chunk);
+                    *offset += len;
+                    ready(Some(chunk))
+                }))
+            }
+            _ => InMemoryStream::wrap(data),
+        };
+        let stream = match this.frame_size {
+            Some(frame_size) => InMemoryStream::wrap(reframe(stream, frame_size)),
+            None => stream,
+        };
+        let stream = match addr.route {
+            RouteId::Records => InMemoryStream::wrap(sequence(stream)),
+            _ => stream,
+        };
+
+        channel.send((addr, stream, ack_tx)).await.map_err(|_e| {
+            io::Error::new::<String>(io::ErrorKind::ConnectionAborted, "channel closed".into())
+        })?;
 
         ack_rx
             .await
@@ -200,11 +250,59 @@ impl Transport for Weak<InMemoryTransport> {
         from: HelperIdentity,
         route: R,
     ) -> Self::RecordsStream {
-        ReceiveRecords::new(
+        LogErrors::new(ReceiveRecords::new(
             (route.query_id(), from, route.gate()),
             self.upgrade().unwrap().record_streams.clone(),
-        )
+        ))
+    }
+
+    fn abort(&self, query_id: QueryId, reason: AbortReason) {
+        self.upgrade()
+            .unwrap()
+            .record_streams
+            .abort(query_id, reason);
     }
+
+    fn abort_reason(&self, query_id: QueryId) -> Option<AbortReason> {
+        self.upgrade()
+            .unwrap()
+            .record_streams
+            .abort_reason(query_id)
+    }
+}
+
+/// Strips the nonces that the sender added to every record chunk with [`sequence`] and checks
+/// that chunks arrive in the order they were sent. Protocols index records by their position in
+/// the stream, so a chunk delivered out of order would silently corrupt them; tests fail right
+/// away instead.
+fn check_order(key: StreamKey, stream: InMemoryStream) -> impl Stream<Item = StreamItem> + Send {
+    stream.enumerate().map(move |(i, message)| {
+        let (nonce, payload) = unsequence_message(message);
+        assert_eq!(
+            u64::try_from(i).unwrap(),
+            nonce,
+            "records for {key:?} delivered out of order"
+        );
+        payload
+    })
+}
+
+/// Splits every chunk of `data` into frames of at most `frame_size` bytes. Byte stream transports
+/// like HTTP do not preserve the boundaries of the chunks that were sent either, so receivers that
+/// rely on them fail here the same way they would over the network. Frames are not held back to
+/// be filled up, because peers may wait for a partial chunk before sending more.
+fn reframe<S: Stream<Item = StreamItem> + Send + 'static>(
+    data: S,
+    frame_size: NonZeroUsize,
+) -> impl Stream<Item = StreamItem> + Send {
+    data.flat_map(move |chunk| {
+        stream::iter(
+            chunk
+                .chunks(frame_size.get())
+                .map(<[u8]>::to_vec)
+                .collect::<Vec<_>>(),
+        )
+    })
 }
 
 /// Convenience struct to support heterogeneous in-memory streams
@@ -319,6 +417,8 @@ pub struct Setup {
     tx: ConnectionTx,
     rx: ConnectionRx,
     connections: HashMap<HelperIdentity, ConnectionTx>,
+    interceptor: Option<Arc<dyn StreamInterceptor>>,
+    frame_size: Option<NonZeroUsize>,
 }
 
 impl Setup {
@@ -330,9 +430,26 @@ impl Setup {
             tx,
             rx,
             connections: HashMap::default(),
+            interceptor: None,
+            frame_size: None,
         }
     }
 
+    /// Passes every record stream that this helper sends through `interceptor`.
+    #[must_use]
+    pub fn with_interceptor(mut self, interceptor: Arc<dyn StreamInterceptor>) -> Self {
+        self.interceptor = Some(interceptor);
+        self
+    }
+
+    /// Delivers every record stream that this helper sends in frames of `frame_size` bytes,
+    /// regardless of how the sender chunked it.
+    #[must_use]
+    pub fn with_frame_size(mut self, frame_size: NonZeroUsize) -> Self {
+        self.frame_size = Some(frame_size);
+        self
+    }
+
     /// Establishes a link between this helper and another one
     ///
     /// ## Panics
@@ -352,7 +469,12 @@ impl Setup {
         self,
         callbacks: TransportCallbacks<Weak<InMemoryTransport>>,
     ) -> (ConnectionTx, Arc<InMemoryTransport>) {
-        let transport = Arc::new(InMemoryTransport::new(self.identity, self.connections));
+        let transport = Arc::new(InMemoryTransport::new(
+            self.identity,
+            self.connections,
+            self.interceptor,
+            self.frame_size,
+        ));
         transport.listen(callbacks, self.rx);
 
         (self.tx, transport)
@@ -385,7 +507,12 @@ mod tests {
 
     const STEP: &str = "in-memory-transport";
 
+    /// Sends `data` the same way as [`Transport::send`] does, numbering record chunks.
     async fn send_and_ack(sender: &ConnectionTx, addr: Addr, data: InMemoryStream) {
+        let data = match addr.route {
+            RouteId::Records => InMemoryStream::wrap(sequence(data)),
+            _ => data,
+        };
         let (tx, rx) = oneshot::channel();
         sender.send((addr, data, tx)).await.unwrap();
         rx.await
@@ -413,7 +540,7 @@ mod tests {
                             .expect("query callback invoked more than once")
                             .send(query_config)
                             .unwrap();
-                        Ok(QueryId)
+                        Ok(QueryId::default())
                     })
                 }),
                 ..Default::default()
@@ -437,7 +564,8 @@ mod tests {
         let transport = Arc::downgrade(&transport);
         let expected = vec![vec![1], vec![2]];
 
-        let mut stream = transport.receive(HelperIdentity::TWO, (QueryId, Gate::from(STEP)));
+        let mut stream =
+            transport.receive(HelperIdentity::TWO, (QueryId::default(), Gate::from(STEP)));
 
         // make sure it is not ready as it hasn't received the records stream yet.
         assert!(matches!(
@@ -446,7 +574,7 @@ mod tests {
         ));
         send_and_ack(
             &tx,
-            Addr::records(HelperIdentity::TWO, QueryId, Gate::from(STEP)),
+            Addr::records(HelperIdentity::TWO, QueryId::default(), Gate::from(STEP)),
             InMemoryStream::from_iter(expected.clone()),
         )
         .await;
@@ -462,13 +590,13 @@ mod tests {
 
         send_and_ack(
             &tx,
-            Addr::records(HelperIdentity::TWO, QueryId, Gate::from(STEP)),
+            Addr::records(HelperIdentity::TWO, QueryId::default(), Gate::from(STEP)),
             InMemoryStream::from_iter(expected.clone()),
         )
         .await;
 
-        let stream =
-            Arc::downgrade(&transport).receive(HelperIdentity::TWO, (QueryId, Gate::from(STEP)));
+        let stream = Arc::downgrade(&transport)
+            .receive(HelperIdentity::TWO, (QueryId::default(), Gate::from(STEP)));
 
         assert_eq!(expected, stream.collect::<Vec<_>>().await);
     }
@@ -487,14 +615,18 @@ mod tests {
             let to_transport = transports.get(&to).unwrap();
             let gate = Gate::from(STEP);
 
-            let mut recv = to_transport.receive(from, (QueryId, gate.clone()));
+            let mut recv = to_transport.receive(from, (QueryId::default(), gate.clone()));
             assert!(matches!(
                 poll_immediate(&mut recv).next().await,
                 Some(Poll::Pending)
             ));
 
             from_transport
-                .send(to, (RouteId::Records, QueryId, gate.clone()), stream)
+                .send(
+                    to,
+                    (RouteId::Records, QueryId::default(), gate.clone()),
+                    stream,
+                )
                 .await
                 .unwrap();
             stream_tx.send(vec![1, 2, 3]).await.unwrap();
@@ -531,6 +663,76 @@ mod tests {
         send_and_verify(HelperIdentity::TWO, HelperIdentity::ONE, &transports).await;
     }
 
+    #[tokio::test]
+    async fn records_delivered_in_order() {
+        const RECORDS: u8 = 100;
+        let network = InMemoryNetwork::default();
+        let transport1 = network.transport(HelperIdentity::ONE);
+        let transport2 = network.transport(HelperIdentity::TWO);
+        let gate = Gate::from(STEP);
+
+        let (stream_tx, stream_rx) = channel(usize::from(RECORDS));
+        transport1
+            .send(
+                HelperIdentity::TWO,
+                (RouteId::Records, QueryId::default(), gate.clone()),
+                InMemoryStream::from(stream_rx),
+            )
+            .await
+            .unwrap();
+
+        // fast producer: pushes all records without waiting for the receiver to catch up
+        let producer = tokio::spawn(async move {
+            for i in 0..RECORDS {
+                stream_tx.send(vec![i]).await.unwrap();
+            }
+        });
+
+        let recv = transport2.receive(HelperIdentity::ONE, (QueryId::default(), gate));
+        let received = recv.collect::<Vec<_>>().await;
+        producer.await.unwrap();
+
+        assert_eq!(usize::from(RECORDS), received.len());
+        for (expected_seq, record) in received.into_iter().enumerate() {
+            assert_eq!(
+                vec![u8::try_from(expected_seq).unwrap()],
+                record,
+                "record {expected_seq} delivered out of order"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn records_out_of_order_fail() {
+        let (tx, transport) =
+            Setup::new(HelperIdentity::ONE).into_active_conn(TransportCallbacks::default());
+        let mut messages = sequence(stream::iter(vec![vec![1], vec![2]]))
+            .collect::<Vec<_>>()
+            .await;
+        messages.swap(0, 1);
+
+        // `send_and_ack` would number the swapped messages again
+        let (ack_tx, ack_rx) = oneshot::channel();
+        tx.send((
+            Addr::records(HelperIdentity::TWO, QueryId::default(), Gate::from(STEP)),
+            InMemoryStream::from_iter(messages),
+            ack_tx,
+        ))
+        .await
+        .unwrap();
+        ack_rx.await.unwrap().unwrap();
+
+        let mut stream = Arc::downgrade(&transport)
+            .receive(HelperIdentity::TWO, (QueryId::default(), Gate::from(STEP)));
+        let err = AssertUnwindSafe(stream.next()).catch_unwind().await;
+        assert_eq!(
+            Some(true),
+            err.unwrap_err()
+                .downcast_ref::<String>()
+                .map(|s| s.contains("delivered out of order"))
+        );
+    }
+
     #[tokio::test]
     async fn panic_if_stream_received_twice() {
         let (tx, owned_transport) =
@@ -540,10 +742,11 @@ mod tests {
         let stream = InMemoryStream::from(stream_rx);
         let transport = Arc::downgrade(&owned_transport);
 
-        let mut recv_stream = transport.receive(HelperIdentity::TWO, (QueryId, gate.clone()));
+        let mut recv_stream =
+            transport.receive(HelperIdentity::TWO, (QueryId::default(), gate.clone()));
         send_and_ack(
             &tx,
-            Addr::records(HelperIdentity::TWO, QueryId, gate.clone()),
+            Addr::records(HelperIdentity::TWO, QueryId::default(), gate.clone()),
             stream,
         )
         .await;
@@ -552,7 +755,8 @@ mod tests {
         assert_eq!(vec![4, 5, 6], recv_stream.next().await.unwrap());
 
         // the same stream cannot be received again
-        let mut err_recv = transport.receive(HelperIdentity::TWO, (QueryId, gate.clone()));
+        let mut err_recv =
+            transport.receive(HelperIdentity::TWO, (QueryId::default(), gate.clone()));
         let err = AssertUnwindSafe(err_recv.next()).catch_unwind().await;
         assert_eq!(
             Some(true),
@@ -563,7 +767,8 @@ mod tests {
 
         // even after the input stream is closed
         drop(stream_tx);
-        let mut err_recv = transport.receive(HelperIdentity::TWO, (QueryId, gate.clone()));
+        let mut err_recv =
+            transport.receive(HelperIdentity::TWO, (QueryId::default(), gate.clone()));
         let err = AssertUnwindSafe(err_recv.next()).catch_unwind().await;
         assert_eq!(
             Some(true),
@@ -588,12 +793,12 @@ mod tests {
         transport1
             .send(
                 HelperIdentity::TWO,
-                (RouteId::Records, QueryId, gate.clone()),
+                (RouteId::Records, QueryId::default(), gate.clone()),
                 rx,
             )
             .await
             .unwrap();
-        let mut recv = transport2.receive(HelperIdentity::ONE, (QueryId, gate));
+        let mut recv = transport2.receive(HelperIdentity::ONE, (QueryId::default(), gate));
 
         tx.send(0, Fp31::try_from(0_u128).unwrap()).await;
         // can't receive the value at index 0 because of buffering inside the sender
@@ -607,4 +812,30 @@ mod tests {
         // must be received by now
         assert_eq!(vec![vec![0, 1]], recv.collect::<Vec<_>>().await);
     }
+
+    #[tokio::test]
+    async fn framed_network_splits_records_into_frames() {
+        let network = InMemoryNetwork::framed(Default::default());
+        let transport1 = network.transport(HelperIdentity::ONE);
+        let transport2 = network.transport(HelperIdentity::TWO);
+        let gate = Gate::from(STEP);
+        let sent = vec![(0..20).collect::<Vec<u8>>(), vec![20, 21]];
+
+        transport1
+            .send(
+                HelperIdentity::TWO,
+                (RouteId::Records, QueryId::default(), gate.clone()),
+                futures::stream::iter(sent.clone()),
+            )
+            .await
+            .unwrap();
+        let received = transport2
+            .receive(HelperIdentity::ONE, (QueryId::default(), gate))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(received.len() > sent.len());
+        assert!(received.iter().all(|frame| !frame.is_empty()));
+        assert_eq!(sent.concat(), received.concat());
+    }
 }
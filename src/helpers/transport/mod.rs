@@ -1,4 +1,4 @@
-use std::borrow::Borrow;
+use std::{borrow::Borrow, num::NonZeroUsize};
 
 use async_trait::async_trait;
 use futures::Stream;
@@ -16,12 +16,14 @@ mod receive;
 mod stream;
 
 #[cfg(feature = "in-memory-infra")]
-pub use in_memory::{InMemoryNetwork, InMemoryTransport};
+pub use in_memory::{InMemoryNetwork, InMemoryTransport, StreamInterceptor};
 pub use receive::{LogErrors, ReceiveRecords};
 #[cfg(feature = "web-app")]
 pub use stream::WrappedAxumBodyStream;
 pub use stream::{
-    BodyStream, BytesStream, LengthDelimitedStream, RecordsStream, StreamCollection, StreamKey,
+    compress, sequence, AbortReason, BodyStream, BufferedBytesStream, BytesStream,
+    DecompressedStream, LengthDelimitedStream, RecordsStream, Redeliveries, ResumableStream,
+    SequenceError, StreamCollection, StreamKey, TeeStream, UnsequencedStream, WireCompression,
     WrappedBoxBodyStream,
 };
 
@@ -123,6 +125,13 @@ impl RouteParams<RouteId, QueryId, Gate> for (RouteId, QueryId, Gate) {
     }
 }
 
+/// Liveness of another helper, as last observed by this helper's transport.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerState {
+    Alive,
+    Unreachable,
+}
+
 /// Transport that supports per-query,per-step channels
 #[async_trait]
 pub trait Transport: Clone + Send + Sync + 'static {
@@ -156,6 +165,29 @@ pub trait Transport: Clone + Send + Sync + 'static {
         route: R,
     ) -> Self::RecordsStream;
 
+    /// Aborts every record stream of `query_id` that has not been received yet, see
+    /// [`StreamCollection::abort`].
+    fn abort(&self, query_id: QueryId, reason: AbortReason);
+
+    /// Returns the reason why the record streams of `query_id` were aborted, if they were.
+    fn abort_reason(&self, query_id: QueryId) -> Option<AbortReason>;
+
+    /// Returns the last known state of the given helper. Transports that do not track liveness
+    /// report every peer as alive.
+    fn peer_state(&self, _id: HelperIdentity) -> PeerState {
+        PeerState::Alive
+    }
+
+    /// Establishes connections to the other helpers ahead of time and refreshes their
+    /// [`PeerState`]. Transports that are always connected have nothing to do.
+    async fn connect_peers(&self) {}
+
+    /// The number of records that gateways should coalesce into a single message sent over this
+    /// transport. `None` leaves the choice to the gateway configuration.
+    fn batch_records(&self) -> Option<NonZeroUsize> {
+        None
+    }
+
     /// Alias for `Clone::clone`.
     ///
     /// `Transport` is implemented for `Weak<InMemoryTranport>` and `Arc<HttpTransport>`. Clippy won't
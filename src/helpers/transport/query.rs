@@ -1,6 +1,9 @@
 use std::{
     fmt::{Debug, Display, Formatter},
     num::NonZeroU32,
+    ops::Range,
+    str::FromStr,
+    sync::Arc,
 };
 
 use serde::{Deserialize, Deserializer, Serialize};
@@ -9,7 +12,7 @@ use crate::{
     ff::FieldType,
     helpers::{
         transport::{BodyStream, NoQueryId, NoStep},
-        GatewayConfig, RoleAssignment, RouteId, RouteParams,
+        GatewayConfig, HelperIdentity, RoleAssignment, RouteId, RouteParams, WireCompression,
     },
     protocol::{step::Step, QueryId},
 };
@@ -82,13 +85,65 @@ impl From<QuerySize> for usize {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 #[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub struct QueryConfig {
     pub size: QuerySize,
     pub field_type: FieldType,
     pub query_type: QueryType,
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    pub priority: QueryPriority,
+    /// Compression of the records that helpers send to each other while running this query.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    pub wire_compression: WireCompression,
+    /// Time that helpers give this query to run once they have its input. Communication with
+    /// peers that is still waiting after that fails with a timeout. If not set, queries can run
+    /// forever.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    pub timeout_seconds: Option<NonZeroU32>,
+}
+
+/// How urgently a query should be executed. When the coordinator of a query runs at its
+/// concurrency limit, queries with higher priority get the next free execution slot ahead of the
+/// ones that have been waiting longer. Waiting queries gain priority over time, so low priority queries are delayed, but never
+/// starved.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum QueryPriority {
+    /// Batch jobs that can wait.
+    Low,
+    #[default]
+    Normal,
+    /// Interactive queries that someone is waiting for.
+    High,
+}
+
+/// Log level requested for a single query. Events of that query that are at least as severe as
+/// this level are logged, even if the helper logs less for everything else. It only adds events,
+/// so choosing a level below the one of the helper has no effect.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum QueryLogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<QueryLogLevel> for tracing::Level {
+    fn from(level: QueryLogLevel) -> Self {
+        match level {
+            QueryLogLevel::Error => Self::ERROR,
+            QueryLogLevel::Warn => Self::WARN,
+            QueryLogLevel::Info => Self::INFO,
+            QueryLogLevel::Debug => Self::DEBUG,
+            QueryLogLevel::Trace => Self::TRACE,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -104,6 +159,8 @@ pub struct PrepareQuery {
     pub query_id: QueryId,
     pub config: QueryConfig,
     pub roles: RoleAssignment,
+    /// Helper that coordinates the query and sends this request. It can play any role in it.
+    pub coordinator: HelperIdentity,
 }
 
 impl RouteParams<RouteId, NoQueryId, NoStep> for &QueryConfig {
@@ -133,9 +190,9 @@ impl RouteParams<RouteId, NoQueryId, NoStep> for &QueryConfig {
 }
 
 impl From<&QueryConfig> for GatewayConfig {
-    fn from(_value: &QueryConfig) -> Self {
+    fn from(value: &QueryConfig) -> Self {
         // TODO: pick the correct value for active and test it
-        Self::default()
+        Self::default().with_wire_compression(value.wire_compression)
     }
 }
 
@@ -156,8 +213,29 @@ impl QueryConfig {
             size: size.try_into()?,
             field_type,
             query_type,
+            priority: QueryPriority::default(),
+            wire_compression: WireCompression::default(),
+            timeout_seconds: None,
         })
     }
+
+    #[must_use]
+    pub fn with_priority(mut self, priority: QueryPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    #[must_use]
+    pub fn with_wire_compression(mut self, wire_compression: WireCompression) -> Self {
+        self.wire_compression = wire_compression;
+        self
+    }
+
+    #[must_use]
+    pub fn with_timeout_seconds(mut self, timeout_seconds: NonZeroU32) -> Self {
+        self.timeout_seconds = Some(timeout_seconds);
+        self
+    }
 }
 
 impl RouteParams<RouteId, QueryId, NoStep> for &PrepareQuery {
@@ -197,7 +275,16 @@ impl Debug for QueryInput {
     }
 }
 
+/// Part of a completed query result, requested by clients that fetch results in pages. The page
+/// holds up to `limit` bytes of the result, starting at `offset`.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ResultPage {
+    pub query_id: QueryId,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 pub enum QueryType {
     #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
@@ -232,9 +319,10 @@ impl AsRef<str> for QueryType {
 
 impl Step for QueryType {}
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "enable-serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "clap", derive(clap::Args))]
+#[allow(clippy::struct_excessive_bools)] // independent opt-ins, each a CLI flag and query param
 pub struct IpaQueryConfig {
     #[cfg_attr(feature = "clap", arg(long, default_value = "5"))]
     pub per_user_credit_cap: u32,
@@ -249,8 +337,94 @@ pub struct IpaQueryConfig {
     /// shares from input reports directly. Setting this to true also activates an alternate
     /// input report format in which all fields are secret-shared. This option is provided
     /// only for development and testing purposes and may be removed in the future.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
     #[cfg_attr(feature = "clap", arg(long))]
     pub plaintext_match_keys: bool,
+
+    /// If true, breakdown keys that are greater than or equal to `max_breakdown_key` are
+    /// obliviously clamped to `max_breakdown_key - 1`, so their credit is counted in the top
+    /// bucket. Otherwise the behavior for such keys is unspecified.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub clamp_breakdown_keys: bool,
+
+    /// If true, IPA does not trust `max_breakdown_key` and instead sizes the aggregation by the
+    /// largest breakdown key present in the input. The maximum is computed obliviously and only
+    /// that single value is revealed, which the output histogram would disclose anyway.
+    /// `clamp_breakdown_keys` has no effect, because no key can exceed the detected maximum.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub detect_max_breakdown_key: bool,
+
+    /// If true, a trigger event that has the same match key, breakdown key and trigger value as
+    /// the trigger event right before it (after sorting by match key) is treated as a duplicate
+    /// and its trigger value is obliviously set to zero. This guards against upstream pipelines
+    /// that emit the same report twice, at the cost of a comparison per row.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub dedup: bool,
+
+    /// If true, a match key of zero is treated as a missing identifier. Trigger values of rows
+    /// with an all-zero match key are obliviously set to zero, so those rows contribute no credit
+    /// to any breakdown, even though they sort into a single "user". Costs a zero check per row.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub drop_zero_match_keys: bool,
+
+    /// If true, trigger values are interpreted as signed: values above `(p - 1) / 2` are
+    /// negative, so refunds and chargebacks subtract from the credit of the source event they are
+    /// attributed to. Credits that net out to a negative value are clamped to zero before
+    /// per-user capping. Has no effect when the per-user cap is one.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub signed_trigger_values: bool,
+
+    /// If set, no trigger value is larger than this or, with `signed_trigger_values`, smaller
+    /// than its negation. Before accumulating credit, IPA checks that the trigger values of all
+    /// input rows cannot add up to more than the field holds and rejects the query otherwise, which
+    /// catches fields that are too small for the input. Trigger values are secret-shared, so the
+    /// bound itself is not verified.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub max_trigger_value: Option<NonZeroU32>,
+
+    /// If true, IPA checks at the end of the query that the output histogram adds up to the total
+    /// of the credits left after per-user capping, so that no credit was lost or counted twice.
+    /// Only the difference between the two totals is revealed. Breakdown keys that are not below
+    /// `max_breakdown_key` fail the check unless they are clamped, because their credit is dropped.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub check_histogram_total: bool,
+
+    /// IPA assumes that the first row after sorting starts a new user, so it is never attributed
+    /// to anything before it. Callers that split the input by user across several IPA queries can
+    /// set this to mark the first row as continuing the last user of the previous chunk instead.
+    /// Events of previous chunks are not available, so a trigger event at the start of the chunk
+    /// still has nothing to be attributed to, and per-user caps only apply within a chunk.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub first_row_continues_previous: bool,
+
+    /// If set, queries with more input rows than this are rejected before any MPC work starts.
+    /// Rows are counted as they arrive, so the rest of an oversized input is never read. Rows
+    /// past the query size count towards the limit, because they are received all the same.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub max_input_rows: Option<NonZeroU32>,
+
+    /// If set, helpers send a POST request to this URL once the query completes, so clients do
+    /// not need to poll for results. Helpers only call back hosts from their allowlist and reject
+    /// queries that ask for any other callback.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub callback_url: Option<CallbackUrl>,
+
+    /// If set, this query logs at the given level, while the helper keeps logging everything
+    /// else at the level it was started with. Useful to debug a single query without drowning in
+    /// the logs of all other queries.
+    #[cfg_attr(feature = "enable-serde", serde(default))]
+    #[cfg_attr(feature = "clap", arg(long, value_enum))]
+    pub log_level: Option<QueryLogLevel>,
 }
 
 impl Default for IpaQueryConfig {
@@ -261,6 +435,17 @@ impl Default for IpaQueryConfig {
             attribution_window_seconds: None,
             num_multi_bits: 3,
             plaintext_match_keys: false,
+            clamp_breakdown_keys: false,
+            detect_max_breakdown_key: false,
+            dedup: false,
+            drop_zero_match_keys: false,
+            signed_trigger_values: false,
+            max_trigger_value: None,
+            check_histogram_total: false,
+            first_row_continues_previous: false,
+            max_input_rows: None,
+            callback_url: None,
+            log_level: None,
         }
     }
 }
@@ -284,6 +469,17 @@ impl IpaQueryConfig {
             ),
             num_multi_bits,
             plaintext_match_keys: false,
+            clamp_breakdown_keys: false,
+            detect_max_breakdown_key: false,
+            dedup: false,
+            drop_zero_match_keys: false,
+            signed_trigger_values: false,
+            max_trigger_value: None,
+            check_histogram_total: false,
+            first_row_continues_previous: false,
+            max_input_rows: None,
+            callback_url: None,
+            log_level: None,
         }
     }
 
@@ -303,7 +499,113 @@ impl IpaQueryConfig {
             attribution_window_seconds: None,
             num_multi_bits,
             plaintext_match_keys: false,
+            clamp_breakdown_keys: false,
+            detect_max_breakdown_key: false,
+            dedup: false,
+            drop_zero_match_keys: false,
+            signed_trigger_values: false,
+            max_trigger_value: None,
+            check_histogram_total: false,
+            first_row_continues_previous: false,
+            max_input_rows: None,
+            callback_url: None,
+            log_level: None,
+        }
+    }
+}
+
+/// Absolute `http` or `https` URL that is notified when a query completes. URLs with user info, a
+/// query string or a fragment are rejected. That rules out URLs that look like they point to one
+/// host but connect to another, and lets the URL be passed as a query parameter without escaping.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct CallbackUrl {
+    url: Arc<str>,
+    host: Range<usize>,
+}
+
+impl CallbackUrl {
+    pub const MAX_LEN: usize = 255;
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.url
+    }
+
+    /// Host part of this URL, without the port. IPv6 addresses are enclosed in brackets.
+    #[must_use]
+    pub fn host(&self) -> &str {
+        &self.url[self.host.clone()]
+    }
+
+    /// Byte range of the host within `url`, if it is an absolute http or https URL.
+    fn parse_host(url: &str) -> Option<Range<usize>> {
+        let start = ["https://", "http://"]
+            .into_iter()
+            .find_map(|scheme| url.starts_with(scheme).then_some(scheme.len()))?;
+        let authority = url[start..].split('/').next().unwrap();
+        let len = if authority.starts_with('[') {
+            authority.find(']')? + 1
+        } else {
+            authority.find(':').unwrap_or(authority.len())
+        };
+
+        (len > 0).then_some(start..start + len)
+    }
+}
+
+impl FromStr for CallbackUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() > Self::MAX_LEN {
+            return Err(format!(
+                "callback URL must not be longer than {} bytes",
+                Self::MAX_LEN
+            ));
+        }
+        if let Some(c) = s
+            .chars()
+            .find(|c| !c.is_ascii_graphic() || matches!(c, '@' | '?' | '#' | '&' | '+' | '%'))
+        {
+            return Err(format!("callback URL {s} must not contain {c:?}"));
         }
+        let Some(host) = Self::parse_host(s) else {
+            return Err(format!(
+                "callback URL {s} must be an absolute http or https URL"
+            ));
+        };
+
+        Ok(Self {
+            url: s.into(),
+            host,
+        })
+    }
+}
+
+impl TryFrom<String> for CallbackUrl {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<CallbackUrl> for String {
+    fn from(value: CallbackUrl) -> Self {
+        value.as_str().to_owned()
+    }
+}
+
+impl Display for CallbackUrl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for CallbackUrl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CallbackUrl").field(&self.as_str()).finish()
     }
 }
 
@@ -9,7 +9,7 @@ use tracing::error;
 
 use crate::{
     error::BoxError,
-    helpers::transport::stream::{StreamCollection, StreamKey},
+    helpers::transport::stream::{AbortReason, StreamCollection, StreamKey},
 };
 
 /// Adapt a stream of `Result<T: Into<Vec<u8>>, Error>` to a stream of `Vec<u8>`.
@@ -66,6 +66,14 @@ where
 /// If stream is not received yet, each poll generates a waker that is used internally to wake up
 /// the task when stream is received.
 /// Once stream is received, it is moved to this struct and it acts as a proxy to it.
+///
+/// Records are yielded in exactly the order they were produced by the sender. Each [`StreamKey`]
+/// maps to a single underlying stream and this struct never buffers or re-orders items, so
+/// `RecordId`-indexed protocols can rely on positional delivery.
+///
+/// If the stream is aborted via [`StreamCollection::abort`] before it is received, including
+/// when this starts waiting only after the abort, this yields a single error carrying the
+/// [`AbortReason`] and terminates.
 pub struct ReceiveRecords<S> {
     inner: ReceiveRecordsInner<S>,
 }
@@ -79,7 +87,7 @@ impl<S> ReceiveRecords<S> {
 }
 
 impl<S: Stream + Unpin> Stream for ReceiveRecords<S> {
-    type Item = S::Item;
+    type Item = Result<S::Item, AbortReason>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         Pin::get_mut(self).inner.poll_next_unpin(cx)
@@ -90,24 +98,85 @@ impl<S: Stream + Unpin> Stream for ReceiveRecords<S> {
 enum ReceiveRecordsInner<S> {
     Pending(StreamKey, StreamCollection<S>),
     Ready(S),
+    Aborted,
 }
 
 impl<S: Stream + Unpin> Stream for ReceiveRecordsInner<S> {
-    type Item = S::Item;
+    type Item = Result<S::Item, AbortReason>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = Pin::get_mut(self);
         loop {
             match this {
-                Self::Pending(key, streams) => {
-                    if let Some(stream) = streams.add_waker(key, cx.waker()) {
-                        *this = Self::Ready(stream);
-                    } else {
-                        return Poll::Pending;
+                Self::Pending(key, streams) => match streams.add_waker(key, cx.waker()) {
+                    Ok(Some(stream)) => *this = Self::Ready(stream),
+                    Ok(None) => return Poll::Pending,
+                    Err(reason) => {
+                        *this = Self::Aborted;
+                        return Poll::Ready(Some(Err(reason)));
                     }
-                }
-                Self::Ready(stream) => return stream.poll_next_unpin(cx),
+                },
+                Self::Ready(stream) => return stream.poll_next_unpin(cx).map(|r| r.map(Ok)),
+                Self::Aborted => return Poll::Ready(None),
             }
         }
     }
 }
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use futures::{
+        future::poll_immediate,
+        stream::{self, Empty},
+        StreamExt,
+    };
+
+    use super::ReceiveRecords;
+    use crate::{
+        helpers::{AbortReason, HelperIdentity, StreamCollection},
+        protocol::{step::Gate, QueryId},
+    };
+
+    #[tokio::test]
+    async fn abort_pending_receiver() {
+        let streams = StreamCollection::<Empty<Vec<u8>>>::default();
+        let query_id = QueryId::default();
+        let other_query = QueryId::from([1; 16]);
+        let mut receiver = ReceiveRecords::new(
+            (query_id, HelperIdentity::ONE, Gate::default()),
+            streams.clone(),
+        );
+        let mut other_receiver = ReceiveRecords::new(
+            (other_query, HelperIdentity::ONE, Gate::default()),
+            streams.clone(),
+        );
+        assert!(poll_immediate(receiver.next()).await.is_none());
+        assert!(poll_immediate(other_receiver.next()).await.is_none());
+
+        streams.abort(query_id, AbortReason::PeerFailed);
+        streams.add_stream(
+            (query_id, HelperIdentity::ONE, Gate::default()),
+            stream::empty(),
+        );
+
+        assert_eq!(Some(Err(AbortReason::PeerFailed)), receiver.next().await);
+        assert_eq!(None, receiver.next().await);
+        assert!(poll_immediate(other_receiver.next()).await.is_none());
+
+        // receivers that show up late are aborted too, and a second abort keeps the first reason
+        streams.abort(query_id, AbortReason::Timeout);
+        let mut late_receiver = ReceiveRecords::new(
+            (query_id, HelperIdentity::TWO, Gate::default()),
+            streams.clone(),
+        );
+        assert_eq!(
+            Some(Err(AbortReason::PeerFailed)),
+            late_receiver.next().await
+        );
+        assert_eq!(
+            Some(AbortReason::PeerFailed),
+            streams.abort_reason(query_id)
+        );
+        assert_eq!(None, streams.abort_reason(other_query));
+    }
+}
@@ -8,7 +8,7 @@ use futures::{Stream, TryStreamExt};
 use hyper::Body;
 use pin_project::pin_project;
 
-use crate::error::BoxError;
+use crate::{error::BoxError, helpers::transport::stream::BytesStream};
 
 type AxumInner = futures::stream::MapErr<BodyStream, fn(axum::Error) -> crate::error::BoxError>;
 
@@ -64,6 +64,15 @@ impl WrappedAxumBodyStream {
             .unwrap(),
         )
     }
+
+    /// Wraps a stream of byte chunks, returning an instance of `crate::helpers::BodyStream`.
+    ///
+    /// # Panics
+    /// See [`Self::from_body`].
+    #[must_use]
+    pub fn from_bytes_stream<S: BytesStream + 'static>(stream: S) -> Self {
+        Self::from_body(Body::wrap_stream(stream))
+    }
 }
 
 #[cfg(feature = "real-world-infra")]
@@ -5,7 +5,7 @@ use std::{
 
 use futures::Stream;
 
-use crate::helpers::transport::stream::BoxBytesStream;
+use crate::helpers::transport::stream::{BoxBytesStream, BytesStream};
 
 pub struct WrappedBoxBodyStream(BoxBytesStream);
 
@@ -16,6 +16,12 @@ impl WrappedBoxBodyStream {
     pub fn new(inner: axum::extract::BodyStream) -> Self {
         Self(Box::pin(super::WrappedAxumBodyStream::new_internal(inner)))
     }
+
+    /// Wraps a stream of byte chunks, returning an instance of `crate::helpers::BodyStream`.
+    #[must_use]
+    pub fn from_bytes_stream<S: BytesStream + 'static>(stream: S) -> Self {
+        Self(Box::pin(stream))
+    }
 }
 
 impl Stream for WrappedBoxBodyStream {
@@ -0,0 +1,120 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::{Stream, TryStreamExt};
+
+use crate::{error::BoxError, helpers::transport::stream::BytesStream, sync::Arc};
+
+/// A bytes stream that has been collected into memory, so it can be streamed more than once.
+///
+/// Request bodies are consumed by the first attempt to send them, so retrying a send requires
+/// another copy of the body. Cloning this stream before it is polled gives such a copy without
+/// copying the underlying bytes. Only bodies that are small enough to be kept in memory should be
+/// buffered, larger bodies must be streamed directly and can't be retried.
+#[derive(Clone, Debug)]
+pub struct BufferedBytesStream {
+    chunks: Arc<Vec<Bytes>>,
+    next: usize,
+}
+
+impl BufferedBytesStream {
+    /// Collects `stream` into memory.
+    ///
+    /// ## Errors
+    /// If `stream` fails or yields more than `limit` bytes.
+    pub async fn try_buffer<S: BytesStream>(stream: S, limit: usize) -> Result<Self, BoxError> {
+        let mut size = 0;
+        let chunks = stream
+            .and_then(|chunk| {
+                size += chunk.len();
+                futures::future::ready(if size > limit {
+                    Err(format!("body is larger than {limit} bytes and can't be buffered").into())
+                } else {
+                    Ok(chunk)
+                })
+            })
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        Ok(Self {
+            chunks: Arc::new(chunks),
+            next: 0,
+        })
+    }
+
+    /// Total number of bytes in the buffered stream.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(Bytes::len).sum()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Stream for BufferedBytesStream {
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let chunk = self.chunks.get(self.next).cloned();
+        if chunk.is_some() {
+            self.next += 1;
+        }
+
+        Poll::Ready(chunk.map(Ok))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.chunks.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use bytes::Bytes;
+    use futures::{stream, StreamExt};
+
+    use super::BufferedBytesStream;
+    use crate::{error::BoxError, helpers::BytesStream};
+
+    fn chunked_body() -> impl BytesStream {
+        stream::iter(
+            [&b"hello"[..], b" ", b"buffered", b" world"]
+                .map(|chunk| Ok::<_, BoxError>(Bytes::from(chunk))),
+        )
+    }
+
+    #[tokio::test]
+    async fn can_be_streamed_twice() {
+        let buffered = BufferedBytesStream::try_buffer(chunked_body(), 1024)
+            .await
+            .unwrap();
+        assert_eq!(20, buffered.len());
+
+        let retry = buffered.clone();
+        let first = buffered.to_vec().await;
+        let second = retry.to_vec().await;
+
+        assert_eq!(b"hello buffered world".to_vec(), first);
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn rejects_large_bodies() {
+        assert!(BufferedBytesStream::try_buffer(chunked_body(), 19)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn propagates_errors() {
+        let body = chunked_body().chain(stream::once(async { Err("boom".into()) }));
+        assert!(BufferedBytesStream::try_buffer(body, 1024).await.is_err());
+    }
+}
@@ -16,20 +16,46 @@ use crate::{
 /// and step.
 pub type StreamKey = (QueryId, HelperIdentity, Gate);
 
+/// Reason why a stream was aborted before it could be received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum AbortReason {
+    #[error("query was cancelled")]
+    Cancelled,
+    #[error("query failed on this helper")]
+    Failed,
+    #[error("peer helper failed")]
+    PeerFailed,
+    #[error("timed out waiting for the stream")]
+    Timeout,
+}
+
 /// Thread-safe append-only collection of homogeneous record streams.
 /// Streams are indexed by [`StreamKey`] and the lifecycle of each stream is described by the
 /// [`StreamState`] struct.
 ///
-/// Each stream can be inserted and taken away exactly once, any deviation from this behaviour will
-/// result in panic.
+/// Each stream can be taken away exactly once, taking it again results in panic. Adding a stream
+/// for a key that has one already is a no-op, so senders can safely repeat a delivery. Transports
+/// that need the repeated delivery resume the first one on their own, see [`ResumableStream`].
+///
+/// [`ResumableStream`]: crate::helpers::ResumableStream
 pub struct StreamCollection<S> {
-    inner: Arc<Mutex<HashMap<StreamKey, StreamState<S>>>>,
+    inner: Arc<Mutex<Streams<S>>>,
+}
+
+struct Streams<S> {
+    streams: HashMap<StreamKey, StreamState<S>>,
+    /// Queries whose streams were aborted. Streams of these queries that are requested or arrive
+    /// later are aborted right away.
+    aborted: HashMap<QueryId, AbortReason>,
 }
 
 impl<S> Default for StreamCollection<S> {
     fn default() -> Self {
         Self {
-            inner: Arc::new(Mutex::new(HashMap::default())),
+            inner: Arc::new(Mutex::new(Streams {
+                streams: HashMap::default(),
+                aborted: HashMap::default(),
+            })),
         }
     }
 }
@@ -43,12 +69,19 @@ impl<S> Clone for StreamCollection<S> {
 }
 
 impl<S: Stream> StreamCollection<S> {
-    /// Adds a new stream associated with the given key.
+    /// Adds a new stream associated with the given key. If there was another stream associated
+    /// with the same key some time in the past, `stream` is dropped and the first one is kept.
     ///
     /// ## Panics
-    /// If there was another stream associated with the same key some time in the past.
+    /// if mutex is poisoned.
     pub fn add_stream(&self, key: StreamKey, stream: S) {
-        let mut streams = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(&reason) = inner.aborted.get(&key.0) {
+            // nobody is going to receive this stream
+            inner.streams.insert(key, StreamState::Aborted(reason));
+            return;
+        }
+        let streams = &mut inner.streams;
         match streams.entry(key) {
             Entry::Occupied(mut entry) => match entry.get_mut() {
                 rs @ StreamState::Waiting(_) => {
@@ -59,11 +92,13 @@ impl<S: Stream> StreamCollection<S> {
                     };
                     waker.wake();
                 }
-                rs @ (StreamState::Ready(_) | StreamState::Completed) => {
-                    let state = format!("{rs:?}");
-                    let key = entry.key().clone();
-                    drop(streams);
-                    panic!("{key:?} entry state expected to be waiting, got {state:?}");
+                // nobody is going to receive this stream anymore
+                StreamState::Aborted(_) => {}
+                StreamState::Ready(_) | StreamState::Completed => {
+                    tracing::debug!(
+                        "{:?} was delivered again, keeping the first stream",
+                        entry.key()
+                    );
                 }
             },
             Entry::Vacant(entry) => {
@@ -75,19 +110,23 @@ impl<S: Stream> StreamCollection<S> {
     /// Adds a new waker to notify when the stream is ready. If stream is ready, this method takes
     /// it out, leaving a tombstone in its place, and returns it.
     ///
+    /// ## Errors
+    /// If the stream was aborted before it could be received.
+    ///
     /// ## Panics
     /// If [`Waker`] that exists already inside this collection will not wake the given one.
-    pub fn add_waker(&self, key: &StreamKey, waker: &Waker) -> Option<S> {
-        let mut streams = self.inner.lock().unwrap();
+    pub fn add_waker(&self, key: &StreamKey, waker: &Waker) -> Result<Option<S>, AbortReason> {
+        let mut inner = self.inner.lock().unwrap();
+        let aborted = inner.aborted.get(&key.0).copied();
 
-        match streams.entry(key.clone()) {
+        match inner.streams.entry(key.clone()) {
             Entry::Occupied(mut entry) => {
                 match entry.get_mut() {
                     StreamState::Waiting(old_waker) => {
                         let will_wake = old_waker.will_wake(waker);
-                        drop(streams); // avoid mutex poisoning
+                        drop(inner); // avoid mutex poisoning
                         assert!(will_wake);
-                        None
+                        Ok(None)
                     }
                     rs @ StreamState::Ready(_) => {
                         let StreamState::Ready(stream) =
@@ -96,17 +135,45 @@ impl<S: Stream> StreamCollection<S> {
                             unreachable!();
                         };
 
-                        Some(stream)
+                        Ok(Some(stream))
                     }
+                    StreamState::Aborted(reason) => Err(*reason),
                     StreamState::Completed => {
-                        drop(streams);
+                        drop(inner);
                         panic!("{key:?} stream has been consumed already")
                     }
                 }
             }
             Entry::Vacant(entry) => {
-                entry.insert(StreamState::Waiting(waker.clone()));
-                None
+                if let Some(reason) = aborted {
+                    entry.insert(StreamState::Aborted(reason));
+                    Err(reason)
+                } else {
+                    entry.insert(StreamState::Waiting(waker.clone()));
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    /// Aborts every stream of the given query that has not been received yet. Receivers that are
+    /// waiting for one of these streams, or start waiting for one later, terminate with `reason`.
+    /// Streams that arrive after this call are dropped. Aborting a query again keeps the first
+    /// reason.
+    ///
+    /// ## Panics
+    /// if mutex is poisoned.
+    pub fn abort(&self, query_id: QueryId, reason: AbortReason) {
+        let mut inner = self.inner.lock().unwrap();
+        let reason = *inner.aborted.entry(query_id).or_insert(reason);
+        for ((stream_query_id, _, _), state) in &mut inner.streams {
+            if *stream_query_id != query_id || matches!(state, StreamState::Completed) {
+                continue;
+            }
+            if let StreamState::Waiting(waker) =
+                std::mem::replace(state, StreamState::Aborted(reason))
+            {
+                waker.wake();
             }
         }
     }
@@ -116,8 +183,18 @@ impl<S: Stream> StreamCollection<S> {
     /// ## Panics
     /// if mutex is poisoned.
     pub fn clear(&self) {
-        let mut streams = self.inner.lock().unwrap();
-        streams.clear();
+        let mut inner = self.inner.lock().unwrap();
+        inner.streams.clear();
+        inner.aborted.clear();
+    }
+
+    /// Returns the reason why streams of the given query were aborted, if they were.
+    ///
+    /// ## Panics
+    /// if mutex is poisoned.
+    #[must_use]
+    pub fn abort_reason(&self, query_id: QueryId) -> Option<AbortReason> {
+        self.inner.lock().unwrap().aborted.get(&query_id).copied()
     }
 }
 
@@ -130,6 +207,8 @@ enum StreamState<S> {
     /// Stream was successfully received and taken away from [`StreamCollection`].
     /// It may not be requested or received again.
     Completed,
+    /// Stream was aborted before it could be received.
+    Aborted(AbortReason),
 }
 
 impl<S> Debug for StreamState<S> {
@@ -144,6 +223,9 @@ impl<S> Debug for StreamState<S> {
             StreamState::Completed => {
                 write!(f, "Completed")
             }
+            StreamState::Aborted(reason) => {
+                write!(f, "Aborted({reason:?})")
+            }
         }
     }
 }
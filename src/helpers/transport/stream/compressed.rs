@@ -0,0 +1,250 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, stream::Fuse, Stream, StreamExt};
+
+/// Compression applied to records that helpers send to each other. It is part of the query
+/// configuration, so all helpers use the same setting for a query.
+///
+/// Compression trades CPU for bandwidth. It pays off when batches are redundant, e.g. shares of
+/// sparse trigger values that are mostly zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "enable-serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum WireCompression {
+    /// Messages are sent as they are.
+    #[default]
+    None,
+    /// Every message is compressed with zstd.
+    Zstd,
+}
+
+/// Every compressed message is preceded by the little-endian `u32` lengths of the compressed and
+/// the original payload.
+const HEADER_SIZE: usize = 8;
+
+/// Compresses every message of `stream` with `compression`. The receiving end restores them with
+/// [`DecompressedStream`].
+///
+/// ## Panics
+/// If a message or its compressed form is larger than `u32::MAX` bytes.
+pub fn compress<S: Stream<Item = Vec<u8>>>(
+    stream: S,
+    compression: WireCompression,
+) -> impl Stream<Item = Vec<u8>> {
+    stream.map(move |payload| match compression {
+        WireCompression::None => payload,
+        WireCompression::Zstd => {
+            let compressed = zstd::bulk::compress(&payload, zstd::DEFAULT_COMPRESSION_LEVEL)
+                .expect("compressing in-memory buffers does not fail");
+            let mut message = Vec::with_capacity(HEADER_SIZE + compressed.len());
+            message.extend_from_slice(&u32::try_from(compressed.len()).unwrap().to_le_bytes());
+            message.extend_from_slice(&u32::try_from(payload.len()).unwrap().to_le_bytes());
+            message.extend(compressed);
+            message
+        }
+    })
+}
+
+/// Receiving side of [`compress`]. Message boundaries do not need to match the chunks of the
+/// inner stream.
+///
+/// Headers are checked against `max_message_size`, the largest message the sender can produce,
+/// before anything is buffered or allocated for the message.
+///
+/// Record streams carry no errors, so a message that cannot be decompressed is logged and ends
+/// the stream. Receivers then fail the same way as if the peer stopped sending.
+pub struct DecompressedStream<S> {
+    inner: Fuse<S>,
+    compression: WireCompression,
+    max_message_size: usize,
+    buffer: Vec<u8>,
+}
+
+impl<S: Stream<Item = Vec<u8>>> DecompressedStream<S> {
+    #[must_use]
+    pub fn new(inner: S, compression: WireCompression, max_message_size: usize) -> Self {
+        Self {
+            inner: inner.fuse(),
+            compression,
+            max_message_size,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<S: Stream<Item = Vec<u8>> + Unpin> Stream for DecompressedStream<S> {
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.compression == WireCompression::None {
+            return this.inner.poll_next_unpin(cx);
+        }
+
+        loop {
+            if this.buffer.len() >= HEADER_SIZE {
+                let compressed_len =
+                    usize::try_from(u32::from_le_bytes(this.buffer[..4].try_into().unwrap()))
+                        .unwrap();
+                let len = usize::try_from(u32::from_le_bytes(
+                    this.buffer[4..HEADER_SIZE].try_into().unwrap(),
+                ))
+                .unwrap();
+                if len > this.max_message_size
+                    || compressed_len > zstd::zstd_safe::compress_bound(this.max_message_size)
+                {
+                    tracing::error!(
+                        "message of {len} bytes, {compressed_len} compressed, exceeds the limit \
+                         of {} bytes",
+                        this.max_message_size
+                    );
+                    this.buffer.clear();
+                    return Poll::Ready(None);
+                }
+                if this.buffer.len() >= HEADER_SIZE + compressed_len {
+                    let message = this
+                        .buffer
+                        .drain(..HEADER_SIZE + compressed_len)
+                        .collect::<Vec<_>>();
+                    match zstd::bulk::decompress(&message[HEADER_SIZE..], len) {
+                        Ok(payload) if payload.len() == len => return Poll::Ready(Some(payload)),
+                        Ok(payload) => {
+                            tracing::error!(
+                                "expected {len} bytes after decompression, got {}",
+                                payload.len()
+                            );
+                        }
+                        Err(e) => tracing::error!("failed to decompress message: {e}"),
+                    }
+                    this.buffer.clear();
+                    return Poll::Ready(None);
+                }
+            }
+
+            if let Some(chunk) = ready!(this.inner.poll_next_unpin(cx)) {
+                this.buffer.extend(chunk);
+            } else {
+                if !this.buffer.is_empty() {
+                    tracing::error!(
+                        "stream terminated with {} bytes of an incomplete message",
+                        this.buffer.len()
+                    );
+                    this.buffer.clear();
+                }
+                return Poll::Ready(None);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use futures::{stream, StreamExt};
+
+    use super::{compress, DecompressedStream, WireCompression};
+
+    const MAX_MESSAGE_SIZE: usize = 1000;
+
+    async fn round_trip(messages: &[Vec<u8>], chunk_size: usize) -> Vec<u8> {
+        let compressed = compress(stream::iter(messages.to_vec()), WireCompression::Zstd)
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+        let chunks = compressed
+            .chunks(chunk_size)
+            .map(<[u8]>::to_vec)
+            .collect::<Vec<_>>();
+        DecompressedStream::new(
+            stream::iter(chunks),
+            WireCompression::Zstd,
+            MAX_MESSAGE_SIZE,
+        )
+        .collect::<Vec<_>>()
+        .await
+        .concat()
+    }
+
+    #[tokio::test]
+    async fn restores_messages_across_chunks() {
+        let messages = vec![vec![0; 1000], Vec::new(), b"not very compressible".to_vec()];
+        for chunk_size in [1, 7, 4096] {
+            assert_eq!(messages.concat(), round_trip(&messages, chunk_size).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn none_passes_messages_through() {
+        let messages = vec![b"first".to_vec(), b"second".to_vec()];
+        let sent = compress(stream::iter(messages.clone()), WireCompression::None)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(messages, sent);
+        let received =
+            DecompressedStream::new(stream::iter(sent), WireCompression::None, MAX_MESSAGE_SIZE)
+                .collect::<Vec<_>>()
+                .await;
+        assert_eq!(messages, received);
+    }
+
+    #[tokio::test]
+    async fn corrupted_message_ends_stream() {
+        let mut compressed = compress(stream::iter(vec![vec![0; 100]]), WireCompression::Zstd)
+            .collect::<Vec<_>>()
+            .await;
+        // claim that the payload is shorter than it is
+        compressed[0][4..8].copy_from_slice(&50_u32.to_le_bytes());
+        compressed.push(
+            compress(stream::iter(vec![vec![1; 100]]), WireCompression::Zstd)
+                .collect::<Vec<_>>()
+                .await
+                .concat(),
+        );
+
+        let received = DecompressedStream::new(
+            stream::iter(compressed),
+            WireCompression::Zstd,
+            MAX_MESSAGE_SIZE,
+        )
+        .collect::<Vec<_>>()
+        .await;
+        assert!(received.is_empty());
+    }
+
+    #[tokio::test]
+    async fn oversized_message_ends_stream() {
+        let mut compressed = compress(stream::iter(vec![vec![0; 100]]), WireCompression::Zstd)
+            .collect::<Vec<_>>()
+            .await;
+        // a peer could claim a huge payload to make the receiver allocate for it
+        compressed[0][4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let received = DecompressedStream::new(
+            stream::iter(compressed.clone()),
+            WireCompression::Zstd,
+            MAX_MESSAGE_SIZE,
+        )
+        .collect::<Vec<_>>()
+        .await;
+        assert!(received.is_empty());
+
+        // same for the compressed length, which would have the receiver buffer the stream
+        compressed[0][4..8].copy_from_slice(&100_u32.to_le_bytes());
+        compressed[0][..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let received = DecompressedStream::new(
+            stream::iter(compressed),
+            WireCompression::Zstd,
+            MAX_MESSAGE_SIZE,
+        )
+        .collect::<Vec<_>>()
+        .await;
+        assert!(received.is_empty());
+    }
+
+    #[tokio::test]
+    async fn accepts_messages_up_to_limit() {
+        let messages = vec![vec![7; MAX_MESSAGE_SIZE]];
+        assert_eq!(messages.concat(), round_trip(&messages, 4096).await);
+    }
+}
@@ -9,7 +9,7 @@ use std::{
     task::{Context, Poll},
 };
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::{
     stream::{iter, once, Fuse, FusedStream, Iter, Map, Once},
     Stream, StreamExt,
@@ -164,6 +164,8 @@ where
     #[pin]
     stream: Fuse<S>,
     buffer: BufDeque,
+    /// Number of records that are yet to be returned, if the total is known.
+    remaining: Option<usize>,
     phantom_data: PhantomData<T>,
 }
 
@@ -177,9 +179,35 @@ where
         Self {
             stream: stream.fuse(),
             buffer: BufDeque::new(),
+            remaining: None,
             phantom_data: PhantomData,
         }
     }
+
+    /// Tells this stream that the input holds `total_records` records, so it can report how many
+    /// of them are left through [`Self::remaining_records`] and [`Stream::size_hint`]. The count
+    /// is trusted, the input is not checked against it.
+    #[must_use]
+    pub fn with_total_len(mut self, total_records: usize) -> Self {
+        self.remaining = Some(total_records);
+        self
+    }
+
+    /// Number of records that this stream is yet to return, if the total was set with
+    /// [`Self::with_total_len`].
+    #[must_use]
+    pub fn remaining_records(&self) -> Option<usize> {
+        self.remaining
+    }
+
+    /// Stops parsing records and returns the underlying stream of bytes, for callers that need
+    /// the remaining input unframed. Bytes that were already pulled from the inner stream but not
+    /// returned as records yet, such as the beginning of a record that is split across chunks,
+    /// come first, so no data is lost.
+    #[must_use]
+    pub fn into_inner(self) -> impl BytesStream {
+        iter(self.buffer.buffered.into_iter().map(Ok::<_, BoxError>)).chain(self.stream)
+    }
 }
 
 impl<T, S> Stream for RecordsStream<T, S>
@@ -194,6 +222,9 @@ where
         loop {
             let count = max(1, this.buffer.contiguous_len() / T::Size::USIZE);
             if let Some(items) = this.buffer.read_multi(count) {
+                if let Some(remaining) = this.remaining {
+                    *remaining = remaining.saturating_sub(items.len());
+                }
                 return Poll::Ready(Some(Ok(items)));
             }
 
@@ -209,6 +240,15 @@ where
             }
         }
     }
+
+    /// Every item holds at least one record, so the number of records left is an upper bound on
+    /// the number of items. There is at least one more item while there are records left.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.remaining {
+            Some(remaining) => (remaining.min(1), Some(remaining)),
+            None => (0, None),
+        }
+    }
 }
 
 impl<T, S> FusedStream for RecordsStream<T, S>
@@ -237,7 +277,20 @@ where
 
 impl<T: Serializable> From<Vec<u8>> for RecordsStream<T, Once<Ready<Result<Bytes, BoxError>>>> {
     fn from(vec: Vec<u8>) -> Self {
-        RecordsStream::new(once(std::future::ready(Ok(Bytes::from(vec)))))
+        Bytes::from(vec).into()
+    }
+}
+
+/// Wraps the buffer as is, without copying it.
+impl<T: Serializable> From<Bytes> for RecordsStream<T, Once<Ready<Result<Bytes, BoxError>>>> {
+    fn from(bytes: Bytes) -> Self {
+        RecordsStream::new(once(std::future::ready(Ok(bytes))))
+    }
+}
+
+impl<T: Serializable> From<BytesMut> for RecordsStream<T, Once<Ready<Result<Bytes, BoxError>>>> {
+    fn from(bytes: BytesMut) -> Self {
+        bytes.freeze().into()
     }
 }
 
@@ -470,6 +523,24 @@ mod test {
             assert_eq!(collected[0], vec![Fp31::try_from(3).unwrap(); 10]);
         }
 
+        #[tokio::test]
+        async fn records_stream_from_bytes() {
+            let bytes = Bytes::from(vec![3; 10]);
+            let mut stream = RecordsStream::<Fp31, _>::from(bytes.clone());
+
+            // the buffer is handed over to the inner stream without copying it
+            let inner = stream.stream.get_mut().next().await.unwrap().unwrap();
+            assert_eq!(bytes.as_ptr(), inner.as_ptr());
+
+            for stream in [
+                RecordsStream::from(bytes.clone()),
+                RecordsStream::from(BytesMut::from(&bytes[..])),
+            ] {
+                let collected = stream.try_collect::<Vec<Vec<Fp31>>>().await.unwrap();
+                assert_eq!(collected, vec![vec![Fp31::try_from(3).unwrap(); 10]]);
+            }
+        }
+
         #[tokio::test]
         async fn records_stream_fp32_bit_prime() {
             const ARR_SIZE: usize = 50;
@@ -529,6 +600,52 @@ mod test {
             }
         }
 
+        #[tokio::test]
+        async fn size_hint_counts_remaining_records() {
+            const TOTAL: usize = 5;
+            let row_sz = <Fp32BitPrime as Serializable>::Size::USIZE;
+            // one record per chunk, so every item holds exactly one record
+            let chunks = vec![vec![1_u8; row_sz]; TOTAL];
+            let mut stream = RecordsStream::<Fp32BitPrime, _>::from(chunks).with_total_len(TOTAL);
+            assert_eq!((1, Some(TOTAL)), stream.size_hint());
+
+            for _ in 0..2 {
+                stream.next().await.unwrap().unwrap();
+            }
+            assert_eq!(Some(TOTAL - 2), stream.remaining_records());
+            assert_eq!((1, Some(TOTAL - 2)), stream.size_hint());
+
+            let rest = stream.try_concat().await.unwrap();
+            assert_eq!(TOTAL - 2, rest.len());
+        }
+
+        #[tokio::test]
+        async fn size_hint_is_unknown_without_total() {
+            let stream = RecordsStream::<Fp31, _>::from(vec![3; 10]);
+            assert_eq!((0, None), stream.size_hint());
+        }
+
+        #[tokio::test]
+        async fn into_inner_returns_unparsed_bytes() {
+            const CHUNK_SIZE: usize = 3;
+            let row_sz = <Fp32BitPrime as Serializable>::Size::USIZE;
+            let vec = (0..5 * <Fp32BitPrime as Serializable>::Size::U8).collect::<Vec<_>>();
+            let chunks = vec
+                .chunks(CHUNK_SIZE)
+                .map(ToOwned::to_owned)
+                .collect::<Vec<_>>();
+            let mut stream = RecordsStream::<Fp32BitPrime, _>::from(chunks);
+
+            // the first record spans two chunks, leaving part of the second one buffered
+            let first = stream.next().await.unwrap().unwrap();
+            let expected = Fp32BitPrime::deserialize(<&GenericArray<u8, _>>::from(&vec[..row_sz]));
+            assert_eq!(vec![expected], first);
+            assert!(stream.buffer.len() > 0);
+
+            let rest = stream.into_inner().to_vec().await;
+            assert_eq!(&vec[row_sz..], &rest[..]);
+        }
+
         // checks that the `RecordsStream` will return chunks that are multiples of `SIZE_IN_BYTES`
         #[tokio::test]
         async fn returns_multiples() {
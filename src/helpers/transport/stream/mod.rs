@@ -1,22 +1,43 @@
 #[cfg(feature = "web-app")]
 mod axum_body;
 mod box_body;
+mod buffered;
 mod collection;
+mod compressed;
 mod input;
+mod sequenced;
+mod tee;
 
 use std::pin::Pin;
 
 #[cfg(feature = "web-app")]
 pub use axum_body::WrappedAxumBodyStream;
 pub use box_body::WrappedBoxBodyStream;
+pub use buffered::BufferedBytesStream;
 use bytes::Bytes;
-pub use collection::{StreamCollection, StreamKey};
+pub use collection::{AbortReason, StreamCollection, StreamKey};
+pub use compressed::{compress, DecompressedStream, WireCompression};
 use futures::Stream;
 pub use input::{LengthDelimitedStream, RecordsStream};
+#[cfg(feature = "in-memory-infra")]
+pub use sequenced::unsequence_message;
+pub use sequenced::{sequence, Redeliveries, ResumableStream, SequenceError, UnsequencedStream};
+pub use tee::TeeStream;
+use tokio::io::AsyncWrite;
 
 use crate::error::BoxError;
 
 pub trait BytesStream: Stream<Item = Result<Bytes, BoxError>> + Send {
+    /// Writes every chunk of this stream to `sink` before passing it on unchanged, e.g. to
+    /// archive the exact input bytes a query consumed. Errors writing to the sink are reported
+    /// by the returned stream.
+    fn tee<W: AsyncWrite + Send>(self, sink: W) -> TeeStream<Self, W>
+    where
+        Self: Sized,
+    {
+        TeeStream::new(self, sink)
+    }
+
     /// Collects the entire stream into a vec; only intended for use in tests
     /// # Panics
     /// if the stream has any failure
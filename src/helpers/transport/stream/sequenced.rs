@@ -0,0 +1,384 @@
+use std::{
+    cmp::Ordering,
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{ready, stream::Fuse, Stream, StreamExt};
+use pin_project::pin_project;
+
+use crate::{
+    error::BoxError,
+    helpers::transport::stream::BytesStream,
+    sync::{Arc, Mutex},
+};
+
+/// Every message is preceded by a little-endian `u64` nonce and a little-endian `u32` length of
+/// the payload.
+const HEADER_SIZE: usize = 12;
+
+/// Prefixes every message of `stream` with a nonce that counts the messages sent on it, starting
+/// from zero. Record streams are opened per query, helper and gate, so this gives every message a
+/// sequence number that is unique within its `(query_id, gate)` channel. The receiving end strips
+/// the nonces with [`UnsequencedStream`].
+///
+/// ## Panics
+/// If a message is larger than `u32::MAX` bytes.
+pub fn sequence<S: Stream<Item = Vec<u8>>>(stream: S) -> impl Stream<Item = Vec<u8>> {
+    stream.enumerate().map(|(nonce, payload)| {
+        let mut message = Vec::with_capacity(HEADER_SIZE + payload.len());
+        message.extend_from_slice(&u64::try_from(nonce).unwrap().to_le_bytes());
+        message.extend_from_slice(&u32::try_from(payload.len()).unwrap().to_le_bytes());
+        message.extend(payload);
+        message
+    })
+}
+
+/// Splits a single message written by [`sequence`] into its nonce and payload. Transports that
+/// deliver every message as one piece use this instead of [`UnsequencedStream`].
+///
+/// ## Panics
+/// If `message` is not exactly one message.
+#[cfg(feature = "in-memory-infra")]
+pub fn unsequence_message(mut message: Vec<u8>) -> (u64, Vec<u8>) {
+    assert!(message.len() >= HEADER_SIZE, "message is too short");
+    let mut header = &message[..HEADER_SIZE];
+    let nonce = header.get_u64_le();
+    let len = usize::try_from(header.get_u32_le()).unwrap();
+    assert_eq!(HEADER_SIZE + len, message.len(), "message length mismatch");
+    message.drain(..HEADER_SIZE);
+
+    (nonce, message)
+}
+
+/// Errors that [`UnsequencedStream`] reports on its own, as opposed to errors of the inner stream.
+#[derive(Debug, thiserror::Error)]
+pub enum SequenceError {
+    /// Messages between the expected nonce and the one that arrived were lost.
+    #[error("expected message {expected}, got {got}")]
+    Gap { expected: u64, got: u64 },
+    /// The stream ended in the middle of a message.
+    #[error("stream terminated with {0} extra bytes")]
+    Truncated(usize),
+}
+
+/// Receiving side of [`sequence`]. Yields payloads of messages in the order of their nonces.
+///
+/// A message that was delivered twice, e.g. because the sender retried it, carries a nonce that
+/// was seen already and is dropped silently. A message with a nonce past the next expected one
+/// means that some messages were lost, which is reported as an error. Message boundaries do not
+/// need to match the chunks of the inner stream.
+#[pin_project]
+pub struct UnsequencedStream<S: BytesStream> {
+    #[pin]
+    inner: Fuse<S>,
+    buffer: BytesMut,
+    next_nonce: u64,
+}
+
+impl<S: BytesStream> UnsequencedStream<S> {
+    #[must_use]
+    pub fn new(inner: S) -> Self {
+        Self::resume(inner, 0)
+    }
+
+    /// Reads a stream that repeats messages of an earlier one, dropping the first `next_nonce`
+    /// messages because they were received already.
+    #[must_use]
+    pub fn resume(inner: S, next_nonce: u64) -> Self {
+        Self {
+            inner: inner.fuse(),
+            buffer: BytesMut::new(),
+            next_nonce,
+        }
+    }
+
+    /// Nonce of the next message this stream yields, i.e. the number of messages yielded so far
+    /// including the ones it resumed from.
+    #[must_use]
+    pub fn next_nonce(&self) -> u64 {
+        self.next_nonce
+    }
+}
+
+impl<S: BytesStream> Stream for UnsequencedStream<S> {
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if this.buffer.len() >= HEADER_SIZE {
+                let mut header = &this.buffer[..HEADER_SIZE];
+                let nonce = header.get_u64_le();
+                let len = usize::try_from(header.get_u32_le()).unwrap();
+                if this.buffer.len() >= HEADER_SIZE + len {
+                    let mut message = this.buffer.split_to(HEADER_SIZE + len);
+                    message.advance(HEADER_SIZE);
+                    match nonce.cmp(this.next_nonce) {
+                        Ordering::Less => {
+                            tracing::debug!("dropping duplicate message {nonce}");
+                            continue;
+                        }
+                        Ordering::Equal => {
+                            *this.next_nonce += 1;
+                            return Poll::Ready(Some(Ok(message.freeze())));
+                        }
+                        Ordering::Greater => {
+                            this.buffer.clear();
+                            return Poll::Ready(Some(Err(SequenceError::Gap {
+                                expected: *this.next_nonce,
+                                got: nonce,
+                            }
+                            .into())));
+                        }
+                    }
+                }
+            }
+
+            match ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(Ok(chunk)) => this.buffer.extend_from_slice(&chunk),
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None if this.buffer.is_empty() => return Poll::Ready(None),
+                None => {
+                    let len = this.buffer.len();
+                    this.buffer.clear();
+                    return Poll::Ready(Some(Err(SequenceError::Truncated(len).into())));
+                }
+            }
+        }
+    }
+}
+
+/// Receiving side of [`sequence`] for a channel whose stream may be delivered more than once,
+/// e.g. because the sender retried a request that broke off. Every delivery starts over with its
+/// own [`UnsequencedStream`], resuming from the number of messages received so far on the
+/// channel, so messages that an earlier delivery yielded already are dropped.
+///
+/// If a delivery breaks off, this waits for the next one instead of ending, so the wait is only
+/// bounded by the query. The stream ends with the first delivery that ends cleanly, and later
+/// deliveries are dropped unread. Gaps are reported as errors and end the stream.
+pub struct ResumableStream<S: BytesStream + Unpin> {
+    current: Option<UnsequencedStream<S>>,
+    /// Number of messages yielded so far, which is where the next delivery resumes from.
+    next_nonce: u64,
+    deliveries: Arc<Mutex<Deliveries<S>>>,
+}
+
+/// Adds deliveries to the [`ResumableStream`] it was created with.
+pub struct Redeliveries<S: BytesStream + Unpin> {
+    deliveries: Arc<Mutex<Deliveries<S>>>,
+}
+
+struct Deliveries<S> {
+    queued: VecDeque<S>,
+    waker: Option<Waker>,
+    /// Set once the stream has ended or was dropped. Nobody reads deliveries after that.
+    closed: bool,
+}
+
+impl<S: BytesStream + Unpin> ResumableStream<S> {
+    /// Creates a stream that starts with `first` delivery, together with the handle that adds
+    /// later ones.
+    #[must_use]
+    pub fn new(first: S) -> (Self, Redeliveries<S>) {
+        let deliveries = Arc::new(Mutex::new(Deliveries {
+            queued: VecDeque::new(),
+            waker: None,
+            closed: false,
+        }));
+        let stream = Self {
+            current: Some(UnsequencedStream::new(first)),
+            next_nonce: 0,
+            deliveries: Arc::clone(&deliveries),
+        };
+
+        (stream, Redeliveries { deliveries })
+    }
+
+    fn close(&mut self) {
+        self.current = None;
+        let mut deliveries = self.deliveries.lock().unwrap();
+        deliveries.closed = true;
+        deliveries.queued.clear();
+    }
+}
+
+impl<S: BytesStream + Unpin> Redeliveries<S> {
+    /// Adds another delivery of the same channel. It is read once the deliveries before it broke
+    /// off, or dropped if the stream has ended already.
+    ///
+    /// ## Panics
+    /// If mutex is poisoned.
+    pub fn add(&self, delivery: S) {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        if deliveries.closed {
+            tracing::debug!("dropping delivery of a stream that has ended");
+            return;
+        }
+        deliveries.queued.push_back(delivery);
+        if let Some(waker) = deliveries.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<S: BytesStream + Unpin> Stream for ResumableStream<S> {
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = Pin::get_mut(self);
+        loop {
+            let Some(current) = this.current.as_mut() else {
+                let mut deliveries = this.deliveries.lock().unwrap();
+                if deliveries.closed {
+                    return Poll::Ready(None);
+                }
+                if let Some(delivery) = deliveries.queued.pop_front() {
+                    this.current = Some(UnsequencedStream::resume(delivery, this.next_nonce));
+                    continue;
+                }
+                deliveries.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            };
+
+            match ready!(current.poll_next_unpin(cx)) {
+                Some(Ok(payload)) => {
+                    this.next_nonce = current.next_nonce();
+                    return Poll::Ready(Some(Ok(payload)));
+                }
+                Some(Err(err))
+                    if matches!(
+                        err.downcast_ref::<SequenceError>(),
+                        Some(SequenceError::Gap { .. })
+                    ) =>
+                {
+                    this.close();
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Some(Err(err)) => {
+                    tracing::debug!(
+                        "delivery broke off after message {}: {err}, waiting for a retry",
+                        this.next_nonce
+                    );
+                    this.current = None;
+                }
+                None => {
+                    this.close();
+                    return Poll::Ready(None);
+                }
+            }
+        }
+    }
+}
+
+impl<S: BytesStream + Unpin> Drop for ResumableStream<S> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use bytes::Bytes;
+    use futures::{stream, StreamExt, TryStreamExt};
+
+    use super::{sequence, ResumableStream, UnsequencedStream};
+    use crate::{error::BoxError, helpers::BytesStream};
+
+    async fn sequenced(messages: &[&[u8]]) -> Vec<Vec<u8>> {
+        sequence(stream::iter(messages.iter().copied().map(<[u8]>::to_vec)))
+            .collect()
+            .await
+    }
+
+    /// Concatenates the messages and splits them into chunks of `chunk_size` bytes, so that
+    /// message boundaries do not line up with chunks.
+    fn rechunk(messages: &[Vec<u8>], chunk_size: usize) -> UnsequencedStream<impl BytesStream> {
+        let bytes = messages.concat();
+        let chunks = bytes
+            .chunks(chunk_size)
+            .map(|chunk| Ok::<_, BoxError>(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        UnsequencedStream::new(stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn drops_duplicates() {
+        let mut messages = sequenced(&[&b"first"[..], b"", b"second"]).await;
+        messages.insert(2, messages[1].clone());
+        messages.insert(1, messages[0].clone());
+
+        let received = rechunk(&messages, 5)
+            .map_ok(|payload| payload.to_vec())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![b"first".to_vec(), Vec::new(), b"second".to_vec()],
+            received
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_on_gap() {
+        let mut messages = sequenced(&[&b"first"[..], b"second", b"third"]).await;
+        messages.remove(1);
+
+        let mut stream = rechunk(&messages, 7);
+        assert_eq!(b"first", &stream.next().await.unwrap().unwrap()[..]);
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert_eq!("expected message 1, got 2", err.to_string());
+    }
+
+    #[tokio::test]
+    async fn fails_on_truncated_message() {
+        let mut messages = sequenced(&[&b"first"[..]]).await;
+        messages[0].pop();
+
+        let mut stream = rechunk(&messages, 3);
+        assert!(stream.next().await.unwrap().is_err());
+    }
+
+    /// Delivers the given messages, breaking off with an error after `break_after` of them.
+    fn delivery(messages: &[Vec<u8>], break_after: Option<usize>) -> impl BytesStream + Unpin {
+        let chunks = messages
+            .iter()
+            .take(break_after.unwrap_or(messages.len()))
+            .map(|message| Ok::<_, BoxError>(Bytes::copy_from_slice(message)))
+            .chain(break_after.map(|_| Err("connection reset".into())))
+            .collect::<Vec<_>>();
+        stream::iter(chunks)
+    }
+
+    #[tokio::test]
+    async fn resumes_after_broken_delivery() {
+        let messages = sequenced(&[&b"first"[..], b"second", b"third"]).await;
+
+        let (mut stream, redeliveries) = ResumableStream::new(delivery(&messages, Some(2)));
+        assert_eq!(b"first", &stream.next().await.unwrap().unwrap()[..]);
+        assert_eq!(b"second", &stream.next().await.unwrap().unwrap()[..]);
+
+        // the retry starts over from the first message
+        redeliveries.add(delivery(&messages, None));
+        assert_eq!(b"third", &stream.next().await.unwrap().unwrap()[..]);
+        assert!(stream.next().await.is_none());
+
+        // deliveries after the stream has ended are dropped
+        redeliveries.add(delivery(&messages, None));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn fails_on_gap_between_deliveries() {
+        let messages = sequenced(&[&b"first"[..], b"second", b"third"]).await;
+
+        let (mut stream, redeliveries) = ResumableStream::new(delivery(&messages, Some(1)));
+        redeliveries.add(delivery(&messages[2..], None));
+        assert_eq!(b"first", &stream.next().await.unwrap().unwrap()[..]);
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert_eq!("expected message 1, got 2", err.to_string());
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::{ready, Stream};
+use pin_project::pin_project;
+use tokio::io::AsyncWrite;
+
+use crate::{error::BoxError, helpers::transport::stream::BytesStream};
+
+/// Stream returned by [`BytesStream::tee`]. Yields the chunks of the inner stream unchanged,
+/// after each of them has been written to the sink in full. The sink is flushed once the inner
+/// stream ends, so everything the consumer saw has been archived by the time it sees the end of
+/// the stream.
+///
+/// Failing to write to the sink is reported as an error item. Errors from either the inner stream
+/// or the sink end the stream, because anything yielded after them would be missing from the
+/// archive.
+#[pin_project]
+pub struct TeeStream<S, W> {
+    #[pin]
+    inner: S,
+    #[pin]
+    sink: W,
+    pending: Option<(Bytes, usize)>,
+    state: State,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Streaming,
+    Flushing,
+    Done,
+}
+
+impl<S: BytesStream, W: AsyncWrite + Send> TeeStream<S, W> {
+    pub(super) fn new(inner: S, sink: W) -> Self {
+        Self {
+            inner,
+            sink,
+            pending: None,
+            state: State::Streaming,
+        }
+    }
+}
+
+impl<S: BytesStream, W: AsyncWrite + Send> Stream for TeeStream<S, W> {
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match *this.state {
+                State::Done => return Poll::Ready(None),
+                State::Flushing => {
+                    let res = ready!(this.sink.as_mut().poll_flush(cx));
+                    *this.state = State::Done;
+                    return Poll::Ready(res.err().map(|e| Err(e.into())));
+                }
+                State::Streaming => {}
+            }
+
+            if let Some((chunk, written)) = this.pending {
+                while *written < chunk.len() {
+                    match ready!(this.sink.as_mut().poll_write(cx, &chunk[*written..])) {
+                        Ok(0) => {
+                            *this.state = State::Done;
+                            return Poll::Ready(Some(Err(io::Error::new(
+                                io::ErrorKind::WriteZero,
+                                "sink stopped accepting bytes",
+                            )
+                            .into())));
+                        }
+                        Ok(n) => *written += n,
+                        Err(e) => {
+                            *this.state = State::Done;
+                            return Poll::Ready(Some(Err(e.into())));
+                        }
+                    }
+                }
+                let (chunk, _) = this.pending.take().unwrap();
+                return Poll::Ready(Some(Ok(chunk)));
+            }
+
+            match ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(Ok(chunk)) => *this.pending = Some((chunk, 0)),
+                Some(Err(e)) => {
+                    *this.state = State::Done;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                None => *this.state = State::Flushing,
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use bytes::Bytes;
+    use futures::{stream, StreamExt, TryStreamExt};
+    use tokio::io::AsyncWrite;
+
+    use crate::{error::BoxError, helpers::BytesStream};
+
+    fn chunks(chunks: &[&'static [u8]]) -> impl BytesStream {
+        stream::iter(
+            chunks
+                .iter()
+                .map(|&chunk| Ok::<_, BoxError>(Bytes::from_static(chunk)))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Accepts at most one byte per write, then fails once it has `capacity` bytes.
+    struct SmallSink {
+        written: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl AsyncWrite for SmallSink {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            if self.written.len() == self.capacity {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, "sink is full")));
+            }
+            self.written.push(buf[0]);
+            Poll::Ready(Ok(1))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn archives_consumed_bytes() {
+        let mut archive = Vec::new();
+        let consumed = chunks(&[b"first", b"", b"second"])
+            .tee(&mut archive)
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await
+            .unwrap();
+
+        assert_eq!(b"firstsecond".to_vec(), consumed);
+        assert_eq!(consumed, archive);
+    }
+
+    #[tokio::test]
+    async fn partial_writes_are_completed() {
+        let mut sink = SmallSink {
+            written: Vec::new(),
+            capacity: usize::MAX,
+        };
+        let consumed = chunks(&[b"abc", b"de"])
+            .tee(&mut sink)
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await
+            .unwrap();
+
+        assert_eq!(consumed, sink.written);
+    }
+
+    #[tokio::test]
+    async fn sink_errors_end_the_stream() {
+        let mut sink = SmallSink {
+            written: Vec::new(),
+            capacity: 4,
+        };
+        let mut stream = Box::pin(chunks(&[b"abc", b"de", b"fg"]).tee(&mut sink));
+
+        assert_eq!(b"abc", &stream.next().await.unwrap().unwrap()[..]);
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert_eq!("sink is full", err.to_string());
+        assert!(stream.next().await.is_none());
+    }
+}
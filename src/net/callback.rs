@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use hyper::{client::HttpConnector, header::CONTENT_TYPE, Body, Client, Request};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use serde::Serialize;
+
+use crate::{helpers::query::CallbackUrl, protocol::QueryId, query::CompletionNotifier};
+
+/// Body of the completion notification. It only points to the query, clients fetch the results
+/// from the helpers the same way they do when polling.
+#[derive(Serialize)]
+struct CompletionNotification {
+    query_id: QueryId,
+}
+
+/// Notifies clients about completed queries by sending a JSON `POST` request to their callback
+/// URL.
+pub struct HttpCompletionNotifier {
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+}
+
+impl Default for HttpCompletionNotifier {
+    fn default() -> Self {
+        let connector = HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        Self {
+            client: Client::builder().build(connector),
+        }
+    }
+}
+
+#[async_trait]
+impl CompletionNotifier for HttpCompletionNotifier {
+    async fn notify(&self, url: CallbackUrl, query_id: QueryId) {
+        let body = serde_json::to_vec(&CompletionNotification { query_id })
+            .expect("notification can be serialized");
+        let req = match Request::post(url.as_str())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+        {
+            Ok(req) => req,
+            Err(e) => {
+                tracing::warn!("failed to build callback request to {url}: {e}");
+                return;
+            }
+        };
+
+        match self.client.request(req).await {
+            Ok(resp) if resp.status().is_success() => {
+                tracing::info!("notified {url} about completion of query {query_id}");
+            }
+            Ok(resp) => {
+                tracing::warn!("callback to {url} failed with status {}", resp.status());
+            }
+            Err(e) => tracing::warn!("callback to {url} failed: {e}"),
+        }
+    }
+}
@@ -26,7 +26,7 @@ use crate::{
     config::{ClientConfig, HyperClientConfigurator, NetworkConfig, PeerConfig},
     helpers::{
         query::{PrepareQuery, QueryConfig, QueryInput},
-        HelperIdentity,
+        sequence, HelperIdentity,
     },
     net::{http_serde, server::HTTP_CLIENT_ID_HEADER, Error},
     protocol::{step::Gate, QueryId},
@@ -298,6 +298,16 @@ impl MpcHelperClient {
         }
     }
 
+    /// Checks that the helper is up and able to serve requests.
+    /// # Errors
+    /// If the helper cannot be reached or responds with an error.
+    pub async fn health(&self) -> Result<(), Error> {
+        let req = http_serde::health::Request
+            .try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        let resp = self.request(req).await?;
+        Self::resp_ok(resp).await
+    }
+
     /// Helper to read a possible error response to a request that returns nothing on success
     ///
     /// # Errors
@@ -355,6 +365,9 @@ impl MpcHelperClient {
     /// Sends a batch of messages associated with a query's step to another helper. Messages are a
     /// contiguous block of records. Also includes [`crate::protocol::RecordId`] information and
     /// [`crate::helpers::network::ChannelId`].
+    ///
+    /// Every message is numbered with [`sequence`], so that the receiving helper can drop
+    /// messages that were delivered twice and detect lost ones.
     /// # Errors
     /// If the request has illegal arguments, or fails to deliver to helper
     /// # Panics
@@ -365,7 +378,7 @@ impl MpcHelperClient {
         gate: &Gate,
         data: S,
     ) -> Result<ResponseFuture, Error> {
-        let body = hyper::Body::wrap_stream::<_, _, Error>(data.map(Ok));
+        let body = hyper::Body::wrap_stream::<_, _, Error>(sequence(data).map(Ok));
         let req = http_serde::query::step::Request::new(query_id, gate.clone(), body);
         let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
         Ok(self.request(req))
@@ -411,6 +424,30 @@ impl MpcHelperClient {
             Err(Error::from_failed_resp(resp).await)
         }
     }
+
+    /// Pull up to `limit` bytes of the query results, starting at `offset`. Waits for the query
+    /// to complete if needed. Unlike [`Self::query_results`], this can be called again to resume
+    /// a download, as long as the helper still retains the results.
+    ///
+    /// ## Errors
+    /// If the request has illegal arguments, or fails to deliver to helper
+    #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))]
+    pub async fn query_result_page(
+        &self,
+        query_id: QueryId,
+        offset: usize,
+        limit: usize,
+    ) -> Result<body::Bytes, Error> {
+        let req = http_serde::query::result_page::Request::new(query_id, offset, limit);
+        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+
+        let resp = self.request(req).await?;
+        if resp.status().is_success() {
+            Ok(body::to_bytes(resp.into_body()).await?)
+        } else {
+            Err(Error::from_failed_resp(resp).await)
+        }
+    }
 }
 
 fn make_http_connector() -> HttpConnector {
@@ -461,13 +498,17 @@ pub(crate) mod tests {
             let pi = Arc::clone(inner);
             let qi = Arc::clone(inner);
             let si = Arc::clone(inner);
+            let ai = Arc::clone(inner);
             let ci = Arc::clone(inner);
+            let pgi = Arc::clone(inner);
             TransportCallbacks {
                 receive_query: Box::new(move |t, req| (ri.receive_query)(t, req)),
                 prepare_query: Box::new(move |t, req| (pi.prepare_query)(t, req)),
                 query_input: Box::new(move |t, req| (qi.query_input)(t, req)),
                 query_status: Box::new(move |t, req| (si.query_status)(t, req)),
+                active_queries: Box::new(move |t| (ai.active_queries)(t)),
                 complete_query: Box::new(move |t, req| (ci.complete_query)(t, req)),
+                result_page: Box::new(move |t, req| (pgi.result_page)(t, req)),
             }
         }
 
@@ -555,20 +596,38 @@ pub(crate) mod tests {
         assert_eq!(expected_output, &output);
     }
 
+    #[tokio::test]
+    async fn health() {
+        test_query_command(
+            |client| async move { client.health().await.unwrap() },
+            TransportCallbacks::default(),
+        )
+        .await;
+    }
+
     #[tokio::test]
     async fn create() {
-        let expected_query_id = QueryId;
+        let expected_query_id = QueryId::default();
         let expected_query_config = QueryConfig::new(TestMultiply, FieldType::Fp31, 1).unwrap();
 
         let cb = TransportCallbacks {
-            receive_query: Box::new(move |_transport, query_config| {
-                assert_eq!(query_config, expected_query_config);
-                Box::pin(ready(Ok(expected_query_id)))
+            receive_query: Box::new({
+                let expected_query_config = expected_query_config.clone();
+                move |_transport, query_config| {
+                    assert_eq!(query_config, expected_query_config);
+                    Box::pin(ready(Ok(expected_query_id)))
+                }
             }),
             ..Default::default()
         };
+        let expected_query_config = &expected_query_config;
         let query_id = test_query_command(
-            |client| async move { client.create_query(expected_query_config).await.unwrap() },
+            |client| async move {
+                client
+                    .create_query(expected_query_config.clone())
+                    .await
+                    .unwrap()
+            },
             cb,
         )
         .await;
@@ -578,9 +637,10 @@ pub(crate) mod tests {
     #[tokio::test]
     async fn prepare() {
         let input = PrepareQuery {
-            query_id: QueryId,
+            query_id: QueryId::default(),
             config: QueryConfig::new(TestMultiply, FieldType::Fp31, 1).unwrap(),
             roles: RoleAssignment::new(HelperIdentity::make_three()),
+            coordinator: HelperIdentity::ONE,
         };
         let expected_data = input.clone();
         let cb = TransportCallbacks {
@@ -602,7 +662,7 @@ pub(crate) mod tests {
 
     #[tokio::test]
     async fn input() {
-        let expected_query_id = QueryId;
+        let expected_query_id = QueryId::default();
         let expected_input = &[8u8; 25];
         let cb = TransportCallbacks {
             query_input: Box::new(move |_transport, query_input| {
@@ -632,7 +692,7 @@ pub(crate) mod tests {
         let TestServer {
             client, transport, ..
         } = TestServer::builder().build().await;
-        let expected_query_id = QueryId;
+        let expected_query_id = QueryId::default();
         let expected_step = Gate::default().narrow("test-step");
         let expected_payload = vec![7u8; MESSAGE_PAYLOAD_SIZE_BYTES];
 
@@ -648,8 +708,10 @@ pub(crate) mod tests {
 
         MpcHelperClient::resp_ok(resp).await.unwrap();
 
-        let mut stream =
-            Arc::clone(&transport).receive(HelperIdentity::ONE, (QueryId, expected_step.clone()));
+        let mut stream = Arc::clone(&transport).receive(
+            HelperIdentity::ONE,
+            (QueryId::default(), expected_step.clone()),
+        );
 
         assert_eq!(
             poll_immediate(&mut stream).next().await,
@@ -663,7 +725,7 @@ pub(crate) mod tests {
             Fp31::try_from(1u128).unwrap(),
             Fp31::try_from(2u128).unwrap(),
         ))]);
-        let expected_query_id = QueryId;
+        let expected_query_id = QueryId::default();
         let raw_results = expected_results.to_vec();
         let cb = TransportCallbacks {
             complete_query: Box::new(move |_transport, query_id| {
@@ -680,4 +742,29 @@ pub(crate) mod tests {
         .await;
         assert_eq!(results.to_vec(), expected_results.into_bytes());
     }
+
+    #[tokio::test]
+    async fn result_page() {
+        const RESULTS: &[u8] = b"0123456789";
+        let cb = TransportCallbacks {
+            result_page: Box::new(move |_transport, page| {
+                assert_eq!(page.query_id, QueryId::default());
+                let start = page.offset.min(RESULTS.len());
+                let end = start.saturating_add(page.limit).min(RESULTS.len());
+                Box::pin(ready(Ok(RESULTS[start..end].to_vec())))
+            }),
+            ..Default::default()
+        };
+        let page = test_query_command(
+            |client| async move {
+                client
+                    .query_result_page(QueryId::default(), 4, 3)
+                    .await
+                    .unwrap()
+            },
+            cb,
+        )
+        .await;
+        assert_eq!(b"456", &page[..]);
+    }
 }
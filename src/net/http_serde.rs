@@ -79,6 +79,46 @@ pub mod echo {
     pub const AXUM_PATH: &str = "/echo";
 }
 
+pub mod health {
+    use hyper::http::uri;
+
+    use crate::net::Error;
+
+    #[derive(Debug, Default, Clone, PartialEq, Eq)]
+    pub struct Request;
+
+    impl Request {
+        #[allow(clippy::unused_self)] // same signature as the other requests
+        pub fn try_into_http_request(
+            self,
+            scheme: uri::Scheme,
+            authority: uri::Authority,
+        ) -> Result<hyper::Request<hyper::Body>, Error> {
+            let uri = uri::Uri::builder()
+                .scheme(scheme)
+                .authority(authority)
+                .path_and_query(AXUM_PATH)
+                .build()?;
+            Ok(hyper::Request::get(uri).body(hyper::Body::empty())?)
+        }
+    }
+
+    pub const AXUM_PATH: &str = "/health";
+}
+
+pub mod queries {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{protocol::QueryId, query::QueryStatus};
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct ResponseBody {
+        pub queries: Vec<(QueryId, QueryStatus)>,
+    }
+
+    pub const AXUM_PATH: &str = "/queries";
+}
+
 pub mod query {
     use std::{
         fmt::{Display, Formatter},
@@ -90,9 +130,12 @@ pub mod query {
 
     use crate::{
         ff::FieldType,
-        helpers::query::{
-            ContributionBits, IpaQueryConfig, QueryConfig, QuerySize, QueryType,
-            SparseAggregateQueryConfig,
+        helpers::{
+            query::{
+                ContributionBits, IpaQueryConfig, QueryConfig, QueryPriority, QuerySize, QueryType,
+                SparseAggregateQueryConfig,
+            },
+            WireCompression,
         },
         net::Error,
     };
@@ -119,53 +162,29 @@ pub mod query {
                 size: QuerySize,
                 field_type: FieldType,
                 query_type: String,
+                #[serde(default)]
+                priority: QueryPriority,
+                #[serde(default)]
+                wire_compression: WireCompression,
+                timeout_seconds: Option<NonZeroU32>,
             }
             let Query(QueryTypeParam {
                 size,
                 field_type,
                 query_type,
+                priority,
+                wire_compression,
+                timeout_seconds,
             }) = req.extract().await?;
 
             let query_type = match query_type.as_str() {
                 #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
                 QueryType::TEST_MULTIPLY_STR => Ok(QueryType::TestMultiply),
                 QueryType::SEMIHONEST_IPA_STR | QueryType::MALICIOUS_IPA_STR => {
-                    #[derive(serde::Deserialize)]
-                    struct IPAQueryConfigParam {
-                        per_user_credit_cap: u32,
-                        max_breakdown_key: u32,
-                        attribution_window_seconds: Option<NonZeroU32>,
-                        num_multi_bits: u32,
-                        #[serde(default)]
-                        plaintext_match_keys: bool,
-                    }
-                    let Query(IPAQueryConfigParam {
-                        per_user_credit_cap,
-                        max_breakdown_key,
-                        attribution_window_seconds,
-                        num_multi_bits,
-                        plaintext_match_keys,
-                    }) = req.extract().await?;
-
+                    let Query(config) = req.extract::<Query<IpaQueryConfig>>().await?;
                     match query_type.as_str() {
-                        QueryType::SEMIHONEST_IPA_STR => {
-                            Ok(QueryType::SemiHonestIpa(IpaQueryConfig {
-                                per_user_credit_cap,
-                                max_breakdown_key,
-                                attribution_window_seconds,
-                                num_multi_bits,
-                                plaintext_match_keys,
-                            }))
-                        }
-                        QueryType::MALICIOUS_IPA_STR => {
-                            Ok(QueryType::MaliciousIpa(IpaQueryConfig {
-                                per_user_credit_cap,
-                                max_breakdown_key,
-                                attribution_window_seconds,
-                                num_multi_bits,
-                                plaintext_match_keys,
-                            }))
-                        }
+                        QueryType::SEMIHONEST_IPA_STR => Ok(QueryType::SemiHonestIpa(config)),
+                        QueryType::MALICIOUS_IPA_STR => Ok(QueryType::MaliciousIpa(config)),
                         &_ => unreachable!(),
                     }
                 }
@@ -201,6 +220,9 @@ pub mod query {
                 size,
                 field_type,
                 query_type,
+                priority,
+                wire_compression,
+                timeout_seconds,
             }))
         }
     }
@@ -214,7 +236,16 @@ pub mod query {
                 f = self.field_type,
                 size = self.size
             )?;
-            match self.query_type {
+            if self.priority != QueryPriority::default() {
+                write!(f, "&priority={:?}", self.priority)?;
+            }
+            if self.wire_compression != WireCompression::default() {
+                write!(f, "&wire_compression={:?}", self.wire_compression)?;
+            }
+            if let Some(timeout_seconds) = self.timeout_seconds {
+                write!(f, "&timeout_seconds={timeout_seconds}")?;
+            }
+            match &self.query_type {
                 #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
                 QueryType::TestMultiply => Ok(()),
                 QueryType::SemiHonestIpa(config) | QueryType::MaliciousIpa(config) => {
@@ -228,10 +259,54 @@ pub mod query {
                         write!(f, "&plaintext_match_keys=true")?;
                     }
 
+                    if config.clamp_breakdown_keys {
+                        write!(f, "&clamp_breakdown_keys=true")?;
+                    }
+
+                    if config.detect_max_breakdown_key {
+                        write!(f, "&detect_max_breakdown_key=true")?;
+                    }
+
+                    if config.dedup {
+                        write!(f, "&dedup=true")?;
+                    }
+
+                    if config.drop_zero_match_keys {
+                        write!(f, "&drop_zero_match_keys=true")?;
+                    }
+
+                    if config.signed_trigger_values {
+                        write!(f, "&signed_trigger_values=true")?;
+                    }
+
                     if let Some(window) = config.attribution_window_seconds {
                         write!(f, "&attribution_window_seconds={}", window.get())?;
                     }
 
+                    if let Some(max) = config.max_trigger_value {
+                        write!(f, "&max_trigger_value={}", max.get())?;
+                    }
+
+                    if config.check_histogram_total {
+                        write!(f, "&check_histogram_total=true")?;
+                    }
+
+                    if config.first_row_continues_previous {
+                        write!(f, "&first_row_continues_previous=true")?;
+                    }
+
+                    if let Some(limit) = config.max_input_rows {
+                        write!(f, "&max_input_rows={}", limit.get())?;
+                    }
+
+                    if let Some(url) = &config.callback_url {
+                        write!(f, "&callback_url={url}")?;
+                    }
+
+                    if let Some(level) = config.log_level {
+                        write!(f, "&log_level={level:?}")?;
+                    }
+
                     Ok(())
                 }
                 QueryType::SemiHonestSparseAggregate(config)
@@ -252,8 +327,11 @@ pub mod query {
 
     pub mod create {
         use async_trait::async_trait;
-        use axum::extract::{FromRequest, RequestParts};
-        use hyper::http::uri;
+        use axum::{
+            extract::{FromRequest, RequestParts},
+            Json,
+        };
+        use hyper::{header::CONTENT_TYPE, http::uri};
 
         use crate::{
             helpers::query::QueryConfig,
@@ -292,12 +370,28 @@ pub mod query {
             }
         }
 
+        /// Query config can be sent either as query parameters, which is what helpers use to talk
+        /// to each other, or as a JSON body, which is easier for external clients to produce.
+        /// The latter is used if the request has a JSON content type.
         #[async_trait]
-        impl<B: Send> FromRequest<B> for Request {
+        impl FromRequest<hyper::Body> for Request {
             type Rejection = Error;
 
-            async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
-                let QueryConfigQueryParams(query_config) = req.extract().await?;
+            async fn from_request(
+                req: &mut RequestParts<hyper::Body>,
+            ) -> Result<Self, Self::Rejection> {
+                let is_json = req
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map_or(false, |v| v.starts_with("application/json"));
+                let query_config = if is_json {
+                    let Json(query_config) = req.extract().await?;
+                    query_config
+                } else {
+                    let QueryConfigQueryParams(query_config) = req.extract().await?;
+                    query_config
+                };
                 Ok(Self { query_config })
             }
         }
@@ -320,7 +414,7 @@ pub mod query {
         use hyper::header::CONTENT_TYPE;
 
         use crate::{
-            helpers::{query::PrepareQuery, RoleAssignment},
+            helpers::{query::PrepareQuery, HelperIdentity, RoleAssignment},
             net::{
                 http_serde::query::{QueryConfigQueryParams, BASE_AXUM_PATH},
                 Error,
@@ -347,12 +441,13 @@ pub mod query {
                     .path_and_query(format!(
                         "{}/{}?{}",
                         BASE_AXUM_PATH,
-                        self.data.query_id.as_ref(),
+                        self.data.query_id,
                         QueryConfigQueryParams(self.data.config),
                     ))
                     .build()?;
                 let body = RequestBody {
                     roles: self.data.roles,
+                    coordinator: self.data.coordinator,
                 };
                 let body = hyper::Body::from(serde_json::to_string(&body)?);
                 Ok(hyper::Request::post(uri)
@@ -370,12 +465,13 @@ pub mod query {
             ) -> Result<Self, Self::Rejection> {
                 let Path(query_id) = req.extract().await?;
                 let QueryConfigQueryParams(config) = req.extract().await?;
-                let Json(RequestBody { roles }) = req.extract().await?;
+                let Json(RequestBody { roles, coordinator }) = req.extract().await?;
                 Ok(Request {
                     data: PrepareQuery {
                         query_id,
                         config,
                         roles,
+                        coordinator,
                     },
                 })
             }
@@ -384,6 +480,7 @@ pub mod query {
         #[cfg_attr(feature = "enable-serde", derive(serde::Serialize, serde::Deserialize))]
         struct RequestBody {
             roles: RoleAssignment,
+            coordinator: HelperIdentity,
         }
 
         pub const AXUM_PATH: &str = "/:query_id";
@@ -423,8 +520,7 @@ pub mod query {
                     .authority(authority)
                     .path_and_query(format!(
                         "{}/{}/input",
-                        BASE_AXUM_PATH,
-                        self.query_input.query_id.as_ref(),
+                        BASE_AXUM_PATH, self.query_input.query_id,
                     ))
                     .build()?;
                 let body = Body::wrap_stream(self.query_input.input_stream);
@@ -499,7 +595,7 @@ pub mod query {
                     .path_and_query(format!(
                         "{}/{}/step/{}",
                         BASE_AXUM_PATH,
-                        self.query_id.as_ref(),
+                        self.query_id,
                         self.gate.as_ref()
                     ))
                     .build()?;
@@ -565,7 +661,7 @@ pub mod query {
                     .path_and_query(format!(
                         "{}/{}",
                         crate::net::http_serde::query::BASE_AXUM_PATH,
-                        self.query_id.as_ref()
+                        self.query_id
                     ))
                     .build()?;
                 Ok(hyper::Request::get(uri).body(hyper::Body::empty())?)
@@ -619,7 +715,7 @@ pub mod query {
                     .path_and_query(format!(
                         "{}/{}/complete",
                         crate::net::http_serde::query::BASE_AXUM_PATH,
-                        self.query_id.as_ref()
+                        self.query_id
                     ))
                     .build()?;
                 Ok(hyper::Request::get(uri).body(hyper::Body::empty())?)
@@ -638,4 +734,77 @@ pub mod query {
 
         pub const AXUM_PATH: &str = "/:query_id/complete";
     }
+
+    pub mod result_page {
+        use async_trait::async_trait;
+        use axum::extract::{FromRequest, Path, Query, RequestParts};
+
+        use crate::{
+            helpers::query::ResultPage,
+            net::{http_serde::query::BASE_AXUM_PATH, Error},
+            protocol::QueryId,
+        };
+
+        /// Page of query results. Without a `limit`, the page extends to the end of the results.
+        #[derive(Debug, Clone)]
+        pub struct Request {
+            pub page: ResultPage,
+        }
+
+        impl Request {
+            #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))] // needed because client is blocking; remove when non-blocking
+            pub fn new(query_id: QueryId, offset: usize, limit: usize) -> Self {
+                Self {
+                    page: ResultPage {
+                        query_id,
+                        offset,
+                        limit,
+                    },
+                }
+            }
+
+            #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))] // needed because client is blocking; remove when non-blocking
+            pub fn try_into_http_request(
+                self,
+                scheme: axum::http::uri::Scheme,
+                authority: axum::http::uri::Authority,
+            ) -> Result<hyper::Request<hyper::Body>, Error> {
+                let uri = axum::http::uri::Uri::builder()
+                    .scheme(scheme)
+                    .authority(authority)
+                    .path_and_query(format!(
+                        "{}/{}/result?offset={}&limit={}",
+                        BASE_AXUM_PATH, self.page.query_id, self.page.offset, self.page.limit
+                    ))
+                    .build()?;
+                Ok(hyper::Request::get(uri).body(hyper::Body::empty())?)
+            }
+        }
+
+        #[async_trait]
+        impl<B: Send> FromRequest<B> for Request {
+            type Rejection = Error;
+
+            async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+                #[derive(serde::Deserialize)]
+                struct PageParams {
+                    #[serde(default)]
+                    offset: usize,
+                    limit: Option<usize>,
+                }
+
+                let Path(query_id) = req.extract().await?;
+                let Query(PageParams { offset, limit }) = req.extract().await?;
+                Ok(Request {
+                    page: ResultPage {
+                        query_id,
+                        offset,
+                        limit: limit.unwrap_or(usize::MAX),
+                    },
+                })
+            }
+        }
+
+        pub const AXUM_PATH: &str = "/:query_id/result";
+    }
 }
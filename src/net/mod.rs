@@ -1,3 +1,4 @@
+mod callback;
 mod client;
 mod error;
 mod http_serde;
@@ -6,6 +7,7 @@ mod server;
 pub mod test;
 mod transport;
 
+pub use callback::HttpCompletionNotifier;
 pub use client::{ClientIdentity, MpcHelperClient};
 pub use error::Error;
 pub use server::{MpcHelperServer, TracingSpanMaker};
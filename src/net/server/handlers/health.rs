@@ -0,0 +1,14 @@
+use axum::{routing::get, Router};
+use hyper::StatusCode;
+
+use crate::net::http_serde;
+
+/// Lets other helpers check that this one is up. Liveness probes only look at the status code.
+#[allow(clippy::unused_async)] // needs to be async for axum handler
+async fn handler() -> StatusCode {
+    StatusCode::OK
+}
+
+pub fn router() -> Router {
+    Router::new().route(http_serde::health::AXUM_PATH, get(handler))
+}
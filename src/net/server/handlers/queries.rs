@@ -0,0 +1,48 @@
+use axum::{routing::get, Extension, Json, Router};
+
+use crate::{
+    net::{http_serde::queries, HttpTransport},
+    sync::Arc,
+};
+
+/// Lists queries this helper is currently working on, so operators can see what a busy helper
+/// is doing.
+async fn handler(transport: Extension<Arc<HttpTransport>>) -> Json<queries::ResponseBody> {
+    let transport = Arc::clone(&transport);
+    Json(queries::ResponseBody {
+        queries: transport.active_queries().await,
+    })
+}
+
+pub fn router(transport: Arc<HttpTransport>) -> Router {
+    Router::new()
+        .route(queries::AXUM_PATH, get(handler))
+        .layer(Extension(transport))
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::future::ready;
+
+    use super::*;
+    use crate::{
+        helpers::TransportCallbacks, net::test::TestServer, protocol::QueryId, query::QueryStatus,
+    };
+
+    #[tokio::test]
+    async fn lists_active_queries() {
+        let expected = vec![
+            (QueryId::default(), QueryStatus::Running),
+            (QueryId::from([1; 16]), QueryStatus::AwaitingInputs),
+        ];
+        let response = expected.clone();
+        let cb = TransportCallbacks {
+            active_queries: Box::new(move |_transport| Box::pin(ready(response.clone()))),
+            ..Default::default()
+        };
+        let TestServer { transport, .. } = TestServer::builder().with_callbacks(cb).build().await;
+
+        let Json(queries::ResponseBody { queries }) = handler(Extension(transport)).await;
+        assert_eq!(expected, queries);
+    }
+}
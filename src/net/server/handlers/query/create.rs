@@ -20,6 +20,15 @@ async fn handler(
         Err(err @ NewQueryError::State { .. }) => {
             Err(Error::application(StatusCode::CONFLICT, err))
         }
+        Err(err @ NewQueryError::TooManyQueries(_)) => {
+            Err(Error::application(StatusCode::TOO_MANY_REQUESTS, err))
+        }
+        Err(err @ NewQueryError::PeerUnreachable(_)) => {
+            Err(Error::application(StatusCode::SERVICE_UNAVAILABLE, err))
+        }
+        Err(err @ NewQueryError::CallbackNotAllowed(_)) => {
+            Err(Error::application(StatusCode::FORBIDDEN, err))
+        }
         Err(err) => Err(Error::application(StatusCode::INTERNAL_SERVER_ERROR, err)),
     }
 }
@@ -36,6 +45,7 @@ mod tests {
 
     use axum::http::Request;
     use hyper::{
+        header::CONTENT_TYPE,
         http::uri::{Authority, Scheme},
         Body, StatusCode,
     };
@@ -44,8 +54,11 @@ mod tests {
     use crate::{
         ff::FieldType,
         helpers::{
-            query::{IpaQueryConfig, QueryConfig, QueryType, SparseAggregateQueryConfig},
-            TransportCallbacks,
+            query::{
+                IpaQueryConfig, QueryConfig, QueryLogLevel, QueryPriority, QueryType,
+                SparseAggregateQueryConfig,
+            },
+            TransportCallbacks, WireCompression,
         },
         net::{
             server::handlers::query::test_helpers::{assert_req_fails_with, IntoFailingReq},
@@ -55,15 +68,15 @@ mod tests {
     };
 
     async fn create_test(expected_query_config: QueryConfig) {
+        let req = http_serde::query::create::Request::new(expected_query_config.clone());
         let cb = TransportCallbacks {
             receive_query: Box::new(move |_transport, query_config| {
                 assert_eq!(query_config, expected_query_config);
-                Box::pin(ready(Ok(QueryId)))
+                Box::pin(ready(Ok(QueryId::default())))
             }),
             ..Default::default()
         };
         let TestServer { server, .. } = TestServer::builder().with_callbacks(cb).build().await;
-        let req = http_serde::query::create::Request::new(expected_query_config);
         let req = req
             .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
             .unwrap();
@@ -77,7 +90,7 @@ mod tests {
 
         let http_serde::query::create::ResponseBody { query_id } =
             serde_json::from_slice(&body_bytes).unwrap();
-        assert_eq!(QueryId, query_id);
+        assert_eq!(QueryId::default(), query_id);
     }
 
     #[tokio::test]
@@ -95,6 +108,17 @@ mod tests {
                     attribution_window_seconds: None,
                     num_multi_bits: 3,
                     plaintext_match_keys: true,
+                    clamp_breakdown_keys: false,
+                    detect_max_breakdown_key: false,
+                    dedup: false,
+                    drop_zero_match_keys: false,
+                    signed_trigger_values: false,
+                    max_trigger_value: None,
+                    check_histogram_total: false,
+                    first_row_continues_previous: false,
+                    max_input_rows: None,
+                    callback_url: None,
+                    log_level: None,
                 }),
                 FieldType::Fp32BitPrime,
                 1,
@@ -115,11 +139,113 @@ mod tests {
                 attribution_window_seconds: NonZeroU32::new(86_400),
                 num_multi_bits: 3,
                 plaintext_match_keys: true,
+                clamp_breakdown_keys: false,
+                detect_max_breakdown_key: false,
+                dedup: false,
+                drop_zero_match_keys: false,
+                signed_trigger_values: false,
+                max_trigger_value: NonZeroU32::new(1_000),
+                check_histogram_total: true,
+                first_row_continues_previous: false,
+                max_input_rows: None,
+                callback_url: None,
+                log_level: Some(QueryLogLevel::Debug),
             }),
+            priority: QueryPriority::High,
+            wire_compression: WireCompression::Zstd,
+            timeout_seconds: NonZeroU32::new(600),
         })
         .await;
     }
 
+    #[tokio::test]
+    async fn create_test_ipa_json_body() {
+        let expected_query_config = QueryConfig {
+            size: 1.try_into().unwrap(),
+            field_type: FieldType::Fp32BitPrime,
+            query_type: QueryType::SemiHonestIpa(IpaQueryConfig {
+                per_user_credit_cap: 8,
+                max_breakdown_key: 20,
+                attribution_window_seconds: NonZeroU32::new(86_400),
+                num_multi_bits: 3,
+                plaintext_match_keys: true,
+                clamp_breakdown_keys: false,
+                detect_max_breakdown_key: false,
+                dedup: true,
+                drop_zero_match_keys: false,
+                signed_trigger_values: false,
+                max_trigger_value: None,
+                check_histogram_total: false,
+                first_row_continues_previous: false,
+                max_input_rows: None,
+                callback_url: None,
+                log_level: None,
+            }),
+            priority: QueryPriority::Low,
+            wire_compression: WireCompression::None,
+            timeout_seconds: None,
+        };
+        let json = serde_json::to_string(&expected_query_config).unwrap();
+        assert_eq!(
+            expected_query_config,
+            serde_json::from_str::<QueryConfig>(&json).unwrap()
+        );
+
+        let cb = TransportCallbacks {
+            receive_query: Box::new(move |_transport, query_config| {
+                assert_eq!(query_config, expected_query_config);
+                Box::pin(ready(Ok(QueryId::default())))
+            }),
+            ..Default::default()
+        };
+        let TestServer { server, .. } = TestServer::builder().with_callbacks(cb).build().await;
+        let req = hyper::Request::post(format!(
+            "http://localhost{}",
+            http_serde::query::BASE_AXUM_PATH
+        ))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(json))
+        .unwrap();
+        let resp = server.handle_req(req).await;
+
+        assert_eq!(StatusCode::OK, resp.status());
+        let body_bytes = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let http_serde::query::create::ResponseBody { query_id } =
+            serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(QueryId::default(), query_id);
+    }
+
+    #[test]
+    fn ipa_config_flags_default_to_false() {
+        let config: IpaQueryConfig = serde_json::from_str(
+            r#"{"per_user_credit_cap":8,"max_breakdown_key":20,"num_multi_bits":3}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            IpaQueryConfig {
+                per_user_credit_cap: 8,
+                max_breakdown_key: 20,
+                ..IpaQueryConfig::default()
+            },
+            config
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_json_body() {
+        let TestServer { server, .. } = TestServer::builder().build().await;
+        let req = hyper::Request::post(format!(
+            "http://localhost{}",
+            http_serde::query::BASE_AXUM_PATH
+        ))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"size":0}"#))
+        .unwrap();
+        let resp = server.handle_req(req).await;
+
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+    }
+
     #[tokio::test]
     async fn create_test_aggregate() {
         create_test(QueryConfig {
@@ -129,6 +255,9 @@ mod tests {
                 contribution_bits: 8.try_into().unwrap(),
                 num_contributions: 20,
             }),
+            priority: QueryPriority::default(),
+            wire_compression: WireCompression::None,
+            timeout_seconds: None,
         })
         .await;
         create_test(QueryConfig {
@@ -138,6 +267,9 @@ mod tests {
                 contribution_bits: 8.try_into().unwrap(),
                 num_contributions: 20,
             }),
+            priority: QueryPriority::default(),
+            wire_compression: WireCompression::None,
+            timeout_seconds: None,
         })
         .await;
     }
@@ -41,7 +41,7 @@ mod tests {
 
     #[tokio::test]
     async fn input_test() {
-        let expected_query_id = QueryId;
+        let expected_query_id = QueryId::default();
         let expected_input = &[4u8; 4];
         let cb = TransportCallbacks {
             query_input: Box::new(move |_transport, query_input| {
@@ -83,7 +83,7 @@ mod tests {
     impl Default for OverrideReq {
         fn default() -> Self {
             Self {
-                query_id: QueryId.as_ref().to_string(),
+                query_id: QueryId::default().to_string(),
                 input_stream: vec![4; 4],
             }
         }
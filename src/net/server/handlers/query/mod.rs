@@ -1,6 +1,7 @@
 mod create;
 mod input;
 mod prepare;
+mod result_page;
 mod results;
 mod status;
 mod step;
@@ -33,6 +34,7 @@ pub fn query_router(transport: Arc<HttpTransport>) -> Router {
         .merge(create::router(Arc::clone(&transport)))
         .merge(input::router(Arc::clone(&transport)))
         .merge(status::router(Arc::clone(&transport)))
+        .merge(result_page::router(Arc::clone(&transport)))
         .merge(results::router(transport))
 }
 
@@ -1,26 +1,53 @@
 use std::sync::Arc;
 
-use axum::{response::IntoResponse, routing::post, Extension, Router};
+use axum::{
+    response::{IntoResponse, Response},
+    routing::post,
+    Extension, Router,
+};
 use hyper::StatusCode;
 
 use crate::{
-    net::{http_serde, server::ClientIdentity, HttpTransport},
+    net::{http_serde, server::ClientIdentity, Error, HttpTransport},
     query::PrepareQueryError,
 };
 
 /// Called by whichever peer helper is the leader for an individual query, to initiatialize
 /// processing of that query.
+///
+/// The leader is identified by the authenticated connection rather than by anything the request
+/// claims, and it must be the coordinator named in the request. The leader can play any role in
+/// the query, but one helper cannot set up queries on behalf of another one.
 async fn handler(
     transport: Extension<Arc<HttpTransport>>,
-    _from: Extension<ClientIdentity>, // require that client is an authenticated helper
+    from: Extension<ClientIdentity>,
     req: http_serde::query::prepare::Request,
-) -> Result<(), PrepareQueryError> {
-    Arc::clone(&transport).prepare_query(req.data).await
+) -> Result<(), Response> {
+    let leader = req.data.coordinator;
+    if **from != leader {
+        return Err(Error::application(
+            StatusCode::FORBIDDEN,
+            format!(
+                "{:?} cannot prepare query {} led by {leader:?}",
+                **from, req.data.query_id
+            ),
+        )
+        .into_response());
+    }
+
+    Arc::clone(&transport)
+        .prepare_query(req.data)
+        .await
+        .map_err(IntoResponse::into_response)
 }
 
 impl IntoResponse for PrepareQueryError {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+        let status = match self {
+            PrepareQueryError::TooManyQueries(_) => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        (status, self.to_string()).into_response()
     }
 }
 
@@ -35,14 +62,14 @@ mod tests {
     use std::future::ready;
 
     use axum::http::Request;
-    use hyper::{Body, StatusCode};
+    use hyper::{header::CONTENT_TYPE, Body, StatusCode};
 
     use super::*;
     use crate::{
         ff::FieldType,
         helpers::{
             query::{PrepareQuery, QueryConfig, QueryType::TestMultiply},
-            HelperIdentity, RoleAssignment, TransportCallbacks,
+            HelperIdentity, Role, RoleAssignment, TransportCallbacks,
         },
         net::{
             server::{
@@ -60,9 +87,10 @@ mod tests {
     #[tokio::test]
     async fn prepare_test() {
         let req = http_serde::query::prepare::Request::new(PrepareQuery {
-            query_id: QueryId,
+            query_id: QueryId::default(),
             config: QueryConfig::new(TestMultiply, FieldType::Fp31, 1).unwrap(),
             roles: RoleAssignment::new(HelperIdentity::make_three()),
+            coordinator: HelperIdentity::ONE,
         });
         let expected_prepare_query = req.data.clone();
 
@@ -76,7 +104,7 @@ mod tests {
         let TestServer { transport, .. } = TestServer::builder().with_callbacks(cb).build().await;
         handler(
             Extension(transport),
-            Extension(ClientIdentity(HelperIdentity::TWO)),
+            Extension(ClientIdentity(HelperIdentity::ONE)),
             req.clone(),
         )
         .await
@@ -90,6 +118,7 @@ mod tests {
         field_type: String,
         size: Option<i32>,
         roles: Vec<String>,
+        coordinator: String,
     }
 
     impl IntoFailingReq for OverrideReq {
@@ -101,9 +130,14 @@ mod tests {
                 query_id = self.query_id,
                 ft = self.field_type
             );
-            let body = serde_json::to_vec(&self.roles).unwrap();
+            let body = format!(
+                r#"{{"roles":[{}],"coordinator":{}}}"#,
+                self.roles.join(","),
+                self.coordinator
+            );
             hyper::Request::post(uri)
                 .maybe_extension(self.client_id)
+                .header(CONTENT_TYPE, "application/json")
                 .body(hyper::Body::from(body))
                 .unwrap()
         }
@@ -115,11 +149,12 @@ mod tests {
                 .map(|id| serde_json::to_string(&id).unwrap())
                 .to_vec();
             Self {
-                client_id: Some(ClientIdentity(HelperIdentity::TWO)),
-                query_id: QueryId.as_ref().to_string(),
+                client_id: Some(ClientIdentity(HelperIdentity::ONE)),
+                query_id: QueryId::default().to_string(),
                 field_type: format!("{:?}", FieldType::Fp31),
                 size: Some(1),
                 roles,
+                coordinator: "1".into(),
             }
         }
     }
@@ -160,6 +195,36 @@ mod tests {
         assert_req_fails_with(req, StatusCode::BAD_REQUEST).await;
     }
 
+    #[tokio::test]
+    async fn sender_must_be_leader() {
+        let req = OverrideReq {
+            client_id: Some(ClientIdentity(HelperIdentity::TWO)),
+            ..Default::default()
+        };
+        assert_req_fails_with(req, StatusCode::FORBIDDEN).await;
+    }
+
+    #[tokio::test]
+    async fn leader_can_play_any_role() {
+        let cb = TransportCallbacks {
+            prepare_query: Box::new(move |_transport, prepare_query| {
+                assert_eq!(HelperIdentity::TWO, prepare_query.coordinator);
+                assert_eq!(HelperIdentity::TWO, prepare_query.roles.identity(Role::H3));
+                Box::pin(ready(Ok(())))
+            }),
+            ..Default::default()
+        };
+        let TestServer { server, .. } = TestServer::builder().with_callbacks(cb).build().await;
+        let req = OverrideReq {
+            client_id: Some(ClientIdentity(HelperIdentity::TWO)),
+            roles: vec!["3".into(), "1".into(), "2".into()],
+            coordinator: "2".into(),
+            ..Default::default()
+        };
+        let resp = server.handle_req(req.into_req(0)).await;
+        assert_eq!(StatusCode::OK, resp.status());
+    }
+
     #[tokio::test]
     async fn auth_required() {
         let req = OverrideReq {
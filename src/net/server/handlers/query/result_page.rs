@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use axum::{routing::get, Extension, Router};
+use hyper::StatusCode;
+
+use crate::{
+    helpers::Transport,
+    net::{http_serde, server::Error, HttpTransport},
+    query::QueryCompletionError,
+};
+
+/// Returns a page of the query results, blocking the sender until the query is completed if it
+/// asks for the first time.
+async fn handler(
+    transport: Extension<Arc<HttpTransport>>,
+    req: http_serde::query::result_page::Request,
+) -> Result<Vec<u8>, Error> {
+    let transport = Transport::clone_ref(&*transport);
+    match transport.result_page(req.page).await {
+        Ok(page) => Ok(page),
+        Err(e @ QueryCompletionError::NoSuchQuery(_)) => {
+            Err(Error::application(StatusCode::NOT_FOUND, e))
+        }
+        Err(e @ QueryCompletionError::StateError { .. }) => {
+            Err(Error::application(StatusCode::CONFLICT, e))
+        }
+        Err(e) => Err(Error::application(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+pub fn router(transport: Arc<HttpTransport>) -> Router {
+    Router::new()
+        .route(http_serde::query::result_page::AXUM_PATH, get(handler))
+        .layer(Extension(transport))
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::future::ready;
+
+    use axum::http::Request;
+    use hyper::StatusCode;
+
+    use super::*;
+    use crate::{
+        helpers::{query::ResultPage, TransportCallbacks},
+        net::{
+            server::handlers::query::test_helpers::{assert_req_fails_with, IntoFailingReq},
+            test::TestServer,
+        },
+        protocol::QueryId,
+        query::{QueryStatus, StateError},
+    };
+
+    #[tokio::test]
+    async fn pages_concatenate_to_results() {
+        const RESULTS: &[u8] = b"stored result envelope";
+        let cb = TransportCallbacks {
+            result_page: Box::new(move |_transport, page| {
+                assert_eq!(page.query_id, QueryId::default());
+                let start = page.offset.min(RESULTS.len());
+                let end = start.saturating_add(page.limit).min(RESULTS.len());
+                Box::pin(ready(Ok(RESULTS[start..end].to_vec())))
+            }),
+            ..Default::default()
+        };
+        let TestServer { transport, .. } = TestServer::builder().with_callbacks(cb).build().await;
+
+        let mut fetched = Vec::new();
+        loop {
+            let req =
+                http_serde::query::result_page::Request::new(QueryId::default(), fetched.len(), 5);
+            let page = handler(Extension(Arc::clone(&transport)), req)
+                .await
+                .unwrap();
+            let last = page.len() < 5;
+            fetched.extend(page);
+            if last {
+                break;
+            }
+        }
+        assert_eq!(RESULTS, fetched);
+    }
+
+    #[tokio::test]
+    async fn missing_limit_fetches_the_rest() {
+        let cb = TransportCallbacks {
+            result_page: Box::new(move |_transport, page| {
+                assert_eq!(
+                    ResultPage {
+                        query_id: QueryId::default(),
+                        offset: 3,
+                        limit: usize::MAX,
+                    },
+                    page
+                );
+                Box::pin(ready(Ok(Vec::new())))
+            }),
+            ..Default::default()
+        };
+        let TestServer { server, .. } = TestServer::builder().with_callbacks(cb).build().await;
+        let req = hyper::Request::get(format!(
+            "http://localhost{}/{}/result?offset=3",
+            http_serde::query::BASE_AXUM_PATH,
+            QueryId::default()
+        ))
+        .body(hyper::Body::empty())
+        .unwrap();
+        let resp = server.handle_req(req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+    }
+
+    #[tokio::test]
+    async fn query_errors_map_to_status_codes() {
+        async fn status(error: fn(QueryId) -> QueryCompletionError) -> StatusCode {
+            let cb = TransportCallbacks {
+                result_page: Box::new(move |_transport, page| {
+                    Box::pin(ready(Err(error(page.query_id))))
+                }),
+                ..Default::default()
+            };
+            let TestServer { server, .. } = TestServer::builder().with_callbacks(cb).build().await;
+            let req = hyper::Request::get(format!(
+                "http://localhost{}/{}/result",
+                http_serde::query::BASE_AXUM_PATH,
+                QueryId::default()
+            ))
+            .body(hyper::Body::empty())
+            .unwrap();
+            server.handle_req(req).await.status()
+        }
+
+        assert_eq!(
+            StatusCode::NOT_FOUND,
+            status(QueryCompletionError::NoSuchQuery).await
+        );
+        assert_eq!(
+            StatusCode::CONFLICT,
+            status(|_| StateError::InvalidState {
+                from: QueryStatus::AwaitingCompletion,
+                to: QueryStatus::Running,
+            }
+            .into())
+            .await
+        );
+    }
+
+    struct OverrideReq {
+        query_id: String,
+        params: &'static str,
+    }
+
+    impl IntoFailingReq for OverrideReq {
+        fn into_req(self, port: u16) -> Request<hyper::Body> {
+            let uri = format!(
+                "http://localhost:{}{}/{}/result?{}",
+                port,
+                http_serde::query::BASE_AXUM_PATH,
+                self.query_id,
+                self.params,
+            );
+            hyper::Request::get(uri).body(hyper::Body::empty()).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_query_id() {
+        let req = OverrideReq {
+            query_id: "not-a-query-id".into(),
+            params: "offset=0&limit=10",
+        };
+
+        assert_req_fails_with(req, StatusCode::UNPROCESSABLE_ENTITY).await;
+    }
+
+    #[tokio::test]
+    async fn malformed_offset() {
+        let req = OverrideReq {
+            query_id: QueryId::default().to_string(),
+            params: "offset=-1",
+        };
+
+        assert_req_fails_with(req, StatusCode::UNPROCESSABLE_ENTITY).await;
+    }
+}
@@ -6,6 +6,7 @@ use hyper::StatusCode;
 use crate::{
     helpers::Transport,
     net::{http_serde, server::Error, HttpTransport},
+    query::QueryCompletionError,
 };
 
 /// Handles the completion of the query by blocking the sender until query is completed.
@@ -17,6 +18,12 @@ async fn handler(
     let transport = Transport::clone_ref(&*transport);
     match transport.complete_query(req.query_id).await {
         Ok(result) => Ok(result.into_bytes()),
+        Err(e @ QueryCompletionError::NoSuchQuery(_)) => {
+            Err(Error::application(StatusCode::NOT_FOUND, e))
+        }
+        Err(e @ QueryCompletionError::StateError { .. }) => {
+            Err(Error::application(StatusCode::CONFLICT, e))
+        }
         Err(e) => Err(Error::application(StatusCode::INTERNAL_SERVER_ERROR, e)),
     }
 }
@@ -53,7 +60,7 @@ mod tests {
             Fp31::try_from(1u128).unwrap(),
             Fp31::try_from(2u128).unwrap(),
         ))]);
-        let expected_query_id = QueryId;
+        let expected_query_id = QueryId::default();
         let raw_results = expected_results.to_vec();
         let cb = TransportCallbacks {
             complete_query: Box::new(move |_transport, query_id| {
@@ -64,7 +71,7 @@ mod tests {
             ..Default::default()
         };
         let TestServer { transport, .. } = TestServer::builder().with_callbacks(cb).build().await;
-        let req = http_serde::query::results::Request::new(QueryId);
+        let req = http_serde::query::results::Request::new(QueryId::default());
         let results = handler(Extension(transport), req.clone()).await.unwrap();
         assert_eq!(results, expected_results.into_bytes());
     }
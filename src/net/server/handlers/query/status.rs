@@ -47,7 +47,7 @@ mod tests {
     #[tokio::test]
     async fn status_test() {
         let expected_status = QueryStatus::Running;
-        let expected_query_id = QueryId;
+        let expected_query_id = QueryId::default();
         let cb = TransportCallbacks {
             query_status: Box::new(move |_transport, query_id| {
                 assert_eq!(query_id, expected_query_id);
@@ -56,7 +56,7 @@ mod tests {
             ..Default::default()
         };
         let TestServer { transport, .. } = TestServer::builder().with_callbacks(cb).build().await;
-        let req = http_serde::query::status::Request::new(QueryId);
+        let req = http_serde::query::status::Request::new(QueryId::default());
         let response = handler(Extension(transport), req.clone()).await.unwrap();
 
         let Json(http_serde::query::status::ResponseBody { status }) = response;
@@ -87,4 +87,13 @@ mod tests {
 
         assert_req_fails_with(req, StatusCode::UNPROCESSABLE_ENTITY).await;
     }
+
+    #[tokio::test]
+    async fn truncated_query_id() {
+        let req = OverrideReq {
+            query_id: "00112233".into(),
+        };
+
+        assert_req_fails_with(req, StatusCode::UNPROCESSABLE_ENTITY).await;
+    }
 }
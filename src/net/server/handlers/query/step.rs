@@ -32,12 +32,16 @@ mod tests {
     use std::task::Poll;
 
     use axum::http::Request;
-    use futures::{stream::poll_immediate, StreamExt};
+    use futures::{
+        future::ready,
+        stream::{once, poll_immediate},
+        StreamExt,
+    };
     use hyper::{Body, StatusCode};
 
     use super::*;
     use crate::{
-        helpers::{HelperIdentity, MESSAGE_PAYLOAD_SIZE_BYTES},
+        helpers::{sequence, HelperIdentity, MESSAGE_PAYLOAD_SIZE_BYTES},
         net::{
             server::handlers::query::{
                 test_helpers::{assert_req_fails_with, IntoFailingReq},
@@ -59,8 +63,10 @@ mod tests {
 
         let step = Gate::default().narrow("test");
         let payload = vec![213; DATA_LEN * MESSAGE_PAYLOAD_SIZE_BYTES];
+        // peers number their messages
+        let body = sequence(once(ready(payload.clone()))).concat().await;
         let req =
-            http_serde::query::step::Request::new(QueryId, step.clone(), payload.clone().into());
+            http_serde::query::step::Request::new(QueryId::default(), step.clone(), body.into());
 
         handler(
             Extension(Arc::clone(&transport)),
@@ -70,7 +76,8 @@ mod tests {
         .await
         .unwrap();
 
-        let mut stream = Arc::clone(&transport).receive(HelperIdentity::TWO, (QueryId, step));
+        let mut stream =
+            Arc::clone(&transport).receive(HelperIdentity::TWO, (QueryId::default(), step));
 
         assert_eq!(
             poll_immediate(&mut stream).next().await,
@@ -105,7 +112,7 @@ mod tests {
         fn default() -> Self {
             Self {
                 client_id: Some(ClientIdentity(HelperIdentity::ONE)),
-                query_id: QueryId.as_ref().to_string(),
+                query_id: QueryId::default().to_string(),
                 gate: Gate::default().narrow("test"),
                 payload: vec![1; DATA_LEN * MESSAGE_PAYLOAD_SIZE_BYTES],
             }
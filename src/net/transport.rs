@@ -1,30 +1,38 @@
 use std::{
     borrow::Borrow,
+    collections::{hash_map::Entry, HashMap},
     future::Future,
+    num::NonZeroUsize,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use async_trait::async_trait;
 use bytes::Bytes;
-use futures::{Stream, TryFutureExt};
+use futures::{future::join_all, Stream, TryFutureExt};
+use tokio::task::JoinHandle;
 
 use crate::{
     config::{NetworkConfig, ServerConfig},
     error::BoxError,
     helpers::{
-        query::{PrepareQuery, QueryConfig, QueryInput},
-        BodyStream, CompleteQueryResult, HelperIdentity, LogErrors, NoResourceIdentifier,
-        PrepareQueryResult, QueryIdBinding, QueryInputResult, QueryStatusResult,
-        ReceiveQueryResult, ReceiveRecords, RouteId, RouteParams, StepBinding, StreamCollection,
-        Transport, TransportCallbacks,
+        query::{PrepareQuery, QueryConfig, QueryInput, ResultPage},
+        AbortReason, ActiveQueriesResult, BodyStream, CompleteQueryResult, HelperIdentity,
+        LogErrors, NoResourceIdentifier, PeerState, PrepareQueryResult, QueryIdBinding,
+        QueryInputResult, QueryResultPageResult, QueryStatusResult, ReceiveQueryResult,
+        ReceiveRecords, Redeliveries, ResumableStream, RouteId, RouteParams, StepBinding,
+        StreamCollection, StreamKey, Transport, TransportCallbacks,
     },
     net::{client::MpcHelperClient, error::Error, MpcHelperServer},
     protocol::{step::Gate, QueryId},
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
-type LogHttpErrors = LogErrors<BodyStream, Bytes, BoxError>;
+type LogHttpErrors = LogErrors<ResumableStream<BodyStream>, Bytes, BoxError>;
+
+/// How long [`Transport::connect_peers`] waits for every other helper to respond.
+const PEER_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// HTTP transport for IPA helper service.
 pub struct HttpTransport {
@@ -34,6 +42,13 @@ pub struct HttpTransport {
     // TODO(615): supporting multiple queries likely require a hashmap here. It will be ok if we
     // only allow one query at a time.
     record_streams: StreamCollection<LogHttpErrors>,
+    /// Adds repeated deliveries of a record stream to the one that was received first, so that
+    /// the receiver resumes where the broken delivery left off.
+    redeliveries: Mutex<HashMap<StreamKey, Redeliveries<BodyStream>>>,
+    /// Updated by [`HttpTransport::probe_peers`]. Peers are assumed to be alive until a probe
+    /// fails.
+    peer_states: Mutex<[PeerState; 3]>,
+    batch_records: Option<NonZeroUsize>,
 }
 
 impl HttpTransport {
@@ -45,7 +60,8 @@ impl HttpTransport {
         clients: [MpcHelperClient; 3],
         callbacks: TransportCallbacks<Arc<HttpTransport>>,
     ) -> (Arc<Self>, MpcHelperServer) {
-        let transport = Self::new_internal(identity, clients, callbacks);
+        let transport =
+            Self::new_internal(identity, clients, callbacks, server_config.batch_records);
         let server = MpcHelperServer::new(Arc::clone(&transport), server_config, network_config);
         (transport, server)
     }
@@ -54,12 +70,59 @@ impl HttpTransport {
         identity: HelperIdentity,
         clients: [MpcHelperClient; 3],
         callbacks: TransportCallbacks<Arc<HttpTransport>>,
+        batch_records: Option<NonZeroUsize>,
     ) -> Arc<Self> {
         Arc::new(Self {
             identity,
             callbacks,
             clients,
             record_streams: StreamCollection::default(),
+            redeliveries: Mutex::default(),
+            peer_states: Mutex::new([PeerState::Alive; 3]),
+            batch_records,
+        })
+    }
+
+    /// Pings every other helper once and records whether it responded within `timeout`.
+    ///
+    /// ## Panics
+    /// If the peer state mutex is poisoned.
+    pub async fn probe_peers(&self, timeout: Duration) {
+        let states = join_all(self.identity.others().map(|peer| async move {
+            let state = match tokio::time::timeout(timeout, self.clients[peer].health()).await {
+                Ok(Ok(())) => PeerState::Alive,
+                Ok(Err(e)) => {
+                    tracing::debug!("health check of {peer:?} failed: {e}");
+                    PeerState::Unreachable
+                }
+                Err(_) => PeerState::Unreachable,
+            };
+            (peer, state)
+        }))
+        .await;
+
+        let mut peer_states = self.peer_states.lock().unwrap();
+        for (peer, state) in states {
+            if peer_states[peer] != state {
+                tracing::warn!("{peer:?} is now {state:?}");
+            }
+            peer_states[peer] = state;
+        }
+    }
+
+    /// Starts probing other helpers every `interval` in the background, so that new queries can
+    /// be rejected upfront when one of them is down. Probing stops once this transport is dropped.
+    pub fn start_liveness_probe(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let this = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let Some(transport) = this.upgrade() else {
+                    break;
+                };
+                transport.probe_peers(interval).await;
+            }
         })
     }
 
@@ -79,6 +142,10 @@ impl HttpTransport {
         (Arc::clone(&self).callbacks.query_status)(self, query_id)
     }
 
+    pub fn active_queries(self: Arc<Self>) -> ActiveQueriesResult {
+        (Arc::clone(&self).callbacks.active_queries)(self)
+    }
+
     pub fn complete_query(self: Arc<Self>, query_id: QueryId) -> CompleteQueryResult {
         /// Cleans up the `records_stream` collection after drop to ensure this transport
         /// can process the next query even in case of a panic.
@@ -98,6 +165,7 @@ impl HttpTransport {
         impl Drop for ClearOnDrop {
             fn drop(&mut self) {
                 self.transport.record_streams.clear();
+                self.transport.redeliveries.lock().unwrap().clear();
             }
         }
 
@@ -107,9 +175,20 @@ impl HttpTransport {
         })
     }
 
-    /// Connect an inbound stream of MPC record data.
+    pub fn result_page(self: Arc<Self>, page: ResultPage) -> QueryResultPageResult {
+        (Arc::clone(&self).callbacks.result_page)(self, page)
+    }
+
+    /// Connect an inbound stream of MPC record data. Peers number the messages of the stream, see
+    /// [`MpcHelperClient::step`], and messages that arrive twice are dropped.
+    ///
+    /// A peer may deliver the same stream again, e.g. after the request broke off. The repeated
+    /// delivery resumes the stream received first, after the messages that were received already.
     ///
     /// This is called by peer helpers via the HTTP server.
+    ///
+    /// ## Panics
+    /// If mutex is poisoned.
     pub fn receive_stream(
         self: Arc<Self>,
         query_id: QueryId,
@@ -117,14 +196,21 @@ impl HttpTransport {
         from: HelperIdentity,
         stream: BodyStream,
     ) {
-        self.record_streams
-            .add_stream((query_id, from, gate), LogErrors::new(stream));
+        let key = (query_id, from, gate);
+        match self.redeliveries.lock().unwrap().entry(key.clone()) {
+            Entry::Occupied(entry) => entry.get().add(stream),
+            Entry::Vacant(entry) => {
+                let (stream, redeliveries) = ResumableStream::new(stream);
+                entry.insert(redeliveries);
+                self.record_streams.add_stream(key, LogErrors::new(stream));
+            }
+        }
     }
 }
 
 #[async_trait]
 impl Transport for Arc<HttpTransport> {
-    type RecordsStream = ReceiveRecords<LogHttpErrors>;
+    type RecordsStream = LogErrors<ReceiveRecords<LogHttpErrors>, Vec<u8>, AbortReason>;
     type Error = Error;
 
     fn identity(&self) -> HelperIdentity {
@@ -178,10 +264,32 @@ impl Transport for Arc<HttpTransport> {
         from: HelperIdentity,
         route: R,
     ) -> Self::RecordsStream {
-        ReceiveRecords::new(
+        LogErrors::new(ReceiveRecords::new(
             (route.query_id(), from, route.gate()),
             self.record_streams.clone(),
-        )
+        ))
+    }
+
+    fn abort(&self, query_id: QueryId, reason: AbortReason) {
+        self.record_streams.abort(query_id, reason);
+    }
+
+    fn abort_reason(&self, query_id: QueryId) -> Option<AbortReason> {
+        self.record_streams.abort_reason(query_id)
+    }
+
+    fn peer_state(&self, id: HelperIdentity) -> PeerState {
+        self.peer_states.lock().unwrap()[id]
+    }
+
+    async fn connect_peers(&self) {
+        // Health checks go through the same clients as queries, so the connections they open
+        // are kept around for the next query.
+        self.probe_peers(PEER_CONNECT_TIMEOUT).await;
+    }
+
+    fn batch_records(&self) -> Option<NonZeroUsize> {
+        self.batch_records
     }
 }
 
@@ -192,6 +300,7 @@ mod tests {
     use futures::stream::{poll_immediate, StreamExt};
     use futures_util::future::{join_all, try_join_all};
     use generic_array::GenericArray;
+    use hyper::StatusCode;
     use once_cell::sync::Lazy;
     use tokio::sync::mpsc::channel;
     use tokio_stream::wrappers::ReceiverStream;
@@ -199,13 +308,15 @@ mod tests {
 
     use super::*;
     use crate::{
+        app::Error as AppError,
         config::{NetworkConfig, ServerConfig},
         ff::{FieldType, Fp31, Serializable},
-        helpers::query::QueryType::TestMultiply,
+        helpers::{query::QueryType::TestMultiply, sequence},
         net::{
             client::ClientIdentity,
             test::{get_test_identity, TestConfig, TestConfigBuilder, TestServer},
         },
+        query::WarmupError,
         secret_sharing::{replicated::semi_honest::AdditiveShare, IntoShares},
         test_fixture::Reconstruct,
         AppSetup, HelperApp,
@@ -218,6 +329,11 @@ mod tests {
         let (tx, rx) = channel::<Result<Bytes, Box<dyn std::error::Error + Send + Sync>>>(1);
         let expected_chunk1 = vec![0u8, 1, 2, 3];
         let expected_chunk2 = vec![255u8, 254, 253, 252];
+        // peers number their messages
+        let mut messages = sequence(futures::stream::iter([
+            expected_chunk1.clone(),
+            expected_chunk2.clone(),
+        ]));
 
         let TestServer { transport, .. } = TestServer::default().await;
 
@@ -226,11 +342,16 @@ mod tests {
         );
 
         // Register the stream with the transport (normally called by step data HTTP API handler)
-        Arc::clone(&transport).receive_stream(QueryId, STEP.clone(), HelperIdentity::TWO, body);
+        Arc::clone(&transport).receive_stream(
+            QueryId::default(),
+            STEP.clone(),
+            HelperIdentity::TWO,
+            body,
+        );
 
         // Request step data reception (normally called by protocol)
         let mut stream =
-            Arc::clone(&transport).receive(HelperIdentity::TWO, (QueryId, STEP.clone()));
+            Arc::clone(&transport).receive(HelperIdentity::TWO, (QueryId::default(), STEP.clone()));
 
         // make sure it is not ready as it hasn't received any data yet.
         assert!(matches!(
@@ -239,7 +360,9 @@ mod tests {
         ));
 
         // send and verify first chunk
-        tx.send(Ok(expected_chunk1.clone().into())).await.unwrap();
+        tx.send(Ok(messages.next().await.unwrap().into()))
+            .await
+            .unwrap();
 
         assert_eq!(
             poll_immediate(&mut stream).next().await,
@@ -247,7 +370,9 @@ mod tests {
         );
 
         // send and verify second chunk
-        tx.send(Ok(expected_chunk2.clone().into())).await.unwrap();
+        tx.send(Ok(messages.next().await.unwrap().into()))
+            .await
+            .unwrap();
 
         assert_eq!(
             poll_immediate(&mut stream).next().await,
@@ -255,6 +380,42 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn receive_repeated_stream() {
+        let chunks = [vec![0u8, 1], vec![2u8, 3], vec![4u8, 5]];
+        let messages = sequence(futures::stream::iter(chunks.clone()))
+            .map(Bytes::from)
+            .collect::<Vec<_>>()
+            .await;
+
+        let TestServer { transport, .. } = TestServer::default().await;
+        let deliver = |body: Vec<Result<Bytes, BoxError>>| {
+            Arc::clone(&transport).receive_stream(
+                QueryId::default(),
+                STEP.clone(),
+                HelperIdentity::TWO,
+                BodyStream::from_bytes_stream(futures::stream::iter(body)),
+            );
+        };
+
+        // the first delivery breaks off after the second message
+        deliver(vec![
+            Ok(messages[0].clone()),
+            Ok(messages[1].clone()),
+            Err("connection reset".into()),
+        ]);
+        // the peer retries from the start, which must not panic or repeat records
+        deliver(messages.iter().cloned().map(Ok).collect());
+        // and once more after everything was received
+        deliver(messages.iter().cloned().map(Ok).collect());
+
+        let received = Arc::clone(&transport)
+            .receive(HelperIdentity::TWO, (QueryId::default(), STEP.clone()))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(chunks.to_vec(), received);
+    }
+
     // TODO(651): write a test for an error while reading the body (after error handling is finalized)
 
     async fn make_helpers(
@@ -363,6 +524,114 @@ mod tests {
         assert_eq!(Fp31::try_from(20u128).unwrap(), res[0]);
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn new_query_rejected_when_peer_down() {
+        let mut conf = TestConfigBuilder::with_open_ports()
+            .with_disable_https_option(true)
+            .build();
+        let [s1, s2, s3] = conf.sockets.take().unwrap();
+        let [c1, c2, _] = conf.servers;
+        // H3 never starts, so connections to its port are refused.
+        drop(s3);
+
+        let mut apps = Vec::new();
+        let mut transports = Vec::new();
+        for (id, socket, server_config) in
+            [(HelperIdentity::ONE, s1, c1), (HelperIdentity::TWO, s2, c2)]
+        {
+            let (setup, callbacks) = AppSetup::new();
+            let clients = MpcHelperClient::from_conf(&conf.network, ClientIdentity::Helper(id));
+            let (transport, server) =
+                HttpTransport::new(id, server_config, conf.network.clone(), clients, callbacks);
+            server.start_on(Some(socket), ()).await;
+            apps.push(setup.connect(Arc::clone(&transport)));
+            transports.push(transport);
+        }
+
+        let leader = &transports[0];
+        leader.probe_peers(Duration::from_secs(5)).await;
+        assert_eq!(PeerState::Alive, leader.peer_state(HelperIdentity::TWO));
+        assert_eq!(
+            PeerState::Unreachable,
+            leader.peer_state(HelperIdentity::THREE)
+        );
+
+        let [leader_client, ..] = MpcHelperClient::from_conf(&conf.network, ClientIdentity::None);
+        let res = tokio::time::timeout(
+            Duration::from_secs(5),
+            leader_client.create_query(QueryConfig::new(TestMultiply, FieldType::Fp31, 1).unwrap()),
+        )
+        .await
+        .expect("query should be rejected without waiting for the unreachable helper");
+        assert!(matches!(
+            res,
+            Err(Error::FailedHttpRequest { status, .. }) if status == StatusCode::SERVICE_UNAVAILABLE
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn warmup_connects_to_peers() {
+        let mut conf = TestConfigBuilder::with_open_ports()
+            .with_disable_https_option(true)
+            .build();
+
+        let mut apps = Vec::new();
+        let mut transports = Vec::new();
+        let mut servers = Vec::new();
+        for (id, server_config) in zip(HelperIdentity::make_three(), conf.servers) {
+            let (setup, callbacks) = AppSetup::new();
+            let clients = MpcHelperClient::from_conf(&conf.network, ClientIdentity::Helper(id));
+            let (transport, server) =
+                HttpTransport::new(id, server_config, conf.network.clone(), clients, callbacks);
+            apps.push(setup.connect(Arc::clone(&transport)));
+            transports.push(transport);
+            servers.push(server);
+        }
+
+        // No server is running yet, so H1 sees the other helpers as unreachable.
+        transports[0].probe_peers(Duration::from_millis(100)).await;
+        for peer in HelperIdentity::ONE.others() {
+            assert_eq!(PeerState::Unreachable, transports[0].peer_state(peer));
+        }
+
+        for (server, socket) in zip(servers, conf.sockets.take().unwrap()) {
+            server.start_on(Some(socket), ()).await;
+        }
+        for (app, transport) in zip(&apps, &transports) {
+            app.warmup().await.unwrap();
+            for peer in transport.identity().others() {
+                assert_eq!(PeerState::Alive, transport.peer_state(peer));
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn warmup_reports_unreachable_peers() {
+        let mut conf = TestConfigBuilder::with_open_ports()
+            .with_disable_https_option(true)
+            .build();
+        // Only H1 starts, so connections to the other ports are refused.
+        let [s1, _, _] = conf.sockets.take().unwrap();
+        let [c1, _, _] = conf.servers;
+
+        let id = HelperIdentity::ONE;
+        let (setup, callbacks) = AppSetup::new();
+        let clients = MpcHelperClient::from_conf(&conf.network, ClientIdentity::Helper(id));
+        let (transport, server) =
+            HttpTransport::new(id, c1, conf.network.clone(), clients, callbacks);
+        server.start_on(Some(s1), ()).await;
+        let app = setup.connect(Arc::clone(&transport));
+
+        let err = app.warmup().await.unwrap_err();
+        assert!(
+            matches!(err, AppError::Warmup(WarmupError::PeerUnreachable(_))),
+            "{err:?}"
+        );
+        for peer in id.others() {
+            assert_eq!(PeerState::Unreachable, transport.peer_state(peer));
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn three_helpers_http() {
         let conf = TestConfigBuilder::with_open_ports()
@@ -1,6 +1,4 @@
-use std::ops::Add;
-
-use generic_array::{ArrayLength, GenericArray};
+use generic_array::GenericArray;
 use typenum::Unsigned;
 
 use crate::{
@@ -8,49 +6,13 @@ use crate::{
     secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Serializable)]
 #[cfg_attr(test, derive(Clone, PartialEq, Eq))]
 pub struct SparseAggregateInputRow<CV: GaloisField, BK: GaloisField> {
     pub contribution_value: Replicated<CV>,
     pub breakdown_key: Replicated<BK>,
 }
 
-impl<CV: GaloisField, BK: GaloisField> Serializable for SparseAggregateInputRow<CV, BK>
-where
-    Replicated<CV>: Serializable,
-    Replicated<BK>: Serializable,
-    <Replicated<CV> as Serializable>::Size: Add<<Replicated<BK> as Serializable>::Size>,
-    <<Replicated<CV> as Serializable>::Size as Add<<Replicated<BK> as Serializable>::Size>>::Output:
-        ArrayLength<u8>,
-{
-    type Size = <<Replicated<CV> as Serializable>::Size as Add<
-        <Replicated<BK> as Serializable>::Size,
-    >>::Output;
-
-    fn serialize(&self, buf: &mut GenericArray<u8, Self::Size>) {
-        let cv_sz = <Replicated<CV> as Serializable>::Size::USIZE;
-        let bk_sz = <Replicated<BK> as Serializable>::Size::USIZE;
-
-        self.contribution_value
-            .serialize(GenericArray::from_mut_slice(&mut buf[..cv_sz]));
-        self.breakdown_key
-            .serialize(GenericArray::from_mut_slice(&mut buf[cv_sz..cv_sz + bk_sz]));
-    }
-
-    fn deserialize(buf: &GenericArray<u8, Self::Size>) -> Self {
-        let cv_sz = <Replicated<CV> as Serializable>::Size::USIZE;
-        let bk_sz = <Replicated<BK> as Serializable>::Size::USIZE;
-
-        let value = Replicated::<CV>::deserialize(GenericArray::from_slice(&buf[..cv_sz]));
-        let breakdown_key =
-            Replicated::<BK>::deserialize(GenericArray::from_slice(&buf[cv_sz..cv_sz + bk_sz]));
-        Self {
-            contribution_value: value,
-            breakdown_key,
-        }
-    }
-}
-
 impl<CV: GaloisField, BK: GaloisField> SparseAggregateInputRow<CV, BK>
 where
     SparseAggregateInputRow<CV, BK>: Serializable,
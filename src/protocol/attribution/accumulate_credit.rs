@@ -8,7 +8,7 @@ use super::{
 };
 use crate::{
     error::Error,
-    ff::Field,
+    ff::{Field, PrimeField},
     protocol::{context::Context, BasicProtocols, RecordId},
     secret_sharing::Linear as LinearSecretSharing,
 };
@@ -79,19 +79,27 @@ where
 /// each iteration by a factor of two, we ensure that each node only accumulates the value of each successor only once.
 /// <https://github.com/patcg-individual-drafts/ipa/blob/main/IPA-End-to-End.md#oblivious-last-touch-attribution>
 ///
+/// Credits are accumulated in `F`, so if the total credit of a user exceeds
+/// [`PrimeField::max_safe_sum`], it wraps around without any indication. Trigger values are secret,
+/// so that cannot be detected afterwards. If the caller knows that no trigger value is larger than
+/// `max_trigger_value`, it is checked up front that even a single user holding all rows cannot
+/// reach that point. Tests can use `check_accumulation_overflow` to check the actual values.
+///
 /// # Errors
 ///
-/// Fails if the multiplication fails.
+/// [`Error::FieldOverflow`] if the trigger values may add up to more than
+/// [`PrimeField::max_safe_sum`]. Fails if the multiplication fails.
 #[tracing::instrument(name = "accumulate_credit", skip_all)]
 pub async fn accumulate_credit<F, C, T>(
     ctx: C,
     input: &[AccumulateCreditInputRow<F, T>],
     stop_bits: &[T],
     per_user_credit_cap: u32,
+    max_trigger_value: Option<u128>,
     attribution_window_seconds: Option<NonZeroU32>,
 ) -> Result<Vec<AccumulateCreditOutputRow<F, T>>, Error>
 where
-    F: Field,
+    F: PrimeField,
     C: Context,
     T: LinearSecretSharing<F> + BasicProtocols<C, F>,
 {
@@ -103,6 +111,17 @@ where
         );
     }
 
+    // Only trigger values are summed, the cap one case above counts trigger reports.
+    if let Some(max_trigger_value) = max_trigger_value {
+        if max_trigger_value > F::max_safe_summand(input.len()) {
+            return Err(Error::FieldOverflow(format!(
+                "{} trigger values of up to {max_trigger_value} may add up to more than {}",
+                input.len(),
+                F::max_safe_sum()
+            )));
+        }
+    }
+
     let mut credits = input
         .iter()
         .map(|x| x.trigger_value.clone())
@@ -140,27 +159,119 @@ where
     Ok(output)
 }
 
+/// Reveals the credits and stop bits that are fed into [`accumulate_credit`] and checks that none
+/// of the accumulated credits wraps around the field modulus.
+///
+/// This reveals the inputs, so it must never be used outside of tests.
+///
+/// ## Errors
+/// [`Error::FieldOverflow`] if any accumulated credit exceeds [`PrimeField::max_safe_sum`], or
+/// if revealing any of the values fails.
+#[cfg(test)]
+pub async fn check_accumulation_overflow<F, C, T>(
+    ctx: C,
+    input: &[AccumulateCreditInputRow<F, T>],
+    stop_bits: &[T],
+) -> Result<(), Error>
+where
+    F: PrimeField,
+    C: Context,
+    T: LinearSecretSharing<F> + BasicProtocols<C, F>,
+{
+    use futures::future::try_join;
+
+    let credit_ctx = ctx
+        .narrow(&OverflowCheckStep::Credit)
+        .set_total_records(input.len());
+    let stop_bit_ctx = ctx
+        .narrow(&OverflowCheckStep::StopBit)
+        .set_total_records(stop_bits.len());
+
+    let credits = input
+        .iter()
+        .map(|row| row.trigger_value.clone())
+        .collect::<Vec<_>>();
+    let (credits, stop_bits) = try_join(
+        ctx.try_join(credits.into_iter().enumerate().map(|(i, credit)| {
+            let ctx = credit_ctx.clone();
+            async move { credit.reveal(ctx, RecordId::from(i)).await }
+        })),
+        ctx.try_join(stop_bits.iter().cloned().enumerate().map(|(i, bit)| {
+            let ctx = stop_bit_ctx.clone();
+            async move { bit.reveal(ctx, RecordId::from(i)).await }
+        })),
+    )
+    .await?;
+
+    // Stop bits skip the first row, so row `i` accumulates credits from row `i + 1` if
+    // `stop_bits[i]` is set.
+    let mut accumulated = 0_u128;
+    for (i, credit) in credits.iter().enumerate().rev() {
+        let continues = stop_bits.get(i) == Some(&F::ONE);
+        accumulated = credit.as_u128() + if continues { accumulated } else { 0 };
+        if accumulated > F::max_safe_sum() {
+            return Err(Error::FieldOverflow(format!(
+                "accumulated credit of row {i} is {accumulated}, which exceeds {}",
+                F::max_safe_sum()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Step)]
 pub(crate) enum Step {
     ActiveBitTimesStopBit,
 }
 
+// Steps used by [`check_accumulation_overflow`]. They are test-only, so they are not derived
+// and never appear in the compact gate.
+#[cfg(test)]
+pub(crate) enum OverflowCheckStep {
+    Credit,
+    StopBit,
+}
+#[cfg(test)]
+impl crate::protocol::step::Step for OverflowCheckStep {}
+#[cfg(test)]
+impl AsRef<str> for OverflowCheckStep {
+    fn as_ref(&self) -> &'static str {
+        match self {
+            OverflowCheckStep::Credit => "check_credit",
+            OverflowCheckStep::StopBit => "check_stop_bit",
+        }
+    }
+}
+#[cfg(all(feature = "compact-gate", test))]
+impl crate::protocol::step::StepNarrow<OverflowCheckStep> for crate::protocol::step::Compact {
+    fn narrow(&self, _step: &OverflowCheckStep) -> Self {
+        unimplemented!("compact gate is not supported in unit tests")
+    }
+}
+
 #[cfg(all(test, unit_test))]
 mod tests {
     use std::num::NonZeroU32;
 
+    use rand::distributions::{Distribution, Standard};
+
     use crate::{
         accumulation_test_input,
-        ff::Fp32BitPrime,
+        error::Error,
+        ff::{Fp31, Fp32BitPrime, PrimeField},
         protocol::{
             attribution::{
-                accumulate_credit::accumulate_credit, compute_stop_bits,
+                accumulate_credit::{accumulate_credit, check_accumulation_overflow},
+                compute_stop_bits,
                 input::AccumulateCreditInputRow,
             },
             context::{UpgradableContext, Validator},
             BreakdownKey, MatchKey,
         },
-        secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
+        secret_sharing::replicated::{
+            malicious::ExtendableField, semi_honest::AdditiveShare as Replicated,
+        },
         test_fixture::{input::GenericReportTestInput, Reconstruct, Runner, TestWorld},
     };
 
@@ -185,9 +296,16 @@ mod tests {
                     .unwrap()
                     .collect::<Vec<_>>();
 
-                accumulate_credit(ctx, &input, &stop_bits, cap, attribution_window_seconds)
-                    .await
-                    .unwrap()
+                accumulate_credit(
+                    ctx,
+                    &input,
+                    &stop_bits,
+                    cap,
+                    None,
+                    attribution_window_seconds,
+                )
+                .await
+                .unwrap()
             })
             .await
             // We only need the trigger values.
@@ -316,4 +434,132 @@ mod tests {
         let result = accumulate_credit_test(input, PER_USER_CAP, ATTRIBUTION_WINDOW_SECONDS).await;
         assert_eq!(result, EXPECTED);
     }
+
+    async fn overflow_check_test<F>(
+        input: Vec<GenericReportTestInput<F, MatchKey, BreakdownKey>>,
+    ) -> [Result<(), Error>; 3]
+    where
+        F: PrimeField + ExtendableField,
+        Standard: Distribution<F>,
+    {
+        TestWorld::default()
+            .semi_honest(
+                input.into_iter(),
+                |ctx, input: Vec<AccumulateCreditInputRow<F, Replicated<F>>>| async move {
+                    let validator = &ctx.validator::<F>();
+                    let ctx = validator.context();
+
+                    let (itb, hb): (Vec<_>, Vec<_>) = input
+                        .iter()
+                        .map(|x| (x.is_trigger_report.clone(), x.helper_bit.clone()))
+                        .unzip();
+                    let stop_bits = compute_stop_bits(ctx.clone(), &itb, &hb[1..])
+                        .await
+                        .unwrap()
+                        .collect::<Vec<_>>();
+
+                    check_accumulation_overflow(ctx, &input, &stop_bits).await
+                },
+            )
+            .await
+    }
+
+    /// The second user accumulates 20 + 15 = 35 credits, which wraps around in `Fp31`.
+    #[tokio::test]
+    pub async fn accumulate_overflow() {
+        let input: Vec<GenericReportTestInput<Fp31, MatchKey, BreakdownKey>> = accumulation_test_input!(
+            [
+                { is_trigger_report: 0, helper_bit: 0, active_bit: 1, credit: 0 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 10 },
+                { is_trigger_report: 0, helper_bit: 0, active_bit: 1, credit: 0 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 20 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 15 },
+            ];
+            (Fp31, MatchKey, BreakdownKey)
+        );
+        for result in overflow_check_test(input).await {
+            assert!(matches!(result, Err(Error::FieldOverflow(_))), "{result:?}");
+        }
+
+        let input: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = accumulation_test_input!(
+            [
+                { is_trigger_report: 0, helper_bit: 0, active_bit: 1, credit: 0 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 10 },
+                { is_trigger_report: 0, helper_bit: 0, active_bit: 1, credit: 0 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 20 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 15 },
+            ];
+            (Fp32BitPrime, MatchKey, BreakdownKey)
+        );
+        for result in overflow_check_test(input).await {
+            result.unwrap();
+        }
+    }
+
+    async fn max_trigger_value_test<F>(
+        input: Vec<GenericReportTestInput<F, MatchKey, BreakdownKey>>,
+        max_trigger_value: u128,
+    ) -> [Result<(), Error>; 3]
+    where
+        F: PrimeField + ExtendableField,
+        Standard: Distribution<F>,
+    {
+        TestWorld::default()
+            .semi_honest(
+                input.into_iter(),
+                |ctx, input: Vec<AccumulateCreditInputRow<F, Replicated<F>>>| async move {
+                    let validator = &ctx.validator::<F>();
+                    let ctx = validator.context();
+
+                    let (itb, hb): (Vec<_>, Vec<_>) = input
+                        .iter()
+                        .map(|x| (x.is_trigger_report.clone(), x.helper_bit.clone()))
+                        .unzip();
+                    let stop_bits = compute_stop_bits(ctx.clone(), &itb, &hb[1..])
+                        .await
+                        .unwrap()
+                        .collect::<Vec<_>>();
+
+                    accumulate_credit(ctx, &input, &stop_bits, 3, Some(max_trigger_value), None)
+                        .await
+                        .map(|_| ())
+                },
+            )
+            .await
+    }
+
+    /// Five trigger values of up to 10 may add up to 50, which does not fit in `Fp31`, even though
+    /// the actual values do.
+    #[tokio::test]
+    pub async fn max_trigger_value_too_large_for_field() {
+        const MAX_TRIGGER_VALUE: u128 = 10;
+
+        let input: Vec<GenericReportTestInput<Fp31, MatchKey, BreakdownKey>> = accumulation_test_input!(
+            [
+                { is_trigger_report: 0, helper_bit: 0, active_bit: 1, credit: 0 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 1 },
+                { is_trigger_report: 0, helper_bit: 0, active_bit: 1, credit: 0 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 2 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 3 },
+            ];
+            (Fp31, MatchKey, BreakdownKey)
+        );
+        for result in max_trigger_value_test(input, MAX_TRIGGER_VALUE).await {
+            assert!(matches!(result, Err(Error::FieldOverflow(_))), "{result:?}");
+        }
+
+        let input: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = accumulation_test_input!(
+            [
+                { is_trigger_report: 0, helper_bit: 0, active_bit: 1, credit: 0 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 1 },
+                { is_trigger_report: 0, helper_bit: 0, active_bit: 1, credit: 0 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 2 },
+                { is_trigger_report: 1, helper_bit: 1, active_bit: 1, credit: 3 },
+            ];
+            (Fp32BitPrime, MatchKey, BreakdownKey)
+        );
+        for result in max_trigger_value_test(input, MAX_TRIGGER_VALUE).await {
+            result.unwrap();
+        }
+    }
 }
@@ -1,13 +1,26 @@
 extern crate ipa_macros;
 
-use futures::stream::{iter as stream_iter, StreamExt, TryStreamExt};
+use std::{iter::zip, ops::Range};
+
+use futures::{
+    future::{ready, try_join},
+    stream::{iter as stream_iter, once as stream_once, StreamExt, TryStreamExt},
+    Stream,
+};
 use ipa_macros::Step;
 
+use super::prefix_or_binary_tree_style;
 use crate::{
     error::Error,
-    ff::{Gf2, PrimeField, Serializable},
+    ff::{Field, Gf2, PrimeField, Serializable},
+    helpers::TotalRecords,
     protocol::{
-        context::{UpgradableContext, UpgradedContext, Validator},
+        basics::{Reveal, ZeroPositions},
+        boolean::{
+            bitwise_equal::bitwise_equal_constant, greater_than_constant,
+            random_bits_generator::RandomBitsGenerator,
+        },
+        context::{Context, UpgradableContext, UpgradedContext, Validator},
         modulus_conversion::convert_bits,
         sort::{bitwise_to_onehot, generate_permutation::ShuffledPermutationWrapper},
         step::BitOpStep,
@@ -18,9 +31,10 @@ use crate::{
             malicious::{DowngradeMalicious, ExtendableField},
             semi_honest::AdditiveShare as Replicated,
         },
-        BitDecomposed, Linear as LinearSecretSharing,
+        BitDecomposed, Linear as LinearSecretSharing, LinearRefOps,
     },
-    seq_join::seq_join,
+    seq_join::{seq_join, SeqJoin},
+    sync::Arc,
 };
 
 /// This is the number of breakdown keys above which it is more efficient to SORT by breakdown key.
@@ -28,6 +42,14 @@ use crate::{
 /// This number was determined empirically on 27 Feb 2023
 const SIMPLE_AGGREGATION_BREAK_EVEN_POINT: u32 = 32;
 
+/// Returns the number of bits required to represent every breakdown key in `0..max_breakdown_key`.
+/// Breakdown key bits above this width are always zero, so protocols only need to decompose,
+/// upgrade and convert this many bits.
+#[must_use]
+pub fn breakdown_key_bits(max_breakdown_key: u32) -> u32 {
+    u32::BITS - max_breakdown_key.saturating_sub(1).leading_zeros()
+}
+
 /// Aggregation step for Oblivious Attribution protocol.
 /// # Panics
 /// It probably won't
@@ -53,26 +75,309 @@ where
     IC: IntoIterator<Item = S> + ExactSizeIterator + Send,
     IC::IntoIter: Send,
     S: LinearSecretSharing<F> + BasicProtocols<C::UpgradedContext<F>, F> + Serializable + 'static,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+{
+    aggregate_credit_in_range(
+        validator,
+        breakdown_keys,
+        capped_credits,
+        0..max_breakdown_key,
+    )
+    .await
+}
+
+/// Same as [`aggregate_credit`], but only computes the histogram for breakdown keys inside
+/// `key_range`. Element `i` of the output holds the credit for breakdown key `key_range.start + i`.
+///
+/// Credit attributed to keys outside of the range is discarded obliviously: every row is compared
+/// with the secret-shared breakdown key, but only for keys in the range, and only those are
+/// multiplied by credit. For a narrow range of a wide key space, this is much cheaper than the full
+/// aggregation. The limit on the number of breakdown keys applies to the keys in the range.
+///
+/// # Panics
+/// It probably won't
+///
+/// # Errors
+/// If the range contains more breakdown keys than are supported, or if a multiplication fails.
+#[tracing::instrument(name = "aggregate_credit_in_range", skip_all, fields(key_range = ?key_range))]
+// instrumenting this function makes the return type look bad to Clippy
+#[allow(clippy::type_complexity)]
+pub async fn aggregate_credit_in_range<V, C, F, IC, IB, S>(
+    validator: V,
+    breakdown_keys: IB,
+    capped_credits: IC,
+    key_range: Range<u32>,
+) -> Result<(V, Vec<S>), Error>
+where
+    V: Validator<C, F>,
+    C: UpgradableContext<Validator<F> = V>,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    F: PrimeField + ExtendableField,
+    IB: IntoIterator<Item = BitDecomposed<Replicated<Gf2>>> + ExactSizeIterator + Send,
+    IB::IntoIter: Send,
+    IC: IntoIterator<Item = S> + ExactSizeIterator + Send,
+    IC::IntoIter: Send,
+    S: LinearSecretSharing<F> + BasicProtocols<C::UpgradedContext<F>, F> + Serializable + 'static,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
     ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
 {
     let m_ctx = validator.context();
+    let key_count = key_range.end.saturating_sub(key_range.start);
 
-    if max_breakdown_key <= SIMPLE_AGGREGATION_BREAK_EVEN_POINT {
-        let res = simple_aggregate_credit(m_ctx, breakdown_keys, capped_credits, max_breakdown_key)
-            .await?;
+    if key_count <= SIMPLE_AGGREGATION_BREAK_EVEN_POINT {
+        let res = simple_aggregate_credit(m_ctx, breakdown_keys, capped_credits, key_range).await?;
         Ok((validator, res))
     } else {
         Err(Error::Unsupported(
-            format!("query uses {max_breakdown_key} breakdown keys; only {SIMPLE_AGGREGATION_BREAK_EVEN_POINT} are supported")
+            format!("query uses {key_count} breakdown keys; only {SIMPLE_AGGREGATION_BREAK_EVEN_POINT} are supported")
         ))
     }
 }
 
+/// Same as [`aggregate_credit`], but reveals only whether the credit for each breakdown key
+/// exceeds a secret-shared `threshold` instead of revealing the credit itself. Element `i` of the
+/// output is `true` if breakdown key `i` received more than `threshold` credit.
+///
+/// The comparison is done on `threshold - credit`, which is negative exactly when the credit
+/// exceeds the threshold. This requires both values to be less than `F::PRIME / 2`.
+///
+/// # Panics
+/// If the number of breakdown keys does not fit into `usize`.
+///
+/// # Errors
+/// propagates errors from multiplications, comparisons and malicious validation
+#[tracing::instrument(name = "aggregate_credit_thresholded", skip_all)]
+pub async fn aggregate_credit_thresholded<C, F, IB, S>(
+    ctx: C,
+    breakdown_keys: IB,
+    capped_credits: Vec<Replicated<F>>,
+    max_breakdown_key: u32,
+    threshold: Replicated<F>,
+) -> Result<Vec<bool>, Error>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    F: PrimeField + ExtendableField,
+    IB: IntoIterator<Item = BitDecomposed<Replicated<Gf2>>> + ExactSizeIterator + Send,
+    IB::IntoIter: Send,
+    S: LinearSecretSharing<F>
+        + BasicProtocols<C::UpgradedContext<F>, F>
+        + Serializable
+        + DowngradeMalicious<Target = Replicated<F>>
+        + 'static,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+{
+    let reveal_ctx = ctx.narrow(&Step::RevealThresholdComparisons);
+    let validator = ctx.validator::<F>();
+    let m_ctx = validator.context();
+
+    let capped_credits = upgrade_all(&m_ctx, &Step::UpgradeCredits, capped_credits).await?;
+    let threshold = m_ctx
+        .narrow(&Step::UpgradeThreshold)
+        .set_total_records(1)
+        .upgrade_one(RecordId::FIRST, threshold, ZeroPositions::Pvvv)
+        .await?;
+
+    let (validator, aggregated) = aggregate_credit(
+        validator,
+        breakdown_keys,
+        capped_credits.into_iter(),
+        max_breakdown_key,
+    )
+    .await?;
+
+    // `threshold - credit` is in the upper half of the field iff it is negative.
+    let prime: u128 = F::PRIME.into();
+    let half_prime = (prime - 1) / 2;
+    let cmp_ctx = m_ctx
+        .narrow(&Step::CompareToThreshold)
+        .set_total_records(aggregated.len());
+    let random_bits_generator =
+        RandomBitsGenerator::new(cmp_ctx.narrow(&Step::RandomBitsForComparison));
+    let rbg = &random_bits_generator;
+    let above_threshold = cmp_ctx
+        .try_join(aggregated.iter().enumerate().map(|(i, credit)| {
+            let c = cmp_ctx.clone();
+            let diff = threshold.clone() - credit;
+            async move { greater_than_constant(c, RecordId::from(i), rbg, &diff, half_prime).await }
+        }))
+        .await?;
+
+    let above_threshold = validator.validate(above_threshold).await?;
+    let reveal_ctx = reveal_ctx.set_total_records(above_threshold.len());
+    reveal_ctx
+        .try_join(above_threshold.iter().enumerate().map(|(i, bit)| {
+            let c = reveal_ctx.clone();
+            async move { Ok(bit.reveal(c, RecordId::from(i)).await? == F::ONE) }
+        }))
+        .await
+}
+
+/// Same as [`aggregate_credit`], but reveals only the `k` breakdown keys that received the most
+/// credit, together with their credit. Credit for all other breakdown keys stays secret. The output
+/// is sorted by credit, largest first.
+///
+/// Every pair of breakdown keys is compared obliviously, and the rank of a key is the number of keys
+/// that received more credit than it, breaking ties in favour of the smaller breakdown key. Helpers
+/// only learn whether each rank is less than `k`, which tells them which keys make the cut but not
+/// how the other keys are ordered. Only then is the credit of the selected keys revealed.
+///
+/// Like [`aggregate_credit_thresholded`], comparisons are done on differences of credits, which
+/// requires every credit to be less than `F::PRIME / 2`.
+///
+/// # Panics
+/// If the number of breakdown keys does not fit into `u32`.
+///
+/// # Errors
+/// propagates errors from multiplications, comparisons and malicious validation
+#[tracing::instrument(name = "aggregate_credit_top_k", skip_all, fields(k = k))]
+pub async fn aggregate_credit_top_k<C, F, IB, S>(
+    ctx: C,
+    breakdown_keys: IB,
+    capped_credits: Vec<Replicated<F>>,
+    max_breakdown_key: u32,
+    k: usize,
+) -> Result<Vec<(u32, F)>, Error>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    F: PrimeField + ExtendableField,
+    IB: IntoIterator<Item = BitDecomposed<Replicated<Gf2>>> + ExactSizeIterator + Send,
+    IB::IntoIter: Send,
+    S: LinearSecretSharing<F>
+        + BasicProtocols<C::UpgradedContext<F>, F>
+        + Serializable
+        + DowngradeMalicious<Target = Replicated<F>>
+        + 'static,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+{
+    let reveal_selection_ctx = ctx.narrow(&Step::RevealTopKSelection);
+    let reveal_credit_ctx = ctx.narrow(&Step::RevealTopKCredit);
+    let validator = ctx.validator::<F>();
+    let m_ctx = validator.context();
+
+    let capped_credits = upgrade_all(&m_ctx, &Step::UpgradeCredits, capped_credits).await?;
+    let (validator, aggregated) = aggregate_credit(
+        validator,
+        breakdown_keys,
+        capped_credits.into_iter(),
+        max_breakdown_key,
+    )
+    .await?;
+    let n = aggregated.len();
+    let k = k.min(n);
+
+    // A difference is in the upper half of the field iff it is negative.
+    let prime: u128 = F::PRIME.into();
+    let half_prime = (prime - 1) / 2;
+
+    // `credit[i] - credit[j]` is negative iff key `j` received more credit than key `i`
+    let pairs = (0..n)
+        .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+        .collect::<Vec<_>>();
+    let pair_ctx = m_ctx
+        .narrow(&Step::CompareCredits)
+        .set_total_records(pairs.len());
+    let random_bits_generator =
+        RandomBitsGenerator::new(pair_ctx.narrow(&Step::RandomBitsForComparison));
+    let rbg = &random_bits_generator;
+    let j_beats_i = pair_ctx
+        .try_join(pairs.iter().enumerate().map(|(p, &(i, j))| {
+            let c = pair_ctx.clone();
+            let diff = aggregated[i].clone() - &aggregated[j];
+            async move { greater_than_constant(c, RecordId::from(p), rbg, &diff, half_prime).await }
+        }))
+        .await?;
+
+    // On a tie, `i` beats `j` because it is the smaller key.
+    let one = m_ctx.share_known_value(F::ONE);
+    let mut ranks = vec![S::ZERO; n];
+    for (&(i, j), j_beats_i) in zip(&pairs, &j_beats_i) {
+        ranks[i] += j_beats_i;
+        ranks[j] += &(one.clone() - j_beats_i);
+    }
+
+    // `rank - k` is negative iff the key is in the top `k`
+    let k_share = m_ctx.share_known_value(F::truncate_from(u32::try_from(k).unwrap()));
+    let rank_ctx = m_ctx.narrow(&Step::CompareRanks).set_total_records(n);
+    let random_bits_generator =
+        RandomBitsGenerator::new(rank_ctx.narrow(&Step::RandomBitsForComparison));
+    let rbg = &random_bits_generator;
+    let selected = rank_ctx
+        .try_join(ranks.into_iter().enumerate().map(|(i, rank)| {
+            let c = rank_ctx.clone();
+            let diff = rank - &k_share;
+            async move { greater_than_constant(c, RecordId::from(i), rbg, &diff, half_prime).await }
+        }))
+        .await?;
+
+    let mut selected = validator
+        .validate(selected.into_iter().chain(aggregated).collect::<Vec<_>>())
+        .await?;
+    let aggregated = selected.split_off(n);
+
+    let reveal_selection_ctx = reveal_selection_ctx.set_total_records(n);
+    let selected = reveal_selection_ctx
+        .try_join(selected.iter().enumerate().map(|(i, bit)| {
+            let c = reveal_selection_ctx.clone();
+            async move { Ok::<_, Error>(bit.reveal(c, RecordId::from(i)).await? == F::ONE) }
+        }))
+        .await?;
+
+    let top_k = zip(selected, aggregated)
+        .enumerate()
+        .filter_map(|(i, (selected, credit))| selected.then_some((i, credit)))
+        .collect::<Vec<_>>();
+    let reveal_credit_ctx = reveal_credit_ctx.set_total_records(top_k.len());
+    let mut top_k = reveal_credit_ctx
+        .try_join(top_k.iter().enumerate().map(|(record, (i, credit))| {
+            let c = reveal_credit_ctx.clone();
+            async move {
+                let credit = credit.reveal(c, RecordId::from(record)).await?;
+                Ok::<_, Error>((u32::try_from(*i).unwrap(), credit))
+            }
+        }))
+        .await?;
+    top_k.sort_by(|(a_key, a_credit), (b_key, b_credit)| {
+        b_credit
+            .as_u128()
+            .cmp(&a_credit.as_u128())
+            .then(a_key.cmp(b_key))
+    });
+
+    Ok(top_k)
+}
+
+/// Upgrades semi-honest `shares` under the given step of `ctx`, e.g. credits or equality checks.
+async fn upgrade_all<C, F, S>(
+    ctx: &C,
+    step: &Step,
+    shares: Vec<Replicated<F>>,
+) -> Result<Vec<S>, Error>
+where
+    C: UpgradedContext<F, Share = S>,
+    F: PrimeField + ExtendableField,
+{
+    let upgrade_ctx = ctx.narrow(step).set_total_records(shares.len());
+    upgrade_ctx
+        .try_join(shares.into_iter().enumerate().map(|(i, share)| {
+            let c = upgrade_ctx.clone();
+            async move {
+                c.upgrade_one(RecordId::from(i), share, ZeroPositions::Pvvv)
+                    .await
+            }
+        }))
+        .await
+}
+
 async fn simple_aggregate_credit<F, C, IC, IB, S>(
     ctx: C,
     breakdown_keys: IB,
     capped_credits: IC,
-    max_breakdown_key: u32,
+    key_range: Range<u32>,
 ) -> Result<Vec<S>, Error>
 where
     F: PrimeField,
@@ -82,13 +387,14 @@ where
     IC::IntoIter: Send,
     C: UpgradedContext<F, Share = S>,
     S: LinearSecretSharing<F> + BasicProtocols<C, F> + Serializable + 'static,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
 {
     let record_count = breakdown_keys.len();
     // The number of records we compute is currently too high as the last row cannot have
     // any credit associated with it.  TODO: don't compute that row when cap > 1.
 
-    let to_take = usize::try_from(max_breakdown_key).unwrap();
-    let valid_bits_count = u32::BITS - (max_breakdown_key - 1).leading_zeros();
+    let to_take = key_range.len();
+    let valid_bits_count = breakdown_key_bits(key_range.end);
 
     let equality_check_context = ctx
         .narrow(&Step::ComputeEqualityChecks)
@@ -112,9 +418,11 @@ where
             .map(|(i, (bk, cred))| {
                 let ceq = &equality_check_context;
                 let cmul = &check_times_credit_context;
+                let key_range = key_range.clone();
                 async move {
-                    let equality_checks = bitwise_to_onehot(ceq.clone(), i, &bk?).await?;
-                    ceq.try_join(equality_checks.into_iter().take(to_take).enumerate().map(
+                    let equality_checks =
+                        range_equality_checks(ceq.clone(), i, &bk?, key_range).await?;
+                    ceq.try_join(equality_checks.into_iter().enumerate().map(
                         |(check_idx, check)| {
                             let step = BitOpStep::from(check_idx);
                             let c = cmul.narrow(&step);
@@ -128,17 +436,464 @@ where
             }),
     );
     let aggregate = increments
-        .try_fold(
-            vec![S::ZERO; max_breakdown_key as usize],
-            |mut acc, row| async move {
-                for (i, incr) in row.into_iter().enumerate() {
-                    acc[i] += &incr;
+        .try_fold(vec![S::ZERO; to_take], |mut acc, row| async move {
+            for (i, incr) in row.into_iter().enumerate() {
+                acc[i] += &incr;
+            }
+            Ok(acc)
+        })
+        .await?;
+    Ok(aggregate)
+}
+
+/// Compares a bit-decomposed breakdown key with every key in `key_range`. Element `i` of the
+/// output is a share of one if the breakdown key is `key_range.start + i`, and a share of zero
+/// otherwise.
+///
+/// [`bitwise_to_onehot`] compares an `n`-bit key with all `2^n` values at once, which costs about
+/// `2^n` multiplications. If the range covers only a few of them, comparing the key with each of
+/// them separately is cheaper, at `n - 1` multiplications per key.
+async fn range_equality_checks<F, C, S>(
+    ctx: C,
+    record_idx: usize,
+    breakdown_key: &[S],
+    key_range: Range<u32>,
+) -> Result<Vec<S>, Error>
+where
+    F: Field,
+    C: Context,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+{
+    let bits = u32::try_from(breakdown_key.len()).unwrap();
+    let onehot_cost = (1_u64 << bits) - u64::from(bits) - 1;
+    let range_cost = u64::from(key_range.end.saturating_sub(key_range.start))
+        * u64::from(bits.saturating_sub(1));
+
+    if range_cost < onehot_cost {
+        let record_id = RecordId::from(record_idx);
+        ctx.parallel_join(key_range.enumerate().map(|(check_idx, key)| {
+            bitwise_equal_constant(
+                ctx.narrow(&BitOpStep::from(check_idx)),
+                record_id,
+                breakdown_key,
+                u128::from(key),
+            )
+        }))
+        .await
+    } else {
+        let to_skip = usize::try_from(key_range.start).unwrap();
+        Ok(bitwise_to_onehot(ctx, record_idx, breakdown_key)
+            .await?
+            .into_iter()
+            .skip(to_skip)
+            .take(key_range.len())
+            .collect())
+    }
+}
+
+/// Same as [`aggregate_credit`], but also counts the rows that contribute credit to every
+/// breakdown key, e.g. the number of attributed conversions. Element `i` of the output holds the
+/// credit and the count for breakdown key `i`.
+///
+/// A row contributes if its capped credit is not zero, which costs one comparison per row. The
+/// count is then summed up the same way as the credit, using the equality checks that both share.
+///
+/// # Panics
+/// If `breakdown_keys` and `capped_credits` have different lengths.
+///
+/// # Errors
+/// If the query uses more breakdown keys than are supported, or if a multiplication or comparison
+/// fails.
+pub async fn aggregate_credit_with_counts<V, C, F, S>(
+    validator: V,
+    breakdown_keys: Vec<BitDecomposed<Replicated<Gf2>>>,
+    capped_credits: Vec<S>,
+    max_breakdown_key: u32,
+) -> Result<(V, Vec<(S, S)>), Error>
+where
+    V: Validator<C, F>,
+    C: UpgradableContext<Validator<F> = V>,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    F: PrimeField + ExtendableField,
+    S: LinearSecretSharing<F> + BasicProtocols<C::UpgradedContext<F>, F> + Serializable + 'static,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+{
+    let to_take = usize::try_from(max_breakdown_key).unwrap();
+    let aggregate = fold_contributions(
+        validator.context(),
+        breakdown_keys,
+        capped_credits,
+        max_breakdown_key,
+        vec![(S::ZERO, S::ZERO); to_take],
+        |mut acc, row| {
+            for (i, (credit, count)) in row.into_iter().enumerate() {
+                acc[i].0 += &credit;
+                acc[i].1 += &count;
+            }
+            acc
+        },
+    )
+    .await?;
+
+    Ok((validator, aggregate))
+}
+
+/// Multiplies the breakdown key equality checks of every row with its credit and with whether that
+/// credit is non-zero. Element `i` of a row holds both products for breakdown key `i`. Rows are
+/// folded into `init` with `f` in order, as soon as they are computed.
+async fn fold_contributions<C, F, S, T>(
+    ctx: C,
+    breakdown_keys: Vec<BitDecomposed<Replicated<Gf2>>>,
+    capped_credits: Vec<S>,
+    max_breakdown_key: u32,
+    init: T,
+    mut f: impl FnMut(T, Vec<(S, S)>) -> T + Send,
+) -> Result<T, Error>
+where
+    C: UpgradedContext<F, Share = S>,
+    F: PrimeField,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F> + Serializable + 'static,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    T: Send,
+{
+    assert_eq!(breakdown_keys.len(), capped_credits.len());
+    if max_breakdown_key > SIMPLE_AGGREGATION_BREAK_EVEN_POINT {
+        return Err(Error::Unsupported(format!(
+            "query uses {max_breakdown_key} breakdown keys; only {SIMPLE_AGGREGATION_BREAK_EVEN_POINT} are supported"
+        )));
+    }
+
+    let record_count = breakdown_keys.len();
+    let to_take = usize::try_from(max_breakdown_key).unwrap();
+
+    let equality_check_context = ctx
+        .narrow(&Step::ComputeEqualityChecks)
+        .set_total_records(record_count);
+    let check_times_credit_context = ctx
+        .narrow(&Step::CheckTimesCredit)
+        .set_total_records(record_count);
+    let non_zero_credit_context = ctx
+        .narrow(&Step::IsNonZeroCredit)
+        .set_total_records(record_count);
+    let check_times_contribution_context = ctx
+        .narrow(&Step::CheckTimesContribution)
+        .set_total_records(record_count);
+    let random_bits_generator =
+        RandomBitsGenerator::new(non_zero_credit_context.narrow(&Step::RandomBitsForComparison));
+    let rbg = &random_bits_generator;
+
+    let converted_bk = convert_bits(
+        ctx.narrow(&Step::ModConvBreakdownKeyBits)
+            .set_total_records(record_count),
+        stream_iter(breakdown_keys),
+        0..breakdown_key_bits(max_breakdown_key),
+    );
+
+    let increments = seq_join(
+        ctx.active_work(),
+        converted_bk
+            .zip(stream_iter(capped_credits))
+            .enumerate()
+            .map(|(i, (bk, cred))| {
+                let ceq = &equality_check_context;
+                let cmul = &check_times_credit_context;
+                let cnz = &non_zero_credit_context;
+                let ccount = &check_times_contribution_context;
+                async move {
+                    let record_id = RecordId::from(i);
+                    let bk = bk?;
+                    let (equality_checks, contributes) = try_join(
+                        bitwise_to_onehot(ceq.clone(), i, &bk),
+                        // credit is non-zero iff it is greater than zero in the field
+                        greater_than_constant(cnz.clone(), record_id, rbg, &cred, 0),
+                    )
+                    .await?;
+                    ceq.try_join(equality_checks.into_iter().take(to_take).enumerate().map(
+                        |(check_idx, check)| {
+                            let step = BitOpStep::from(check_idx);
+                            let (c_credit, c_count) = (cmul.narrow(&step), ccount.narrow(&step));
+                            let (credit, contributes) = (&cred, &contributes);
+                            async move {
+                                try_join(
+                                    check.multiply(credit, c_credit, record_id),
+                                    check.multiply(contributes, c_count, record_id),
+                                )
+                                .await
+                            }
+                        },
+                    ))
+                    .await
                 }
-                Ok(acc)
-            },
+            }),
+    );
+    increments
+        .try_fold(init, |acc, row| ready(Ok(f(acc, row))))
+        .await
+}
+
+/// Same as [`aggregate_credit`], but suppresses the credit of breakdown keys that fewer than
+/// `min_contributors` distinct users contribute to, e.g. to meet a k-anonymity requirement.
+/// Element `i` of the output holds the credit for breakdown key `i`, or zero if it is suppressed.
+///
+/// Rows of the same user must be next to each other. As in the rest of attribution, there is one
+/// helper bit fewer than there are rows, and `helper_bits[i]` is a share of one if rows `i` and
+/// `i + 1` belong to the same user. A user contributes to a breakdown key if any of their rows has
+/// that key and a non-zero capped credit. For every breakdown key, each row is marked as
+/// contributing if it or any of the following rows of the same user contributes, so the first row
+/// of a user tells whether the user contributes, and only first rows are counted. Each count is then compared with the
+/// threshold and the resulting bit is multiplied by the credit, so a suppressed bucket reveals zero
+/// and nothing else about its credit or its number of contributors.
+///
+/// # Panics
+/// If `breakdown_keys` and `capped_credits` have different lengths, or if there isn't exactly one
+/// helper bit fewer than there are rows.
+///
+/// # Errors
+/// If the query uses more breakdown keys than are supported, or if a multiplication or comparison
+/// fails.
+#[tracing::instrument(
+    name = "aggregate_credit_with_min_contributors",
+    skip_all,
+    fields(min_contributors = min_contributors)
+)]
+pub async fn aggregate_credit_with_min_contributors<V, C, F, S>(
+    validator: V,
+    breakdown_keys: Vec<BitDecomposed<Replicated<Gf2>>>,
+    capped_credits: Vec<S>,
+    helper_bits: &[S],
+    max_breakdown_key: u32,
+    min_contributors: u32,
+) -> Result<(V, Vec<S>), Error>
+where
+    V: Validator<C, F>,
+    C: UpgradableContext<Validator<F> = V>,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    F: PrimeField + ExtendableField,
+    S: LinearSecretSharing<F> + BasicProtocols<C::UpgradedContext<F>, F> + Serializable + 'static,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+{
+    assert_eq!(breakdown_keys.len(), helper_bits.len() + 1);
+    let m_ctx = validator.context();
+    let record_count = breakdown_keys.len();
+    let to_take = usize::try_from(max_breakdown_key).unwrap();
+
+    // the credit of every breakdown key, and whether each row contributes to it
+    let (credits, contributions) = fold_contributions(
+        m_ctx.clone(),
+        breakdown_keys,
+        capped_credits,
+        max_breakdown_key,
+        (
+            vec![S::ZERO; to_take],
+            vec![Vec::with_capacity(record_count); to_take],
+        ),
+        |(mut credits, mut contributions), row| {
+            for (i, (credit, contributes)) in row.into_iter().enumerate() {
+                credits[i] += &credit;
+                contributions[i].push(contributes);
+            }
+            (credits, contributions)
+        },
+    )
+    .await?;
+    if min_contributors == 0 {
+        return Ok((validator, credits));
+    }
+
+    let user_ctx = m_ctx.narrow(&Step::UserContributes);
+    let not_first_ctx = m_ctx
+        .narrow(&Step::ContributesAfterFirstRow)
+        .set_total_records(helper_bits.len());
+    let counts = m_ctx
+        .try_join(
+            contributions
+                .into_iter()
+                .enumerate()
+                .map(|(check_idx, contributions)| {
+                    let step = BitOpStep::from(check_idx);
+                    let (c_or, c_mul) = (user_ctx.narrow(&step), not_first_ctx.narrow(&step));
+                    async move {
+                        let user_contributes =
+                            prefix_or_binary_tree_style(c_or, helper_bits, &contributions, false)
+                                .await?;
+                        // row `i + 1` is not the first row of its user if it belongs to the same
+                        // user as row `i`
+                        let not_first = c_mul
+                            .try_join(zip(helper_bits, &user_contributes[1..]).enumerate().map(
+                                |(i, (helper_bit, contributes))| {
+                                    let c = c_mul.clone();
+                                    async move {
+                                        helper_bit.multiply(contributes, c, RecordId::from(i)).await
+                                    }
+                                },
+                            ))
+                            .await?;
+                        let mut count = S::ZERO;
+                        for contributes in &user_contributes {
+                            count += contributes;
+                        }
+                        for contributes in &not_first {
+                            count -= contributes;
+                        }
+                        Ok::<_, Error>(count)
+                    }
+                }),
         )
         .await?;
-    Ok(aggregate)
+
+    let cmp_ctx = m_ctx
+        .narrow(&Step::CompareToMinContributors)
+        .set_total_records(to_take);
+    let suppress_ctx = m_ctx
+        .narrow(&Step::SuppressSmallBuckets)
+        .set_total_records(to_take);
+    let random_bits_generator =
+        RandomBitsGenerator::new(cmp_ctx.narrow(&Step::RandomBitsForComparison));
+    let rbg = &random_bits_generator;
+    let suppressed = cmp_ctx
+        .try_join(
+            zip(&credits, &counts)
+                .enumerate()
+                .map(|(i, (credit, count))| {
+                    let (c_cmp, c_mul) = (cmp_ctx.clone(), suppress_ctx.clone());
+                    let record_id = RecordId::from(i);
+                    async move {
+                        // `count >= min_contributors` iff `count > min_contributors - 1`
+                        let enough = greater_than_constant(
+                            c_cmp,
+                            record_id,
+                            rbg,
+                            count,
+                            u128::from(min_contributors - 1),
+                        )
+                        .await?;
+                        enough.multiply(credit, c_mul, record_id).await
+                    }
+                }),
+        )
+        .await?;
+
+    Ok((validator, suppressed))
+}
+
+/// Streaming version of [`aggregate_credit`]. Yields the revealed `(breakdown_key, credit)` for
+/// every breakdown key in `0..max_breakdown_key`, in order, as soon as the credit for that key has
+/// been summed up and revealed.
+///
+/// Equality checks against every breakdown key are computed and validated for all rows first.
+/// After that, each breakdown key gets its own validator: the checks for the key are multiplied by
+/// credit, summed up, validated and revealed before the next key starts. This costs a few more
+/// multiplications than [`aggregate_credit`], because every key upgrades its checks and credits
+/// again, but callers can start consuming the histogram while the later keys are still being
+/// computed.
+///
+/// # Panics
+/// If `breakdown_keys` and `capped_credits` have different lengths.
+///
+/// # Errors
+/// The stream yields an error if a multiplication, the validation or a reveal fails.
+pub fn aggregate_credit_stream<C, F, S>(
+    ctx: C,
+    breakdown_keys: Vec<BitDecomposed<Replicated<Gf2>>>,
+    capped_credits: Vec<Replicated<F>>,
+    max_breakdown_key: u32,
+) -> impl Stream<Item = Result<(u32, F), Error>>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    F: PrimeField + ExtendableField,
+    S: LinearSecretSharing<F>
+        + BasicProtocols<C::UpgradedContext<F>, F>
+        + Serializable
+        + DowngradeMalicious<Target = Replicated<F>>
+        + 'static,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+{
+    assert_eq!(breakdown_keys.len(), capped_credits.len());
+    let record_count = breakdown_keys.len();
+    let check_times_credit_context = ctx.narrow(&Step::CheckTimesCredit);
+    // Keys are revealed one at a time, so the reveals must not wait for a full batch of records
+    // before they are sent.
+    let reveal_context = ctx
+        .narrow(&Step::RevealBucketCredit)
+        .set_total_records(TotalRecords::Indeterminate);
+
+    let rows = async move {
+        let validator = ctx.narrow(&Step::ComputeEqualityChecks).validator::<F>();
+        let m_ctx = validator.context();
+        let equality_check_context = m_ctx.set_total_records(record_count);
+        let converted_bk = convert_bits(
+            m_ctx
+                .narrow(&Step::ModConvBreakdownKeyBits)
+                .set_total_records(record_count),
+            stream_iter(breakdown_keys),
+            0..breakdown_key_bits(max_breakdown_key),
+        );
+        let equality_checks = seq_join(
+            m_ctx.active_work(),
+            converted_bk.enumerate().map(|(i, bk)| {
+                let ceq = equality_check_context.clone();
+                async move { range_equality_checks(ceq, i, &bk?, 0..max_breakdown_key).await }
+            }),
+        )
+        .try_collect::<Vec<_>>()
+        .await?;
+        let equality_checks = validator.validate(equality_checks).await?;
+
+        Ok::<_, Error>(Arc::new((equality_checks, capped_credits)))
+    };
+
+    stream_once(rows)
+        .map_ok(move |rows| {
+            let cmul = check_times_credit_context.clone();
+            let reveal_ctx = reveal_context.clone();
+            stream_iter(0..max_breakdown_key).then(move |breakdown_key| {
+                let rows = Arc::clone(&rows);
+                let validator = cmul
+                    .narrow(&BitOpStep::from(breakdown_key))
+                    .validator::<F>();
+                let reveal_ctx = reveal_ctx.clone();
+                async move {
+                    let (equality_checks, capped_credits) = &*rows;
+                    let key_idx = usize::try_from(breakdown_key).unwrap();
+                    let m_ctx = validator.context();
+                    let (checks, credits) = try_join(
+                        upgrade_all(
+                            &m_ctx,
+                            &Step::UpgradeEqualityChecks,
+                            equality_checks
+                                .iter()
+                                .map(|checks| checks[key_idx].clone())
+                                .collect(),
+                        ),
+                        upgrade_all(&m_ctx, &Step::UpgradeCredits, capped_credits.clone()),
+                    )
+                    .await?;
+
+                    let mul_ctx = m_ctx.set_total_records(record_count);
+                    let increments =
+                        mul_ctx
+                            .try_join(zip(&checks, &credits).enumerate().map(
+                                |(i, (check, credit))| {
+                                    check.multiply(credit, mul_ctx.clone(), RecordId::from(i))
+                                },
+                            ))
+                            .await?;
+                    let mut credit = S::ZERO;
+                    for incr in &increments {
+                        credit += incr;
+                    }
+
+                    let credit = validator.validate(credit).await?;
+                    let credit = credit
+                        .reveal(reveal_ctx, RecordId::from(breakdown_key))
+                        .await?;
+                    Ok((breakdown_key, credit))
+                }
+            })
+        })
+        .try_flatten()
 }
 
 #[derive(Step)]
@@ -146,18 +901,92 @@ pub(crate) enum Step {
     ComputeEqualityChecks,
     CheckTimesCredit,
     ModConvBreakdownKeyBits,
+    UpgradeCredits,
+    UpgradeThreshold,
+    CompareToThreshold,
+    RandomBitsForComparison,
+    RevealThresholdComparisons,
+    IsNonZeroCredit,
+    CheckTimesContribution,
+    CompareCredits,
+    CompareRanks,
+    RevealTopKSelection,
+    RevealTopKCredit,
+    CompareToMinContributors,
+    SuppressSmallBuckets,
+    UpgradeEqualityChecks,
+    RevealBucketCredit,
+    UserContributes,
+    ContributesAfterFirstRow,
 }
 
 #[cfg(all(test, unit_test))]
 mod tests {
-    use super::aggregate_credit;
+    use std::collections::HashSet;
+
+    use futures::{pin_mut, Stream, TryStreamExt};
+
+    use super::{
+        aggregate_credit, aggregate_credit_in_range, aggregate_credit_stream,
+        aggregate_credit_thresholded, aggregate_credit_top_k, aggregate_credit_with_counts,
+        aggregate_credit_with_min_contributors, breakdown_key_bits, Step,
+    };
     use crate::{
+        error::Error,
         ff::{Field, Fp32BitPrime, Gf2},
-        protocol::context::UpgradableContext,
+        protocol::context::{Context, UpgradableContext},
         secret_sharing::BitDecomposed,
-        test_fixture::{Reconstruct, Runner, TestWorld},
+        telemetry::metrics::MULTIPLICATIONS,
+        test_fixture::{Reconstruct, Runner, TestWorld, TestWorldConfig},
     };
 
+    /// Steps narrow gates by name, so two variants with the same name would share PRSS
+    /// randomness. Names are derived from variant identifiers, this makes sure none of them
+    /// collide or drift.
+    #[test]
+    fn step_names_are_unique() {
+        let expected = [
+            (Step::ComputeEqualityChecks, "compute_equality_checks"),
+            (Step::CheckTimesCredit, "check_times_credit"),
+            (Step::ModConvBreakdownKeyBits, "mod_conv_breakdown_key_bits"),
+            (Step::UpgradeCredits, "upgrade_credits"),
+            (Step::UpgradeThreshold, "upgrade_threshold"),
+            (Step::CompareToThreshold, "compare_to_threshold"),
+            (Step::RandomBitsForComparison, "random_bits_for_comparison"),
+            (
+                Step::RevealThresholdComparisons,
+                "reveal_threshold_comparisons",
+            ),
+            (Step::IsNonZeroCredit, "is_non_zero_credit"),
+            (Step::CheckTimesContribution, "check_times_contribution"),
+            (Step::CompareCredits, "compare_credits"),
+            (Step::CompareRanks, "compare_ranks"),
+            (Step::RevealTopKSelection, "reveal_top_k_selection"),
+            (Step::RevealTopKCredit, "reveal_top_k_credit"),
+            (
+                Step::CompareToMinContributors,
+                "compare_to_min_contributors",
+            ),
+            (Step::SuppressSmallBuckets, "suppress_small_buckets"),
+            (Step::UpgradeEqualityChecks, "upgrade_equality_checks"),
+            (Step::RevealBucketCredit, "reveal_bucket_credit"),
+            (Step::UserContributes, "user_contributes"),
+            (
+                Step::ContributesAfterFirstRow,
+                "contributes_after_first_row",
+            ),
+        ];
+
+        for (step, name) in &expected {
+            assert_eq!(*name, step.as_ref());
+        }
+        let names = expected
+            .iter()
+            .map(|(step, _)| step.as_ref())
+            .collect::<HashSet<_>>();
+        assert_eq!(expected.len(), names.len());
+    }
+
     #[tokio::test]
     pub async fn aggregate() {
         const MAX_BREAKDOWN_KEY: u32 = 8;
@@ -193,10 +1022,9 @@ mod tests {
                 INPUT.iter().map(|&(bk, credit)| {
                     (
                         // decomposed breakdown key
-                        BitDecomposed::decompose(
-                            u32::BITS - (MAX_BREAKDOWN_KEY - 1).leading_zeros(),
-                            |i| Gf2::try_from((u128::from(bk) >> i) & 1).unwrap(),
-                        ),
+                        BitDecomposed::decompose(breakdown_key_bits(MAX_BREAKDOWN_KEY), |i| {
+                            Gf2::try_from((u128::from(bk) >> i) & 1).unwrap()
+                        }),
                         // credit
                         Fp32BitPrime::truncate_from(credit),
                     )
@@ -219,4 +1047,504 @@ mod tests {
             .reconstruct();
         assert_eq!(result, EXPECTED);
     }
+
+    /// Pins the number of multiplications that simple aggregation needs, so that a change that
+    /// makes it more expensive does not go unnoticed.
+    #[tokio::test]
+    pub async fn aggregate_multiplications() {
+        const MAX_BREAKDOWN_KEY: u32 = 4;
+        const EXPECTED: &[u128] = &[0, 4, 9, 0];
+
+        // (breakdown_key, credit)
+        const INPUT: &[(u32, u32)] = &[(2, 5), (1, 4), (2, 1), (0, 0), (2, 0), (2, 3), (3, 0)];
+
+        let world = TestWorld::new_with(TestWorldConfig::default().enable_metrics());
+        let result = world
+            .semi_honest(
+                INPUT.iter().map(|&(bk, credit)| {
+                    (
+                        BitDecomposed::decompose(breakdown_key_bits(MAX_BREAKDOWN_KEY), |i| {
+                            Gf2::try_from((u128::from(bk) >> i) & 1).unwrap()
+                        }),
+                        Fp32BitPrime::truncate_from(credit),
+                    )
+                }),
+                |ctx, shares| async move {
+                    let (bk_shares, credit_shares): (Vec<_>, Vec<_>) = shares.into_iter().unzip();
+                    let validator = ctx.validator::<Fp32BitPrime>();
+                    let (_validator, output) = aggregate_credit(
+                        validator,
+                        bk_shares.into_iter(),
+                        credit_shares.into_iter(),
+                        MAX_BREAKDOWN_KEY,
+                    )
+                    .await
+                    .unwrap();
+                    output
+                },
+            )
+            .await
+            .reconstruct();
+        assert_eq!(result, EXPECTED);
+
+        // Every row needs two multiplications to convert each of the two breakdown key bits, one
+        // to expand them into four equality checks and one per check to multiply it by credit.
+        let per_row = 2 * 2 + 1 + 4;
+        let rows = u64::try_from(INPUT.len()).unwrap();
+        assert_eq!(
+            3 * rows * per_row,
+            world.metrics_snapshot().get_counter(MULTIPLICATIONS)
+        );
+    }
+
+    #[tokio::test]
+    pub async fn aggregate_with_counts() {
+        const MAX_BREAKDOWN_KEY: u32 = 4;
+
+        // (breakdown_key, credit)
+        const INPUT: &[(u32, u32)] = &[(2, 5), (1, 4), (2, 1), (0, 0), (2, 0), (2, 3), (3, 0)];
+
+        let world = TestWorld::default();
+        let (credits, counts): (Vec<Fp32BitPrime>, Vec<Fp32BitPrime>) = world
+            .semi_honest(
+                INPUT.iter().map(|&(bk, credit)| {
+                    (
+                        BitDecomposed::decompose(breakdown_key_bits(MAX_BREAKDOWN_KEY), |i| {
+                            Gf2::try_from((u128::from(bk) >> i) & 1).unwrap()
+                        }),
+                        Fp32BitPrime::truncate_from(credit),
+                    )
+                }),
+                |ctx, shares| async move {
+                    let (bk_shares, credit_shares): (Vec<_>, Vec<_>) = shares.into_iter().unzip();
+                    let validator = ctx.validator::<Fp32BitPrime>();
+                    let (_validator, output) = aggregate_credit_with_counts(
+                        validator,
+                        bk_shares,
+                        credit_shares,
+                        MAX_BREAKDOWN_KEY,
+                    )
+                    .await
+                    .unwrap();
+                    output.into_iter().unzip::<_, _, Vec<_>, Vec<_>>()
+                },
+            )
+            .await
+            .reconstruct();
+
+        // breakdown key 2 has three rows with credit and one without
+        assert_eq!(
+            [0_u128, 4, 9, 0].map(Fp32BitPrime::truncate_from).to_vec(),
+            credits
+        );
+        assert_eq!(
+            [0_u128, 1, 3, 0].map(Fp32BitPrime::truncate_from).to_vec(),
+            counts
+        );
+    }
+
+    #[tokio::test]
+    pub async fn aggregate_with_min_contributors() {
+        const MAX_BREAKDOWN_KEY: u32 = 4;
+        const MIN_CONTRIBUTORS: u32 = 2;
+
+        // credit per breakdown key is 6, 3, 12 and 8, from 2, 1, 3 and 1 users. Breakdown key 3
+        // has two contributing rows, but both come from the same user.
+        const EXPECTED: &[u128] = &[6, 0, 12, 0];
+
+        // (user, breakdown_key, credit)
+        const INPUT: &[(u32, u32, u32)] = &[
+            (1, 0, 1),
+            (1, 0, 2),
+            (1, 1, 3),
+            (2, 0, 3),
+            (2, 2, 1),
+            (3, 1, 0),
+            (3, 2, 4),
+            (3, 2, 2),
+            (4, 2, 5),
+            (4, 3, 7),
+            (4, 3, 1),
+        ];
+
+        let rows = INPUT.iter().map(|&(_, bk, credit)| {
+            (
+                BitDecomposed::decompose(breakdown_key_bits(MAX_BREAKDOWN_KEY), |i| {
+                    Gf2::try_from((u128::from(bk) >> i) & 1).unwrap()
+                }),
+                Fp32BitPrime::truncate_from(credit),
+            )
+        });
+        let helper_bits = INPUT
+            .windows(2)
+            .map(|pair| Fp32BitPrime::truncate_from(pair[0].0 == pair[1].0))
+            .collect::<Vec<_>>();
+
+        let world = TestWorld::default();
+        let result = world
+            .semi_honest(
+                (rows, helper_bits.into_iter()),
+                |ctx, (shares, helper_bits): (Vec<_>, Vec<_>)| async move {
+                    let (bk_shares, credit_shares): (Vec<_>, Vec<_>) = shares.into_iter().unzip();
+                    let validator = ctx.validator::<Fp32BitPrime>();
+                    let (_validator, output) = aggregate_credit_with_min_contributors(
+                        validator,
+                        bk_shares,
+                        credit_shares,
+                        &helper_bits,
+                        MAX_BREAKDOWN_KEY,
+                        MIN_CONTRIBUTORS,
+                    )
+                    .await
+                    .unwrap();
+                    output
+                },
+            )
+            .await
+            .reconstruct();
+        assert_eq!(result, EXPECTED);
+    }
+
+    #[tokio::test]
+    pub async fn aggregate_stream() {
+        const MAX_BREAKDOWN_KEY: u32 = 8;
+
+        // (breakdown_key, credit)
+        const INPUT: &[(u32, u32)] = &[
+            (3, 0),
+            (4, 18),
+            (0, 0),
+            (2, 2),
+            (2, 10),
+            (5, 6),
+            (7, 1),
+            (0, 0),
+        ];
+
+        /// Collects the stream, checking that breakdown keys arrive one at a time and in order.
+        async fn collect<S: Stream<Item = Result<(u32, Fp32BitPrime), Error>>>(
+            stream: S,
+        ) -> Vec<Fp32BitPrime> {
+            pin_mut!(stream);
+            let mut streamed = Vec::new();
+            while let Some((breakdown_key, credit)) = stream.try_next().await.unwrap() {
+                assert_eq!(streamed.len(), usize::try_from(breakdown_key).unwrap());
+                streamed.push(credit);
+            }
+            streamed
+        }
+
+        let input = || {
+            INPUT.iter().map(|&(bk, credit)| {
+                (
+                    BitDecomposed::decompose(breakdown_key_bits(MAX_BREAKDOWN_KEY), |i| {
+                        Gf2::try_from((u128::from(bk) >> i) & 1).unwrap()
+                    }),
+                    Fp32BitPrime::truncate_from(credit),
+                )
+            })
+        };
+
+        let world = TestWorld::default();
+        let [(b0, s0), (b1, s1), (b2, s2)] = world
+            .semi_honest(input(), |ctx, shares| async move {
+                let (bk_shares, credit_shares): (Vec<_>, Vec<_>) = shares.into_iter().unzip();
+                let (_validator, batch) = aggregate_credit(
+                    ctx.narrow("batch").validator::<Fp32BitPrime>(),
+                    bk_shares.clone().into_iter(),
+                    credit_shares.clone().into_iter(),
+                    MAX_BREAKDOWN_KEY,
+                )
+                .await
+                .unwrap();
+
+                let streamed = collect(aggregate_credit_stream(
+                    ctx.narrow("stream"),
+                    bk_shares,
+                    credit_shares,
+                    MAX_BREAKDOWN_KEY,
+                ))
+                .await;
+
+                (batch, streamed)
+            })
+            .await;
+        let batch: Vec<Fp32BitPrime> = [b0, b1, b2].reconstruct();
+        assert_eq!(
+            batch,
+            [0_u128, 0, 12, 0, 18, 6, 0, 1].map(Fp32BitPrime::truncate_from)
+        );
+        // every helper learns the same histogram
+        assert_eq!(batch, s0);
+        assert_eq!(s0, s1);
+        assert_eq!(s1, s2);
+
+        let [m0, m1, m2] = world
+            .malicious(input(), |ctx, shares| async move {
+                let (bk_shares, credit_shares): (Vec<_>, Vec<_>) = shares.into_iter().unzip();
+                collect(aggregate_credit_stream(
+                    ctx,
+                    bk_shares,
+                    credit_shares,
+                    MAX_BREAKDOWN_KEY,
+                ))
+                .await
+            })
+            .await;
+        assert_eq!(batch, m0);
+        assert_eq!(m0, m1);
+        assert_eq!(m1, m2);
+    }
+
+    #[test]
+    fn breakdown_key_bit_width() {
+        assert_eq!(0, breakdown_key_bits(0));
+        assert_eq!(0, breakdown_key_bits(1));
+        assert_eq!(1, breakdown_key_bits(2));
+        assert_eq!(2, breakdown_key_bits(3));
+        assert_eq!(3, breakdown_key_bits(8));
+        assert_eq!(4, breakdown_key_bits(9));
+        assert_eq!(32, breakdown_key_bits(u32::MAX));
+    }
+
+    #[tokio::test]
+    pub async fn aggregate_in_range() {
+        const KEY_RANGE: std::ops::Range<u32> = 10..20;
+
+        const EXPECTED: &[u128] = &[7, 0, 0, 0, 0, 3, 0, 0, 0, 11];
+
+        // (breakdown_key, credit)
+        const INPUT: &[(u32, u32)] = &[
+            (0, 5),
+            (10, 4),
+            (31, 8),
+            (15, 3),
+            (9, 1),
+            (19, 11),
+            (20, 2),
+            (10, 3),
+            (25, 6),
+        ];
+
+        let world = TestWorld::default();
+        let result = world
+            .semi_honest(
+                INPUT.iter().map(|&(bk, credit)| {
+                    (
+                        BitDecomposed::decompose(breakdown_key_bits(KEY_RANGE.end), |i| {
+                            Gf2::try_from((u128::from(bk) >> i) & 1).unwrap()
+                        }),
+                        Fp32BitPrime::truncate_from(credit),
+                    )
+                }),
+                |ctx, shares| async move {
+                    let (bk_shares, credit_shares): (Vec<_>, Vec<_>) = shares.into_iter().unzip();
+                    let validator = ctx.validator::<Fp32BitPrime>();
+                    let (_validator, output) = aggregate_credit_in_range(
+                        validator,
+                        bk_shares.into_iter(),
+                        credit_shares.into_iter(),
+                        KEY_RANGE,
+                    )
+                    .await
+                    .unwrap();
+                    output
+                },
+            )
+            .await
+            .reconstruct();
+        assert_eq!(result, EXPECTED);
+    }
+
+    #[tokio::test]
+    pub async fn aggregate_in_wide_range() {
+        const KEY_RANGE: std::ops::Range<u32> = 1_000_000..1_000_004;
+        const BITS: u32 = 20;
+
+        const EXPECTED: &[u128] = &[4, 0, 7, 0];
+
+        // (breakdown_key, credit)
+        const INPUT: &[(u32, u32)] = &[
+            (1_000_000, 4),
+            (0, 5),
+            (1_000_002, 6),
+            (999_999, 8),
+            (1_000_004, 2),
+            (1_000_002, 1),
+            (1_048_575, 3),
+        ];
+
+        let world = TestWorld::new_with(TestWorldConfig::default().enable_metrics());
+        let result = world
+            .semi_honest(
+                INPUT.iter().map(|&(bk, credit)| {
+                    (
+                        BitDecomposed::decompose(BITS, |i| {
+                            Gf2::try_from((u128::from(bk) >> i) & 1).unwrap()
+                        }),
+                        Fp32BitPrime::truncate_from(credit),
+                    )
+                }),
+                |ctx, shares| async move {
+                    let (bk_shares, credit_shares): (Vec<_>, Vec<_>) = shares.into_iter().unzip();
+                    let validator = ctx.validator::<Fp32BitPrime>();
+                    let (_validator, output) = aggregate_credit_in_range(
+                        validator,
+                        bk_shares.into_iter(),
+                        credit_shares.into_iter(),
+                        KEY_RANGE,
+                    )
+                    .await
+                    .unwrap();
+                    output
+                },
+            )
+            .await
+            .reconstruct();
+        assert_eq!(result, EXPECTED);
+
+        // Expanding 20 bits into all of their values would take about a million multiplications
+        // per row. Instead, every row is compared with the four keys in the range, which takes 19
+        // multiplications each, on top of converting the bits and multiplying checks by credit.
+        assert_eq!(BITS, breakdown_key_bits(KEY_RANGE.end));
+        let per_row = u64::from(2 * BITS + 4 * (BITS - 1) + 4);
+        let rows = u64::try_from(INPUT.len()).unwrap();
+        assert_eq!(
+            3 * rows * per_row,
+            world.metrics_snapshot().get_counter(MULTIPLICATIONS)
+        );
+    }
+
+    #[tokio::test]
+    pub async fn aggregate_thresholded() {
+        const MAX_BREAKDOWN_KEY: u32 = 4;
+        const THRESHOLD: u32 = 10;
+
+        // credit per breakdown key is 9, 10, 11 and 0
+        const EXPECTED: &[bool] = &[false, false, true, false];
+
+        // (breakdown_key, credit)
+        const INPUT: &[(u32, u32)] = &[(0, 4), (1, 10), (2, 6), (0, 5), (2, 5), (3, 0)];
+
+        let input = || {
+            (
+                INPUT.iter().map(|&(bk, credit)| {
+                    (
+                        BitDecomposed::decompose(breakdown_key_bits(MAX_BREAKDOWN_KEY), |i| {
+                            Gf2::try_from((u128::from(bk) >> i) & 1).unwrap()
+                        }),
+                        Fp32BitPrime::truncate_from(credit),
+                    )
+                }),
+                Fp32BitPrime::truncate_from(THRESHOLD),
+            )
+        };
+
+        let world = TestWorld::default();
+        let semi_honest = world
+            .semi_honest(input(), |ctx, (shares, threshold)| async move {
+                let (bk_shares, credit_shares): (Vec<_>, Vec<_>) = shares.into_iter().unzip();
+                aggregate_credit_thresholded(
+                    ctx,
+                    bk_shares.into_iter(),
+                    credit_shares,
+                    MAX_BREAKDOWN_KEY,
+                    threshold,
+                )
+                .await
+                .unwrap()
+            })
+            .await;
+        let malicious = world
+            .malicious(input(), |ctx, (shares, threshold)| async move {
+                let (bk_shares, credit_shares): (Vec<_>, Vec<_>) = shares.into_iter().unzip();
+                aggregate_credit_thresholded(
+                    ctx,
+                    bk_shares.into_iter(),
+                    credit_shares,
+                    MAX_BREAKDOWN_KEY,
+                    threshold,
+                )
+                .await
+                .unwrap()
+            })
+            .await;
+
+        // All helpers learn the comparison results, and nothing but them.
+        for result in semi_honest.into_iter().chain(malicious) {
+            assert_eq!(result, EXPECTED);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn aggregate_top_k() {
+        const MAX_BREAKDOWN_KEY: u32 = 6;
+        const K: usize = 3;
+
+        // credit per breakdown key is 9, 14, 3, 14, 0 and 7. Keys 1 and 3 tie, so the smaller key
+        // comes first.
+        const EXPECTED: &[(u32, u128)] = &[(1, 14), (3, 14), (0, 9)];
+
+        // (breakdown_key, credit)
+        const INPUT: &[(u32, u32)] = &[
+            (0, 4),
+            (1, 10),
+            (0, 5),
+            (3, 14),
+            (2, 3),
+            (1, 4),
+            (5, 7),
+            (4, 0),
+        ];
+
+        let input = || {
+            INPUT.iter().map(|&(bk, credit)| {
+                (
+                    BitDecomposed::decompose(breakdown_key_bits(MAX_BREAKDOWN_KEY), |i| {
+                        Gf2::try_from((u128::from(bk) >> i) & 1).unwrap()
+                    }),
+                    Fp32BitPrime::truncate_from(credit),
+                )
+            })
+        };
+
+        let world = TestWorld::default();
+        let semi_honest = world
+            .semi_honest(input(), |ctx, shares| async move {
+                let (bk_shares, credit_shares): (Vec<_>, Vec<_>) = shares.into_iter().unzip();
+                aggregate_credit_top_k(
+                    ctx,
+                    bk_shares.into_iter(),
+                    credit_shares,
+                    MAX_BREAKDOWN_KEY,
+                    K,
+                )
+                .await
+                .unwrap()
+            })
+            .await;
+        let malicious = world
+            .malicious(input(), |ctx, shares| async move {
+                let (bk_shares, credit_shares): (Vec<_>, Vec<_>) = shares.into_iter().unzip();
+                aggregate_credit_top_k(
+                    ctx,
+                    bk_shares.into_iter(),
+                    credit_shares,
+                    MAX_BREAKDOWN_KEY,
+                    K,
+                )
+                .await
+                .unwrap()
+            })
+            .await;
+
+        // All helpers learn the top keys and their credit, and nothing about the other keys.
+        let expected = EXPECTED
+            .iter()
+            .map(|&(bk, credit)| (bk, Fp32BitPrime::truncate_from(credit)))
+            .collect::<Vec<_>>();
+        for result in semi_honest.into_iter().chain(malicious) {
+            assert_eq!(result, expected);
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use std::iter::{repeat, zip};
+use std::iter::{once as once_iter, repeat, zip};
 
 use futures::{
     stream::{iter, once},
@@ -37,11 +37,50 @@ where
     S: LinearSecretSharing<F> + BasicProtocols<C, F>,
     for<'a> &'a S: LinearRefOps<'a, S, F>,
 {
-    if cap == 1 {
-        return Ok(credit_capping_max_one(ctx, input)
-            .await?
-            .collect::<Vec<_>>());
-    }
+    let (_, capped_credits) = original_and_capped_credits(ctx, input, cap).await?;
+    Ok(capped_credits)
+}
+
+/// Same as [`credit_capping`], but also reports how much credit was discarded by capping. Returns
+/// the capped credits and, for every row, the credit it had before capping minus the credit it has
+/// after. Summed over the rows of a user, the latter is the user's pre-cap total minus their
+/// post-cap total. Summing it over all rows gives the total credit discarded by capping, which
+/// can be revealed without revealing anything about individual users.
+///
+/// ## Errors
+/// Same as [`credit_capping`].
+#[tracing::instrument(name = "user_capping_with_capped_amount", skip_all)]
+pub async fn credit_capping_with_capped_amount<F, C, S>(
+    ctx: C,
+    input: &[CreditCappingInputRow<F, S>],
+    cap: u32,
+) -> Result<(Vec<S>, Vec<S>), Error>
+where
+    F: PrimeField,
+    C: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+    for<'a> &'a S: LinearRefOps<'a, S, F>,
+{
+    let (original_credits, capped_credits) = original_and_capped_credits(ctx, input, cap).await?;
+    let capped_amounts = zip(&original_credits, &capped_credits)
+        .map(|(original, capped)| original - capped)
+        .collect();
+    Ok((capped_credits, capped_amounts))
+}
+
+/// Runs the capping protocol, returning the credits of source events before capping along with
+/// the capped credits.
+async fn original_and_capped_credits<F, C, S>(
+    ctx: C,
+    input: &[CreditCappingInputRow<F, S>],
+    cap: u32,
+) -> Result<(Vec<S>, Vec<S>), Error>
+where
+    F: PrimeField,
+    C: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+    for<'a> &'a S: LinearRefOps<'a, S, F>,
+{
     let input_len = input.len();
 
     if (u128::from(cap) * 2) >= F::PRIME.into() {
@@ -57,6 +96,11 @@ where
     //
     let original_credits = mask_source_credits(input, ctx.set_total_records(input_len)).await?;
 
+    if cap == 1 {
+        let capped_credits = credit_capping_max_one(ctx, input, &original_credits).await?;
+        return Ok((original_credits, capped_credits));
+    }
+
     //
     // Step 2. Cap each report's value to `cap`
     //
@@ -115,7 +159,7 @@ where
     // the cap. We can change the logic to do the opposite, i.e. cap the newest reports first, by
     // reversing the order of the input.
     //
-    compute_final_credits(
+    let final_credits = compute_final_credits(
         ctx,
         input,
         &prefix_summed_credits,
@@ -123,7 +167,80 @@ where
         &capped_credits,
         cap,
     )
-    .await
+    .await?;
+
+    Ok((original_credits, final_credits))
+}
+
+/// Which events share a credit cap.
+#[derive(Debug)]
+pub enum CapScope<'a, S> {
+    /// Credit of every user is capped at `cap` in total.
+    PerUser,
+    /// Credit of every user is capped at `cap` for each breakdown key separately, so a user can
+    /// contribute up to the cap to every campaign. Input rows must be sorted by match key and
+    /// then by breakdown key, and `same_breakdown_key[i]` must be a sharing of one if row `i + 1`
+    /// has the same breakdown key as row `i`, and zero otherwise.
+    PerUserPerBreakdown { same_breakdown_key: &'a [S] },
+}
+
+/// Same as [`credit_capping`], but caps credit within the groups of events defined by `scope`.
+///
+/// For [`CapScope::PerUserPerBreakdown`], a group ends wherever either the match key or the
+/// breakdown key changes, so the capping reduction resets at both kinds of boundaries.
+///
+/// ## Errors
+/// Same as [`credit_capping`].
+///
+/// ## Panics
+/// If `same_breakdown_key` does not have one element less than `input`.
+pub async fn scoped_credit_capping<F, C, S>(
+    ctx: C,
+    input: &[CreditCappingInputRow<F, S>],
+    cap: u32,
+    scope: CapScope<'_, S>,
+) -> Result<Vec<S>, Error>
+where
+    F: PrimeField,
+    C: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+    for<'a> &'a S: LinearRefOps<'a, S, F>,
+{
+    let same_breakdown_key = match scope {
+        CapScope::PerUser => return credit_capping(ctx, input, cap).await,
+        CapScope::PerUserPerBreakdown { same_breakdown_key } => same_breakdown_key,
+    };
+    assert_eq!(input.len(), same_breakdown_key.len() + 1);
+
+    let same_group_ctx = ctx
+        .narrow(&Step::SameUserAndBreakdownKey)
+        .set_total_records(same_breakdown_key.len());
+    let same_group_bits = ctx
+        .try_join(zip(&input[1..], same_breakdown_key).enumerate().map(
+            |(i, (row, same_breakdown_key))| {
+                let c = same_group_ctx.clone();
+                async move {
+                    row.helper_bit
+                        .multiply(same_breakdown_key, c, RecordId::from(i))
+                        .await
+                }
+            },
+        ))
+        .await?;
+
+    let grouped_input = input
+        .iter()
+        .zip(once_iter(input[0].helper_bit.clone()).chain(same_group_bits))
+        .map(|(row, helper_bit)| {
+            CreditCappingInputRow::new(
+                row.is_trigger_report.clone(),
+                helper_bit,
+                row.trigger_value.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    credit_capping(ctx, &grouped_input, cap).await
 }
 
 ///
@@ -143,16 +260,13 @@ where
 async fn credit_capping_max_one<F, C, S>(
     ctx: C,
     input: &[CreditCappingInputRow<F, S>],
-) -> Result<impl Iterator<Item = S> + '_, Error>
+    uncapped_credits: &[S],
+) -> Result<Vec<S>, Error>
 where
     F: Field,
     C: Context,
     S: LinearSecretSharing<F> + BasicProtocols<C, F>,
 {
-    let input_len = input.len();
-
-    let uncapped_credits = mask_source_credits(input, ctx.set_total_records(input_len)).await?;
-
     let helper_bits = input
         .iter()
         .skip(1)
@@ -204,7 +318,67 @@ where
     let capped_count = capped_credits.len();
     Ok(capped_credits
         .into_iter()
-        .chain(uncapped_credits.into_iter().skip(capped_count)))
+        .chain(uncapped_credits.iter().skip(capped_count).cloned())
+        .collect())
+}
+
+/// Sets credits that netted out to a negative value to zero.
+///
+/// With signed trigger values, refunds are represented by the additive inverse of their value
+/// and subtract from the credit during accumulation, so a credit above `(p - 1) / 2` is negative.
+/// The capping protocol would treat such a credit as a large positive one and cap it, so it has
+/// to be clamped to zero first. Together with capping, this keeps credits in `[0, cap]`.
+///
+/// ## Errors
+/// Propagates errors from comparisons and multiplications.
+pub async fn zero_negative_credits<F, C, S>(
+    ctx: C,
+    input: &mut [CreditCappingInputRow<F, S>],
+) -> Result<(), Error>
+where
+    F: PrimeField,
+    C: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+    for<'a> &'a S: LinearRefOps<'a, S, F>,
+{
+    let prime: u128 = F::PRIME.into();
+    let max_positive = (prime - 1) / 2;
+
+    let ctx = ctx.set_total_records(input.len());
+    let random_bits_generator =
+        RandomBitsGenerator::new(ctx.narrow(&Step::RandomBitsForSignComparison));
+    let rbg = &random_bits_generator;
+    let is_negative_ctx = ctx.narrow(&Step::IsCreditNegative);
+    let zero_credit_ctx = ctx.narrow(&Step::ZeroNegativeCredit);
+
+    let credits = ctx
+        .try_join(input.iter().enumerate().map(|(i, row)| {
+            let record_id = RecordId::from(i);
+            let is_negative_ctx = is_negative_ctx.clone();
+            let zero_credit_ctx = zero_credit_ctx.clone();
+            async move {
+                let is_negative = greater_than_constant(
+                    is_negative_ctx,
+                    record_id,
+                    rbg,
+                    &row.trigger_value,
+                    max_positive,
+                )
+                .await?;
+                let negative_credit = row
+                    .trigger_value
+                    .multiply(&is_negative, zero_credit_ctx, record_id)
+                    .await?;
+                Ok::<_, Error>(row.trigger_value.clone() - &negative_credit)
+            }
+        }))
+        .await?;
+
+    for (row, credit) in input.iter_mut().zip(credits) {
+        row.trigger_value = credit;
+    }
+
+    Ok(())
 }
 
 async fn mask_source_credits<F, C, T>(
@@ -457,18 +631,31 @@ pub(crate) enum Step {
     IfNextEventHasSameMatchKeyOrElse,
     PrefixOrTimesHelperBit,
     PrefixOrCompareBits,
+    RandomBitsForSignComparison,
+    IsCreditNegative,
+    SameUserAndBreakdownKey,
+    ZeroNegativeCredit,
 }
 
 #[cfg(all(test, unit_test))]
 mod tests {
+    use std::cmp::min;
+
     use crate::{
         credit_capping_test_input,
         ff::{Field, Fp32BitPrime, PrimeField},
         protocol::{
-            attribution::{credit_capping::credit_capping, input::CreditCappingInputRow},
+            attribution::{
+                credit_capping::{
+                    credit_capping, credit_capping_with_capped_amount, scoped_credit_capping,
+                    CapScope,
+                },
+                input::CreditCappingInputRow,
+            },
             context::{UpgradableContext, Validator},
             BreakdownKey, MatchKey,
         },
+        rand::{thread_rng, Rng},
         secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
         test_fixture::{input::GenericReportTestInput, Reconstruct, Runner, TestWorld},
     };
@@ -526,6 +713,167 @@ mod tests {
         assert_eq!(result, EXPECTED);
     }
 
+    /// Caps `(user, breakdown_key, credit)` rows in the clear, keeping the most recent credits of
+    /// every group first. Rows must be sorted by user, and by breakdown key within each user if
+    /// `per_breakdown` is set.
+    fn scoped_capping_in_the_clear(
+        rows: &[(u64, u128, u128)],
+        cap: u32,
+        per_breakdown: bool,
+    ) -> Vec<u128> {
+        let mut remaining = u128::from(cap);
+        let mut capped = vec![0; rows.len()];
+        for i in (0..rows.len()).rev() {
+            let (user, breakdown_key, credit) = rows[i];
+            let new_group = rows.get(i + 1).map_or(true, |&(next_user, next_key, _)| {
+                next_user != user || (per_breakdown && next_key != breakdown_key)
+            });
+            if new_group {
+                remaining = u128::from(cap);
+            }
+            capped[i] = min(credit, remaining);
+            remaining -= capped[i];
+        }
+        capped
+    }
+
+    /// Runs [`scoped_credit_capping`] on `(user, breakdown_key, credit)` rows, which must be sorted
+    /// by user and then by breakdown key.
+    async fn run_scoped_capping(
+        rows: &[(u64, u128, u128)],
+        cap: u32,
+        per_breakdown: bool,
+    ) -> Vec<u128> {
+        let input: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, &(user, breakdown_key, credit))| {
+                let helper_bit = i > 0 && rows[i - 1].0 == user;
+                credit_capping_test_input!(
+                    { is_trigger_report: 0, helper_bit: u128::from(helper_bit), breakdown_key: breakdown_key, credit: credit };
+                    (Fp32BitPrime, MatchKey, BreakdownKey)
+                )
+            })
+            .collect();
+        let same_breakdown_key = rows
+            .windows(2)
+            .map(|pair| Fp32BitPrime::truncate_from(pair[0].1 == pair[1].1))
+            .collect::<Vec<_>>();
+
+        let world = TestWorld::default();
+        let result: Vec<Fp32BitPrime> = world
+            .semi_honest(
+                (input.into_iter(), same_breakdown_key.into_iter()),
+                |ctx,
+                 (input, same_breakdown_key): (
+                    Vec<CreditCappingInputRow<Fp32BitPrime, Replicated<_>>>,
+                    Vec<Replicated<_>>,
+                )| async move {
+                    let validator = ctx.validator(); // We're not running validation for this in this case.
+                    let scope = if per_breakdown {
+                        CapScope::PerUserPerBreakdown {
+                            same_breakdown_key: &same_breakdown_key,
+                        }
+                    } else {
+                        CapScope::PerUser
+                    };
+                    scoped_credit_capping(validator.context(), &input, cap, scope)
+                        .await
+                        .unwrap()
+                },
+            )
+            .await
+            .reconstruct();
+        result.iter().map(Field::as_u128).collect()
+    }
+
+    #[tokio::test]
+    pub async fn per_user_per_breakdown() {
+        const CAP: u32 = 7;
+
+        // Rows are sorted by user and breakdown key. The first user has credits for breakdown
+        // keys 1 and 2.
+        let rows = [(1, 1, 5), (1, 1, 4), (1, 2, 6), (2, 3, 2)];
+
+        for (per_breakdown, expected) in [(false, [0_u128, 1, 6, 2]), (true, [3, 4, 6, 2])] {
+            assert_eq!(
+                expected.to_vec(),
+                scoped_capping_in_the_clear(&rows, CAP, per_breakdown)
+            );
+            assert_eq!(
+                expected.to_vec(),
+                run_scoped_capping(&rows, CAP, per_breakdown).await,
+                "per breakdown: {per_breakdown}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    pub async fn per_user_per_breakdown_matches_plaintext() {
+        const CAP: u32 = 7;
+        const USERS: u64 = 5;
+
+        let mut rng = thread_rng();
+        let mut rows = (0..USERS)
+            .flat_map(|user| {
+                (0..rng.gen_range(1..6))
+                    .map(|_| (user, rng.gen_range(0..3), rng.gen_range(0..6)))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        // The sort is stable, so every group keeps its events in the order they were generated.
+        rows.sort_by_key(|&(user, breakdown_key, _)| (user, breakdown_key));
+
+        for per_breakdown in [false, true] {
+            assert_eq!(
+                scoped_capping_in_the_clear(&rows, CAP, per_breakdown),
+                run_scoped_capping(&rows, CAP, per_breakdown).await,
+                "per breakdown: {per_breakdown}, rows: {rows:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    pub async fn capped_amount() {
+        const CAP: u32 = 10;
+
+        // The first user has 15 credits before capping, the second one has 4.
+        let input: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = credit_capping_test_input!(
+            [
+                { is_trigger_report: 0, helper_bit: 0, breakdown_key: 1, credit: 6 },
+                { is_trigger_report: 0, helper_bit: 1, breakdown_key: 2, credit: 9 },
+                { is_trigger_report: 1, helper_bit: 1, breakdown_key: 0, credit: 9 },
+                { is_trigger_report: 0, helper_bit: 0, breakdown_key: 1, credit: 4 },
+            ];
+            (Fp32BitPrime, MatchKey, BreakdownKey)
+        );
+
+        let world = TestWorld::default();
+        let (credits, capped_away): (Vec<Fp32BitPrime>, Fp32BitPrime) = world
+            .semi_honest(
+                input.into_iter(),
+                |ctx, input: Vec<CreditCappingInputRow<Fp32BitPrime, Replicated<_>>>| async move {
+                    let validator = ctx.validator(); // We're not running validation for this in this case.
+                    let (credits, capped_amounts) =
+                        credit_capping_with_capped_amount(validator.context(), &input, CAP)
+                            .await
+                            .unwrap();
+                    let total = capped_amounts
+                        .into_iter()
+                        .fold(Replicated::ZERO, |acc, amount| acc + amount);
+                    (credits, total)
+                },
+            )
+            .await
+            .reconstruct();
+
+        assert_eq!(
+            credits.iter().map(Field::as_u128).collect::<Vec<_>>(),
+            [1, 9, 0, 4]
+        );
+        assert_eq!(15 - u128::from(CAP), capped_away.as_u128());
+    }
+
     #[tokio::test]
     #[should_panic]
     pub async fn invalid_cap_value() {
@@ -0,0 +1,104 @@
+use futures::future::try_join;
+use ipa_macros::Step;
+
+use crate::{
+    error::Error,
+    ff::PrimeField,
+    protocol::{
+        boolean::{greater_than_constant, random_bits_generator::RandomBitsGenerator},
+        context::UpgradedContext,
+        ipa::ArithmeticallySharedIPAInputs,
+        BasicProtocols, RecordId,
+    },
+    secret_sharing::{Linear as LinearSecretSharing, LinearRefOps},
+};
+
+/// Sets the trigger value of every trigger event that duplicates the trigger event right before it
+/// to zero.
+///
+/// `same_keys[i]` must be a sharing of one if rows `i` and `i + 1` have the same match key and
+/// breakdown key, and zero otherwise. Together with the trigger bits and trigger values this
+/// decides whether row `i + 1` is a duplicate, without revealing which rows are.
+///
+/// Rows are compared with their original predecessor, so all but the first of a run of identical
+/// trigger events lose their value.
+///
+/// # Errors
+/// Propagates errors from multiplications and comparisons.
+///
+/// # Panics
+/// If `same_keys` does not have exactly one element less than `rows`.
+pub async fn zero_duplicate_trigger_values<F, C, S>(
+    ctx: C,
+    rows: &mut [ArithmeticallySharedIPAInputs<F, S>],
+    same_keys: &[S],
+) -> Result<(), Error>
+where
+    F: PrimeField,
+    C: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+    for<'a> &'a S: LinearRefOps<'a, S, F>,
+{
+    assert_eq!(rows.len(), same_keys.len() + 1);
+
+    let ctx = ctx.set_total_records(same_keys.len());
+    let random_bits_generator =
+        RandomBitsGenerator::new(ctx.narrow(&Step::RandomBitsForComparison));
+    let rbg = &random_bits_generator;
+    let both_triggers_ctx = ctx.narrow(&Step::BothTriggerEvents);
+    let values_differ_ctx = ctx.narrow(&Step::TriggerValuesDiffer);
+    let candidate_ctx = ctx.narrow(&Step::SameKeysAndBothTriggerEvents);
+    let duplicate_ctx = ctx.narrow(&Step::IsDuplicate);
+    let zero_value_ctx = ctx.narrow(&Step::ZeroDuplicateTriggerValue);
+
+    let rows_ref = &*rows;
+    let new_values = ctx
+        .try_join(same_keys.iter().enumerate().map(|(i, same)| {
+            let (prev, cur) = (&rows_ref[i], &rows_ref[i + 1]);
+            let record_id = RecordId::from(i);
+            let both_triggers_ctx = both_triggers_ctx.clone();
+            let values_differ_ctx = values_differ_ctx.clone();
+            let candidate_ctx = candidate_ctx.clone();
+            let duplicate_ctx = duplicate_ctx.clone();
+            let zero_value_ctx = zero_value_ctx.clone();
+            async move {
+                let value_diff = cur.trigger_value.clone() - &prev.trigger_value;
+                let (both_triggers, values_differ) = try_join(
+                    prev.is_trigger_bit
+                        .multiply(&cur.is_trigger_bit, both_triggers_ctx, record_id),
+                    // `value_diff` is non-zero iff it is greater than zero in the field.
+                    greater_than_constant(values_differ_ctx, record_id, rbg, &value_diff, 0),
+                )
+                .await?;
+                let candidate = same
+                    .multiply(&both_triggers, candidate_ctx, record_id)
+                    .await?;
+                let is_duplicate = candidate.clone()
+                    - &candidate
+                        .multiply(&values_differ, duplicate_ctx, record_id)
+                        .await?;
+                let dropped_value = cur
+                    .trigger_value
+                    .multiply(&is_duplicate, zero_value_ctx, record_id)
+                    .await?;
+                Ok::<_, Error>(cur.trigger_value.clone() - &dropped_value)
+            }
+        }))
+        .await?;
+
+    for (row, value) in rows.iter_mut().skip(1).zip(new_values) {
+        row.trigger_value = value;
+    }
+
+    Ok(())
+}
+
+#[derive(Step)]
+pub(crate) enum Step {
+    RandomBitsForComparison,
+    BothTriggerEvents,
+    TriggerValuesDiffer,
+    SameKeysAndBothTriggerEvents,
+    IsDuplicate,
+    ZeroDuplicateTriggerValue,
+}
@@ -9,7 +9,12 @@ use futures::future::try_join4;
 use crate::{
     error::Error,
     helpers::Role,
-    protocol::{basics::Reshare, context::Context, RecordId},
+    protocol::{
+        basics::{Reshare, Reveal},
+        context::Context,
+        RecordId,
+    },
+    secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
 };
 use crate::{ff::Field, secret_sharing::Linear as LinearSecretSharing};
 
@@ -39,6 +44,106 @@ impl<F: Field, S: LinearSecretSharing<F>> ApplyAttributionWindowInputRow<F, S> {
 
 pub type ApplyAttributionWindowOutputRow<F, S> = AccumulateCreditInputRow<F, S>;
 
+/// Reveals the bit columns of the attribution input and checks that they follow the conventions
+/// attribution protocols rely on:
+/// * `is_trigger_report` and `helper_bit` are either 0 or 1;
+/// * the first row has `helper_bit = 0`, as there is no preceding row to share a match key with;
+/// * source rows (`is_trigger_report = 0`) carry no `trigger_value`.
+///
+/// Inputs that are padded for aggregation also pass the `aggregation_bit` of every row, which
+/// must be 0 or 1. Padding rows (`aggregation_bit = 0`) must have `helper_bit = 0` and carry no
+/// `trigger_value`, so they never join a user's events or contribute credit.
+///
+/// Hand-constructed inputs that break these conventions do not fail attribution, they silently
+/// produce wrong results. This check is meant to be run by tests before feeding the rows into
+/// attribution. It reveals the inputs, so it must never be used outside of tests.
+///
+/// ## Errors
+/// If revealing any of the values fails.
+///
+/// ## Panics
+/// If any of the invariants above does not hold, or if `aggregation_bits` does not have one bit
+/// for every row.
+#[cfg(test)]
+pub async fn debug_assert_attribution_invariants<C, F>(
+    ctx: C,
+    rows: &[ApplyAttributionWindowInputRow<F, Replicated<F>>],
+    aggregation_bits: Option<&[Replicated<F>]>,
+) -> Result<(), Error>
+where
+    C: Context,
+    F: Field,
+{
+    if let Some(aggregation_bits) = aggregation_bits {
+        assert_eq!(
+            rows.len(),
+            aggregation_bits.len(),
+            "every row must have an aggregation bit"
+        );
+    }
+
+    let ctx = ctx.set_total_records(rows.len());
+    let is_trigger_ctx = ctx.narrow(&AttributionInvariantStep::IsTriggerReport);
+    let helper_bit_ctx = ctx.narrow(&AttributionInvariantStep::HelperBit);
+    let trigger_value_ctx = ctx.narrow(&AttributionInvariantStep::TriggerValue);
+    let aggregation_bit_ctx = ctx.narrow(&AttributionInvariantStep::AggregationBit);
+
+    let revealed = ctx
+        .try_join(rows.iter().enumerate().map(|(i, row)| {
+            let record_id = RecordId::from(i);
+            let aggregation_bit = aggregation_bits.map(|bits| &bits[i]);
+            let aggregation_bit_ctx = aggregation_bit_ctx.clone();
+            try_join4(
+                row.is_trigger_report
+                    .reveal(is_trigger_ctx.clone(), record_id),
+                row.helper_bit.reveal(helper_bit_ctx.clone(), record_id),
+                row.trigger_value
+                    .reveal(trigger_value_ctx.clone(), record_id),
+                async move {
+                    match aggregation_bit {
+                        Some(bit) => bit.reveal(aggregation_bit_ctx, record_id).await.map(Some),
+                        None => Ok(None),
+                    }
+                },
+            )
+        }))
+        .await?;
+
+    let is_bit = |v: F| v == F::ZERO || v == F::ONE;
+    for (i, (is_trigger_report, helper_bit, trigger_value, aggregation_bit)) in
+        revealed.into_iter().enumerate()
+    {
+        assert!(
+            is_bit(is_trigger_report),
+            "row {i}: is_trigger_report must be 0 or 1, got {is_trigger_report:?}"
+        );
+        assert!(
+            is_bit(helper_bit),
+            "row {i}: helper_bit must be 0 or 1, got {helper_bit:?}"
+        );
+        assert!(
+            i != 0 || helper_bit == F::ZERO,
+            "row 0: helper_bit must be 0 for the first row"
+        );
+        assert!(
+            is_trigger_report == F::ONE || trigger_value == F::ZERO,
+            "row {i}: source rows must not carry trigger value, got {trigger_value:?}"
+        );
+        if let Some(aggregation_bit) = aggregation_bit {
+            assert!(
+                is_bit(aggregation_bit),
+                "row {i}: aggregation_bit must be 0 or 1, got {aggregation_bit:?}"
+            );
+            assert!(
+                aggregation_bit == F::ONE || (helper_bit == F::ZERO && trigger_value == F::ZERO),
+                "row {i}: padding rows must have helper_bit 0 and no trigger value"
+            );
+        }
+    }
+
+    Ok(())
+}
+
 //
 // `accumulate_credit` protocol
 //
@@ -170,3 +275,156 @@ impl crate::protocol::step::StepNarrow<AttributionResharableStep>
         unimplemented!("compact gate is not supported in unit tests")
     }
 }
+
+// Steps used by [`debug_assert_attribution_invariants`]. Test-only, see the comment above.
+#[cfg(test)]
+pub(crate) enum AttributionInvariantStep {
+    IsTriggerReport,
+    HelperBit,
+    TriggerValue,
+    AggregationBit,
+}
+#[cfg(test)]
+impl crate::protocol::step::Step for AttributionInvariantStep {}
+#[cfg(test)]
+impl AsRef<str> for AttributionInvariantStep {
+    fn as_ref(&self) -> &'static str {
+        match self {
+            AttributionInvariantStep::IsTriggerReport => "check_is_trigger_report",
+            AttributionInvariantStep::HelperBit => "check_helper_bit",
+            AttributionInvariantStep::TriggerValue => "check_trigger_value",
+            AttributionInvariantStep::AggregationBit => "check_aggregation_bit",
+        }
+    }
+}
+#[cfg(all(feature = "compact-gate", test))]
+impl crate::protocol::step::StepNarrow<AttributionInvariantStep>
+    for crate::protocol::step::Compact
+{
+    fn narrow(&self, _step: &AttributionInvariantStep) -> Self {
+        unimplemented!("compact gate is not supported in unit tests")
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::{debug_assert_attribution_invariants, ApplyAttributionWindowInputRow};
+    use crate::{
+        attribution_window_test_input,
+        ff::{Field, Fp32BitPrime},
+        protocol::{BreakdownKey, MatchKey},
+        secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
+        test_fixture::{input::GenericReportTestInput, Runner, TestWorld},
+    };
+
+    type Rows = Vec<ApplyAttributionWindowInputRow<Fp32BitPrime, Replicated<Fp32BitPrime>>>;
+
+    async fn check(input: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>>) {
+        TestWorld::default()
+            .semi_honest(input.into_iter(), |ctx, rows: Rows| async move {
+                debug_assert_attribution_invariants(ctx, &rows, None)
+                    .await
+                    .unwrap();
+            })
+            .await;
+    }
+
+    async fn check_padded(
+        input: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>>,
+        aggregation_bits: &[u128],
+    ) {
+        let aggregation_bits = aggregation_bits
+            .iter()
+            .map(|&bit| Fp32BitPrime::truncate_from(bit))
+            .collect::<Vec<_>>();
+        TestWorld::default()
+            .semi_honest(
+                (input.into_iter(), aggregation_bits.into_iter()),
+                |ctx, (rows, aggregation_bits): (Rows, Vec<Replicated<_>>)| async move {
+                    debug_assert_attribution_invariants(ctx, &rows, Some(&aggregation_bits))
+                        .await
+                        .unwrap();
+                },
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn well_formed_input() {
+        let input: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = attribution_window_test_input!(
+            [
+                { timestamp: 100, is_trigger_report: 0, helper_bit: 0, breakdown_key: 1, credit: 0 },
+                { timestamp: 200, is_trigger_report: 1, helper_bit: 1, breakdown_key: 0, credit: 5 },
+                { timestamp: 300, is_trigger_report: 1, helper_bit: 0, breakdown_key: 0, credit: 3 },
+            ];
+            (Fp32BitPrime, MatchKey, BreakdownKey)
+        );
+        check(input).await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "row 0: helper_bit must be 0 for the first row")]
+    async fn first_row_helper_bit_set() {
+        let input: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = attribution_window_test_input!(
+            [
+                { timestamp: 100, is_trigger_report: 0, helper_bit: 1, breakdown_key: 1, credit: 0 },
+                { timestamp: 200, is_trigger_report: 1, helper_bit: 1, breakdown_key: 0, credit: 5 },
+            ];
+            (Fp32BitPrime, MatchKey, BreakdownKey)
+        );
+        check(input).await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "row 1: helper_bit must be 0 or 1")]
+    async fn non_binary_helper_bit() {
+        let input: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = attribution_window_test_input!(
+            [
+                { timestamp: 100, is_trigger_report: 0, helper_bit: 0, breakdown_key: 1, credit: 0 },
+                { timestamp: 200, is_trigger_report: 1, helper_bit: 2, breakdown_key: 0, credit: 5 },
+            ];
+            (Fp32BitPrime, MatchKey, BreakdownKey)
+        );
+        check(input).await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "row 0: source rows must not carry trigger value")]
+    async fn source_row_with_credit() {
+        let input: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = attribution_window_test_input!(
+            [
+                { timestamp: 100, is_trigger_report: 0, helper_bit: 0, breakdown_key: 1, credit: 7 },
+            ];
+            (Fp32BitPrime, MatchKey, BreakdownKey)
+        );
+        check(input).await;
+    }
+
+    fn padded_input() -> Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> {
+        attribution_window_test_input!(
+            [
+                { timestamp: 100, is_trigger_report: 0, helper_bit: 0, breakdown_key: 1, credit: 0 },
+                { timestamp: 200, is_trigger_report: 1, helper_bit: 1, breakdown_key: 0, credit: 5 },
+                { timestamp: 0, is_trigger_report: 0, helper_bit: 0, breakdown_key: 0, credit: 0 },
+            ];
+            (Fp32BitPrime, MatchKey, BreakdownKey)
+        )
+    }
+
+    #[tokio::test]
+    async fn well_formed_padded_input() {
+        check_padded(padded_input(), &[1, 1, 0]).await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "row 1: aggregation_bit must be 0 or 1")]
+    async fn non_binary_aggregation_bit() {
+        check_padded(padded_input(), &[1, 2, 0]).await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "row 1: padding rows must have helper_bit 0 and no trigger value")]
+    async fn padding_row_joins_user() {
+        check_padded(padded_input(), &[1, 0, 0]).await;
+    }
+}
@@ -2,6 +2,7 @@ pub mod accumulate_credit;
 pub mod aggregate_credit;
 pub mod apply_attribution_window;
 pub mod credit_capping;
+pub mod dedup;
 pub mod input;
 
 use std::iter::{once as iter_once, zip};
@@ -13,17 +14,20 @@ use futures::{
 use ipa_macros::Step;
 
 use self::{
-    accumulate_credit::accumulate_credit, aggregate_credit::aggregate_credit,
-    apply_attribution_window::apply_attribution_window, credit_capping::credit_capping,
-    input::ApplyAttributionWindowInputRow,
+    accumulate_credit::accumulate_credit,
+    aggregate_credit::aggregate_credit,
+    apply_attribution_window::apply_attribution_window,
+    credit_capping::{credit_capping, zero_negative_credits},
+    dedup::zero_duplicate_trigger_values,
+    input::{ApplyAttributionWindowInputRow, CreditCappingInputRow},
 };
 use crate::{
     error::Error,
     ff::{Field, Gf2, PrimeField, Serializable},
     helpers::query::IpaQueryConfig,
     protocol::{
-        basics::SecureMul,
-        boolean::{bitwise_equal::bitwise_equal_gf2, or::or},
+        basics::{Reveal, SecureMul},
+        boolean::{all_zeroes, bitwise_equal::bitwise_equal_gf2, or::or},
         context::{Context, UpgradableContext, UpgradedContext, Validator},
         ipa::{ArithmeticallySharedIPAInputs, BinarySharedIPAInputs},
         modulus_conversion::convert_bits,
@@ -44,7 +48,6 @@ use crate::{
 ///
 /// # Errors
 /// propagates errors from multiplications
-#[tracing::instrument(name = "attribute", skip_all)]
 pub async fn secure_attribution<V, VB, C, S, SB, F>(
     validator: V,
     binary_validator: VB,
@@ -52,6 +55,174 @@ pub async fn secure_attribution<V, VB, C, S, SB, F>(
     binary_shared_values: Vec<BinarySharedIPAInputs<SB>>,
     config: IpaQueryConfig,
 ) -> Result<Vec<Replicated<F>>, Error>
+where
+    V: Validator<C, F>,
+    VB: Validator<C, Gf2>,
+    C: UpgradableContext<Validator<F> = V>,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F>
+        + BasicProtocols<C::UpgradedContext<F>, F>
+        + Serializable
+        + DowngradeMalicious<Target = Replicated<F>>
+        + 'static,
+    for<'a> &'a S: LinearRefOps<'a, S, F>,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB> + Context,
+    SB: LinearSecretSharing<Gf2>
+        + BasicProtocols<C::UpgradedContext<Gf2>, Gf2>
+        + DowngradeMalicious<Target = Replicated<Gf2>>
+        + 'static,
+    for<'a> &'a SB: LinearRefOps<'a, SB, Gf2>,
+    F: PrimeField + ExtendableField,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+{
+    let (output, _, _) = attribute_credit(
+        validator,
+        binary_validator,
+        arithmetically_shared_values,
+        binary_shared_values,
+        config,
+        AttributionOutputs::Histogram,
+    )
+    .await?;
+    Ok(output)
+}
+
+/// Same as [`secure_attribution`], but also returns totals that reconcile the attributed credit
+/// with the input. Computing them costs one extra multiplication per row.
+///
+/// # Errors
+/// propagates errors from multiplications
+///
+/// # Panics
+/// Never.
+pub async fn secure_attribution_with_reconciliation<V, VB, C, S, SB, F>(
+    validator: V,
+    binary_validator: VB,
+    arithmetically_shared_values: Vec<ArithmeticallySharedIPAInputs<F, S>>,
+    binary_shared_values: Vec<BinarySharedIPAInputs<SB>>,
+    config: IpaQueryConfig,
+) -> Result<(Vec<Replicated<F>>, CreditReconciliation<Replicated<F>>), Error>
+where
+    V: Validator<C, F>,
+    VB: Validator<C, Gf2>,
+    C: UpgradableContext<Validator<F> = V>,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F>
+        + BasicProtocols<C::UpgradedContext<F>, F>
+        + Serializable
+        + DowngradeMalicious<Target = Replicated<F>>
+        + 'static,
+    for<'a> &'a S: LinearRefOps<'a, S, F>,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB> + Context,
+    SB: LinearSecretSharing<Gf2>
+        + BasicProtocols<C::UpgradedContext<Gf2>, Gf2>
+        + DowngradeMalicious<Target = Replicated<Gf2>>
+        + 'static,
+    for<'a> &'a SB: LinearRefOps<'a, SB, Gf2>,
+    F: PrimeField + ExtendableField,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+{
+    let (output, totals, _) = attribute_credit(
+        validator,
+        binary_validator,
+        arithmetically_shared_values,
+        binary_shared_values,
+        config,
+        AttributionOutputs::Reconciliation,
+    )
+    .await?;
+    let [total_input_credit, total_attributed_credit, total_capped] =
+        <[_; 3]>::try_from(totals).unwrap();
+    Ok((
+        output,
+        CreditReconciliation {
+            total_input_credit,
+            total_attributed_credit,
+            total_capped,
+        },
+    ))
+}
+
+/// Same as [`secure_attribution`], but also returns the intermediate values of attribution, see
+/// [`AttributionTrace`]. This is meant for comparing every stage of attribution with a reference
+/// implementation in tests.
+///
+/// # Errors
+/// propagates errors from multiplications
+///
+/// # Panics
+/// Never.
+pub async fn secure_attribution_with_trace<V, VB, C, S, SB, F>(
+    validator: V,
+    binary_validator: VB,
+    arithmetically_shared_values: Vec<ArithmeticallySharedIPAInputs<F, S>>,
+    binary_shared_values: Vec<BinarySharedIPAInputs<SB>>,
+    config: IpaQueryConfig,
+) -> Result<(Vec<Replicated<F>>, AttributionTrace<Replicated<F>>), Error>
+where
+    V: Validator<C, F>,
+    VB: Validator<C, Gf2>,
+    C: UpgradableContext<Validator<F> = V>,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F>
+        + BasicProtocols<C::UpgradedContext<F>, F>
+        + Serializable
+        + DowngradeMalicious<Target = Replicated<F>>
+        + 'static,
+    for<'a> &'a S: LinearRefOps<'a, S, F>,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB> + Context,
+    SB: LinearSecretSharing<Gf2>
+        + BasicProtocols<C::UpgradedContext<Gf2>, Gf2>
+        + DowngradeMalicious<Target = Replicated<Gf2>>
+        + 'static,
+    for<'a> &'a SB: LinearRefOps<'a, SB, Gf2>,
+    F: PrimeField + ExtendableField,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+{
+    let (output, _, trace) = attribute_credit(
+        validator,
+        binary_validator,
+        arithmetically_shared_values,
+        binary_shared_values,
+        config,
+        AttributionOutputs::Trace,
+    )
+    .await?;
+    let [is_trigger_bits, trigger_values, helper_bits, accumulated_credits, capped_credits] =
+        <[_; 5]>::try_from(trace).unwrap();
+    Ok((
+        output,
+        AttributionTrace {
+            is_trigger_bits,
+            trigger_values,
+            helper_bits,
+            accumulated_credits,
+            capped_credits,
+        },
+    ))
+}
+
+/// Runs attribution and computes the extra values that `outputs` asks for. The totals of
+/// [`CreditReconciliation`] are returned in the order of its fields for
+/// [`AttributionOutputs::Reconciliation`], the intermediate values of [`AttributionTrace`] for
+/// [`AttributionOutputs::Trace`]. Whatever was not asked for is empty.
+#[tracing::instrument(name = "attribute", skip_all)]
+#[allow(clippy::too_many_lines, clippy::type_complexity)]
+async fn attribute_credit<V, VB, C, S, SB, F>(
+    validator: V,
+    binary_validator: VB,
+    mut arithmetically_shared_values: Vec<ArithmeticallySharedIPAInputs<F, S>>,
+    binary_shared_values: Vec<BinarySharedIPAInputs<SB>>,
+    config: IpaQueryConfig,
+    outputs: AttributionOutputs,
+) -> Result<
+    (
+        Vec<Replicated<F>>,
+        Vec<Replicated<F>>,
+        Vec<Vec<Replicated<F>>>,
+    ),
+    Error,
+>
 where
     V: Validator<C, F>,
     VB: Validator<C, Gf2>,
@@ -81,15 +252,36 @@ where
     // This propagates throughout aggregation (all the code understands this).
     // And a breakdown for the last row isn't necessary because an impression on that row can't convert.
     // So we drop the last breakdown key right away.
-    let helper_bits_gf2 = compute_helper_bits_gf2(m_binary_ctx, &binary_shared_values).await?;
+    let helper_bits_gf2 =
+        compute_helper_bits_gf2(m_binary_ctx.clone(), &binary_shared_values).await?;
     let breakdown_key_bits_gf2: Vec<_> = binary_shared_values
         .iter()
         .map(|x| x.breakdown_key.clone())
         .take(row_count - 1)
         .collect();
 
-    let (validated_helper_bits_gf2, validated_breakdown_key_bits_gf2) = binary_validator
-        .validate((helper_bits_gf2, breakdown_key_bits_gf2))
+    let same_keys_gf2 = if config.dedup {
+        compute_same_keys_gf2(m_binary_ctx.clone(), &binary_shared_values).await?
+    } else {
+        Vec::new()
+    };
+    let zero_match_keys_gf2 = if config.drop_zero_match_keys {
+        compute_zero_match_keys_gf2(m_binary_ctx, &binary_shared_values).await?
+    } else {
+        Vec::new()
+    };
+
+    let (
+        validated_helper_bits_gf2,
+        (
+            validated_breakdown_key_bits_gf2,
+            (validated_same_keys_gf2, validated_zero_match_keys_gf2),
+        ),
+    ) = binary_validator
+        .validate((
+            helper_bits_gf2,
+            (breakdown_key_bits_gf2, (same_keys_gf2, zero_match_keys_gf2)),
+        ))
         .await?;
 
     let convert_ctx = m_ctx
@@ -100,20 +292,95 @@ where
         .try_collect::<Vec<_>>()
         .await?;
 
+    if config.dedup {
+        let convert_ctx = m_ctx
+            .narrow(&AttributionStep::ConvertSameKeys)
+            .set_total_records(validated_same_keys_gf2.len());
+        let same_keys = convert_bits(convert_ctx, stream_iter(validated_same_keys_gf2), 0..1)
+            .map_ok(|b| b.into_iter().next().unwrap())
+            .try_collect::<Vec<_>>()
+            .await?;
+        zero_duplicate_trigger_values(
+            m_ctx.narrow(&AttributionStep::Dedup),
+            &mut arithmetically_shared_values,
+            &same_keys,
+        )
+        .await?;
+    }
+
+    if config.drop_zero_match_keys {
+        let convert_ctx = m_ctx
+            .narrow(&AttributionStep::ConvertZeroMatchKeys)
+            .set_total_records(validated_zero_match_keys_gf2.len());
+        let zero_match_keys = convert_bits(
+            convert_ctx,
+            stream_iter(validated_zero_match_keys_gf2),
+            0..1,
+        )
+        .map_ok(|b| b.into_iter().next().unwrap())
+        .try_collect::<Vec<_>>()
+        .await?;
+        drop_zero_match_keys(
+            m_ctx.narrow(&AttributionStep::DropZeroMatchKeys),
+            &mut arithmetically_shared_values,
+            &zero_match_keys,
+        )
+        .await?;
+    }
+
     let is_trigger_bits = arithmetically_shared_values
         .iter()
         .map(|x| x.is_trigger_bit.clone())
         .collect::<Vec<_>>();
+    let mut trace_values = Vec::new();
+    if outputs == AttributionOutputs::Trace {
+        trace_values.push(is_trigger_bits.clone());
+        trace_values.push(
+            arithmetically_shared_values
+                .iter()
+                .map(|x| x.trigger_value.clone())
+                .collect(),
+        );
+    }
     let stop_bits = compute_stop_bits(m_ctx.clone(), &is_trigger_bits, &helper_bits)
         .await?
         .collect::<Vec<_>>();
 
     // Semantically, `helper_bit` indicates if the preceding row has the same value of `match_key`.
-    // For the first row, this cannot be the case as there is no preceding row, so we just provide a zero.
+    // There is no preceding row for the first one, so it starts a new user, unless the caller says
+    // that it continues the last user of the previous chunk. Either way, stop bits are only
+    // computed from the second row on, so no credit ever flows into the first row from before it.
     debug_assert_eq!(arithmetically_shared_values.len(), helper_bits.len() + 1);
+    debug_assert_eq!(helper_bits.len(), stop_bits.len());
+    let first_helper_bit = if config.first_row_continues_previous {
+        S::share_known_value(&m_ctx, F::ONE)
+    } else {
+        S::ZERO
+    };
+    if outputs == AttributionOutputs::Trace {
+        trace_values.push(
+            iter_once(first_helper_bit.clone())
+                .chain(helper_bits.iter().cloned())
+                .collect(),
+        );
+    }
+    // Trigger values of source events are always zero, so summing all rows gives the total
+    // credit in the input. With a cap of one, every trigger event is worth one credit instead.
+    let total_input_credit = (outputs == AttributionOutputs::Reconciliation).then(|| {
+        arithmetically_shared_values
+            .iter()
+            .fold(S::ZERO, |acc, row| {
+                if config.per_user_credit_cap == 1 {
+                    acc + &row.is_trigger_bit
+                } else {
+                    acc + &row.trigger_value
+                }
+            })
+    });
+
     let attribution_input_rows = zip(
         arithmetically_shared_values,
-        iter_once(S::ZERO).chain(helper_bits),
+        iter_once(first_helper_bit).chain(helper_bits),
     )
     .map(|(arithmetic, hb)| {
         ApplyAttributionWindowInputRow::new(
@@ -133,21 +400,66 @@ where
     )
     .await?;
 
-    let accumulated_credits = accumulate_credit(
+    let mut accumulated_credits = accumulate_credit(
         m_ctx.narrow(&AttributionStep::AccumulateCredit),
         &windowed_reports,
         &stop_bits,
         config.per_user_credit_cap,
+        config.max_trigger_value.map(|max| {
+            // signed trigger values range from `-max` to `max`
+            u128::from(max.get()) * if config.signed_trigger_values { 2 } else { 1 }
+        }),
         config.attribution_window_seconds,
     )
     .await?;
 
+    if config.signed_trigger_values && config.per_user_credit_cap != 1 {
+        zero_negative_credits(
+            m_ctx.narrow(&AttributionStep::ZeroNegativeCredits),
+            &mut accumulated_credits,
+        )
+        .await?;
+    }
+
     let user_capped_credits = credit_capping(
         m_ctx.narrow(&AttributionStep::PerformUserCapping),
         &accumulated_credits,
         config.per_user_credit_cap,
     )
     .await?;
+    if outputs == AttributionOutputs::Trace {
+        trace_values.push(
+            accumulated_credits
+                .iter()
+                .map(|x| x.trigger_value.clone())
+                .collect(),
+        );
+        trace_values.push(user_capped_credits.clone());
+    }
+
+    let totals = if let Some(total_input_credit) = total_input_credit {
+        let total_uncapped_credit = sum_source_credits(
+            m_ctx.narrow(&AttributionStep::ReconcileAttributedCredit),
+            &accumulated_credits,
+        )
+        .await?;
+        let total_attributed_credit = user_capped_credits
+            .iter()
+            .fold(S::ZERO, |acc, credit| acc + credit);
+        let total_capped = total_uncapped_credit - &total_attributed_credit;
+        vec![total_input_credit, total_attributed_credit, total_capped]
+    } else {
+        Vec::new()
+    };
+
+    // The capped total is validated together with the output, before the check reveals anything.
+    let capped_total = if config.check_histogram_total {
+        vec![user_capped_credits
+            .iter()
+            .fold(S::ZERO, |acc, credit| acc + credit)]
+    } else {
+        Vec::new()
+    };
 
     let (validator, output) = aggregate_credit(
         validator,
@@ -158,15 +470,142 @@ where
     .await?;
 
     //Validate before returning the result to the report collector
-    validator.validate(output).await
+    let (output, (totals, (trace_values, capped_total))) = validator
+        .validate((output, (totals, (trace_values, capped_total))))
+        .await?;
+
+    if let Some(capped_total) = capped_total.first() {
+        check_histogram_total(
+            m_ctx.narrow(&AttributionStep::CheckHistogramTotal),
+            &output,
+            capped_total,
+        )
+        .await?;
+    }
+
+    Ok((output, totals, trace_values))
+}
+
+/// Checks that every capped credit ended up in exactly one breakdown bucket, so the histogram adds
+/// up to the total of capped credits. Only the difference between the two totals is revealed,
+/// so nothing is learned about them when the check passes.
+///
+/// ## Errors
+/// [`Error::HistogramTotalMismatch`] if the totals differ, or if revealing their difference fails.
+async fn check_histogram_total<C, F>(
+    ctx: C,
+    histogram: &[Replicated<F>],
+    capped_total: &Replicated<F>,
+) -> Result<(), Error>
+where
+    C: Context,
+    F: Field,
+{
+    let histogram_total = histogram
+        .iter()
+        .fold(Replicated::ZERO, |acc, value| acc + value);
+    let difference = (histogram_total - capped_total)
+        .reveal(ctx.set_total_records(1), RecordId::FIRST)
+        .await?;
+    if difference == F::ZERO {
+        Ok(())
+    } else {
+        Err(Error::HistogramTotalMismatch)
+    }
+}
+
+/// Values that attribution computes in addition to the histogram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AttributionOutputs {
+    /// Just the histogram.
+    Histogram,
+    /// The histogram and the [`CreditReconciliation`] totals.
+    Reconciliation,
+    /// The histogram and the [`AttributionTrace`] of every row.
+    Trace,
+}
+
+/// Sums the credit that [`accumulate_credit`] assigned to source events, before per-user capping.
+async fn sum_source_credits<F, C, S>(
+    ctx: C,
+    accumulated_credits: &[CreditCappingInputRow<F, S>],
+) -> Result<S, Error>
+where
+    F: Field,
+    C: Context,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+{
+    let ctx = ctx.set_total_records(accumulated_credits.len());
+    let one = S::share_known_value(&ctx, F::ONE);
+    let source_credits = ctx
+        .try_join(accumulated_credits.iter().enumerate().map(|(i, row)| {
+            let c = ctx.clone();
+            let is_source = one.clone() - &row.is_trigger_report;
+            async move {
+                row.trigger_value
+                    .multiply(&is_source, c, RecordId::from(i))
+                    .await
+            }
+        }))
+        .await?;
+
+    Ok(source_credits
+        .iter()
+        .fold(S::ZERO, |acc, credit| acc + credit))
+}
+
+/// Secret-shared totals that let operators reconcile the attributed credit with the input.
+///
+/// Credit of trigger events that could not be attributed to any source event, for example because
+/// the user has no source events or they are outside of the attribution window, is
+/// `total_input_credit - total_attributed_credit - total_capped`. When `per_user_credit_cap` is
+/// one, every trigger event counts as one credit regardless of its trigger value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CreditReconciliation<T> {
+    /// Sum of trigger values of all input rows.
+    pub total_input_credit: T,
+    /// Credit assigned to source events after per-user capping. This is the sum of the output
+    /// histogram, as long as all breakdown keys are below `max_breakdown_key`.
+    pub total_attributed_credit: T,
+    /// Credit that was attributed to source events, but then dropped by per-user capping.
+    pub total_capped: T,
+}
+
+/// Intermediate values of attribution, one per input row sorted by match key. See
+/// [`secure_attribution_with_trace`].
+///
+/// Revealing any of these leaks far more than the histogram, so they must only be revealed in
+/// tests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttributionTrace<T> {
+    /// Whether the row is a trigger event. Rows with a zero match key are not if
+    /// `drop_zero_match_keys` is set.
+    pub is_trigger_bits: Vec<T>,
+    /// Trigger values, after duplicate trigger events are zeroed if `dedup` is set and rows with a
+    /// zero match key are zeroed if `drop_zero_match_keys` is set.
+    pub trigger_values: Vec<T>,
+    /// Whether the row belongs to the same user as the previous one.
+    pub helper_bits: Vec<T>,
+    /// Credit accumulated by every row from the trigger events that follow it. With a cap of one,
+    /// this is one for rows that are immediately followed by a trigger event of the same user.
+    pub accumulated_credits: Vec<T>,
+    /// Credit of source events after per-user capping.
+    pub capped_credits: Vec<T>,
 }
 
 #[derive(Step)]
 pub(crate) enum AttributionStep {
     ConvertHelperBits,
+    ConvertSameKeys,
+    Dedup,
+    ConvertZeroMatchKeys,
+    DropZeroMatchKeys,
     ApplyAttributionWindow,
     AccumulateCredit,
+    ZeroNegativeCredits,
     PerformUserCapping,
+    ReconcileAttributedCredit,
+    CheckHistogramTotal,
 }
 
 ///
@@ -426,6 +865,170 @@ where
     .await
 }
 
+/// Computes, for every pair of adjacent rows, whether both their match keys and breakdown keys
+/// are equal.
+async fn compute_same_keys_gf2<C, S>(
+    ctx: C,
+    binary_shared_values: &[BinarySharedIPAInputs<S>],
+) -> Result<Vec<S>, Error>
+where
+    C: Context,
+    S: LinearSecretSharing<Gf2> + BasicProtocols<C, Gf2>,
+    for<'a> &'a S: LinearRefOps<'a, S, Gf2>,
+{
+    let narrowed_ctx = ctx
+        .narrow(&Step::ComputeSameKeys)
+        .set_total_records(binary_shared_values.len() - 1);
+    let keys = |row: &BinarySharedIPAInputs<S>| {
+        row.match_key
+            .iter()
+            .chain(row.breakdown_key.iter())
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    ctx.try_join(
+        binary_shared_values
+            .windows(2)
+            .enumerate()
+            .map(|(i, rows)| {
+                let c = narrowed_ctx.clone();
+                let record_id = RecordId::from(i);
+                let (a, b) = (keys(&rows[0]), keys(&rows[1]));
+                async move { bitwise_equal_gf2(c, record_id, &a, &b).await }
+            }),
+    )
+    .await
+}
+
+/// Computes, for every row, whether all bits of its match key are zero. Only shares of the
+/// result are produced, so nobody learns which rows have a zero match key.
+async fn compute_zero_match_keys_gf2<C, S>(
+    ctx: C,
+    binary_shared_values: &[BinarySharedIPAInputs<S>],
+) -> Result<Vec<S>, Error>
+where
+    C: Context,
+    S: LinearSecretSharing<Gf2> + BasicProtocols<C, Gf2>,
+    for<'a> &'a S: LinearRefOps<'a, S, Gf2>,
+{
+    let narrowed_ctx = ctx
+        .narrow(&Step::ComputeZeroMatchKeys)
+        .set_total_records(binary_shared_values.len());
+
+    ctx.try_join(binary_shared_values.iter().enumerate().map(|(i, row)| {
+        let c = narrowed_ctx.clone();
+        let record_id = RecordId::from(i);
+        async move { all_zeroes(c, record_id, &row.match_key).await }
+    }))
+    .await
+}
+
+/// Turns every row with a zero match key into a source event without value, so it neither
+/// contributes credit nor, because every other row of that "user" is dropped too, receives any.
+/// Clearing the trigger bit as well matters when the per-user cap is one, because then every
+/// trigger event is worth one credit regardless of its value.
+///
+/// `zero_match_keys[i]` must be a sharing of one if row `i` has a zero match key, and zero
+/// otherwise.
+///
+/// # Errors
+/// Propagates errors from multiplications.
+async fn drop_zero_match_keys<F, C, S>(
+    ctx: C,
+    rows: &mut [ArithmeticallySharedIPAInputs<F, S>],
+    zero_match_keys: &[S],
+) -> Result<(), Error>
+where
+    F: PrimeField,
+    C: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C, F>,
+    for<'a> &'a S: LinearRefOps<'a, S, F>,
+{
+    assert_eq!(rows.len(), zero_match_keys.len());
+
+    let ctx = ctx.set_total_records(rows.len());
+    let trigger_bit_ctx = ctx.narrow(&Step::DropTriggerBit);
+    let trigger_value_ctx = ctx.narrow(&Step::DropTriggerValue);
+
+    let rows_ref = &*rows;
+    let kept = ctx
+        .try_join(
+            zip(rows_ref, zero_match_keys)
+                .enumerate()
+                .map(|(i, (row, is_zero))| {
+                    let record_id = RecordId::from(i);
+                    let trigger_bit_ctx = trigger_bit_ctx.clone();
+                    let trigger_value_ctx = trigger_value_ctx.clone();
+                    async move {
+                        let (dropped_bit, dropped_value) = try_join(
+                            row.is_trigger_bit
+                                .multiply(is_zero, trigger_bit_ctx, record_id),
+                            row.trigger_value
+                                .multiply(is_zero, trigger_value_ctx, record_id),
+                        )
+                        .await?;
+                        Ok::<_, Error>((
+                            row.is_trigger_bit.clone() - &dropped_bit,
+                            row.trigger_value.clone() - &dropped_value,
+                        ))
+                    }
+                }),
+        )
+        .await?;
+
+    for (row, (is_trigger_bit, trigger_value)) in rows.iter_mut().zip(kept) {
+        row.is_trigger_bit = is_trigger_bit;
+        row.trigger_value = trigger_value;
+    }
+
+    Ok(())
+}
+
+/// Reveals the user boundaries of the input sorted by match key, i.e. the indices of the rows
+/// whose match key differs from the match key of the preceding row. The first row is never
+/// reported as a boundary.
+///
+/// This discloses the number of events every user has to all helpers. It must only be used when
+/// that is acceptable, for example to split inputs that are too large to be processed at once,
+/// see [`partition_by_user_boundary`].
+///
+/// ## Errors
+/// Propagates errors from multiplications and reveal.
+///
+/// [`partition_by_user_boundary`]: crate::protocol::ipa::partition_by_user_boundary
+pub async fn reveal_user_boundaries<C, S>(
+    ctx: C,
+    binary_shared_values: &[BinarySharedIPAInputs<S>],
+) -> Result<Vec<usize>, Error>
+where
+    C: Context,
+    S: LinearSecretSharing<Gf2> + BasicProtocols<C, Gf2>,
+    for<'a> &'a S: LinearRefOps<'a, S, Gf2>,
+{
+    if binary_shared_values.len() < 2 {
+        return Ok(Vec::new());
+    }
+
+    let helper_bits = compute_helper_bits_gf2(ctx.clone(), binary_shared_values).await?;
+    let reveal_ctx = ctx
+        .narrow(&Step::RevealUserBoundaries)
+        .set_total_records(helper_bits.len());
+    let helper_bits = ctx
+        .try_join(helper_bits.iter().enumerate().map(|(i, helper_bit)| {
+            let c = reveal_ctx.clone();
+            async move { helper_bit.reveal(c, RecordId::from(i)).await }
+        }))
+        .await?;
+
+    // helper bit `i` compares rows `i` and `i + 1`
+    Ok(helper_bits
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, same_user)| (same_user != Gf2::ONE).then_some(i + 1))
+        .collect())
+}
+
 #[derive(Step)]
 #[allow(clippy::enum_variant_names)]
 pub(in crate::protocol) enum Step {
@@ -433,7 +1036,12 @@ pub(in crate::protocol) enum Step {
     CurrentStopBitTimesSuccessorStopBit,
     CurrentCreditOrCreditUpdate,
     ComputeHelperBits,
+    ComputeSameKeys,
+    ComputeZeroMatchKeys,
     ComputeStopBits,
+    DropTriggerBit,
+    DropTriggerValue,
+    RevealUserBoundaries,
 }
 
 #[derive(Step)]
@@ -447,3 +1055,46 @@ impl From<usize> for InteractionPatternStep {
         Self::Depth(v)
     }
 }
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::check_histogram_total;
+    use crate::{
+        error::Error,
+        ff::{Field, Fp31},
+        secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
+        test_fixture::{Runner, TestWorld},
+    };
+
+    async fn run_check(histogram: &[u128], capped_total: u128) -> [Result<(), Error>; 3] {
+        let world = TestWorld::default();
+        let histogram = histogram
+            .iter()
+            .map(|&v| Fp31::truncate_from(v))
+            .collect::<Vec<_>>();
+
+        world
+            .semi_honest(
+                (histogram.into_iter(), Fp31::truncate_from(capped_total)),
+                |ctx, (histogram, capped_total): (Vec<Replicated<Fp31>>, Replicated<Fp31>)| async move {
+                    check_histogram_total(ctx, &histogram, &capped_total).await
+                },
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn histogram_adds_up() {
+        for result in run_check(&[0, 2, 3, 0, 1], 6).await {
+            result.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn dropped_bucket() {
+        // bucket 2 with three credits went missing
+        for result in run_check(&[0, 2, 0, 1], 6).await {
+            assert!(matches!(result, Err(Error::HistogramTotalMismatch)));
+        }
+    }
+}
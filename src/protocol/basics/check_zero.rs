@@ -61,7 +61,7 @@ pub async fn check_zero<C: Context, F: Field>(
         .reveal(ctx.narrow(&Step::RevealR), record_id)
         .await?;
 
-    Ok(rv == F::ZERO)
+    Ok(rv.ct_eq(&F::ZERO).into())
 }
 
 #[cfg(all(test, unit_test))]
@@ -1,6 +1,7 @@
 pub mod check_zero;
 mod if_else;
 pub(crate) mod mul;
+mod output_party;
 mod reshare;
 mod reveal;
 mod share_known_value;
@@ -9,6 +10,7 @@ pub mod sum_of_product;
 pub use check_zero::check_zero;
 pub use if_else::if_else;
 pub use mul::{MultiplyZeroPositions, SecureMul, ZeroPositions};
+pub use output_party::{deliver_to_output_party, OutputParty};
 pub use reshare::Reshare;
 pub use reveal::Reveal;
 pub use share_known_value::ShareKnownValue;
@@ -1,3 +1,5 @@
+#[cfg(any(test, feature = "count-multiplications"))]
+use crate::telemetry::{labels::ROLE, metrics::MULTIPLICATIONS};
 use crate::{
     error::Error,
     ff::Field,
@@ -23,6 +25,10 @@ use crate::{
 ///
 /// The `zeros_at` argument indicates where there are known zeros in the inputs.
 ///
+/// With the `count-multiplications` feature, every call is counted in the `multiplications`
+/// metric, including sparse multiplications that do not need to communicate. Malicious
+/// multiplication calls this twice.
+///
 /// ## Errors
 /// Lots of things may go wrong here, from timeouts to bad output. They will be signalled
 /// back via the error response
@@ -38,6 +44,8 @@ where
     F: Field,
 {
     let role = ctx.role();
+    #[cfg(any(test, feature = "count-multiplications"))]
+    metrics::increment_counter!(MULTIPLICATIONS, ROLE => role.as_static_str());
     let [need_to_recv, need_to_send, need_random_right] = zeros.work_for(role);
     zeros.0.check(role, "a", a);
     zeros.1.check(role, "b", b);
@@ -92,7 +100,8 @@ mod test {
         protocol::{basics::SecureMul, context::Context, RecordId},
         rand::{thread_rng, Rng},
         seq_join::SeqJoin,
-        test_fixture::{Reconstruct, Runner, TestWorld},
+        telemetry::metrics::MULTIPLICATIONS,
+        test_fixture::{Reconstruct, Runner, TestWorld, TestWorldConfig},
     };
 
     #[tokio::test]
@@ -162,6 +171,39 @@ mod test {
         assert_eq!(expected, results.reconstruct());
     }
 
+    #[tokio::test]
+    pub async fn counts_multiplications() {
+        const COUNT: usize = 10;
+        let world = TestWorld::new_with(TestWorldConfig::default().enable_metrics());
+
+        let mut rng = thread_rng();
+        let a = (0..COUNT).map(|_| rng.gen::<Fp31>()).collect::<Vec<_>>();
+        let b = (0..COUNT).map(|_| rng.gen::<Fp31>()).collect::<Vec<_>>();
+        let _: Vec<Fp31> = world
+            .semi_honest(
+                (a.into_iter(), b.into_iter()),
+                |ctx, (a_shares, b_shares)| async move {
+                    let ctx = ctx.set_total_records(COUNT);
+                    ctx.try_join(zip(a_shares, b_shares).enumerate().map(
+                        |(i, (a_share, b_share))| {
+                            let ctx = ctx.clone();
+                            async move { a_share.multiply(&b_share, ctx, RecordId::from(i)).await }
+                        },
+                    ))
+                    .await
+                    .unwrap()
+                },
+            )
+            .await
+            .reconstruct();
+
+        // every helper counts every multiplication
+        assert_eq!(
+            3 * u64::try_from(COUNT).unwrap(),
+            world.metrics_snapshot().get_counter(MULTIPLICATIONS)
+        );
+    }
+
     async fn multiply_sync<F>(world: &TestWorld, a: u128, b: u128) -> u128
     where
         F: Field,
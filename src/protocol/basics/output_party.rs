@@ -0,0 +1,171 @@
+use async_trait::async_trait;
+
+use crate::{
+    error::Error,
+    ff::Field,
+    helpers::Role,
+    protocol::{context::Context, prss::SharedRandomness, RecordId},
+    secret_sharing::replicated::{
+        semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing,
+    },
+};
+
+/// A party outside of the MPC ring that is designated to receive the protocol output, for
+/// example a report collector that holds no shares while the computation is running.
+#[async_trait]
+pub trait OutputParty<F: Field>: Send + Sync {
+    /// Accepts additive shares of the output from helper `from`. The output party learns the
+    /// result once it has received shares from all three helpers.
+    ///
+    /// ## Errors
+    /// If the shares cannot be delivered to the output party.
+    async fn receive(&self, from: Role, shares: Vec<F>) -> Result<(), Error>;
+}
+
+/// Delivers `values` to the output party instead of revealing them inside the MPC ring.
+///
+/// Each helper converts its replicated share into a fresh additive share by adding a PRSS-based
+/// sharing of zero, and sends only that to the output party. Helpers never exchange shares with
+/// each other, so none of them learns the output.
+///
+/// ## Errors
+/// If the output party rejects the shares.
+pub async fn deliver_to_output_party<C, F, O>(
+    ctx: C,
+    values: &[Replicated<F>],
+    output_party: &O,
+) -> Result<(), Error>
+where
+    C: Context,
+    F: Field,
+    O: OutputParty<F> + ?Sized,
+{
+    let shares = {
+        let prss = ctx.prss();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                // Left randomness of this helper is the right randomness of the helper on the left,
+                // so these cancel out when the output party adds all three shares together.
+                let (left, right): (F, F) = prss.generate_fields(RecordId::from(i));
+                value.left() + left - right
+            })
+            .collect::<Vec<_>>()
+    };
+
+    output_party.receive(ctx.role(), shares).await
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::{deliver_to_output_party, OutputParty};
+    use crate::{
+        error::Error,
+        ff::{Field, Fp31},
+        helpers::{query::IpaQueryConfig, Role},
+        ipa_test_input,
+        protocol::{context::Context, ipa::ipa, BreakdownKey, MatchKey},
+        sync::Mutex,
+        test_fixture::{input::GenericReportTestInput, Runner, TestWorld},
+    };
+
+    /// Output party that collects shares from all helpers and reconstructs the result.
+    #[derive(Default)]
+    struct MockOutputParty {
+        received: Mutex<[Option<Vec<Fp31>>; 3]>,
+    }
+
+    impl MockOutputParty {
+        fn reconstruct(&self) -> Vec<Fp31> {
+            let received = self.received.lock().unwrap();
+            let [Some(s1), Some(s2), Some(s3)] = &*received else {
+                panic!("not all helpers delivered their shares");
+            };
+            assert_eq!(s1.len(), s2.len());
+            assert_eq!(s1.len(), s3.len());
+
+            s1.iter()
+                .zip(s2)
+                .zip(s3)
+                .map(|((&a, &b), &c)| a + b + c)
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl OutputParty<Fp31> for MockOutputParty {
+        async fn receive(&self, from: Role, shares: Vec<Fp31>) -> Result<(), Error> {
+            let prev = self.received.lock().unwrap()[from].replace(shares);
+            assert!(prev.is_none(), "{from:?} delivered shares twice");
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn output_party_reconstructs_histogram() {
+        const HISTOGRAM: [u128; 8] = [0, 2, 3, 0, 17, 0, 1, 30];
+
+        let world = TestWorld::default();
+        let output_party = MockOutputParty::default();
+        let histogram = HISTOGRAM.map(Fp31::truncate_from);
+
+        world
+            .semi_honest(histogram.into_iter(), |ctx, shares| {
+                let output_party = &output_party;
+                async move {
+                    deliver_to_output_party(ctx, &shares, output_party)
+                        .await
+                        .unwrap();
+                }
+            })
+            .await;
+
+        assert_eq!(histogram.to_vec(), output_party.reconstruct());
+    }
+
+    #[tokio::test]
+    async fn output_party_receives_ipa_histogram() {
+        const EXPECTED: [u128; 8] = [0, 2, 3, 0, 0, 0, 0, 0];
+
+        let world = TestWorld::default();
+        let output_party = MockOutputParty::default();
+        let config = &IpaQueryConfig::no_window(3, 8, 3);
+
+        let records: Vec<GenericReportTestInput<Fp31, MatchKey, BreakdownKey>> = ipa_test_input!(
+            [
+                { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+            ];
+            (Fp31, MatchKey, BreakdownKey)
+        );
+
+        world
+            .semi_honest(records.into_iter(), |ctx, input_rows| {
+                let output_party = &output_party;
+                async move {
+                    let histogram = ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                        ctx.narrow("ipa"),
+                        &input_rows,
+                        config.clone(),
+                    )
+                    .await
+                    .unwrap();
+                    deliver_to_output_party(ctx.narrow("deliver"), &histogram, output_party)
+                        .await
+                        .unwrap();
+                }
+            })
+            .await;
+
+        assert_eq!(
+            EXPECTED.map(Fp31::truncate_from).to_vec(),
+            output_party.reconstruct()
+        );
+    }
+}
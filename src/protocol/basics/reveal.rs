@@ -54,6 +54,7 @@ impl<C: Context, F: Field> Reveal<C, RecordId> for Replicated<F> {
     where
         C: 'fut,
     {
+        ctx.spend_reveal_budget()?;
         let (left, right) = self.as_tuple();
 
         ctx.send_channel(ctx.role().peer(Direction::Right))
@@ -90,6 +91,7 @@ impl<'a, F: ExtendableField> Reveal<UpgradedMaliciousContext<'a, F>, RecordId>
     {
         use crate::secret_sharing::replicated::malicious::ThisCodeIsAuthorizedToDowngradeFromMalicious;
 
+        ctx.spend_reveal_budget()?;
         let (left, right) = self.x().access_without_downgrade().as_tuple();
         let left_sender = ctx.send_channel(ctx.role().peer(Direction::Left));
         let left_receiver = ctx.recv_channel::<F>(ctx.role().peer(Direction::Left));
@@ -109,7 +111,7 @@ impl<'a, F: ExtendableField> Reveal<UpgradedMaliciousContext<'a, F>, RecordId>
         )
         .await?;
 
-        if share_from_left == share_from_right {
+        if share_from_left.ct_eq(&share_from_right).into() {
             Ok(left + right + share_from_left)
         } else {
             Err(Error::MaliciousRevealFailed)
@@ -171,9 +173,12 @@ mod tests {
         },
         rand::{thread_rng, Rng},
         secret_sharing::{
-            replicated::malicious::{
-                AdditiveShare as MaliciousReplicated, ExtendableField,
-                ThisCodeIsAuthorizedToDowngradeFromMalicious,
+            replicated::{
+                malicious::{
+                    AdditiveShare as MaliciousReplicated, ExtendableField,
+                    ThisCodeIsAuthorizedToDowngradeFromMalicious,
+                },
+                semi_honest::AdditiveShare as Replicated,
             },
             IntoShares,
         },
@@ -270,6 +275,42 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    pub async fn budget_exceeded() {
+        let mut rng = thread_rng();
+        let world = TestWorld::default();
+
+        let input = [rng.gen::<Fp31>(), rng.gen(), rng.gen()];
+        let results = world
+            .semi_honest(
+                input.into_iter(),
+                |ctx, shares: Vec<Replicated<Fp31>>| async move {
+                    // every value is revealed in a step of its own, they all share the same budget
+                    let ctx = ctx.set_reveal_budget(2);
+                    let mut revealed = Vec::new();
+                    for (i, share) in shares.iter().enumerate() {
+                        let ctx = ctx
+                            .narrow(format!("value{i}").as_str())
+                            .set_total_records(1);
+                        match share.reveal(ctx, RecordId::FIRST).await {
+                            Ok(value) => revealed.push(value),
+                            Err(e) => return (revealed, Some(e)),
+                        }
+                    }
+                    (revealed, None)
+                },
+            )
+            .await;
+
+        for (revealed, err) in results {
+            assert_eq!(input[..2], revealed);
+            assert!(matches!(
+                err,
+                Some(Error::RevealBudgetExceeded { budget: 2 })
+            ));
+        }
+    }
+
     pub async fn reveal_with_additive_attack<F: ExtendableField>(
         ctx: UpgradedMaliciousContext<'_, F>,
         record_id: RecordId,
@@ -3,7 +3,7 @@ use std::iter::zip;
 use crate::{
     error::Error,
     ff::{Field, Gf2},
-    protocol::{boolean::all_zeroes, context::Context, BasicProtocols, RecordId},
+    protocol::{boolean::all_zeroes, context::Context, step::BitOpStep, BasicProtocols, RecordId},
     secret_sharing::{Linear as LinearSecretSharing, LinearRefOps},
 };
 
@@ -67,15 +67,127 @@ where
     all_zeroes(ctx, record_id, &c).await
 }
 
+/// Compares every pair `(a, b)` in `pairs` and returns, for each of them, a share of 1 iff
+/// `a == b`.
+///
+/// This computes the same thing as calling [`bitwise_equal_gf2`] for every pair, but it is
+/// organized column-major: all pairs walk through the multiplication tree together, so every
+/// multiplication in the tree is a single batch of `pairs.len()` records instead of one record per
+/// pair. Pair `i` is processed as record `i` and the steps are the same as in
+/// [`bitwise_equal_gf2`], so `ctx` must be set up for `pairs.len()` records.
+///
+/// # Errors
+/// Propagates errors from multiplications
+///
+/// # Panics
+/// If values are empty or not all of them have the same number of bits.
+pub async fn bitwise_equal_bitsliced<C, S>(ctx: C, pairs: &[(&[S], &[S])]) -> Result<Vec<S>, Error>
+where
+    C: Context,
+    S: LinearSecretSharing<Gf2> + BasicProtocols<C, Gf2>,
+    for<'a> &'a S: LinearRefOps<'a, S, Gf2>,
+{
+    let Some(&(first, _)) = pairs.first() else {
+        return Ok(Vec::new());
+    };
+    let num_bits = first.len();
+    assert!(num_bits > 0, "values must not be empty");
+    assert!(
+        pairs
+            .iter()
+            .all(|(a, b)| a.len() == num_bits && b.len() == num_bits),
+        "all values must have {num_bits} bits"
+    );
+
+    // `columns[k][i]` is a share of 1 iff the k-th bits of both values in pair `i` are equal.
+    let one = S::share_known_value(&ctx, Gf2::ONE);
+    let mut columns = (0..num_bits)
+        .map(|k| {
+            pairs
+                .iter()
+                .map(|(a, b)| &one - &(&a[k] - &b[k]))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    // Same binary tree as `multiply_all_shares`, one column at a time.
+    let mut mult_count = 0_u32;
+    while columns.len() > 1 {
+        let half = columns.len() / 2;
+        let mut multiplications = Vec::with_capacity(half);
+        for i in 0..half {
+            let c = ctx.narrow(&BitOpStep::from(mult_count));
+            multiplications.push(
+                ctx.parallel_join(
+                    zip(&columns[2 * i], &columns[2 * i + 1])
+                        .enumerate()
+                        .map(move |(j, (x, y))| x.multiply(y, c.clone(), RecordId::from(j))),
+                ),
+            );
+            mult_count += 1;
+        }
+        let mut results = ctx.parallel_join(multiplications).await?;
+        if columns.len() % 2 == 1 {
+            results.push(columns.pop().unwrap());
+        }
+        columns = results;
+    }
+
+    Ok(columns.pop().unwrap())
+}
+
 #[cfg(all(test, unit_test))]
 mod tests {
-    use super::bitwise_equal_constant;
+    use super::{bitwise_equal_bitsliced, bitwise_equal_constant, bitwise_equal_gf2};
     use crate::{
-        ff::{Field, Fp31, Fp32BitPrime},
+        ff::{Field, Fp31, Fp32BitPrime, Gf2},
         protocol::{context::Context, RecordId},
+        rand::{thread_rng, Rng},
+        seq_join::SeqJoin,
         test_fixture::{get_bits, Reconstruct, Runner, TestWorld},
     };
 
+    #[tokio::test]
+    pub async fn bitsliced_matches_row_by_row() {
+        const ROWS: usize = 200;
+        const BITS: u32 = 10;
+
+        let world = TestWorld::default();
+        let mut rng = thread_rng();
+        // Small range of values, so a good share of adjacent rows are equal.
+        let input = (0..ROWS)
+            .map(|_| get_bits::<Gf2>(rng.gen_range(0..4), BITS))
+            .collect::<Vec<_>>();
+
+        let (row_by_row, bitsliced): (Vec<Gf2>, Vec<Gf2>) =
+            world
+                .semi_honest(input.into_iter(), |ctx, rows| async move {
+                    let pairs = rows
+                        .windows(2)
+                        .map(|w| (&w[0][..], &w[1][..]))
+                        .collect::<Vec<_>>();
+
+                    let c = ctx.narrow("row_by_row").set_total_records(pairs.len());
+                    let row_by_row = c
+                        .try_join(pairs.iter().enumerate().map(|(i, (a, b))| {
+                            bitwise_equal_gf2(c.clone(), RecordId::from(i), a, b)
+                        }))
+                        .await
+                        .unwrap();
+
+                    let c = ctx.narrow("bitsliced").set_total_records(pairs.len());
+                    let bitsliced = bitwise_equal_bitsliced(c, &pairs).await.unwrap();
+
+                    (row_by_row, bitsliced)
+                })
+                .await
+                .reconstruct();
+
+        assert_eq!(ROWS - 1, bitsliced.len());
+        assert!(bitsliced.contains(&Gf2::ONE));
+        assert_eq!(row_by_row, bitsliced);
+    }
+
     #[tokio::test]
     pub async fn constant() {
         assert_eq!(1, run_bitwise_equal_constant(45, 45, 9).await);
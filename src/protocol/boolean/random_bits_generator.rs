@@ -23,6 +23,9 @@ use crate::{
 ///
 /// This object is safe to share with multiple threads.  It uses an atomic counter
 /// to manage concurrent accesses.
+///
+/// All randomness comes from the PRSS of the context it was created with, so tests that need
+/// reproducible bits should seed the PRSS instead, e.g. via `TestWorldConfig::with_seed`.
 #[derive(Debug)]
 pub struct RandomBitsGenerator<F, C, S> {
     ctx: C,
@@ -109,7 +112,7 @@ mod tests {
             replicated::{semi_honest::AdditiveShare, ReplicatedSecretSharing},
             SharedValue,
         },
-        test_fixture::{join3, join3v, Reconstruct, Runner, TestWorld},
+        test_fixture::{join3, join3v, Reconstruct, Runner, TestWorld, TestWorldConfig},
     };
 
     #[tokio::test]
@@ -201,4 +204,36 @@ mod tests {
         let result = join3v(zip(validators, m_result).map(|(v, m)| v.validate(m))).await;
         let _: Fp31 = result.reconstruct(); // reconstruct() will validate the value.
     }
+
+    #[tokio::test]
+    pub async fn reproducible_with_seed() {
+        const COUNT: usize = 10;
+        const SEED: u64 = 0x5eed;
+
+        async fn generate(world: &TestWorld) -> [Vec<AdditiveShare<Fp31>>; 3] {
+            world
+                .semi_honest((), |ctx, _| async move {
+                    let validator = ctx.validator();
+                    let rbg = RandomBitsGenerator::<Fp31, _, _>::new(
+                        validator.context().set_total_records(COUNT),
+                    );
+                    let shares = try_join_all((0..COUNT).map(|i| rbg.generate(RecordId::from(i))))
+                        .await
+                        .unwrap();
+                    shares.into_iter().map(|r| r.b_p).collect::<Vec<_>>()
+                })
+                .await
+        }
+
+        // Random bits are derived from PRSS, so seeding the PRSS keys is enough to reproduce them.
+        let first = generate(&TestWorld::new_with(
+            TestWorldConfig::default().with_seed(SEED),
+        ))
+        .await;
+        let second = generate(&TestWorld::new_with(
+            TestWorldConfig::default().with_seed(SEED),
+        ))
+        .await;
+        assert_eq!(first, second);
+    }
 }
@@ -18,8 +18,9 @@ use crate::{
         },
         context::{
             prss::InstrumentedIndexedSharedRandomness,
+            spend_reveal,
             validator::{Malicious as Validator, MaliciousAccumulator},
-            Base, Context as ContextTrait, InstrumentedSequentialSharedRandomness,
+            Base, Context as ContextTrait, InstrumentedSequentialSharedRandomness, RevealBudget,
             SpecialAccessToUpgradedContext, UpgradableContext, UpgradedContext,
         },
         prss::Endpoint as PrssEndpoint,
@@ -98,6 +99,16 @@ impl<'a> super::Context for Context<'a> {
         self.inner.total_records()
     }
 
+    fn set_reveal_budget(&self, reveals: usize) -> Self {
+        Self {
+            inner: self.inner.set_reveal_budget(reveals),
+        }
+    }
+
+    fn spend_reveal_budget(&self) -> Result<(), Error> {
+        self.inner.spend_reveal_budget()
+    }
+
     fn prss(&self) -> InstrumentedIndexedSharedRandomness<'_> {
         self.inner.prss()
     }
@@ -120,6 +131,22 @@ impl<'a> super::Context for Context<'a> {
     }
 }
 
+#[cfg(any(test, feature = "test-fixture"))]
+impl<'a> Context<'a> {
+    /// Creates a validator that uses the given share of the MAC key `r` instead of drawing it from
+    /// PRSS. Tests can use it to run malicious protocols on inputs that were shared with
+    /// [`WithMacKey`] under a known key.
+    ///
+    /// [`WithMacKey`]: crate::test_fixture::WithMacKey
+    #[must_use]
+    pub fn validator_with_mac_key<F: ExtendableField>(
+        self,
+        r_share: Replicated<F::ExtendedField>,
+    ) -> Validator<'a, F> {
+        Validator::with_r_share(self, r_share)
+    }
+}
+
 impl<'a> UpgradableContext for Context<'a> {
     type UpgradedContext<F: ExtendableField> = Upgraded<'a, F>;
     type Validator<F: ExtendableField> = Validator<'a, F>;
@@ -150,6 +177,7 @@ pub struct Upgraded<'a, F: ExtendableField> {
     inner: Arc<UpgradedInner<'a, F>>,
     gate: Gate,
     total_records: TotalRecords,
+    reveal_budget: Option<RevealBudget>,
 }
 
 impl<'a, F: ExtendableField> Upgraded<'a, F> {
@@ -166,6 +194,7 @@ impl<'a, F: ExtendableField> Upgraded<'a, F> {
             inner: UpgradedInner::new(source, acc, r_share),
             gate: source.gate().narrow(malicious_step),
             total_records: TotalRecords::Unspecified,
+            reveal_budget: source.reveal_budget.clone(),
         }
     }
 
@@ -182,6 +211,7 @@ impl<'a, F: ExtendableField> Upgraded<'a, F> {
             self.inner.gateway,
             self.gate.clone(),
             self.total_records,
+            self.reveal_budget.clone(),
         )
     }
 }
@@ -290,6 +320,7 @@ impl<'a, F: ExtendableField> super::Context for Upgraded<'a, F> {
             inner: Arc::clone(&self.inner),
             gate: self.gate.narrow(step),
             total_records: self.total_records,
+            reveal_budget: self.reveal_budget.clone(),
         }
     }
 
@@ -298,6 +329,7 @@ impl<'a, F: ExtendableField> super::Context for Upgraded<'a, F> {
             inner: Arc::clone(&self.inner),
             gate: self.gate.clone(),
             total_records: self.total_records.overwrite(total_records),
+            reveal_budget: self.reveal_budget.clone(),
         }
     }
 
@@ -305,6 +337,19 @@ impl<'a, F: ExtendableField> super::Context for Upgraded<'a, F> {
         self.total_records
     }
 
+    fn set_reveal_budget(&self, reveals: usize) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            gate: self.gate.clone(),
+            total_records: self.total_records,
+            reveal_budget: Some(RevealBudget::new(reveals)),
+        }
+    }
+
+    fn spend_reveal_budget(&self) -> Result<(), Error> {
+        spend_reveal(self.reveal_budget.as_ref())
+    }
+
     fn prss(&self) -> InstrumentedIndexedSharedRandomness<'_> {
         let prss = self.inner.prss.indexed(self.gate());
 
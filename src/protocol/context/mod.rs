@@ -19,7 +19,7 @@ use crate::{
     protocol::{
         basics::ZeroPositions,
         prss::Endpoint as PrssEndpoint,
-        step::{Gate, Step, StepNarrow},
+        step::{Gate, ScopeStep, Step, StepNarrow},
         RecordId,
     },
     secret_sharing::{
@@ -27,6 +27,7 @@ use crate::{
         SecretSharing,
     },
     seq_join::SeqJoin,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 /// Context used by each helper to perform secure computation. Provides access to shared randomness
@@ -46,6 +47,20 @@ pub trait Context: Clone + Send + Sync + SeqJoin {
     where
         Gate: StepNarrow<S>;
 
+    /// Makes a sub-context for running a sub-protocol independently of its siblings, for example
+    /// one of several partitions that are aggregated in parallel. Sub-protocols running in scopes
+    /// with different `index` never share communication channels or PRSS, even if they narrow
+    /// their contexts with exactly the same steps.
+    ///
+    /// With the compact gate, only contexts at the root of the protocol can be scoped.
+    ///
+    /// # Panics
+    /// If `index` is not less than [`ScopeStep::MAX_SCOPES`].
+    #[must_use]
+    fn scoped(&self, index: usize) -> Self {
+        self.narrow(&ScopeStep::from(index))
+    }
+
     /// Sets the context's total number of records field. Communication channels are
     /// closed based on sending the expected total number of records.
     #[must_use]
@@ -78,10 +93,66 @@ pub trait Context: Clone + Send + Sync + SeqJoin {
         InstrumentedSequentialSharedRandomness,
     );
 
+    /// Limits the number of values that can be revealed with this context to `reveals`. The
+    /// budget is shared with every context derived from the returned one, e.g. with [`narrow`],
+    /// so it caps the reveals of a whole protocol rather than of a single step.
+    ///
+    /// [`narrow`]: Self::narrow
+    #[must_use]
+    fn set_reveal_budget(&self, reveals: usize) -> Self;
+
+    /// Takes one reveal out of the budget set with [`set_reveal_budget`]. Contexts without a
+    /// budget can reveal any number of values.
+    ///
+    /// ## Errors
+    /// [`Error::RevealBudgetExceeded`] if the budget is used up. The value must not be revealed
+    /// then.
+    ///
+    /// [`set_reveal_budget`]: Self::set_reveal_budget
+    fn spend_reveal_budget(&self) -> Result<(), Error>;
+
     fn send_channel<M: Message>(&self, role: Role) -> SendingEnd<M>;
     fn recv_channel<M: Message>(&self, role: Role) -> ReceivingEnd<M>;
 }
 
+/// Number of values that a protocol may still reveal. This is a mechanical safeguard against
+/// protocols that disclose more than they are meant to. Clones share the same budget.
+#[derive(Clone, Debug)]
+pub struct RevealBudget {
+    budget: usize,
+    remaining: Arc<AtomicUsize>,
+}
+
+impl RevealBudget {
+    #[must_use]
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            remaining: Arc::new(AtomicUsize::new(budget)),
+        }
+    }
+
+    /// Takes one reveal out of this budget.
+    ///
+    /// ## Errors
+    /// If there is nothing left to take.
+    pub fn spend(&self) -> Result<(), Error> {
+        self.remaining
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |remaining| {
+                remaining.checked_sub(1)
+            })
+            .map(|_| ())
+            .map_err(|_| Error::RevealBudgetExceeded {
+                budget: self.budget,
+            })
+    }
+}
+
+/// Spends one reveal from `budget`, if there is one.
+fn spend_reveal(budget: Option<&RevealBudget>) -> Result<(), Error> {
+    budget.map_or(Ok(()), RevealBudget::spend)
+}
+
 pub trait UpgradableContext: Context {
     type UpgradedContext<F: ExtendableField>: UpgradedContext<F>;
     type Validator<F: ExtendableField>: Validator<Self, F>;
@@ -157,6 +228,7 @@ pub struct Base<'a> {
     inner: Arc<Inner<'a>>,
     gate: Gate,
     total_records: TotalRecords,
+    reveal_budget: Option<RevealBudget>,
 }
 
 impl<'a> Base<'a> {
@@ -166,6 +238,7 @@ impl<'a> Base<'a> {
             gateway,
             Gate::default(),
             TotalRecords::Unspecified,
+            None,
         )
     }
 
@@ -174,11 +247,13 @@ impl<'a> Base<'a> {
         gateway: &'a Gateway,
         gate: Gate,
         total_records: TotalRecords,
+        reveal_budget: Option<RevealBudget>,
     ) -> Self {
         Self {
             inner: Inner::new(participant, gateway),
             gate,
             total_records,
+            reveal_budget,
         }
     }
 }
@@ -200,6 +275,7 @@ impl<'a> Context for Base<'a> {
             inner: Arc::clone(&self.inner),
             gate: self.gate.narrow(step),
             total_records: self.total_records,
+            reveal_budget: self.reveal_budget.clone(),
         }
     }
 
@@ -208,6 +284,7 @@ impl<'a> Context for Base<'a> {
             inner: Arc::clone(&self.inner),
             gate: self.gate.clone(),
             total_records: self.total_records.overwrite(total_records),
+            reveal_budget: self.reveal_budget.clone(),
         }
     }
 
@@ -215,6 +292,19 @@ impl<'a> Context for Base<'a> {
         self.total_records
     }
 
+    fn set_reveal_budget(&self, reveals: usize) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            gate: self.gate.clone(),
+            total_records: self.total_records,
+            reveal_budget: Some(RevealBudget::new(reveals)),
+        }
+    }
+
+    fn spend_reveal_budget(&self) -> Result<(), Error> {
+        spend_reveal(self.reveal_budget.as_ref())
+    }
+
     fn prss(&self) -> InstrumentedIndexedSharedRandomness {
         let prss = self.inner.prss.indexed(self.gate());
 
@@ -517,4 +607,47 @@ mod tests {
             })
             .await;
     }
+
+    #[tokio::test]
+    async fn scopes_are_independent() {
+        async fn run(
+            ctx: SemiHonestContext<'_>,
+            shares: &[Replicated<Fp31>],
+        ) -> Vec<Replicated<Fp31>> {
+            join_all(
+                shares
+                    .iter()
+                    .enumerate()
+                    .map(|(i, share)| toy_protocol(ctx.clone(), i, share)),
+            )
+            .await
+        }
+
+        let world = TestWorld::default();
+        let input = (0..10u128).map(Fp31::truncate_from).collect::<Vec<_>>();
+
+        let result = world
+            .semi_honest(input.clone().into_iter(), |ctx, shares| async move {
+                let ctx = ctx.set_total_records(shares.len());
+                let (scope_0, scope_1) = (ctx.scoped(0), ctx.scoped(1));
+                assert_ne!(
+                    scope_0.prss().generate_values(0_u128),
+                    scope_1.prss().generate_values(0_u128)
+                );
+
+                // the same sub-protocol, narrowing with the same steps, runs in both scopes at once
+                futures::future::join(run(scope_0, &shares), run(scope_1, &shares)).await
+            })
+            .await;
+
+        let [(a0, a1), (b0, b1), (c0, c1)] = result;
+        assert_eq!(input, [a0, b0, c0].reconstruct());
+        assert_eq!(input, [a1, b1, c1].reconstruct());
+    }
+
+    #[test]
+    #[should_panic(expected = "scope index 64 is out of range")]
+    fn scope_index_is_bounded() {
+        let _ = ScopeStep::from(ScopeStep::MAX_SCOPES);
+    }
 }
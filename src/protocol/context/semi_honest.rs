@@ -76,6 +76,16 @@ impl<'a> super::Context for Context<'a> {
         self.inner.total_records()
     }
 
+    fn set_reveal_budget(&self, reveals: usize) -> Self {
+        Self {
+            inner: self.inner.set_reveal_budget(reveals),
+        }
+    }
+
+    fn spend_reveal_budget(&self) -> Result<(), Error> {
+        self.inner.spend_reveal_budget()
+    }
+
     fn prss(&self) -> InstrumentedIndexedSharedRandomness<'_> {
         self.inner.prss()
     }
@@ -158,6 +168,14 @@ impl<'a, F: ExtendableField> super::Context for Upgraded<'a, F> {
         self.inner.total_records()
     }
 
+    fn set_reveal_budget(&self, reveals: usize) -> Self {
+        Self::new(self.inner.set_reveal_budget(reveals))
+    }
+
+    fn spend_reveal_budget(&self) -> Result<(), Error> {
+        self.inner.spend_reveal_budget()
+    }
+
     fn prss(&self) -> InstrumentedIndexedSharedRandomness<'_> {
         self.inner.prss()
     }
@@ -113,6 +113,7 @@ where
         self,
         input: BitConversionTriple<Replicated<F>>,
     ) -> Result<BitConversionTriple<C::Share>, Error> {
+        input.check_role(self.ctx.role())?;
         let [v0, v1, v2] = input.0;
         let (t0, t1, t2) = try_join3(
             self.ctx
@@ -247,10 +247,19 @@ impl<'a, F: ExtendableField> Validator<MaliciousContext<'a>, F> for Malicious<'a
 
 impl<'a, F: ExtendableField> Malicious<'a, F> {
     #[must_use]
-    #[allow(clippy::needless_pass_by_value)]
     pub fn new(ctx: MaliciousContext<'a>) -> Self {
         // Use the current step in the context for initialization.
         let r_share: Replicated<F::ExtendedField> = ctx.prss().generate_replicated(RecordId::FIRST);
+        Self::with_r_share(ctx, r_share)
+    }
+
+    /// Creates a validator that uses `r_share` as its share of the MAC key, rather than drawing
+    /// one from PRSS.
+    #[allow(clippy::needless_pass_by_value)]
+    pub(super) fn with_r_share(
+        ctx: MaliciousContext<'a>,
+        r_share: Replicated<F::ExtendedField>,
+    ) -> Self {
         let prss = ctx.prss();
         let u: F::ExtendedField = prss.zero(RecordId::FIRST + 1);
         let w: F::ExtendedField = prss.zero(RecordId::FIRST + 2);
@@ -1,12 +1,21 @@
-use std::{iter::zip, marker::PhantomData, ops::Add};
+use std::{
+    cmp::min,
+    iter::{from_fn, zip},
+    marker::PhantomData,
+    ops::Range,
+};
 
 use async_trait::async_trait;
+use bitvec::{order::Lsb0, vec::BitVec, view::BitView};
+use bytes::Bytes;
 use futures::{
     future::{try_join, try_join3},
     stream::iter as stream_iter,
+    TryStreamExt,
 };
-use generic_array::{ArrayLength, GenericArray};
+use generic_array::GenericArray;
 use ipa_macros::Step;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 use typenum::Unsigned;
 
 use crate::{
@@ -14,19 +23,25 @@ use crate::{
     ff::{Field, GaloisField, Gf2, PrimeField, Serializable},
     helpers::{query::IpaQueryConfig, Role},
     protocol::{
-        attribution::secure_attribution,
-        basics::Reshare,
+        attribution::{
+            aggregate_credit::breakdown_key_bits, secure_attribution,
+            secure_attribution_with_reconciliation, secure_attribution_with_trace,
+            AttributionOutputs, AttributionTrace, CreditReconciliation,
+        },
+        basics::{Reshare, Reveal, ShareKnownValue},
         context::{
             Context, UpgradableContext, UpgradeContext, UpgradeToMalicious, UpgradedContext,
             Validator,
         },
-        modulus_conversion::BitConversionTriple,
+        modulus_conversion::{convert_bits, BitConversionTriple},
         sort::{
             apply_sort::apply_sort_permutation,
             generate_permutation::{
-                generate_permutation_and_reveal_shuffled, ShuffledPermutationWrapper,
+                generate_permutation_and_reveal_shuffled, RevealedAndRandomPermutations,
+                ShuffledPermutationWrapper,
             },
         },
+        step::BitOpStep,
         BasicProtocols, RecordId,
     },
     secret_sharing::{
@@ -35,18 +50,57 @@ use crate::{
             semi_honest::AdditiveShare as Replicated,
             ReplicatedSecretSharing,
         },
-        BitDecomposed, Linear as LinearSecretSharing, LinearRefOps,
+        BitDecomposed, Linear as LinearSecretSharing, LinearRefOps, SharedValue,
     },
+    seq_join::SeqJoin,
 };
 
+/// Bounds on the shares that IPA computes with, in `F` and in [`Gf2`]. Both semi-honest and
+/// malicious shares implement it for their upgraded contexts.
+pub trait IpaShare<C: Context, V: SharedValue>:
+    LinearSecretSharing<V> + BasicProtocols<C, V> + DowngradeMalicious<Target = Replicated<V>> + 'static
+{
+}
+
+impl<C, V, S> IpaShare<C, V> for S
+where
+    C: Context,
+    V: SharedValue,
+    S: LinearSecretSharing<V>
+        + BasicProtocols<C, V>
+        + DowngradeMalicious<Target = Replicated<V>>
+        + 'static,
+{
+}
+
 #[derive(Step)]
 pub(crate) enum Step {
     GenSortPermutationFromMatchKeys,
+    SortValidator,
+    SortBinaryValidator,
     ApplySortPermutation,
     AfterConvertAllBits,
     UpgradeMatchKeyBits,
     UpgradeBreakdownKeyBits,
+    ClampBreakdownKeys,
+    DetectMaxBreakdownKey,
     BinaryValidator,
+    ConvertTimestamps,
+}
+
+#[derive(Step)]
+pub(crate) enum ClampBreakdownKeyStep {
+    Compare,
+    Select,
+}
+
+#[derive(Step)]
+pub(crate) enum MaxBreakdownKeyStep {
+    #[dynamic]
+    Round(usize),
+    Compare,
+    Select,
+    Reveal,
 }
 
 #[derive(Step)]
@@ -58,7 +112,11 @@ pub(crate) enum IPAInputRowResharableStep {
     TriggerValue,
 }
 
-#[derive(Debug)]
+/// Version of the [`IPAInputRow`] layout written by [`IPAInputRow::serialize_versioned`]. Bump it
+/// whenever fields are added, removed or reordered.
+pub const WIRE_VERSION: u8 = 1;
+
+#[derive(Debug, Serializable)]
 #[cfg_attr(test, derive(Clone, PartialEq, Eq))]
 pub struct IPAInputRow<F: Field, MK: GaloisField, BK: GaloisField> {
     pub timestamp: Replicated<F>,
@@ -68,104 +126,133 @@ pub struct IPAInputRow<F: Field, MK: GaloisField, BK: GaloisField> {
     pub trigger_value: Replicated<F>,
 }
 
-impl<F: Field, MK: GaloisField, BK: GaloisField> Serializable for IPAInputRow<F, MK, BK>
+/// An input row paired with the identifier of the report it was created from.
+///
+/// The identifier is not secret-shared: every helper sees the same value, and it travels next to
+/// the row rather than inside it, so it can never enter the MPC computation. It is only meant to
+/// let a deployment correlate input rows with their source reports for auditing. Callers must
+/// split it off with [`WithReportId::into_parts`] before passing rows to IPA.
+#[derive(Debug)]
+#[cfg_attr(test, derive(Clone, PartialEq, Eq))]
+pub struct WithReportId<T> {
+    pub report_id: Option<u64>,
+    pub row: T,
+}
+
+impl<T> WithReportId<T> {
+    #[must_use]
+    pub fn into_parts(self) -> (Option<u64>, T) {
+        (self.report_id, self.row)
+    }
+}
+
+/// A source (impression) event, for pipelines that keep sources and triggers apart. See
+/// [`ipa_split`].
+///
+/// Unlike in [`IPAInputRow`], the timestamp is binary shared, because [`ipa_split`] sorts events
+/// by it.
+#[derive(Debug)]
+#[cfg_attr(test, derive(Clone, PartialEq, Eq))]
+pub struct IPASourceRow<TS: GaloisField, MK: GaloisField, BK: GaloisField> {
+    pub timestamp: Replicated<TS>,
+    pub mk_shares: Replicated<MK>,
+    pub breakdown_key: Replicated<BK>,
+}
+
+/// A trigger (conversion) event, the counterpart of [`IPASourceRow`].
+#[derive(Debug)]
+#[cfg_attr(test, derive(Clone, PartialEq, Eq))]
+pub struct IPATriggerRow<F: Field, TS: GaloisField, MK: GaloisField> {
+    pub timestamp: Replicated<TS>,
+    pub mk_shares: Replicated<MK>,
+    pub trigger_value: Replicated<F>,
+}
+
+/// Checks that both halves of a serialized replicated share hold the little-endian encoding of a
+/// value of `V`. Deserialization accepts any bytes, reducing or truncating values that do not fit.
+fn check_canonical<V: Field>(share: &[u8]) -> Result<(), String> {
+    for half in share.chunks(share.len() / 2) {
+        let mut buf = [0u8; 16];
+        buf[..half.len()].copy_from_slice(half);
+        let value = u128::from_le_bytes(buf);
+        if V::try_from(value).map(|v| v.as_u128()).ok() != Some(value) {
+            return Err(format!("{value} is not a valid field value"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Names of the [`IPAInputRow`] fields, in the order they are serialized.
+const FIELD_NAMES: [&str; 5] = [
+    "timestamp",
+    "mk_shares",
+    "is_trigger_bit",
+    "breakdown_key",
+    "trigger_value",
+];
+
+impl<F: Field, MK: GaloisField, BK: GaloisField> IPAInputRow<F, MK, BK>
 where
-    Replicated<BK>: Serializable,
-    Replicated<MK>: Serializable,
     Replicated<F>: Serializable,
-    <Replicated<BK> as Serializable>::Size: Add<<Replicated<F> as Serializable>::Size>,
-    <Replicated<F> as Serializable>::Size:
-        Add<
-            <<Replicated<BK> as Serializable>::Size as Add<
-                <Replicated<F> as Serializable>::Size,
-            >>::Output,
-        >,
-    <Replicated<MK> as Serializable>::Size: Add<
-        <<Replicated<F> as Serializable>::Size as Add<
-            <<Replicated<BK> as Serializable>::Size as Add<
-                <Replicated<F> as Serializable>::Size,
-            >>::Output,
-        >>::Output,
-    >,
-    <Replicated<F> as Serializable>::Size: Add<
-        <<Replicated<MK> as Serializable>::Size as Add<
-            <<Replicated<F> as Serializable>::Size as Add<
-                <<Replicated<BK> as Serializable>::Size as Add<
-                    <Replicated<F> as Serializable>::Size,
-                >>::Output,
-            >>::Output,
-        >>::Output,
-    >,
-    <<Replicated<F> as Serializable>::Size as Add<
-        <<Replicated<MK> as Serializable>::Size as Add<
-            <<Replicated<F> as Serializable>::Size as Add<
-                <<Replicated<BK> as Serializable>::Size as Add<
-                    <Replicated<F> as Serializable>::Size,
-                >>::Output,
-            >>::Output,
-        >>::Output,
-    >>::Output: ArrayLength<u8>,
+    Replicated<MK>: Serializable,
+    Replicated<BK>: Serializable,
 {
-    type Size = <<Replicated<F> as Serializable>::Size as Add<
-        <<Replicated<MK> as Serializable>::Size as Add<
-            <<Replicated<F> as Serializable>::Size as Add<
-                <<Replicated<BK> as Serializable>::Size as Add<
-                    <Replicated<F> as Serializable>::Size,
-                >>::Output,
-            >>::Output,
-        >>::Output,
-    >>::Output;
-
-    fn serialize(&self, buf: &mut GenericArray<u8, Self::Size>) {
-        let mk_sz = <Replicated<MK> as Serializable>::Size::USIZE;
-        let bk_sz = <Replicated<BK> as Serializable>::Size::USIZE;
+    /// Byte offsets at which the fields start within a serialized row, in the order of
+    /// [`FIELD_NAMES`], followed by the end of the row.
+    ///
+    /// Offsets are computed when the constant is evaluated, at compile time. Row sizes that do not
+    /// fit into `usize`, e.g. with very large fields on a 32-bit target, fail to compile instead of
+    /// producing offsets that wrap around.
+    const FIELD_OFFSETS: [usize; 6] = {
         let f_sz = <Replicated<F> as Serializable>::Size::USIZE;
+        let sizes = [
+            f_sz,
+            <Replicated<MK> as Serializable>::Size::USIZE,
+            f_sz,
+            <Replicated<BK> as Serializable>::Size::USIZE,
+            f_sz,
+        ];
+
+        let mut offsets = [0_usize; 6];
+        let mut i = 0;
+        while i < sizes.len() {
+            offsets[i + 1] = match offsets[i].checked_add(sizes[i]) {
+                Some(offset) => offset,
+                None => panic!("IPAInputRow size overflows usize"),
+            };
+            i += 1;
+        }
+        offsets
+    };
 
-        self.timestamp
-            .serialize(GenericArray::from_mut_slice(&mut buf[..f_sz]));
-        self.mk_shares
-            .serialize(GenericArray::from_mut_slice(&mut buf[f_sz..f_sz + mk_sz]));
-        self.is_trigger_bit.serialize(GenericArray::from_mut_slice(
-            &mut buf[f_sz + mk_sz..f_sz + mk_sz + f_sz],
-        ));
-        self.breakdown_key.serialize(GenericArray::from_mut_slice(
-            &mut buf[f_sz + mk_sz + f_sz..f_sz + mk_sz + f_sz + bk_sz],
-        ));
-        self.trigger_value.serialize(GenericArray::from_mut_slice(
-            &mut buf[f_sz + mk_sz + f_sz + bk_sz..],
-        ));
-    }
-
-    fn deserialize(buf: &GenericArray<u8, Self::Size>) -> Self {
-        let mk_sz = <Replicated<MK> as Serializable>::Size::USIZE;
-        let bk_sz = <Replicated<BK> as Serializable>::Size::USIZE;
-        let f_sz = <Replicated<F> as Serializable>::Size::USIZE;
+    /// Number of bytes in a serialized row. Same as the [`Serializable::Size`] of this row, but
+    /// checked for overflow at compile time, see [`FIELD_OFFSETS`].
+    ///
+    /// [`FIELD_OFFSETS`]: Self::FIELD_OFFSETS
+    pub const SIZE_IN_BYTES: usize = Self::FIELD_OFFSETS[5];
 
-        let timestamp = Replicated::<F>::deserialize(GenericArray::from_slice(&buf[..f_sz]));
-        let mk_shares =
-            Replicated::<MK>::deserialize(GenericArray::from_slice(&buf[f_sz..f_sz + mk_sz]));
-        let is_trigger_bit = Replicated::<F>::deserialize(GenericArray::from_slice(
-            &buf[f_sz + mk_sz..f_sz + mk_sz + f_sz],
-        ));
-        let breakdown_key = Replicated::<BK>::deserialize(GenericArray::from_slice(
-            &buf[f_sz + mk_sz + f_sz..f_sz + mk_sz + f_sz + bk_sz],
-        ));
-        let trigger_value = Replicated::<F>::deserialize(GenericArray::from_slice(
-            &buf[f_sz + mk_sz + f_sz + bk_sz..],
-        ));
-        Self {
-            timestamp,
-            mk_shares,
-            is_trigger_bit,
-            breakdown_key,
-            trigger_value,
-        }
+    /// Byte ranges of the fields within a serialized row, in the order of [`FIELD_NAMES`].
+    fn field_ranges() -> [Range<usize>; 5] {
+        let offsets = Self::FIELD_OFFSETS;
+        [0, 1, 2, 3, 4].map(|i| offsets[i]..offsets[i + 1])
+    }
+
+    /// Describes the serialized layout of a row: the name of every field together with the byte
+    /// range it occupies, in the order they are written. Client authors can use this to check
+    /// that they pack rows the way helpers expect to read them.
+    #[must_use]
+    pub fn layout_description() -> Vec<(&'static str, Range<usize>)> {
+        zip(FIELD_NAMES, Self::field_ranges()).collect()
     }
 }
 
 impl<F: Field, MK: GaloisField, BK: GaloisField> IPAInputRow<F, MK, BK>
 where
     IPAInputRow<F, MK, BK>: Serializable,
+    Replicated<F>: Serializable,
+    Replicated<MK>: Serializable,
+    Replicated<BK>: Serializable,
 {
     /// Splits the given slice into chunks aligned with the size of this struct and returns an
     /// iterator that produces deserialized instances.
@@ -173,14 +260,237 @@ where
     /// ## Panics
     /// Panics if the slice buffer is not aligned with the size of this struct.
     pub fn from_byte_slice(input: &[u8]) -> impl Iterator<Item = Self> + '_ {
+        Self::check_alignment(input).expect("input is not aligned");
+        Self::rows(input)
+    }
+
+    /// Fallible version of [`from_byte_slice`] that reports malformed input as an error instead
+    /// of panicking. It also rejects shares that are not written the way [`serialize`] writes
+    /// them, e.g. prime field values at or above the modulus, which [`from_byte_slice`] silently
+    /// reduces. Every row is checked before the iterator is returned. The wire format only relies
+    /// on `core` slicing and [`GenericArray`], so this is suitable for clients that cannot afford
+    /// to abort on malformed input.
+    ///
+    /// ## Errors
+    /// If the slice buffer is not aligned with the size of this struct, or if any share is not a
+    /// canonical encoding of a value of its field.
+    ///
+    /// [`from_byte_slice`]: Self::from_byte_slice
+    /// [`serialize`]: Serializable::serialize
+    pub fn try_from_byte_slice(input: &[u8]) -> Result<impl Iterator<Item = Self> + '_, Error> {
+        Self::check_alignment(input)?;
+        for (i, row) in input.chunks(Self::SIZE_IN_BYTES).enumerate() {
+            Self::check_field_values(row)
+                .map_err(|e| Error::ParseError(format!("row {i}: {e}").into()))?;
+        }
+
+        Ok(Self::rows(input))
+    }
+
+    fn check_alignment(input: &[u8]) -> Result<(), Error> {
+        let row_sz = <IPAInputRow<F, MK, BK> as Serializable>::Size::USIZE;
+        if input.len() % row_sz == 0 {
+            Ok(())
+        } else {
+            Err(Error::ParseError(
+                format!(
+                    "input of {} bytes is not aligned to {row_sz} byte rows",
+                    input.len()
+                )
+                .into(),
+            ))
+        }
+    }
+
+    /// Checks that every share in a serialized row is a canonical encoding of a value of its
+    /// field.
+    fn check_field_values(row: &[u8]) -> Result<(), String> {
+        let shares = Self::field_ranges().map(|range| &row[range]);
+        let checks = [
+            check_canonical::<F>(shares[0]),
+            check_canonical::<MK>(shares[1]),
+            check_canonical::<F>(shares[2]),
+            check_canonical::<BK>(shares[3]),
+            check_canonical::<F>(shares[4]),
+        ];
+        zip(FIELD_NAMES, checks)
+            .try_for_each(|(name, check)| check.map_err(|e| format!("{name}: {e}")))
+    }
+
+    fn rows(input: &[u8]) -> impl Iterator<Item = Self> + '_ {
+        input
+            .chunks(<IPAInputRow<F, MK, BK> as Serializable>::Size::USIZE)
+            .map(|chunk| IPAInputRow::<F, MK, BK>::deserialize(GenericArray::from_slice(chunk)))
+    }
+
+    /// Same as [`from_byte_slice`], but reads rows from a shared [`Bytes`] buffer. The returned
+    /// iterator holds another reference to the same buffer, so it is not tied to the lifetime of
+    /// the borrow and can be moved into ingest tasks while the caller keeps using `input`.
+    ///
+    /// Rows are decoded straight from the shared buffer. Shares hold owned field values, so each
+    /// field is still read out of its byte range; only the row buffer itself is never copied.
+    ///
+    /// ## Panics
+    /// Panics if the buffer is not aligned with the size of this struct.
+    ///
+    /// [`from_byte_slice`]: Self::from_byte_slice
+    pub fn from_bytes(input: &Bytes) -> impl Iterator<Item = Self> + Send + 'static
+    where
+        Self: Send + 'static,
+    {
+        let row_sz = <IPAInputRow<F, MK, BK> as Serializable>::Size::USIZE;
         assert_eq!(
             0,
-            input.len() % <IPAInputRow<F, MK, BK> as Serializable>::Size::USIZE,
-            "input is not aligned"
+            input.len() % row_sz,
+            "input of {} bytes is not aligned to {row_sz} byte rows",
+            input.len()
+        );
+
+        let input = input.clone();
+        (0..input.len()).step_by(row_sz).map(move |start| {
+            IPAInputRow::<F, MK, BK>::deserialize(GenericArray::from_slice(
+                &input[start..][..row_sz],
+            ))
+        })
+    }
+
+    /// Number of bits that a row takes when written by [`pack`]: `2 * MK::BITS` for the match key
+    /// and as many bytes as [`serialize`] writes for every other field.
+    ///
+    /// [`pack`]: Self::pack
+    /// [`serialize`]: Serializable::serialize
+    fn packed_row_bits() -> usize {
+        let mk_range = Self::field_ranges()[1].clone();
+        8 * (Self::SIZE_IN_BYTES - mk_range.len()) + 2 * usize::try_from(MK::BITS).unwrap()
+    }
+
+    /// Number of bytes required to store `count` rows written by [`pack`].
+    ///
+    /// [`pack`]: Self::pack
+    #[must_use]
+    pub fn packed_len(count: usize) -> usize {
+        (count * Self::packed_row_bits() + 7) / 8
+    }
+
+    /// Serializes `rows` without padding match keys to a whole number of bytes. Rows are not
+    /// padded either, so every row after the first one may start in the middle of a byte. This
+    /// saves space for match keys whose width is not a multiple of 8, e.g. a 20 bit match key
+    /// takes 5 bytes per row instead of 6. Fields are written in the same order as by
+    /// [`serialize`], see [`layout_description`].
+    ///
+    /// [`serialize`]: Serializable::serialize
+    /// [`layout_description`]: Self::layout_description
+    #[must_use]
+    pub fn pack(rows: &[Self]) -> Vec<u8> {
+        let mk_range = Self::field_ranges()[1].clone();
+        let mut bits = BitVec::<u8, Lsb0>::with_capacity(8 * Self::packed_len(rows.len()));
+        let mut buf = GenericArray::default();
+        for row in rows {
+            row.serialize(&mut buf);
+            bits.extend_from_bitslice(buf[..mk_range.start].view_bits::<Lsb0>());
+            row.mk_shares.pack_into(&mut bits);
+            bits.extend_from_bitslice(buf[mk_range.end..].view_bits::<Lsb0>());
+        }
+
+        bits.into_vec()
+    }
+
+    /// Reads rows written by [`pack`]. Rows are not byte aligned, so every row is reassembled
+    /// bit by bit from the part of the input it occupies.
+    ///
+    /// ## Panics
+    /// If the length of `input` is not the length of a whole number of packed rows.
+    ///
+    /// [`pack`]: Self::pack
+    pub fn from_packed_byte_slice(input: &[u8]) -> impl Iterator<Item = Self> + '_ {
+        let row_bits = Self::packed_row_bits();
+        // a row takes more than a byte, so the padding after the last row never fits another one
+        let count = input.len() * 8 / row_bits;
+        assert_eq!(
+            Self::packed_len(count),
+            input.len(),
+            "input of {} bytes does not hold a whole number of packed rows",
+            input.len()
         );
+
+        let mk_range = Self::field_ranges()[1].clone();
+        let mk_bits = row_bits - 8 * (Self::SIZE_IN_BYTES - mk_range.len());
         input
-            .chunks(<IPAInputRow<F, MK, BK> as Serializable>::Size::USIZE)
-            .map(|chunk| IPAInputRow::<F, MK, BK>::deserialize(GenericArray::from_slice(chunk)))
+            .view_bits::<Lsb0>()
+            .chunks_exact(row_bits)
+            .map(move |packed| {
+                let (before, rest) = packed.split_at(8 * mk_range.start);
+                let (mk_shares, after) = rest.split_at(mk_bits);
+
+                // the match key is read separately, so its bytes are left empty
+                let mut buf = GenericArray::<u8, <Self as Serializable>::Size>::default();
+                let unpacked = buf.view_bits_mut::<Lsb0>();
+                unpacked[..before.len()].copy_from_bitslice(before);
+                unpacked[8 * mk_range.end..].copy_from_bitslice(after);
+
+                let mut row = Self::deserialize(&buf);
+                row.mk_shares = Replicated::unpack_from(mk_shares);
+                row
+            })
+    }
+
+    /// Number of bytes occupied by a row written with [`serialize_versioned`].
+    ///
+    /// ## Panics
+    /// If the size of a versioned row does not fit into `usize`.
+    ///
+    /// [`serialize_versioned`]: Self::serialize_versioned
+    #[must_use]
+    pub fn versioned_size() -> usize {
+        <IPAInputRow<F, MK, BK> as Serializable>::Size::USIZE
+            .checked_add(1)
+            .expect("versioned row size overflows usize")
+    }
+
+    /// Serializes this row prefixed with [`WIRE_VERSION`], so that readers can tell rows written
+    /// with a different layout apart instead of misinterpreting them. Use this for rows that may
+    /// be read by a different build; [`Serializable::serialize`] is fine within a single one.
+    ///
+    /// ## Panics
+    /// If `buf` is not exactly [`versioned_size`] bytes long.
+    ///
+    /// [`versioned_size`]: Self::versioned_size
+    pub fn serialize_versioned(&self, buf: &mut [u8]) {
+        assert_eq!(Self::versioned_size(), buf.len());
+        buf[0] = WIRE_VERSION;
+        self.serialize(GenericArray::from_mut_slice(&mut buf[1..]));
+    }
+
+    /// Reads a row written with [`serialize_versioned`].
+    ///
+    /// ## Errors
+    /// If the row was written with a different version of the wire format or `buf` does not have
+    /// the size of a versioned row.
+    ///
+    /// [`serialize_versioned`]: Self::serialize_versioned
+    pub fn deserialize_versioned(buf: &[u8]) -> Result<Self, Error> {
+        Self::deserialize_version(buf, WIRE_VERSION)
+    }
+
+    fn deserialize_version(buf: &[u8], supported: u8) -> Result<Self, Error> {
+        let Some((&version, row)) = buf.split_first() else {
+            return Err(Error::ParseError("versioned row is empty".into()));
+        };
+        if version != supported {
+            return Err(Error::UnsupportedWireVersion(version));
+        }
+        if buf.len() != Self::versioned_size() {
+            return Err(Error::ParseError(
+                format!(
+                    "versioned row must be {} bytes long, got {}",
+                    Self::versioned_size(),
+                    buf.len()
+                )
+                .into(),
+            ));
+        }
+
+        Ok(Self::deserialize(GenericArray::from_slice(row)))
     }
 }
 
@@ -202,6 +512,84 @@ impl<F: Field, S: LinearSecretSharing<F>> ArithmeticallySharedIPAInputs<F, S> {
     }
 }
 
+impl<F: Field> ArithmeticallySharedIPAInputs<F, Replicated<F>>
+where
+    Replicated<F>: Serializable,
+{
+    /// Number of bytes occupied by a row written with [`serialize_compact`] for the given number
+    /// of breakdown key bits.
+    ///
+    /// [`serialize_compact`]: Self::serialize_compact
+    #[must_use]
+    pub fn compact_size(breakdown_key_bits: usize) -> usize {
+        3 * <Replicated<F> as Serializable>::Size::USIZE
+            + breakdown_key_bits * <Replicated<Gf2> as Serializable>::Size::USIZE
+    }
+
+    /// Serializes this row together with its breakdown key bits, leaving out the match key.
+    ///
+    /// Once the input is sorted and helper bits are computed, match key shares are no longer used
+    /// by attribution, so there is no point in carrying them around in checkpoints or transfers.
+    /// Rows written this way can't be used to recompute helper bits.
+    ///
+    /// ## Panics
+    /// If `buf` is not exactly [`compact_size`] bytes long.
+    ///
+    /// [`compact_size`]: Self::compact_size
+    pub fn serialize_compact(
+        &self,
+        breakdown_key: &BitDecomposed<Replicated<Gf2>>,
+        buf: &mut [u8],
+    ) {
+        let f_sz = <Replicated<F> as Serializable>::Size::USIZE;
+        let bit_sz = <Replicated<Gf2> as Serializable>::Size::USIZE;
+        assert_eq!(Self::compact_size(breakdown_key.len()), buf.len());
+
+        let (fields, bits) = buf.split_at_mut(3 * f_sz);
+        for (share, chunk) in zip(
+            [&self.timestamp, &self.is_trigger_bit, &self.trigger_value],
+            fields.chunks_mut(f_sz),
+        ) {
+            share.serialize(GenericArray::from_mut_slice(chunk));
+        }
+        for (bit, chunk) in zip(breakdown_key.iter(), bits.chunks_mut(bit_sz)) {
+            bit.serialize(GenericArray::from_mut_slice(chunk));
+        }
+    }
+
+    /// Reads a row written with [`serialize_compact`] and returns it together with its breakdown
+    /// key bits.
+    ///
+    /// ## Panics
+    /// If `buf` is not exactly [`compact_size`] bytes long.
+    ///
+    /// [`serialize_compact`]: Self::serialize_compact
+    /// [`compact_size`]: Self::compact_size
+    #[must_use]
+    pub fn deserialize_compact(
+        buf: &[u8],
+        breakdown_key_bits: usize,
+    ) -> (Self, BitDecomposed<Replicated<Gf2>>) {
+        let f_sz = <Replicated<F> as Serializable>::Size::USIZE;
+        let bit_sz = <Replicated<Gf2> as Serializable>::Size::USIZE;
+        assert_eq!(Self::compact_size(breakdown_key_bits), buf.len());
+
+        let (fields, bits) = buf.split_at(3 * f_sz);
+        let [timestamp, is_trigger_bit, trigger_value] = [0, 1, 2].map(|i| {
+            Replicated::<F>::deserialize(GenericArray::from_slice(&fields[i * f_sz..][..f_sz]))
+        });
+        let breakdown_key = BitDecomposed::new(
+            bits.chunks(bit_sz)
+                .map(|chunk| Replicated::<Gf2>::deserialize(GenericArray::from_slice(chunk))),
+        );
+
+        (
+            Self::new(timestamp, is_trigger_bit, trigger_value),
+            breakdown_key,
+        )
+    }
+}
+
 #[async_trait]
 impl<F, S, C> Reshare<C, RecordId> for ArithmeticallySharedIPAInputs<F, S>
 where
@@ -296,11 +684,17 @@ where
 /// IPA Protocol
 ///
 /// We return `Replicated<F>` as output since there is compute after this and in `aggregate_credit`, last communication operation was sort.
+///
+/// Every invocation narrows `sh_ctx` with the same steps. To run IPA more than once under the same
+/// parent context, e.g. for several campaigns within one query, give each invocation its own
+/// [`Context::scoped`] sub-context, otherwise they would reuse gates and PRSS.
+///
+/// The first row after sorting starts a new user, so nothing before it is attributed to it,
+/// unless [`IpaQueryConfig::first_row_continues_previous`] says otherwise.
 /// # Errors
 /// Propagates errors from multiplications
 /// # Panics
 /// Propagates errors from multiplications
-#[allow(clippy::too_many_lines)]
 pub async fn ipa<'a, C, S, SB, F, MK, BK>(
     sh_ctx: C,
     input_rows: &[IPAInputRow<F, MK, BK>],
@@ -309,18 +703,64 @@ pub async fn ipa<'a, C, S, SB, F, MK, BK>(
 where
     C: UpgradableContext,
     C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
-    S: LinearSecretSharing<F>
-        + BasicProtocols<C::UpgradedContext<F>, F>
-        + Reshare<C::UpgradedContext<F>, RecordId>
-        + Serializable
-        + DowngradeMalicious<Target = Replicated<F>>
-        + 'static,
+    S: IpaShare<C::UpgradedContext<F>, F> + Serializable,
     for<'r> &'r S: LinearRefOps<'r, S, F>,
     C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB>,
-    SB: LinearSecretSharing<Gf2>
-        + BasicProtocols<C::UpgradedContext<Gf2>, Gf2>
-        + DowngradeMalicious<Target = Replicated<Gf2>>
-        + 'static,
+    SB: IpaShare<C::UpgradedContext<Gf2>, Gf2>,
+    for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
+    F: PrimeField + ExtendableField,
+    MK: GaloisField,
+    BK: GaloisField,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+    for<'u> UpgradeContext<'u, C::UpgradedContext<F>, F, RecordId>: UpgradeToMalicious<'u, BitConversionTriple<Replicated<F>>, BitConversionTriple<S>>
+        + UpgradeToMalicious<
+            'u,
+            ArithmeticallySharedIPAInputs<F, Replicated<F>>,
+            ArithmeticallySharedIPAInputs<F, S>,
+        >,
+{
+    let (sorted_rows, config) = sort_input(sh_ctx.clone(), input_rows, config).await?;
+    ipa_from_sorted(sh_ctx, &sorted_rows, config).await
+}
+
+/// A row of [`ipa`] input once [`sort_input`] has sorted it by match key.
+///
+/// Rows are sorted in the upgraded context and validated before they are returned, so they only
+/// hold plain replicated shares. Pipelines can store them between stages, e.g. to survive a
+/// restart, and attribute them later with [`ipa_from_sorted`], without sorting again. Breakdown
+/// keys are already clamped, or limited to the detected maximum, if the query asked for it.
+#[derive(Debug, Serializable)]
+#[cfg_attr(test, derive(Clone, PartialEq, Eq))]
+pub struct IPAModulusConvertedInputRow<F: Field, MK: GaloisField, BK: GaloisField> {
+    pub timestamp: Replicated<F>,
+    pub mk_shares: Replicated<MK>,
+    pub is_trigger_bit: Replicated<F>,
+    pub breakdown_key: Replicated<BK>,
+    pub trigger_value: Replicated<F>,
+}
+
+/// Runs the first half of [`ipa`]: sorts the input by match key. Returns the sorted rows together
+/// with the configuration to attribute them with, whose maximum breakdown key is the detected one
+/// if the query asked to detect it. Both can be handed to [`ipa_from_sorted`].
+///
+/// ## Errors
+/// Propagates errors from multiplications and validation
+///
+/// ## Panics
+/// If generating the sort permutation fails.
+#[allow(clippy::type_complexity)]
+pub async fn sort_input<C, S, SB, F, MK, BK>(
+    sh_ctx: C,
+    input_rows: &[IPAInputRow<F, MK, BK>],
+    config: IpaQueryConfig,
+) -> Result<(Vec<IPAModulusConvertedInputRow<F, MK, BK>>, IpaQueryConfig), Error>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: IpaShare<C::UpgradedContext<F>, F> + Serializable,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB>,
+    SB: IpaShare<C::UpgradedContext<Gf2>, Gf2>,
     for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
     F: PrimeField + ExtendableField,
     MK: GaloisField,
@@ -348,29 +788,485 @@ where
     .await
     .unwrap();
 
-    let validator = sh_ctx.narrow(&Step::AfterConvertAllBits).validator();
-    let m_ctx = validator.context();
+    sort_rows(sh_ctx, input_rows, &sort_permutation, config).await
+}
 
-    let gf2_match_key_bits = get_gf2_match_key_bits(input_rows);
-    let gf2_breakdown_key_bits = get_gf2_breakdown_key_bits(input_rows);
+/// Runs [`ipa`] and writes the output shares of this helper to `writer`, without revealing them.
+///
+/// Every helper writes its shares of the histogram in breakdown key order, each of them serialized
+/// as [`Replicated<F>`]. Nobody learns the result online. Instead, a separate party collects the
+/// files of all three helpers and reconstructs the histogram offline, e.g. with
+/// `test_fixture::reconstruct_histogram`.
+/// # Errors
+/// Propagates errors from multiplications and from writing to `writer`.
+pub async fn ipa_to_share_files<C, S, SB, F, MK, BK, W>(
+    sh_ctx: C,
+    input_rows: &[IPAInputRow<F, MK, BK>],
+    config: IpaQueryConfig,
+    mut writer: W,
+) -> Result<(), Error>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: IpaShare<C::UpgradedContext<F>, F> + Serializable,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB>,
+    SB: IpaShare<C::UpgradedContext<Gf2>, Gf2>,
+    for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
+    F: PrimeField + ExtendableField,
+    MK: GaloisField,
+    BK: GaloisField,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+    for<'u> UpgradeContext<'u, C::UpgradedContext<F>, F, RecordId>: UpgradeToMalicious<'u, BitConversionTriple<Replicated<F>>, BitConversionTriple<S>>
+        + UpgradeToMalicious<
+            'u,
+            ArithmeticallySharedIPAInputs<F, Replicated<F>>,
+            ArithmeticallySharedIPAInputs<F, S>,
+        >,
+    Replicated<F>: Serializable,
+    W: AsyncWrite + Unpin + Send,
+{
+    let output = ipa::<C, S, SB, F, MK, BK>(sh_ctx, input_rows, config).await?;
 
-    let binary_validator = sh_ctx.narrow(&Step::BinaryValidator).validator::<Gf2>();
-    let binary_m_ctx = binary_validator.context();
+    let share_size = <Replicated<F> as Serializable>::Size::USIZE;
+    let mut buf = vec![0u8; output.len() * share_size];
+    for (share, chunk) in zip(&output, buf.chunks_mut(share_size)) {
+        share.serialize(GenericArray::from_mut_slice(chunk));
+    }
+    writer.write_all(&buf).await?;
+    writer.flush().await?;
 
-    let (upgraded_gf2_match_key_bits, upgraded_gf2_breakdown_key_bits) = try_join(
-        binary_m_ctx
-            .narrow(&Step::UpgradeMatchKeyBits)
-            .upgrade(gf2_match_key_bits),
-        binary_m_ctx
-            .narrow(&Step::UpgradeBreakdownKeyBits)
-            .upgrade(gf2_breakdown_key_bits),
-    )
-    .await?;
+    Ok(())
+}
 
-    let arithmetically_shared_values = input_rows
-        .iter()
-        .map(|input_row| {
-            ArithmeticallySharedIPAInputs::new(
+/// Runs [`ipa`] on source and trigger events that come from separate inputs.
+///
+/// Events from both inputs are merged inside MPC: they are sorted by match key and the events of
+/// every user by timestamp, so the order of the inputs does not matter. The sort takes `TS::BITS`
+/// more bits than the one in [`ipa`]. Timestamps are then converted to `F` for attribution.
+/// Helpers learn which events are triggers, because they arrive on separate inputs.
+///
+/// # Errors
+/// Propagates errors from multiplications
+pub async fn ipa_split<C, S, SB, F, MK, BK, TS>(
+    sh_ctx: C,
+    sources: &[IPASourceRow<TS, MK, BK>],
+    triggers: &[IPATriggerRow<F, TS, MK>],
+    config: IpaQueryConfig,
+) -> Result<Vec<Replicated<F>>, Error>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: IpaShare<C::UpgradedContext<F>, F> + Serializable,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB>,
+    SB: IpaShare<C::UpgradedContext<Gf2>, Gf2>,
+    for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
+    F: PrimeField + ExtendableField,
+    MK: GaloisField,
+    BK: GaloisField,
+    TS: GaloisField,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+    for<'u> UpgradeContext<'u, C::UpgradedContext<F>, F, RecordId>: UpgradeToMalicious<'u, BitConversionTriple<Replicated<F>>, BitConversionTriple<S>>
+        + UpgradeToMalicious<
+            'u,
+            ArithmeticallySharedIPAInputs<F, Replicated<F>>,
+            ArithmeticallySharedIPAInputs<F, S>,
+        >,
+{
+    let events = sources
+        .iter()
+        .map(|source| (&source.timestamp, &source.mk_shares))
+        .chain(
+            triggers
+                .iter()
+                .map(|trigger| (&trigger.timestamp, &trigger.mk_shares)),
+        );
+    // Radix sort starts from the least significant bit and is stable, so sorting by timestamp bits
+    // followed by match key bits orders events by match key first.
+    let sort_keys = events
+        .clone()
+        .map(|(timestamp, mk_shares)| {
+            BitDecomposed::new(gf2_bits(timestamp).chain(gf2_bits(mk_shares)))
+        })
+        .collect::<Vec<_>>();
+    let (sort_permutation, timestamps) = try_join(
+        generate_permutation_and_reveal_shuffled(
+            sh_ctx.narrow(&Step::GenSortPermutationFromMatchKeys),
+            stream_iter(sort_keys),
+            config.num_multi_bits,
+            TS::BITS + MK::BITS,
+        ),
+        convert_timestamps(
+            sh_ctx.narrow(&Step::ConvertTimestamps),
+            events.map(|(timestamp, _)| timestamp.clone()).collect(),
+        ),
+    )
+    .await?;
+
+    let is_trigger = Replicated::share_known_value(&sh_ctx, F::ONE);
+    let (source_timestamps, trigger_timestamps) = timestamps.split_at(sources.len());
+    let input_rows = zip(sources, source_timestamps)
+        .map(|(source, timestamp)| IPAInputRow {
+            timestamp: timestamp.clone(),
+            mk_shares: source.mk_shares.clone(),
+            is_trigger_bit: Replicated::ZERO,
+            breakdown_key: source.breakdown_key.clone(),
+            trigger_value: Replicated::ZERO,
+        })
+        .chain(
+            zip(triggers, trigger_timestamps).map(|(trigger, timestamp)| IPAInputRow {
+                timestamp: timestamp.clone(),
+                mk_shares: trigger.mk_shares.clone(),
+                is_trigger_bit: is_trigger.clone(),
+                breakdown_key: Replicated::ZERO,
+                trigger_value: trigger.trigger_value.clone(),
+            }),
+        )
+        .collect::<Vec<_>>();
+
+    let (sorted_rows, config) =
+        sort_rows(sh_ctx.clone(), &input_rows, &sort_permutation, config).await?;
+    ipa_from_sorted(sh_ctx, &sorted_rows, config).await
+}
+
+/// Runs [`ipa`] and also returns totals that let the report collector reconcile the attributed
+/// credit with the input, see [`CreditReconciliation`].
+///
+/// ## Errors
+/// Propagates errors from multiplications
+///
+/// ## Panics
+/// Propagates errors from multiplications
+pub async fn ipa_with_reconciliation<C, S, SB, F, MK, BK>(
+    sh_ctx: C,
+    input_rows: &[IPAInputRow<F, MK, BK>],
+    config: IpaQueryConfig,
+) -> Result<(Vec<Replicated<F>>, CreditReconciliation<Replicated<F>>), Error>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: IpaShare<C::UpgradedContext<F>, F> + Serializable,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB>,
+    SB: IpaShare<C::UpgradedContext<Gf2>, Gf2>,
+    for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
+    F: PrimeField + ExtendableField,
+    MK: GaloisField,
+    BK: GaloisField,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+    for<'u> UpgradeContext<'u, C::UpgradedContext<F>, F, RecordId>: UpgradeToMalicious<'u, BitConversionTriple<Replicated<F>>, BitConversionTriple<S>>
+        + UpgradeToMalicious<
+            'u,
+            ArithmeticallySharedIPAInputs<F, Replicated<F>>,
+            ArithmeticallySharedIPAInputs<F, S>,
+        >,
+{
+    let (sorted_rows, config) = sort_input(sh_ctx.clone(), input_rows, config).await?;
+    let (output, reconciliation, _) = attribute(
+        sh_ctx,
+        &sorted_rows,
+        config,
+        AttributionOutputs::Reconciliation,
+    )
+    .await?;
+    Ok((output, reconciliation.unwrap()))
+}
+
+/// Runs [`ipa`] and also returns the intermediate values of attribution for rows sorted by match
+/// key, see [`AttributionTrace`]. Tests reveal them to compare every stage of IPA with a plaintext
+/// reference implementation.
+///
+/// ## Errors
+/// Propagates errors from multiplications
+///
+/// ## Panics
+/// Propagates errors from multiplications
+#[cfg(any(test, feature = "test-fixture"))]
+pub async fn ipa_with_trace<C, S, SB, F, MK, BK>(
+    sh_ctx: C,
+    input_rows: &[IPAInputRow<F, MK, BK>],
+    config: IpaQueryConfig,
+) -> Result<(Vec<Replicated<F>>, AttributionTrace<Replicated<F>>), Error>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: IpaShare<C::UpgradedContext<F>, F> + Serializable,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB>,
+    SB: IpaShare<C::UpgradedContext<Gf2>, Gf2>,
+    for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
+    F: PrimeField + ExtendableField,
+    MK: GaloisField,
+    BK: GaloisField,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+    for<'u> UpgradeContext<'u, C::UpgradedContext<F>, F, RecordId>: UpgradeToMalicious<'u, BitConversionTriple<Replicated<F>>, BitConversionTriple<S>>
+        + UpgradeToMalicious<
+            'u,
+            ArithmeticallySharedIPAInputs<F, Replicated<F>>,
+            ArithmeticallySharedIPAInputs<F, S>,
+        >,
+{
+    let (sorted_rows, config) = sort_input(sh_ctx.clone(), input_rows, config).await?;
+    let (output, _, trace) =
+        attribute(sh_ctx, &sorted_rows, config, AttributionOutputs::Trace).await?;
+    Ok((output, trace.unwrap()))
+}
+
+/// Runs the second half of [`ipa`] on rows that [`sort_input`] has already sorted by match key,
+/// skipping the sort, which is the most expensive part of IPA. `config` must be the one that
+/// [`sort_input`] returned together with the rows.
+///
+/// This is useful for pipelines that keep the sorted rows between stages. As long as `sh_ctx`
+/// has not been used to sort the rows, it may belong to a different query than the one that did.
+///
+/// ## Errors
+/// Propagates errors from multiplications
+pub async fn ipa_from_sorted<C, S, SB, F, MK, BK>(
+    sh_ctx: C,
+    sorted_rows: &[IPAModulusConvertedInputRow<F, MK, BK>],
+    config: IpaQueryConfig,
+) -> Result<Vec<Replicated<F>>, Error>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: IpaShare<C::UpgradedContext<F>, F> + Serializable,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB>,
+    SB: IpaShare<C::UpgradedContext<Gf2>, Gf2>,
+    for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
+    F: PrimeField + ExtendableField,
+    MK: GaloisField,
+    BK: GaloisField,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+    for<'u> UpgradeContext<'u, C::UpgradedContext<F>, F, RecordId>: UpgradeToMalicious<'u, BitConversionTriple<Replicated<F>>, BitConversionTriple<S>>
+        + UpgradeToMalicious<
+            'u,
+            ArithmeticallySharedIPAInputs<F, Replicated<F>>,
+            ArithmeticallySharedIPAInputs<F, S>,
+        >,
+{
+    let (output, _, _) =
+        attribute(sh_ctx, sorted_rows, config, AttributionOutputs::Histogram).await?;
+    Ok(output)
+}
+
+/// Upgrades rows that are sorted by match key and attributes credit. Credit reconciliation totals
+/// and the attribution trace are only computed and returned if `outputs` asks for them.
+#[allow(clippy::type_complexity)]
+async fn attribute<C, S, SB, F, MK, BK>(
+    sh_ctx: C,
+    sorted_rows: &[IPAModulusConvertedInputRow<F, MK, BK>],
+    config: IpaQueryConfig,
+    outputs: AttributionOutputs,
+) -> Result<
+    (
+        Vec<Replicated<F>>,
+        Option<CreditReconciliation<Replicated<F>>>,
+        Option<AttributionTrace<Replicated<F>>>,
+    ),
+    Error,
+>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: IpaShare<C::UpgradedContext<F>, F> + Serializable,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB>,
+    SB: IpaShare<C::UpgradedContext<Gf2>, Gf2>,
+    for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
+    F: PrimeField + ExtendableField,
+    MK: GaloisField,
+    BK: GaloisField,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+    for<'u> UpgradeContext<'u, C::UpgradedContext<F>, F, RecordId>: UpgradeToMalicious<'u, BitConversionTriple<Replicated<F>>, BitConversionTriple<S>>
+        + UpgradeToMalicious<
+            'u,
+            ArithmeticallySharedIPAInputs<F, Replicated<F>>,
+            ArithmeticallySharedIPAInputs<F, S>,
+        >,
+{
+    let validator = sh_ctx.narrow(&Step::AfterConvertAllBits).validator();
+    let m_ctx = validator.context();
+    let binary_validator = sh_ctx.narrow(&Step::BinaryValidator).validator::<Gf2>();
+    let binary_m_ctx = binary_validator.context();
+
+    // Bits above the maximum breakdown key are zero after sorting, so there is no need to upgrade
+    // and carry them around.
+    let bk_bits = min(BK::BITS, breakdown_key_bits(config.max_breakdown_key));
+    let (match_keys, breakdown_keys) = try_join(
+        binary_m_ctx
+            .narrow(&Step::UpgradeMatchKeyBits)
+            .upgrade(get_gf2_match_key_bits(
+                sorted_rows.iter().map(|row| &row.mk_shares),
+            )),
+        binary_m_ctx
+            .narrow(&Step::UpgradeBreakdownKeyBits)
+            .upgrade(get_gf2_breakdown_key_bits(
+                sorted_rows.iter().map(|row| &row.breakdown_key),
+                bk_bits,
+            )),
+    )
+    .await?;
+    let binary_shared_values = zip(match_keys, breakdown_keys)
+        .map(|(match_key, breakdown_key)| BinarySharedIPAInputs::new(match_key, breakdown_key))
+        .collect::<Vec<_>>();
+
+    let arithmetically_shared_values = m_ctx
+        .upgrade(
+            sorted_rows
+                .iter()
+                .map(|row| {
+                    ArithmeticallySharedIPAInputs::new(
+                        row.timestamp.clone(),
+                        row.is_trigger_bit.clone(),
+                        row.trigger_value.clone(),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        )
+        .await?;
+
+    match outputs {
+        AttributionOutputs::Reconciliation => {
+            let (output, reconciliation) = secure_attribution_with_reconciliation(
+                validator,
+                binary_validator,
+                arithmetically_shared_values,
+                binary_shared_values,
+                config,
+            )
+            .await?;
+            Ok((output, Some(reconciliation), None))
+        }
+        AttributionOutputs::Trace => {
+            let (output, trace) = secure_attribution_with_trace(
+                validator,
+                binary_validator,
+                arithmetically_shared_values,
+                binary_shared_values,
+                config,
+            )
+            .await?;
+            Ok((output, None, Some(trace)))
+        }
+        AttributionOutputs::Histogram => {
+            let output = secure_attribution(
+                validator,
+                binary_validator,
+                arithmetically_shared_values,
+                binary_shared_values,
+                config,
+            )
+            .await?;
+            Ok((output, None, None))
+        }
+    }
+}
+
+/// Upgrades the input, applies `sort_permutation` to it and validates the sorted rows. See
+/// [`sort_input`] for what is returned.
+#[allow(clippy::too_many_lines, clippy::type_complexity)]
+async fn sort_rows<C, S, SB, F, MK, BK>(
+    sh_ctx: C,
+    input_rows: &[IPAInputRow<F, MK, BK>],
+    sort_permutation: &RevealedAndRandomPermutations,
+    config: IpaQueryConfig,
+) -> Result<(Vec<IPAModulusConvertedInputRow<F, MK, BK>>, IpaQueryConfig), Error>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: IpaShare<C::UpgradedContext<F>, F> + Serializable,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB>,
+    SB: IpaShare<C::UpgradedContext<Gf2>, Gf2>,
+    for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
+    F: PrimeField + ExtendableField,
+    MK: GaloisField,
+    BK: GaloisField,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+    for<'u> UpgradeContext<'u, C::UpgradedContext<F>, F, RecordId>: UpgradeToMalicious<'u, BitConversionTriple<Replicated<F>>, BitConversionTriple<S>>
+        + UpgradeToMalicious<
+            'u,
+            ArithmeticallySharedIPAInputs<F, Replicated<F>>,
+            ArithmeticallySharedIPAInputs<F, S>,
+        >,
+{
+    let validator = sh_ctx.narrow(&Step::SortValidator).validator::<F>();
+    let m_ctx = validator.context();
+
+    let gf2_match_key_bits = get_gf2_match_key_bits(input_rows.iter().map(|row| &row.mk_shares));
+    // Breakdown keys never exceed `max_breakdown_key`, so there is no need to upgrade and carry
+    // around the upper bits that are known to be zero.
+    let bk_bits = min(BK::BITS, breakdown_key_bits(config.max_breakdown_key));
+
+    let binary_validator = sh_ctx.narrow(&Step::SortBinaryValidator).validator::<Gf2>();
+    let binary_m_ctx = binary_validator.context();
+
+    let (upgraded_gf2_match_key_bits, (max_breakdown_key, upgraded_gf2_breakdown_key_bits)) =
+        try_join(
+            binary_m_ctx
+                .narrow(&Step::UpgradeMatchKeyBits)
+                .upgrade(gf2_match_key_bits),
+            async {
+                let upgrade_ctx = binary_m_ctx.narrow(&Step::UpgradeBreakdownKeyBits);
+                if config.detect_max_breakdown_key {
+                    // Nothing is known about the keys yet, so all bits take part in finding the
+                    // maximum. Bits above it are zero in every key and do not need to be upgraded.
+                    let max_breakdown_key = reveal_max_breakdown_key(
+                        sh_ctx.narrow(&Step::DetectMaxBreakdownKey),
+                        get_gf2_breakdown_key_bits(
+                            input_rows.iter().map(|row| &row.breakdown_key),
+                            BK::BITS,
+                        ),
+                    )
+                    .await?
+                    .saturating_add(1);
+                    let breakdown_keys = upgrade_ctx
+                        .upgrade(get_gf2_breakdown_key_bits(
+                            input_rows.iter().map(|row| &row.breakdown_key),
+                            breakdown_key_bits(max_breakdown_key),
+                        ))
+                        .await?;
+                    Ok((max_breakdown_key, breakdown_keys))
+                } else if config.clamp_breakdown_keys {
+                    // Out-of-range keys may have any of the upper bits set, so all of them must
+                    // take part in the comparison.
+                    let breakdown_keys = upgrade_ctx
+                        .upgrade(get_gf2_breakdown_key_bits(
+                            input_rows.iter().map(|row| &row.breakdown_key),
+                            BK::BITS,
+                        ))
+                        .await?;
+                    let breakdown_keys = clamp_breakdown_keys(
+                        binary_m_ctx.narrow(&Step::ClampBreakdownKeys),
+                        breakdown_keys,
+                        config.max_breakdown_key,
+                        bk_bits,
+                    )
+                    .await?;
+                    Ok((config.max_breakdown_key, breakdown_keys))
+                } else {
+                    let breakdown_keys = upgrade_ctx
+                        .upgrade(get_gf2_breakdown_key_bits(
+                            input_rows.iter().map(|row| &row.breakdown_key),
+                            bk_bits,
+                        ))
+                        .await?;
+                    Ok((config.max_breakdown_key, breakdown_keys))
+                }
+            },
+        )
+        .await?;
+    let config = IpaQueryConfig {
+        max_breakdown_key,
+        ..config
+    };
+
+    let arithmetically_shared_values = input_rows
+        .iter()
+        .map(|input_row| {
+            ArithmeticallySharedIPAInputs::new(
                 input_row.timestamp.clone(),
                 input_row.is_trigger_bit.clone(),
                 input_row.trigger_value.clone(),
@@ -388,93 +1284,1144 @@ where
         apply_sort_permutation(
             m_ctx.narrow(&Step::ApplySortPermutation),
             arithmetically_shared_values,
-            &sort_permutation,
+            sort_permutation,
         ),
         apply_sort_permutation(
             binary_m_ctx.narrow(&Step::ApplySortPermutation),
             binary_shared_values,
-            &sort_permutation,
+            sort_permutation,
         ),
     )
     .await?;
 
-    secure_attribution(
-        validator,
-        binary_validator,
-        arithmetically_shared_values,
-        binary_shared_values,
-        config,
+    // Only plain shares leave the upgraded contexts, so the sorted rows are validated first.
+    let (arithmetically_shared_values, binary_shared_values) = try_join(
+        validator.validate(
+            arithmetically_shared_values
+                .into_iter()
+                .map(|row| (row.timestamp, (row.is_trigger_bit, row.trigger_value)))
+                .collect::<Vec<_>>(),
+        ),
+        binary_validator.validate(
+            binary_shared_values
+                .into_iter()
+                .map(|row| (row.match_key, row.breakdown_key))
+                .collect::<Vec<_>>(),
+        ),
     )
-    .await
+    .await?;
+
+    let sorted_rows = zip(arithmetically_shared_values, binary_shared_values)
+        .map(
+            |((timestamp, (is_trigger_bit, trigger_value)), (match_key, breakdown_key))| {
+                IPAModulusConvertedInputRow {
+                    timestamp,
+                    mk_shares: from_gf2_bits(&match_key),
+                    is_trigger_bit,
+                    breakdown_key: from_gf2_bits(&breakdown_key),
+                    trigger_value,
+                }
+            },
+        )
+        .collect();
+
+    Ok((sorted_rows, config))
 }
 
-fn get_gf2_match_key_bits<F, MK, BK>(
-    input_rows: &[IPAInputRow<F, MK, BK>],
-) -> Vec<BitDecomposed<Replicated<Gf2>>>
+/// Splits `row_count` rows sorted by match key into contiguous chunks of at most `max_chunk`
+/// rows, so that events of a single user never end up in different chunks. `user_boundaries`
+/// are the sorted indices of rows that start a new user, as returned by
+/// [`reveal_user_boundaries`].
+///
+/// Each chunk can then be attributed by a separate [`ipa`] invocation and the resulting
+/// histograms added together. Every chunk starts with a new user, so those invocations leave
+/// [`IpaQueryConfig::first_row_continues_previous`] unset. A user that has more than `max_chunk` events is placed into a chunk
+/// of its own, which is the only case when a chunk is larger than `max_chunk`.
+///
+/// ## Panics
+/// If `max_chunk` is zero or boundaries are not sorted in ascending order.
+///
+/// [`reveal_user_boundaries`]: crate::protocol::attribution::reveal_user_boundaries
+#[must_use]
+pub fn partition_by_user_boundary(
+    row_count: usize,
+    user_boundaries: &[usize],
+    max_chunk: usize,
+) -> Vec<Range<usize>> {
+    assert!(max_chunk > 0, "chunk size must be positive");
+
+    let mut chunks = Vec::new();
+    let mut chunk_start = 0;
+    let mut user_start = 0;
+    for &user_end in user_boundaries.iter().chain(std::iter::once(&row_count)) {
+        assert!(user_start <= user_end, "user boundaries must be sorted");
+        if user_end - chunk_start > max_chunk && user_start > chunk_start {
+            chunks.push(chunk_start..user_start);
+            chunk_start = user_start;
+        }
+        user_start = user_end;
+    }
+    if chunk_start < row_count {
+        chunks.push(chunk_start..row_count);
+    }
+
+    chunks
+}
+
+fn get_gf2_match_key_bits<'a, MK: GaloisField>(
+    match_keys: impl Iterator<Item = &'a Replicated<MK>>,
+) -> Vec<BitDecomposed<Replicated<Gf2>>> {
+    match_keys
+        .map(|match_key| BitDecomposed::new(gf2_bits(match_key)))
+        .collect::<Vec<_>>()
+}
+
+/// Bits of a binary shared value, least significant first.
+fn gf2_bits<V: GaloisField>(value: &Replicated<V>) -> impl Iterator<Item = Replicated<Gf2>> + '_ {
+    (0..V::BITS).map(|i| {
+        Replicated::new(
+            Gf2::truncate_from(value.left()[i]),
+            Gf2::truncate_from(value.right()[i]),
+        )
+    })
+}
+
+/// Packs shares of single bits, least significant first, into a binary shared value. This is the
+/// inverse of [`gf2_bits`].
+fn from_gf2_bits<V: GaloisField>(bits: &BitDecomposed<Replicated<Gf2>>) -> Replicated<V> {
+    debug_assert!(bits.len() <= usize::try_from(V::BITS).unwrap());
+    let (left, right) =
+        bits.iter()
+            .enumerate()
+            .fold((0_u128, 0_u128), |(left, right), (i, bit)| {
+                (
+                    left | (bit.left().as_u128() << i),
+                    right | (bit.right().as_u128() << i),
+                )
+            });
+    Replicated::new(V::truncate_from(left), V::truncate_from(right))
+}
+
+/// Converts binary shared timestamps to `F`. Attribution upgrades its inputs again, so the
+/// converted timestamps are validated before they are returned.
+///
+/// ## Errors
+/// Propagates errors from modulus conversion and validation.
+async fn convert_timestamps<C, S, F, TS>(
+    ctx: C,
+    timestamps: Vec<Replicated<TS>>,
+) -> Result<Vec<Replicated<F>>, Error>
 where
-    F: PrimeField,
-    MK: GaloisField,
-    BK: GaloisField,
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: IpaShare<C::UpgradedContext<F>, F>,
+    for<'r> &'r S: LinearRefOps<'r, S, F>,
+    F: PrimeField + ExtendableField,
+    TS: GaloisField,
+    for<'u> UpgradeContext<'u, C::UpgradedContext<F>, F, RecordId>:
+        UpgradeToMalicious<'u, BitConversionTriple<Replicated<F>>, BitConversionTriple<S>>,
 {
-    input_rows
-        .iter()
-        .map(|row| {
-            BitDecomposed::decompose(MK::BITS, |i| {
-                Replicated::new(
-                    Gf2::truncate_from(row.mk_shares.left()[i]),
-                    Gf2::truncate_from(row.mk_shares.right()[i]),
-                )
-            })
+    let validator = ctx.validator::<F>();
+    let m_ctx = validator.context().set_total_records(timestamps.len());
+    let timestamps = convert_bits(m_ctx, stream_iter(timestamps), 0..TS::BITS)
+        .map_ok(|bits| bits.to_additive_sharing_in_large_field::<F>())
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    validator.validate(timestamps).await
+}
+
+/// Decomposes every breakdown key into its `bits` least significant bits.
+fn get_gf2_breakdown_key_bits<'a, BK: GaloisField>(
+    breakdown_keys: impl Iterator<Item = &'a Replicated<BK>>,
+    bits: u32,
+) -> Vec<BitDecomposed<Replicated<Gf2>>> {
+    debug_assert!(bits <= BK::BITS);
+    breakdown_keys
+        .map(|breakdown_key| {
+            BitDecomposed::new(gf2_bits(breakdown_key).take(usize::try_from(bits).unwrap()))
         })
         .collect::<Vec<_>>()
 }
 
-fn get_gf2_breakdown_key_bits<F, MK, BK>(
-    input_rows: &[IPAInputRow<F, MK, BK>],
-) -> Vec<BitDecomposed<Replicated<Gf2>>>
+/// Obliviously replaces every breakdown key that is not less than `max_breakdown_key` with
+/// `max_breakdown_key - 1`, so that credit from out-of-range keys ends up in the top bucket
+/// instead of being dropped. Only the `bits` least significant bits of the clamped keys are
+/// returned.
+///
+/// ## Errors
+/// Propagates errors from multiplications.
+async fn clamp_breakdown_keys<C, SB>(
+    ctx: C,
+    breakdown_keys: Vec<BitDecomposed<SB>>,
+    max_breakdown_key: u32,
+    bits: u32,
+) -> Result<Vec<BitDecomposed<SB>>, Error>
 where
-    F: PrimeField,
-    MK: GaloisField,
-    BK: GaloisField,
-{
-    input_rows
-        .iter()
-        .map(|row| {
-            BitDecomposed::decompose(BK::BITS, |i| {
-                Replicated::new(
-                    Gf2::truncate_from(row.breakdown_key.left()[i]),
-                    Gf2::truncate_from(row.breakdown_key.right()[i]),
-                )
-            })
-        })
-        .collect::<Vec<_>>()
+    C: Context,
+    SB: LinearSecretSharing<Gf2> + BasicProtocols<C, Gf2>,
+    for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
+{
+    let cap = max_breakdown_key.saturating_sub(1);
+    let cap_bit = |j: usize| j < 32 && (cap >> j) & 1 == 1;
+
+    let ctx = ctx.set_total_records(breakdown_keys.len());
+    ctx.try_join(breakdown_keys.into_iter().enumerate().map(|(i, bk)| {
+        let ctx = ctx.clone();
+        async move {
+            let record_id = RecordId::from(i);
+            // If the cap does not fit into the key, no key can exceed it.
+            let gt = if bk.len() < 32 && cap >> bk.len() != 0 {
+                SB::ZERO
+            } else {
+                // Walk from the least significant bit up: the key is greater than the cap
+                // if it is greater at the current bit, or equal there and greater below.
+                let compare_ctx = ctx.narrow(&ClampBreakdownKeyStep::Compare);
+                let mut gt = SB::ZERO;
+                for (j, bit) in bk.iter().enumerate() {
+                    let both = bit
+                        .multiply(&gt, compare_ctx.narrow(&BitOpStep::from(j)), record_id)
+                        .await?;
+                    gt = if cap_bit(j) {
+                        both
+                    } else {
+                        bit.clone() + &gt - &both
+                    };
+                }
+                gt
+            };
+
+            // clamped = key + gt * (cap - key), which for a single bit in Gf2 is
+            // key ^ (gt & key) ^ (gt & cap).
+            let select_ctx = ctx.narrow(&ClampBreakdownKeyStep::Select);
+            let clamped = ctx
+                .parallel_join(bk.iter().take(bits as usize).enumerate().map(|(j, bit)| {
+                    let c = select_ctx.narrow(&BitOpStep::from(j));
+                    let gt = &gt;
+                    async move {
+                        let gt_and_bit = gt.multiply(bit, c, record_id).await?;
+                        let mut clamped = bit.clone() - &gt_and_bit;
+                        if cap_bit(j) {
+                            clamped += gt;
+                        }
+                        Ok::<_, Error>(clamped)
+                    }
+                }))
+                .await?;
+
+            Ok(BitDecomposed::new(clamped))
+        }
+    }))
+    .await
+}
+
+/// Obliviously finds the largest of `breakdown_keys` and reveals it. Keys are compared pairwise
+/// in a binary tree, so only the maximum itself is revealed, not which row holds it. Returns zero
+/// if there are no keys.
+///
+/// The comparisons run under their own validator, which checks them before anything is revealed.
+///
+/// ## Errors
+/// Propagates errors from multiplications, malicious validation and the reveal. Fails if the
+/// maximum does not fit in a `u32`.
+async fn reveal_max_breakdown_key<C, SB>(
+    ctx: C,
+    breakdown_keys: Vec<BitDecomposed<Replicated<Gf2>>>,
+) -> Result<u32, Error>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<Gf2>: UpgradedContext<Gf2, Share = SB>,
+    SB: IpaShare<C::UpgradedContext<Gf2>, Gf2>,
+    for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
+{
+    let reveal_ctx = ctx.narrow(&MaxBreakdownKeyStep::Reveal);
+    let validator = ctx.validator::<Gf2>();
+    let m_ctx = validator.context();
+
+    let mut keys = m_ctx.upgrade(breakdown_keys).await?;
+    let mut round = 0;
+    while keys.len() > 1 {
+        let ctx = m_ctx
+            .narrow(&MaxBreakdownKeyStep::Round(round))
+            .set_total_records(keys.len() / 2);
+        let odd = if keys.len() % 2 == 1 {
+            keys.pop()
+        } else {
+            None
+        };
+        let mut rest = keys.into_iter();
+        let pairs = from_fn(|| Some((rest.next()?, rest.next()?)));
+        keys = ctx
+            .try_join(
+                pairs
+                    .enumerate()
+                    .map(|(i, (a, b))| max_of_two(ctx.clone(), RecordId::from(i), a, b)),
+            )
+            .await?;
+        keys.extend(odd);
+        round += 1;
+    }
+
+    let Some(max) = keys.pop() else {
+        return Ok(0);
+    };
+    let max = validator.validate(max).await?;
+    let reveal_ctx = reveal_ctx.set_total_records(max.len());
+    let bits = reveal_ctx
+        .parallel_join(
+            max.iter()
+                .enumerate()
+                .map(|(j, bit)| bit.reveal(reveal_ctx.clone(), RecordId::from(j))),
+        )
+        .await?;
+
+    // keys may be wider than the `u32` that aggregation is sized with
+    let max = bits
+        .into_iter()
+        .enumerate()
+        .filter(|(_, bit)| *bit == Gf2::ONE)
+        .fold(0_u128, |max, (j, _)| max | (1 << j));
+    u32::try_from(max)
+        .map_err(|_| Error::Unsupported(format!("breakdown key {max} does not fit in 32 bits")))
+}
+
+/// Returns the larger of two keys of the same width.
+async fn max_of_two<C, SB>(
+    ctx: C,
+    record_id: RecordId,
+    a: BitDecomposed<SB>,
+    b: BitDecomposed<SB>,
+) -> Result<BitDecomposed<SB>, Error>
+where
+    C: Context,
+    SB: LinearSecretSharing<Gf2> + BasicProtocols<C, Gf2>,
+    for<'r> &'r SB: LinearRefOps<'r, SB, Gf2>,
+{
+    debug_assert_eq!(a.len(), b.len());
+
+    // Walk from the least significant bit up: `a` is greater if its bit is the larger one at the
+    // most significant position where the keys differ. gt = gt + (a ^ b) * (a - gt).
+    let compare_ctx = ctx.narrow(&MaxBreakdownKeyStep::Compare);
+    let mut gt = SB::ZERO;
+    for (j, (a_j, b_j)) in zip(a.iter(), b.iter()).enumerate() {
+        let differ = a_j.clone() + b_j;
+        let a_or_gt = a_j.clone() - &gt;
+        gt += &differ
+            .multiply(&a_or_gt, compare_ctx.narrow(&BitOpStep::from(j)), record_id)
+            .await?;
+    }
+
+    // max = b + gt * (a - b)
+    let select_ctx = ctx.narrow(&MaxBreakdownKeyStep::Select);
+    let max = ctx
+        .parallel_join(zip(a.iter(), b.iter()).enumerate().map(|(j, (a_j, b_j))| {
+            let c = select_ctx.narrow(&BitOpStep::from(j));
+            let gt = &gt;
+            async move {
+                let diff = a_j.clone() - b_j;
+                Ok::<_, Error>(gt.multiply(&diff, c, record_id).await? + b_j)
+            }
+        }))
+        .await?;
+
+    Ok(BitDecomposed::new(max))
 }
 
 #[cfg(all(test, any(unit_test, feature = "shuttle")))]
 pub mod tests {
-    use std::num::NonZeroU32;
+    use std::{iter::zip, num::NonZeroU32};
 
-    use super::ipa;
+    use bytes::Bytes;
+    use futures::{future::try_join, stream, TryStreamExt};
+    use generic_array::GenericArray;
+
+    use super::{
+        get_gf2_breakdown_key_bits, get_gf2_match_key_bits, ipa, ipa_from_sorted, ipa_split,
+        ipa_to_share_files, ipa_with_reconciliation, ipa_with_trace, partition_by_user_boundary,
+        sort_input, ArithmeticallySharedIPAInputs, BinarySharedIPAInputs, IPAInputRow,
+        IPAModulusConvertedInputRow, IPASourceRow, IPATriggerRow, WithReportId,
+    };
     use crate::{
-        ff::{Field, Fp31, Fp32BitPrime},
-        helpers::{query::IpaQueryConfig, GatewayConfig},
+        error::{BoxError, Error},
+        ff::{Field, Fp31, Fp32BitPrime, Gf20Bit, Serializable},
+        helpers::{query::IpaQueryConfig, GatewayConfig, Role},
         ipa_test_input,
-        protocol::{BreakdownKey, MatchKey},
+        protocol::{
+            attribution::{
+                aggregate_credit::breakdown_key_bits, reveal_user_boundaries, AttributionTrace,
+                CreditReconciliation,
+            },
+            basics::{Reshare, SecureMul},
+            context::{Context, Validator},
+            BreakdownKey, MatchKey, RecordId,
+        },
         rand::{thread_rng, Rng},
+        secret_sharing::{
+            replicated::{
+                malicious::AdditiveShare as MaliciousReplicated,
+                semi_honest::AdditiveShare as Replicated,
+            },
+            SharedValue,
+        },
+        seq_join::SeqJoin,
         test_executor::{run, run_with},
         test_fixture::{
             input::GenericReportTestInput,
-            ipa::{ipa_in_the_clear, test_ipa, IpaSecurityModel},
-            logging, EventGenerator, EventGeneratorConfig, Reconstruct, Runner, TestWorld,
-            TestWorldConfig,
+            ipa::{
+                attribution_trace_in_the_clear, ipa_in_the_clear, test_ipa, IpaSecurityModel,
+                TestRawDataRecord,
+            },
+            logging, reconstruct_histogram, EventGenerator, EventGeneratorConfig, Reconstruct,
+            Runner, TestWorld, TestWorldConfig, WithMacKey,
         },
     };
 
     #[test]
-    fn semi_honest() {
+    fn semi_honest() {
+        const PER_USER_CAP: u32 = 3;
+        const EXPECTED: &[u128] = &[0, 2, 3, 0, 0, 0, 0, 0];
+        const MAX_BREAKDOWN_KEY: u32 = 8;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            let result: Vec<_> = world
+                .semi_honest(records.into_iter(), |ctx, input_rows| async move {
+                    ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                        ctx,
+                        &input_rows,
+                        IpaQueryConfig::no_window(PER_USER_CAP, MAX_BREAKDOWN_KEY, NUM_MULTI_BITS),
+                    )
+                    .await
+                    .unwrap()
+                })
+                .await
+                .reconstruct();
+            assert_eq!(result, EXPECTED);
+        });
+    }
+
+    #[test]
+    fn histogram_total_check() {
+        const PER_USER_CAP: u32 = 3;
+        const EXPECTED: &[u128] = &[0, 2, 3, 0, 0, 0, 0, 0];
+        const MAX_BREAKDOWN_KEY: u32 = 8;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+            let config = IpaQueryConfig {
+                check_histogram_total: true,
+                first_row_continues_previous: false,
+                ..IpaQueryConfig::no_window(PER_USER_CAP, MAX_BREAKDOWN_KEY, NUM_MULTI_BITS)
+            };
+
+            let result: Vec<_> = world
+                .semi_honest(records.clone().into_iter(), |ctx, input_rows| {
+                    let config = config.clone();
+                    async move {
+                        ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(ctx, &input_rows, config)
+                            .await
+                            .unwrap()
+                    }
+                })
+                .await
+                .reconstruct();
+            assert_eq!(result, EXPECTED);
+
+            let result: Vec<_> = world
+                .malicious(records.into_iter(), |ctx, input_rows| {
+                    let config = config.clone();
+                    async move {
+                        ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(ctx, &input_rows, config)
+                            .await
+                            .unwrap()
+                    }
+                })
+                .await
+                .reconstruct();
+            assert_eq!(result, EXPECTED);
+        });
+    }
+
+    #[test]
+    fn histogram_total_check_catches_dropped_credit() {
+        const PER_USER_CAP: u32 = 3;
+        const MAX_BREAKDOWN_KEY: u32 = 3;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            // Aggregation has no bucket for breakdown key 3, so its credit goes missing.
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 3, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 1 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+            let config = IpaQueryConfig {
+                check_histogram_total: true,
+                first_row_continues_previous: false,
+                ..IpaQueryConfig::no_window(PER_USER_CAP, MAX_BREAKDOWN_KEY, NUM_MULTI_BITS)
+            };
+
+            let results = world
+                .semi_honest(records.into_iter(), |ctx, input_rows| {
+                    let config = config.clone();
+                    async move {
+                        ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(ctx, &input_rows, config).await
+                    }
+                })
+                .await;
+            for result in results {
+                assert!(matches!(result, Err(Error::HistogramTotalMismatch)));
+            }
+        });
+    }
+
+    #[test]
+    fn share_files_reconstruct_offline() {
+        const PER_USER_CAP: u32 = 3;
+        const EXPECTED: &[u128] = &[0, 2, 3, 0, 0, 0, 0, 0];
+        const MAX_BREAKDOWN_KEY: u32 = 8;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            let files: [Vec<u8>; 3] = world
+                .semi_honest(records.into_iter(), |ctx, input_rows| async move {
+                    let mut file = Vec::new();
+                    ipa_to_share_files::<_, _, _, Fp31, MatchKey, BreakdownKey, _>(
+                        ctx,
+                        &input_rows,
+                        IpaQueryConfig::no_window(PER_USER_CAP, MAX_BREAKDOWN_KEY, NUM_MULTI_BITS),
+                        &mut file,
+                    )
+                    .await
+                    .unwrap();
+                    file
+                })
+                .await;
+
+            let streams = files.map(|file| stream::iter([Ok::<_, BoxError>(Bytes::from(file))]));
+            let histogram = reconstruct_histogram::<Fp31, _>(streams)
+                .map_ok(|(_, credit)| credit.as_u128())
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(histogram, EXPECTED);
+        });
+    }
+
+    #[test]
+    fn trace_matches_plaintext_reference() {
+        const PER_USER_CAP: u32 = 3;
+        const MAX_BREAKDOWN_KEY: u32 = 8;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            // User 3 converts over the cap, user 1 spreads credit over two sources and user 2 has
+            // no source events at all.
+            let raw_data = [
+                (0, 3, false, 1, 0),
+                (1, 1, false, 2, 0),
+                (2, 3, true, 0, 2),
+                (3, 1, true, 0, 1),
+                (4, 3, true, 0, 4),
+                (5, 1, false, 3, 0),
+                (6, 1, true, 0, 2),
+                (7, 2, true, 0, 1),
+            ]
+            .map(
+                |(timestamp, user_id, is_trigger_report, breakdown_key, trigger_value)| {
+                    TestRawDataRecord {
+                        timestamp,
+                        user_id,
+                        is_trigger_report,
+                        breakdown_key,
+                        trigger_value,
+                    }
+                },
+            );
+            let records = raw_data
+                .iter()
+                .map(|x| {
+                    ipa_test_input!(
+                        {
+                            timestamp: x.timestamp,
+                            match_key: x.user_id,
+                            is_trigger_report: x.is_trigger_report,
+                            breakdown_key: x.breakdown_key,
+                            trigger_value: x.trigger_value,
+                        };
+                        (Fp32BitPrime, MatchKey, BreakdownKey)
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let (histogram, trace): (Vec<Fp32BitPrime>, AttributionTrace<Fp32BitPrime>) = world
+                .semi_honest(records.into_iter(), |ctx, input_rows| async move {
+                    ipa_with_trace::<_, _, _, Fp32BitPrime, MatchKey, BreakdownKey>(
+                        ctx,
+                        &input_rows,
+                        IpaQueryConfig::no_window(PER_USER_CAP, MAX_BREAKDOWN_KEY, NUM_MULTI_BITS),
+                    )
+                    .await
+                    .unwrap()
+                })
+                .await
+                .reconstruct();
+
+            let expected = attribution_trace_in_the_clear::<Fp32BitPrime>(&raw_data, PER_USER_CAP);
+            assert_eq!(expected.is_trigger_bits, trace.is_trigger_bits);
+            assert_eq!(expected.trigger_values, trace.trigger_values);
+            assert_eq!(expected.helper_bits, trace.helper_bits);
+            assert_eq!(expected.accumulated_credits, trace.accumulated_credits);
+            assert_eq!(expected.capped_credits, trace.capped_credits);
+
+            let expected_histogram =
+                ipa_in_the_clear(&raw_data, PER_USER_CAP, None, MAX_BREAKDOWN_KEY);
+            assert_eq!(
+                expected_histogram
+                    .into_iter()
+                    .map(Fp32BitPrime::truncate_from)
+                    .collect::<Vec<_>>(),
+                histogram
+            );
+        });
+    }
+
+    #[test]
+    fn first_row_continues_previous() {
+        const PER_USER_CAP: u32 = 3;
+        const EXPECTED: &[u128] = &[0, 2, 3, 0];
+        const MAX_BREAKDOWN_KEY: u32 = 4;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 4 }, // no source in this chunk
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 3 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            for first_row_continues_previous in [false, true] {
+                let (histogram, trace): (Vec<Fp31>, AttributionTrace<Fp31>) = world
+                    .semi_honest(records.clone().into_iter(), |ctx, input_rows| async move {
+                        ipa_with_trace::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                            ctx,
+                            &input_rows,
+                            IpaQueryConfig {
+                                first_row_continues_previous,
+                                ..IpaQueryConfig::no_window(
+                                    PER_USER_CAP,
+                                    MAX_BREAKDOWN_KEY,
+                                    NUM_MULTI_BITS,
+                                )
+                            },
+                        )
+                        .await
+                        .unwrap()
+                    })
+                    .await
+                    .reconstruct();
+
+                // The first row is only marked as continuing the previous user when asked to.
+                assert_eq!(
+                    vec![u128::from(first_row_continues_previous), 1, 1, 0, 1],
+                    trace
+                        .helper_bits
+                        .iter()
+                        .map(Field::as_u128)
+                        .collect::<Vec<_>>()
+                );
+                // Either way, the leading trigger event has nothing to be attributed to.
+                assert_eq!(
+                    vec![0, 2, 0, 3, 0],
+                    trace
+                        .capped_credits
+                        .iter()
+                        .map(Field::as_u128)
+                        .collect::<Vec<_>>()
+                );
+                assert_eq!(histogram, EXPECTED);
+            }
+        });
+    }
+
+    #[test]
+    fn split_input_matches_unified_input() {
+        const PER_USER_CAP: u32 = 7;
+        const MAX_BREAKDOWN_KEY: u32 = 8;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            // ((timestamp, match key), breakdown key or trigger value)
+            type Events<V> = Vec<((Replicated<Gf20Bit>, Replicated<MatchKey>), Replicated<V>)>;
+
+            let world = TestWorld::default();
+
+            // Events in time order. The source after the last trigger of user 12345 must not
+            // receive its credit.
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 1, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 2, match_key: 68362, is_trigger_report: 0, breakdown_key: 3, trigger_value: 0 },
+                    { timestamp: 3, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                    { timestamp: 4, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 5, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 6, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 1 },
+                    { timestamp: 7, match_key: 12345, is_trigger_report: 0, breakdown_key: 4, trigger_value: 0 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+            let config =
+                &IpaQueryConfig::no_window(PER_USER_CAP, MAX_BREAKDOWN_KEY, NUM_MULTI_BITS);
+
+            let unified: Vec<Fp31> = world
+                .semi_honest(records.clone().into_iter(), |ctx, input_rows| async move {
+                    ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(ctx, &input_rows, config.clone())
+                        .await
+                        .unwrap()
+                })
+                .await
+                .reconstruct();
+
+            // Split inputs are in reverse time order, `ipa_split` must sort them by timestamp.
+            let (mut sources, mut triggers) = (Vec::new(), Vec::new());
+            for record in records.iter().rev() {
+                let timestamp = Gf20Bit::truncate_from(record.timestamp.unwrap().as_u128());
+                let event = (timestamp, record.match_key.unwrap());
+                if record.is_trigger_report.unwrap() == Fp31::ONE {
+                    triggers.push((event, record.trigger_value));
+                } else {
+                    sources.push((event, record.breakdown_key.unwrap()));
+                }
+            }
+
+            let split: Vec<Fp31> = world
+                .semi_honest(
+                    (sources.into_iter(), triggers.into_iter()),
+                    |ctx, (sources, triggers): (Events<BreakdownKey>, Events<Fp31>)| async move {
+                        let sources = sources
+                            .into_iter()
+                            .map(|((timestamp, mk_shares), breakdown_key)| IPASourceRow {
+                                timestamp,
+                                mk_shares,
+                                breakdown_key,
+                            })
+                            .collect::<Vec<_>>();
+                        let triggers = triggers
+                            .into_iter()
+                            .map(|((timestamp, mk_shares), trigger_value)| IPATriggerRow {
+                                timestamp,
+                                mk_shares,
+                                trigger_value,
+                            })
+                            .collect::<Vec<_>>();
+
+                        ipa_split::<_, _, _, Fp31, MatchKey, BreakdownKey, Gf20Bit>(
+                            ctx,
+                            &sources,
+                            &triggers,
+                            config.clone(),
+                        )
+                        .await
+                        .unwrap()
+                    },
+                )
+                .await
+                .reconstruct();
+
+            assert_eq!(
+                [0_u128, 5, 1, 2, 0, 0, 0, 0]
+                    .map(Fp31::truncate_from)
+                    .to_vec(),
+                unified
+            );
+            assert_eq!(unified, split);
+        });
+    }
+
+    #[test]
+    fn report_ids_do_not_affect_histogram() {
+        const PER_USER_CAP: u32 = 3;
+        const EXPECTED: &[u128] = &[0, 2, 3, 0, 0, 0, 0, 0];
+        const MAX_BREAKDOWN_KEY: u32 = 8;
+        const NUM_MULTI_BITS: u32 = 3;
+        const REPORT_IDS: [Option<u64>; 5] = [Some(1004), None, Some(7), Some(1004), Some(42)];
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+            let records = zip(records, REPORT_IDS)
+                .map(|(record, report_id)| GenericReportTestInput {
+                    report_id,
+                    ..record
+                })
+                .collect::<Vec<_>>();
+
+            let result: Vec<_> = world
+                .semi_honest(
+                    records.into_iter(),
+                    |ctx, input_rows: Vec<WithReportId<IPAInputRow<_, _, _>>>| async move {
+                        let (report_ids, input_rows): (Vec<_>, Vec<_>) =
+                            input_rows.into_iter().map(WithReportId::into_parts).unzip();
+                        assert_eq!(REPORT_IDS.to_vec(), report_ids);
+
+                        ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                            ctx,
+                            &input_rows,
+                            IpaQueryConfig::no_window(
+                                PER_USER_CAP,
+                                MAX_BREAKDOWN_KEY,
+                                NUM_MULTI_BITS,
+                            ),
+                        )
+                        .await
+                        .unwrap()
+                    },
+                )
+                .await
+                .reconstruct();
+            assert_eq!(result, EXPECTED);
+        });
+    }
+
+    #[test]
+    fn two_campaigns_in_one_context() {
+        const PER_USER_CAP: u32 = 3;
+        const EXPECTED_A: &[u128] = &[0, 2, 3, 0, 0, 0, 0, 0];
+        const EXPECTED_B: &[u128] = &[0, 0, 0, 2, 0, 0, 1, 0];
+        const MAX_BREAKDOWN_KEY: u32 = 8;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let campaign_a: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+            let campaign_b: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 3, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 0, match_key: 555, is_trigger_report: 0, breakdown_key: 6, trigger_value: 0 },
+                    { timestamp: 0, match_key: 555, is_trigger_report: 1, breakdown_key: 0, trigger_value: 1 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            let (result_a, result_b): (Vec<_>, Vec<_>) =
+                world
+                    .semi_honest(
+                        (campaign_a.into_iter(), campaign_b.into_iter()),
+                        |ctx,
+                         (rows_a, rows_b): (
+                            Vec<IPAInputRow<_, _, _>>,
+                            Vec<IPAInputRow<_, _, _>>,
+                        )| async move {
+                            let config = IpaQueryConfig::no_window(
+                                PER_USER_CAP,
+                                MAX_BREAKDOWN_KEY,
+                                NUM_MULTI_BITS,
+                            );
+                            // Both invocations narrow with the same steps; scopes keep them apart.
+                            try_join(
+                                ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                                    ctx.scoped(0),
+                                    &rows_a,
+                                    config.clone(),
+                                ),
+                                ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                                    ctx.scoped(1),
+                                    &rows_b,
+                                    config,
+                                ),
+                            )
+                            .await
+                            .unwrap()
+                        },
+                    )
+                    .await
+                    .reconstruct();
+            assert_eq!(result_a, EXPECTED_A);
+            assert_eq!(result_b, EXPECTED_B);
+        });
+    }
+
+    #[test]
+    fn reconciliation_totals() {
+        const PER_USER_CAP: u32 = 3;
+        const EXPECTED: &[u128] = &[0, 2, 3, 0, 0, 0, 0, 0];
+        const MAX_BREAKDOWN_KEY: u32 = 8;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    // no source events for this user, so the credit cannot be attributed
+                    { timestamp: 0, match_key: 99999, is_trigger_report: 1, breakdown_key: 0, trigger_value: 4 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            let (result, reconciliation): (Vec<_>, CreditReconciliation<Fp31>) = world
+                .semi_honest(records.into_iter(), |ctx, input_rows| async move {
+                    ipa_with_reconciliation::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                        ctx,
+                        &input_rows,
+                        IpaQueryConfig::no_window(PER_USER_CAP, MAX_BREAKDOWN_KEY, NUM_MULTI_BITS),
+                    )
+                    .await
+                    .unwrap()
+                })
+                .await
+                .reconstruct();
+            assert_eq!(result, EXPECTED);
+            assert_eq!(
+                reconciliation,
+                CreditReconciliation {
+                    total_input_credit: Fp31::truncate_from(11_u128),
+                    total_attributed_credit: Fp31::truncate_from(5_u128),
+                    // user 12345 has 5 credit, but only 3 of it is below the cap
+                    total_capped: Fp31::truncate_from(2_u128),
+                }
+            );
+            assert_eq!(
+                result.iter().map(Field::as_u128).sum::<u128>(),
+                reconciliation.total_attributed_credit.as_u128()
+            );
+        });
+    }
+
+    #[test]
+    fn sorted_input_skips_sort() {
+        const PER_USER_CAP: u32 = 3;
+        const EXPECTED: &[u128] = &[0, 2, 3, 0, 0, 0, 0, 0];
+        const MAX_BREAKDOWN_KEY: u32 = 8;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+            let config =
+                &IpaQueryConfig::no_window(PER_USER_CAP, MAX_BREAKDOWN_KEY, NUM_MULTI_BITS);
+
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            let semi_honest = world
+                .semi_honest(records.clone().into_iter(), |ctx, input_rows| async move {
+                    let full = ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                        ctx.narrow("full"),
+                        &input_rows,
+                        config.clone(),
+                    )
+                    .await
+                    .unwrap();
+                    let (rows, config) = sort_input::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                        ctx.narrow("sort"),
+                        &input_rows,
+                        config.clone(),
+                    )
+                    .await
+                    .unwrap();
+                    let rows = serialization_round_trip(&rows);
+                    let from_sorted = ipa_from_sorted(ctx.narrow("staged"), &rows, config)
+                        .await
+                        .unwrap();
+
+                    (full, from_sorted)
+                })
+                .await;
+            let malicious = world
+                .malicious(records.into_iter(), |ctx, input_rows| async move {
+                    let full = ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                        ctx.narrow("full"),
+                        &input_rows,
+                        config.clone(),
+                    )
+                    .await
+                    .unwrap();
+                    let (rows, config) = sort_input::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                        ctx.narrow("sort"),
+                        &input_rows,
+                        config.clone(),
+                    )
+                    .await
+                    .unwrap();
+                    let rows = serialization_round_trip(&rows);
+                    let from_sorted = ipa_from_sorted(ctx.narrow("staged"), &rows, config)
+                        .await
+                        .unwrap();
+
+                    (full, from_sorted)
+                })
+                .await;
+
+            for [(f0, s0), (f1, s1), (f2, s2)] in [semi_honest, malicious] {
+                let full: Vec<Fp31> = [f0, f1, f2].reconstruct();
+                let from_sorted: Vec<Fp31> = [s0, s1, s2].reconstruct();
+                assert_eq!(full, EXPECTED);
+                assert_eq!(full, from_sorted);
+            }
+        });
+    }
+
+    /// Stores sorted rows and reads them back, as a pipeline that keeps them between stages would.
+    fn serialization_round_trip(
+        rows: &[IPAModulusConvertedInputRow<Fp31, MatchKey, BreakdownKey>],
+    ) -> Vec<IPAModulusConvertedInputRow<Fp31, MatchKey, BreakdownKey>> {
+        let restored = rows
+            .iter()
+            .map(|row| {
+                let mut buf = GenericArray::default();
+                row.serialize(&mut buf);
+                IPAModulusConvertedInputRow::deserialize(&buf)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rows, restored);
+        restored
+    }
+
+    #[test]
+    fn breakdown_key_bit_width_is_derived_from_max_breakdown_key() {
+        const PER_USER_CAP: u32 = 3;
+        const EXPECTED: &[u128] = &[0, 0, 0, 0, 0, 3, 0, 2];
+        const MAX_BREAKDOWN_KEY: u32 = 8;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 7, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 5, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 3 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            let result: Vec<_> = world
+                .semi_honest(
+                    records.into_iter(),
+                    |ctx, input_rows: Vec<IPAInputRow<Fp31, MatchKey, BreakdownKey>>| async move {
+                        // only 3 bits of the 8-bit breakdown key are needed to represent 8 keys
+                        let bits = breakdown_key_bits(MAX_BREAKDOWN_KEY);
+                        assert_eq!(3, bits);
+                        assert!(get_gf2_breakdown_key_bits(
+                            input_rows.iter().map(|row| &row.breakdown_key),
+                            bits
+                        )
+                        .iter()
+                        .all(|bk| bk.len() == 3));
+
+                        ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                            ctx,
+                            &input_rows,
+                            IpaQueryConfig::no_window(
+                                PER_USER_CAP,
+                                MAX_BREAKDOWN_KEY,
+                                NUM_MULTI_BITS,
+                            ),
+                        )
+                        .await
+                        .unwrap()
+                    },
+                )
+                .await
+                .reconstruct();
+            assert_eq!(result, EXPECTED);
+        });
+    }
+
+    #[test]
+    fn clamps_out_of_range_breakdown_keys() {
         const PER_USER_CAP: u32 = 3;
-        const EXPECTED: &[u128] = &[0, 2, 3, 0, 0, 0, 0, 0];
-        const MAX_BREAKDOWN_KEY: u32 = 8;
+        const EXPECTED: &[u128] = &[0, 2, 0, 3];
+        const MAX_BREAKDOWN_KEY: u32 = 4;
         const NUM_MULTI_BITS: u32 = 3;
 
         run(|| async {
@@ -483,10 +2430,9 @@ pub mod tests {
             let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
                 [
                     { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
-                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
-                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
-                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
-                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 200, trigger_value: 0 }, // clamped to 3
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 3 },
                 ];
                 (Fp31, MatchKey, BreakdownKey)
             );
@@ -496,7 +2442,14 @@ pub mod tests {
                     ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
                         ctx,
                         &input_rows,
-                        IpaQueryConfig::no_window(PER_USER_CAP, MAX_BREAKDOWN_KEY, NUM_MULTI_BITS),
+                        IpaQueryConfig {
+                            clamp_breakdown_keys: true,
+                            ..IpaQueryConfig::no_window(
+                                PER_USER_CAP,
+                                MAX_BREAKDOWN_KEY,
+                                NUM_MULTI_BITS,
+                            )
+                        },
                     )
                     .await
                     .unwrap()
@@ -507,6 +2460,336 @@ pub mod tests {
         });
     }
 
+    #[test]
+    fn detected_max_breakdown_key_matches_specified() {
+        const PER_USER_CAP: u32 = 3;
+        const EXPECTED: &[u128] = &[0, 2, 0, 0, 0, 3];
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 5, trigger_value: 0 },
+                    { timestamp: 0, match_key: 77777, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 3 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            let mut results = Vec::new();
+            for (max_breakdown_key, detect_max_breakdown_key) in [(6, false), (1, true)] {
+                let result: Vec<_> = world
+                    .semi_honest(records.clone().into_iter(), |ctx, input_rows| async move {
+                        ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                            ctx,
+                            &input_rows,
+                            IpaQueryConfig {
+                                detect_max_breakdown_key,
+                                ..IpaQueryConfig::no_window(
+                                    PER_USER_CAP,
+                                    max_breakdown_key,
+                                    NUM_MULTI_BITS,
+                                )
+                            },
+                        )
+                        .await
+                        .unwrap()
+                    })
+                    .await
+                    .reconstruct();
+                results.push(result);
+            }
+            assert_eq!(results[0], EXPECTED);
+            assert_eq!(results[0], results[1]);
+        });
+    }
+
+    #[test]
+    fn dedup() {
+        const PER_USER_CAP: u32 = 7;
+        const MAX_BREAKDOWN_KEY: u32 = 4;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 }, // exact duplicate
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 1 }, // different value
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 3 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            for (dedup, expected) in [(false, [0_u128, 5, 3, 0]), (true, [0, 3, 3, 0])] {
+                let result: Vec<_> = world
+                    .semi_honest(records.clone().into_iter(), |ctx, input_rows| async move {
+                        ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                            ctx,
+                            &input_rows,
+                            IpaQueryConfig {
+                                dedup,
+                                ..IpaQueryConfig::no_window(
+                                    PER_USER_CAP,
+                                    MAX_BREAKDOWN_KEY,
+                                    NUM_MULTI_BITS,
+                                )
+                            },
+                        )
+                        .await
+                        .unwrap()
+                    })
+                    .await
+                    .reconstruct();
+                assert_eq!(result, expected);
+            }
+        });
+    }
+
+    #[test]
+    fn drop_zero_match_keys() {
+        const MAX_BREAKDOWN_KEY: u32 = 4;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                    { timestamp: 0, match_key: 0, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 0, is_trigger_report: 1, breakdown_key: 0, trigger_value: 3 }, // no user
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 3, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            // with a cap of one, every trigger event is worth one credit regardless of its value
+            for (per_user_cap, drop_zero_match_keys, expected) in [
+                (7, false, [0_u128, 5, 3, 2]),
+                (7, true, [0, 5, 0, 2]),
+                (1, false, [0, 1, 1, 1]),
+                (1, true, [0, 1, 0, 1]),
+            ] {
+                let result: Vec<_> = world
+                    .semi_honest(records.clone().into_iter(), |ctx, input_rows| async move {
+                        ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                            ctx,
+                            &input_rows,
+                            IpaQueryConfig {
+                                drop_zero_match_keys,
+                                ..IpaQueryConfig::no_window(
+                                    per_user_cap,
+                                    MAX_BREAKDOWN_KEY,
+                                    NUM_MULTI_BITS,
+                                )
+                            },
+                        )
+                        .await
+                        .unwrap()
+                    })
+                    .await
+                    .reconstruct();
+                assert_eq!(result, expected);
+            }
+        });
+    }
+
+    #[test]
+    fn signed_trigger_values() {
+        const PER_USER_CAP: u32 = 7;
+        const MAX_BREAKDOWN_KEY: u32 = 4;
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            // Negative trigger values are written as their additive inverse in Fp31.
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 29 }, // refund of 2
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 26 }, // refund of 5
+                    { timestamp: 0, match_key: 77777, is_trigger_report: 0, breakdown_key: 3, trigger_value: 0 },
+                    { timestamp: 0, match_key: 77777, is_trigger_report: 1, breakdown_key: 0, trigger_value: 4 },
+                    { timestamp: 0, match_key: 77777, is_trigger_report: 1, breakdown_key: 0, trigger_value: 27 }, // refund of 4
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            // Without signed values, the negative net credit of the second user looks like a
+            // large positive one and gets capped.
+            for (signed_trigger_values, expected) in
+                [(false, [0_u128, 3, 7, 0]), (true, [0, 3, 0, 0])]
+            {
+                let result: Vec<_> = world
+                    .semi_honest(records.clone().into_iter(), |ctx, input_rows| async move {
+                        ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                            ctx,
+                            &input_rows,
+                            IpaQueryConfig {
+                                signed_trigger_values,
+                                ..IpaQueryConfig::no_window(
+                                    PER_USER_CAP,
+                                    MAX_BREAKDOWN_KEY,
+                                    NUM_MULTI_BITS,
+                                )
+                            },
+                        )
+                        .await
+                        .unwrap()
+                    })
+                    .await
+                    .reconstruct();
+                assert_eq!(result, expected);
+            }
+        });
+    }
+
+    #[test]
+    fn reshare_keeps_fields_in_place() {
+        run(|| async {
+            let world = TestWorld::default();
+            // distinct values, so swapping any two fields during reshare changes the result
+            let input = [7_u128, 1, 20].map(Fp31::truncate_from);
+
+            for &role in Role::all() {
+                let result = world
+                    .semi_honest(input.into_iter(), |ctx, shares| async move {
+                        let [timestamp, is_trigger_bit, trigger_value]: [_; 3] =
+                            shares.try_into().unwrap();
+                        let row = ArithmeticallySharedIPAInputs::new(
+                            timestamp,
+                            is_trigger_bit,
+                            trigger_value,
+                        )
+                        .reshare(ctx.set_total_records(1), RecordId::FIRST, role)
+                        .await
+                        .unwrap();
+
+                        vec![row.timestamp, row.is_trigger_bit, row.trigger_value]
+                    })
+                    .await
+                    .reconstruct();
+
+                assert_eq!(input.to_vec(), result);
+            }
+        });
+    }
+
+    #[test]
+    fn partition_keeps_users_together() {
+        // users occupy rows [0, 2), [2, 5), [5, 6) and [6, 9)
+        assert_eq!(
+            vec![0..2, 2..6, 6..9],
+            partition_by_user_boundary(9, &[2, 5, 6], 4)
+        );
+        // a user that does not fit into a chunk gets a chunk of its own
+        assert_eq!(vec![0..1, 1..7], partition_by_user_boundary(7, &[1], 3));
+        assert_eq!(vec![0..3], partition_by_user_boundary(3, &[], 3));
+        assert!(partition_by_user_boundary(0, &[], 3).is_empty());
+    }
+
+    #[test]
+    fn partitioned_ipa_matches_single_pass() {
+        const PER_USER_CAP: u32 = 3;
+        const MAX_BREAKDOWN_KEY: u32 = 4;
+        const NUM_MULTI_BITS: u32 = 3;
+        const MAX_CHUNK: usize = 4;
+
+        run(|| async {
+            let world = TestWorld::default();
+            let config =
+                &IpaQueryConfig::no_window(PER_USER_CAP, MAX_BREAKDOWN_KEY, NUM_MULTI_BITS);
+
+            // input is already sorted by match key
+            let records: Vec<GenericReportTestInput<_, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 11111, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 11111, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 0, match_key: 22222, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 22222, is_trigger_report: 0, breakdown_key: 3, trigger_value: 0 },
+                    { timestamp: 0, match_key: 22222, is_trigger_report: 1, breakdown_key: 0, trigger_value: 3 },
+                    { timestamp: 0, match_key: 33333, is_trigger_report: 1, breakdown_key: 0, trigger_value: 4 },
+                    { timestamp: 0, match_key: 44444, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 44444, is_trigger_report: 1, breakdown_key: 0, trigger_value: 1 },
+                    { timestamp: 0, match_key: 44444, is_trigger_report: 1, breakdown_key: 0, trigger_value: 1 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            let [boundaries, ..] = world
+                .semi_honest(
+                    records.clone().into_iter(),
+                    |ctx, input_rows: Vec<IPAInputRow<Fp31, MatchKey, BreakdownKey>>| async move {
+                        let rows = zip(
+                            get_gf2_match_key_bits(input_rows.iter().map(|row| &row.mk_shares)),
+                            get_gf2_breakdown_key_bits(
+                                input_rows.iter().map(|row| &row.breakdown_key),
+                                0,
+                            ),
+                        )
+                        .map(|(mk, bk)| BinarySharedIPAInputs::new(mk, bk))
+                        .collect::<Vec<_>>();
+                        reveal_user_boundaries(ctx, &rows).await.unwrap()
+                    },
+                )
+                .await;
+            assert_eq!(vec![2, 5, 6], boundaries);
+
+            let expected: Vec<Fp31> = world
+                .semi_honest(records.clone().into_iter(), |ctx, input_rows| async move {
+                    ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(ctx, &input_rows, config.clone())
+                        .await
+                        .unwrap()
+                })
+                .await
+                .reconstruct();
+
+            let mut merged = vec![Fp31::ZERO; expected.len()];
+            for chunk in partition_by_user_boundary(records.len(), &boundaries, MAX_CHUNK) {
+                assert!(chunk.len() <= MAX_CHUNK);
+                let result: Vec<Fp31> = world
+                    .semi_honest(
+                        records
+                            .clone()
+                            .into_iter()
+                            .take(chunk.end)
+                            .skip(chunk.start),
+                        |ctx, input_rows| async move {
+                            ipa::<_, _, _, Fp31, MatchKey, BreakdownKey>(
+                                ctx,
+                                &input_rows,
+                                config.clone(),
+                            )
+                            .await
+                            .unwrap()
+                        },
+                    )
+                    .await
+                    .reconstruct();
+                for (total, v) in merged.iter_mut().zip(result) {
+                    *total += v;
+                }
+            }
+
+            assert_eq!(expected, merged);
+        });
+    }
+
     #[test]
     fn malicious() {
         const PER_USER_CAP: u32 = 3;
@@ -630,6 +2913,118 @@ pub mod tests {
         });
     }
 
+    /// Input rows shared with [`WithMacKey`] pass malicious validation when the validator uses the
+    /// same MAC key, and fail it otherwise.
+    #[test]
+    fn malicious_inputs_with_mac_key() {
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 1, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 3, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                    { timestamp: 4, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                ];
+                (Fp32BitPrime, MatchKey, BreakdownKey)
+            );
+            let r = thread_rng().gen::<Fp32BitPrime>();
+
+            // Multiplies the trigger bit with the trigger value, so that the validator checks the
+            // MACs of the inputs, and returns the inputs together with the products.
+            let validate = |validator_key: Fp32BitPrime| {
+                let input = (
+                    records
+                        .iter()
+                        .map(|&value| WithMacKey { value, r })
+                        .collect::<Vec<_>>()
+                        .into_iter(),
+                    validator_key,
+                );
+                world.malicious(
+                    input,
+                    |ctx,
+                     (rows, r_share): (
+                        Vec<ArithmeticallySharedIPAInputs<_, MaliciousReplicated<_>>>,
+                        _,
+                    )| async move {
+                        let validator = ctx.validator_with_mac_key::<Fp32BitPrime>(r_share);
+                        let m_ctx = validator.context().set_total_records(rows.len());
+                        let products = m_ctx
+                            .try_join(rows.iter().enumerate().map(|(i, row)| {
+                                row.is_trigger_bit.multiply(
+                                    &row.trigger_value,
+                                    m_ctx.clone(),
+                                    RecordId::from(i),
+                                )
+                            }))
+                            .await
+                            .unwrap();
+                        let shares = zip(rows, products)
+                            .flat_map(|(row, product)| {
+                                [
+                                    row.timestamp,
+                                    row.is_trigger_bit,
+                                    row.trigger_value,
+                                    product,
+                                ]
+                            })
+                            .collect::<Vec<_>>();
+                        validator.validate(shares).await
+                    },
+                )
+            };
+
+            let expected =
+                [1_u128, 0, 0, 0, 3, 1, 5, 5, 4, 1, 2, 2].map(Fp32BitPrime::truncate_from);
+            let result = validate(r).await.map(Result::unwrap).reconstruct();
+            assert_eq!(result, expected);
+
+            for result in validate(r + Fp32BitPrime::ONE).await {
+                assert!(matches!(result, Err(Error::MaliciousSecurityCheckFailed)));
+            }
+        });
+    }
+
+    #[test]
+    fn malicious_detected_max_breakdown_key() {
+        const PER_USER_CAP: u32 = 3;
+        const EXPECTED: &[u128] = &[0, 2, 0, 0, 0, 3];
+        const NUM_MULTI_BITS: u32 = 3;
+
+        run_with::<_, _, 10>(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<GenericReportTestInput<Fp31, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 5, trigger_value: 0 },
+                    { timestamp: 0, match_key: 77777, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                    { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+                    { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 3 },
+                ];
+                (Fp31, MatchKey, BreakdownKey)
+            );
+
+            let result: Vec<_> = world
+                .malicious(records.into_iter(), |ctx, input_rows| async move {
+                    ipa::<_, _, _, _, MatchKey, BreakdownKey>(
+                        ctx,
+                        &input_rows,
+                        IpaQueryConfig {
+                            detect_max_breakdown_key: true,
+                            ..IpaQueryConfig::no_window(PER_USER_CAP, 1, NUM_MULTI_BITS)
+                        },
+                    )
+                    .await
+                    .unwrap()
+                })
+                .await
+                .reconstruct();
+            assert_eq!(result, EXPECTED);
+        });
+    }
+
     #[test]
     fn cap_of_one() {
         const PER_USER_CAP: u32 = 1;
@@ -832,6 +3227,17 @@ pub mod tests {
                     attribution_window_seconds: ATTRIBUTION_WINDOW_SECONDS,
                     num_multi_bits: NUM_MULTI_BITS,
                     plaintext_match_keys: true,
+                    clamp_breakdown_keys: false,
+                    detect_max_breakdown_key: false,
+                    dedup: false,
+                    drop_zero_match_keys: false,
+                    signed_trigger_values: false,
+                    max_trigger_value: None,
+                    check_histogram_total: false,
+                    first_row_continues_previous: false,
+                    max_input_rows: None,
+                    callback_url: None,
+                    log_level: None,
                 },
                 security,
             )
@@ -899,6 +3305,7 @@ pub mod tests {
 
     #[cfg(all(test, unit_test))]
     mod serialization {
+        use bytes::Bytes;
         use generic_array::GenericArray;
         use proptest::{
             proptest,
@@ -908,13 +3315,20 @@ pub mod tests {
         use typenum::Unsigned;
 
         use crate::{
-            ff::{Field, Fp31, PrimeField, Serializable},
+            error::Error,
+            ff::{Field, Fp31, GaloisField, Gf2, Gf20Bit, Gf5Bit, PrimeField, Serializable},
             ipa_test_input,
             protocol::{
-                ipa::{tests::Fp32BitPrime, IPAInputRow},
+                ipa::{
+                    tests::Fp32BitPrime, ArithmeticallySharedIPAInputs, IPAInputRow, WIRE_VERSION,
+                },
                 BreakdownKey, MatchKey,
             },
-            secret_sharing::{replicated::semi_honest::AdditiveShare, IntoShares},
+            rand::{thread_rng, Rng},
+            secret_sharing::{
+                replicated::{semi_honest::AdditiveShare, ReplicatedSecretSharing},
+                BitDecomposed, IntoShares,
+            },
             test_fixture::input::GenericReportTestInput,
         };
 
@@ -953,12 +3367,270 @@ pub mod tests {
                 &mut buf[<IPAInputRow<F, MatchKey, BreakdownKey> as Serializable>::Size::USIZE..],
             ));
 
+            assert!(
+                IPAInputRow::<F, MatchKey, BreakdownKey>::try_from_byte_slice(&buf[1..]).is_err()
+            );
             assert_eq!(
                 vec![a, b],
                 IPAInputRow::<F, MatchKey, BreakdownKey>::from_byte_slice(&buf).collect::<Vec<_>>()
             );
         }
 
+        #[test]
+        fn rejects_invalid_field_values() {
+            type Row = IPAInputRow<Fp31, MatchKey, BreakdownKey>;
+
+            let mut buf = vec![0u8; 2 * Row::SIZE_IN_BYTES];
+            assert_eq!(2, Row::try_from_byte_slice(&buf).unwrap().count());
+
+            // the last byte is a half of the trigger value share, and Fp31 values are below 31
+            let last = buf.len() - 1;
+            buf[last] = 31;
+            let err = Row::try_from_byte_slice(&buf).err().unwrap();
+            assert!(err.to_string().contains("row 1: trigger_value"), "{err}");
+        }
+
+        #[test]
+        fn layout_description() {
+            fn check<F: Field>()
+            where
+                AdditiveShare<F>: Serializable,
+                IPAInputRow<F, MatchKey, BreakdownKey>: Serializable,
+            {
+                let layout = IPAInputRow::<F, MatchKey, BreakdownKey>::layout_description();
+                assert_eq!(
+                    vec![
+                        "timestamp",
+                        "mk_shares",
+                        "is_trigger_bit",
+                        "breakdown_key",
+                        "trigger_value"
+                    ],
+                    layout.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+                );
+
+                let mut offset = 0;
+                for (name, range) in layout {
+                    assert_eq!(
+                        offset, range.start,
+                        "{name} does not start where the previous field ends"
+                    );
+                    assert!(!range.is_empty(), "{name} is empty");
+                    offset = range.end;
+                }
+                assert_eq!(
+                    <IPAInputRow<F, MatchKey, BreakdownKey> as Serializable>::Size::USIZE,
+                    offset
+                );
+            }
+
+            check::<Fp31>();
+            check::<Fp32BitPrime>();
+        }
+
+        /// The derived implementation must keep the layout that rows were serialized with when
+        /// it was written by hand: every field at the range given by its layout description.
+        #[test]
+        fn derived_matches_hand_written_layout() {
+            type Row = IPAInputRow<Fp32BitPrime, MatchKey, BreakdownKey>;
+
+            let reports: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = ipa_test_input!(
+                [
+                    { timestamp: 123, match_key: 0xDEAD_BEEF_u32, is_trigger_report: 1, breakdown_key: 17, trigger_value: 42 },
+                ];
+                (Fp32BitPrime, MatchKey, BreakdownKey)
+            );
+            let [row, ..]: [Row; 3] = reports[0].share_with(&mut thread_rng());
+
+            let mut derived = GenericArray::default();
+            row.serialize(&mut derived);
+
+            let mut hand_written = vec![0_u8; Row::SIZE_IN_BYTES];
+            let layout = Row::layout_description();
+            let field = |name| {
+                layout
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, range)| range.clone())
+                    .unwrap()
+            };
+            row.timestamp.serialize(GenericArray::from_mut_slice(
+                &mut hand_written[field("timestamp")],
+            ));
+            row.mk_shares.serialize(GenericArray::from_mut_slice(
+                &mut hand_written[field("mk_shares")],
+            ));
+            row.is_trigger_bit.serialize(GenericArray::from_mut_slice(
+                &mut hand_written[field("is_trigger_bit")],
+            ));
+            row.breakdown_key.serialize(GenericArray::from_mut_slice(
+                &mut hand_written[field("breakdown_key")],
+            ));
+            row.trigger_value.serialize(GenericArray::from_mut_slice(
+                &mut hand_written[field("trigger_value")],
+            ));
+
+            assert_eq!(hand_written, derived.to_vec());
+            assert_eq!(row, Row::deserialize(&derived));
+        }
+
+        #[test]
+        fn size_in_bytes_matches_serializable_size() {
+            // Fails to compile if the row size for the types used in production does not fit into
+            // `u32`, i.e. it could overflow on a 32-bit target.
+            const _: () = assert!(
+                IPAInputRow::<Fp32BitPrime, MatchKey, BreakdownKey>::SIZE_IN_BYTES
+                    <= u32::MAX as usize
+            );
+
+            assert_eq!(
+                <IPAInputRow<Fp31, MatchKey, BreakdownKey> as Serializable>::Size::USIZE,
+                IPAInputRow::<Fp31, MatchKey, BreakdownKey>::SIZE_IN_BYTES
+            );
+            assert_eq!(
+                <IPAInputRow<Fp32BitPrime, MatchKey, BreakdownKey> as Serializable>::Size::USIZE,
+                IPAInputRow::<Fp32BitPrime, MatchKey, BreakdownKey>::SIZE_IN_BYTES
+            );
+        }
+
+        #[test]
+        fn compact_round_trip() {
+            const BREAKDOWN_KEY_BITS: usize = 5;
+
+            let mut rng = thread_rng();
+            let mut share = || AdditiveShare::<Fp31>::new(rng.gen(), rng.gen());
+            let row = ArithmeticallySharedIPAInputs::new(share(), share(), share());
+            let breakdown_key = BitDecomposed::new(
+                (0..BREAKDOWN_KEY_BITS).map(|_| AdditiveShare::<Gf2>::new(rng.gen(), rng.gen())),
+            );
+
+            let row_sz = ArithmeticallySharedIPAInputs::<Fp31, AdditiveShare<Fp31>>::compact_size(
+                BREAKDOWN_KEY_BITS,
+            );
+            let mut buf = vec![0u8; row_sz];
+            row.serialize_compact(&breakdown_key, &mut buf);
+            let (actual_row, actual_breakdown_key) =
+                ArithmeticallySharedIPAInputs::<Fp31, AdditiveShare<Fp31>>::deserialize_compact(
+                    &buf,
+                    BREAKDOWN_KEY_BITS,
+                );
+
+            assert_eq!(row.timestamp, actual_row.timestamp);
+            assert_eq!(row.is_trigger_bit, actual_row.is_trigger_bit);
+            assert_eq!(row.trigger_value, actual_row.trigger_value);
+            assert_eq!(breakdown_key, actual_breakdown_key);
+        }
+
+        #[test]
+        fn from_bytes_matches_from_byte_slice() {
+            type Row = IPAInputRow<Fp31, MatchKey, BreakdownKey>;
+
+            let mut rng = thread_rng();
+            let row_sz = <Row as Serializable>::Size::USIZE;
+            let buf = (0..100).fold(Vec::new(), |mut buf, _| {
+                let input: GenericReportTestInput<Fp31, MatchKey, BreakdownKey> = ipa_test_input!(
+                    { timestamp: rng.gen_range(0..31), match_key: rng.gen::<u64>(), is_trigger_report: rng.gen_range(0..2), breakdown_key: rng.gen_range(0..32), trigger_value: rng.gen_range(0..31) };
+                    (Fp31, MatchKey, BreakdownKey)
+                );
+                let [row, ..]: [Row; 3] = input.share_with(&mut rng);
+                buf.resize(buf.len() + row_sz, 0);
+                let len = buf.len();
+                row.serialize(GenericArray::from_mut_slice(&mut buf[len - row_sz..]));
+                buf
+            });
+
+            assert_eq!(
+                Row::from_byte_slice(&buf).collect::<Vec<_>>(),
+                Row::from_bytes(&Bytes::from(buf.clone())).collect::<Vec<_>>()
+            );
+        }
+
+        #[test]
+        fn packed_round_trip() {
+            fn check<MK>(saved_bytes: usize)
+            where
+                MK: GaloisField + IntoShares<AdditiveShare<MK>>,
+                AdditiveShare<MK>: Serializable,
+                IPAInputRow<Fp31, MK, BreakdownKey>: Serializable,
+            {
+                // an odd number of rows, so the last one ends in the middle of a byte if rows are
+                // not a whole number of bytes long
+                const COUNT: usize = 7;
+
+                let mut rng = thread_rng();
+                let rows = (0..COUNT)
+                    .map(|_| {
+                        let input: GenericReportTestInput<Fp31, MK, BreakdownKey> = ipa_test_input!(
+                            { timestamp: rng.gen_range(0..31), match_key: rng.gen::<u64>(), is_trigger_report: rng.gen_range(0..2), breakdown_key: rng.gen_range(0..32), trigger_value: rng.gen_range(0..31) };
+                            (Fp31, MK, BreakdownKey)
+                        );
+                        let [row, ..]: [IPAInputRow<Fp31, MK, BreakdownKey>; 3] =
+                            input.share_with(&mut rng);
+                        row
+                    })
+                    .collect::<Vec<_>>();
+
+                let packed = IPAInputRow::pack(&rows);
+                let row_sz = IPAInputRow::<Fp31, MK, BreakdownKey>::SIZE_IN_BYTES;
+                assert_eq!(
+                    IPAInputRow::<Fp31, MK, BreakdownKey>::packed_len(COUNT),
+                    packed.len()
+                );
+                assert_eq!(COUNT * row_sz - saved_bytes, packed.len());
+                assert_eq!(
+                    rows,
+                    IPAInputRow::<Fp31, MK, BreakdownKey>::from_packed_byte_slice(&packed)
+                        .collect::<Vec<_>>()
+                );
+            }
+
+            // 20 bit match key shares take 5 bytes instead of 6
+            check::<Gf20Bit>(7);
+            // 5 bit match key shares take 10 bits instead of 2 bytes, so rows start in the middle
+            // of a byte
+            check::<Gf5Bit>(5);
+        }
+
+        #[test]
+        #[should_panic(expected = "does not hold a whole number of packed rows")]
+        fn packed_rows_must_be_complete() {
+            type Row = IPAInputRow<Fp31, Gf20Bit, BreakdownKey>;
+            let _ = Row::from_packed_byte_slice(&vec![0_u8; Row::packed_len(2) + 1]);
+        }
+
+        #[test]
+        fn versioned_round_trip() {
+            let input: GenericReportTestInput<Fp31, MatchKey, BreakdownKey> = ipa_test_input!(
+                { timestamp: 1, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 };
+                (Fp31, MatchKey, BreakdownKey)
+            );
+            let [row, ..]: [IPAInputRow<Fp31, MatchKey, BreakdownKey>; 3] =
+                input.share_with(&mut thread_rng());
+
+            let mut buf = vec![0u8; IPAInputRow::<Fp31, MatchKey, BreakdownKey>::versioned_size()];
+            row.serialize_versioned(&mut buf);
+            assert_eq!(WIRE_VERSION, buf[0]);
+            assert_eq!(
+                row,
+                IPAInputRow::<Fp31, MatchKey, BreakdownKey>::deserialize_versioned(&buf).unwrap()
+            );
+
+            // a reader that only understands the next version must reject rows written today
+            let err = IPAInputRow::<Fp31, MatchKey, BreakdownKey>::deserialize_version(
+                &buf,
+                WIRE_VERSION + 1,
+            )
+            .unwrap_err();
+            assert!(matches!(err, Error::UnsupportedWireVersion(v) if v == WIRE_VERSION));
+
+            assert!(matches!(
+                IPAInputRow::<Fp31, MatchKey, BreakdownKey>::deserialize_versioned(
+                    &buf[..buf.len() - 1]
+                ),
+                Err(Error::ParseError(_))
+            ));
+        }
+
         proptest! {
             #[test]
             fn serde(timestamp in 0..u128::MAX, match_key in 0..u64::MAX, trigger_bit in 0..u128::MAX, breakdown_key in 0..u128::MAX, trigger_value in 0..u128::MAX, seed in 0..u128::MAX) {
@@ -980,7 +3652,8 @@ pub mod tests {
         use crate::{
             telemetry::{
                 metrics::{
-                    BYTES_SENT, INDEXED_PRSS_GENERATED, RECORDS_SENT, SEQUENTIAL_PRSS_GENERATED,
+                    BYTES_SENT, INDEXED_PRSS_GENERATED, MULTIPLICATIONS, RECORDS_SENT,
+                    SEQUENTIAL_PRSS_GENERATED,
                 },
                 stats::Metrics,
             },
@@ -1038,6 +3711,8 @@ pub mod tests {
             indexed_prss: u64,
             /// Random values produced by PRSS random generators.
             seq_prss: u64,
+            /// Secure multiplications performed by all helpers. This one is checked exactly.
+            multiplications: u64,
         }
 
         impl PerfMetrics {
@@ -1047,6 +3722,7 @@ pub mod tests {
                     bytes_sent: snapshot.get_counter(BYTES_SENT),
                     indexed_prss: snapshot.get_counter(INDEXED_PRSS_GENERATED),
                     seq_prss: snapshot.get_counter(SEQUENTIAL_PRSS_GENERATED),
+                    multiplications: snapshot.get_counter(MULTIPLICATIONS),
                 }
             }
         }
@@ -1060,12 +3736,13 @@ pub mod tests {
         ) {
             let test_config = TestWorldConfig::default().enable_metrics().with_seed(0);
             let world = TestWorld::new_with(test_config);
+            let query_config = &query_config;
             let _: Vec<_> = match mode {
                 Malicious => world.malicious(generate_input(), |ctx, input_rows| async move {
                     ipa::<_, _, _, Fp32BitPrime, MatchKey, BreakdownKey>(
                         ctx,
                         &input_rows,
-                        query_config,
+                        query_config.clone(),
                     )
                     .await
                     .unwrap()
@@ -1074,7 +3751,7 @@ pub mod tests {
                     ipa::<_, _, _, Fp32BitPrime, MatchKey, BreakdownKey>(
                         ctx,
                         &input_rows,
-                        query_config,
+                        query_config.clone(),
                     )
                     .await
                     .unwrap()
@@ -1084,6 +3761,10 @@ pub mod tests {
             .reconstruct();
 
             let actual = PerfMetrics::from_snapshot(&world.metrics_snapshot());
+            assert_eq!(
+                expected.multiplications, actual.multiplications,
+                "{mode:?} IPA performs a different number of multiplications"
+            );
             assert!(
                 expected >= actual,
                 "{mode:?} IPA performance has degraded. Expected: {expected:?} >= {actual:?}"
@@ -1101,10 +3782,11 @@ pub mod tests {
                 cap_one(),
                 SemiHonest,
                 PerfMetrics {
-                    records_sent: 14_421,
-                    bytes_sent: 47_100,
-                    indexed_prss: 19_137,
+                    records_sent: 14_097,
+                    bytes_sent: 46_776,
+                    indexed_prss: 18_651,
                     seq_prss: 1118,
+                    multiplications: 9_363,
                 },
             )
             .await;
@@ -1116,10 +3798,11 @@ pub mod tests {
                 cap_three(),
                 SemiHonest,
                 PerfMetrics {
-                    records_sent: 21_756,
-                    bytes_sent: 76_440,
-                    indexed_prss: 28_146,
+                    records_sent: 21_432,
+                    bytes_sent: 76_116,
+                    indexed_prss: 27_660,
                     seq_prss: 1118,
+                    multiplications: 18_210,
                 },
             )
             .await;
@@ -1131,10 +3814,11 @@ pub mod tests {
                 cap_one(),
                 Malicious,
                 PerfMetrics {
-                    records_sent: 35_163,
-                    bytes_sent: 130_068,
-                    indexed_prss: 72_447,
+                    records_sent: 35_598,
+                    bytes_sent: 132_780,
+                    indexed_prss: 73_125,
                     seq_prss: 1132,
+                    multiplications: 27_258,
                 },
             )
             .await;
@@ -1146,10 +3830,11 @@ pub mod tests {
                 cap_three(),
                 Malicious,
                 PerfMetrics {
-                    records_sent: 53_865,
-                    bytes_sent: 204_876,
-                    indexed_prss: 109_734,
+                    records_sent: 54_300,
+                    bytes_sent: 207_588,
+                    indexed_prss: 110_412,
                     seq_prss: 1132,
+                    multiplications: 50_136,
                 },
             )
             .await;
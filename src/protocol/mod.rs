@@ -16,54 +16,86 @@ use std::{
     fmt::{Debug, Display, Formatter},
     hash::Hash,
     ops::{Add, AddAssign},
+    str::FromStr,
 };
 
 pub use basics::BasicProtocols;
+use generic_array::GenericArray;
+use typenum::U16;
 
 use crate::{
     error::Error,
-    ff::{Gf40Bit, Gf8Bit},
+    ff::{Gf40Bit, Gf8Bit, Serializable},
 };
 
 pub type MatchKey = Gf40Bit;
 pub type BreakdownKey = Gf8Bit;
 
-/// Unique identifier of the MPC query requested by report collectors
-/// TODO(615): Generating this unique id may be tricky as it may involve communication between helpers and
-/// them collaborating on constructing this unique id. These details haven't been flushed out yet,
-/// so for now it is just an empty struct. Once we know more about it, we will make necessary
-/// amendments to it
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+/// Returns the expected number of row pairs that share a match key when `n_rows` keys are drawn
+/// uniformly at random from a space of `key_bits` bits. Rows of unrelated users that collide are
+/// grouped together by attribution, so operators can use this birthday bound,
+/// `n(n - 1) / 2^(key_bits + 1)`, to check that the match key space is wide enough for the
+/// expected input size.
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn expected_collisions(n_rows: u64, key_bits: u32) -> f64 {
+    let n = n_rows as f64;
+    n * (n - 1.0).max(0.0) / f64::from(key_bits + 1).exp2()
+}
+
+/// Unique identifier of the MPC query requested by report collectors.
+///
+/// It is a 16 byte value, represented as 32 lowercase hex characters in URLs and logs. The helper
+/// that coordinates a query picks a random id and sends it to the other helpers in the prepare
+/// request.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
 #[cfg_attr(
     feature = "serde",
     derive(serde::Serialize, serde::Deserialize),
-    serde(into = "&'static str", try_from = "&str")
+    serde(into = "String", try_from = "String")
 )]
-pub struct QueryId;
+pub struct QueryId([u8; 16]);
 
 impl Display for QueryId {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        // fail when query id becomes meaningful and change the display implementation
-        let _: QueryId = QueryId;
-        write!(f, "{self:?}")
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl Debug for QueryId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "QueryId({self})")
     }
 }
 
 impl QueryId {
-    fn repr() -> &'static str {
-        "0"
+    /// Picks a new id at random. Ids are long enough that helpers do not need to check for
+    /// collisions.
+    #[must_use]
+    pub fn random() -> Self {
+        Self(rand::random())
+    }
+}
+
+impl From<[u8; 16]> for QueryId {
+    fn from(value: [u8; 16]) -> Self {
+        Self(value)
     }
 }
 
-impl AsRef<str> for QueryId {
-    fn as_ref(&self) -> &str {
-        QueryId::repr()
+impl From<QueryId> for String {
+    fn from(value: QueryId) -> Self {
+        value.to_string()
     }
 }
 
-impl From<QueryId> for &'static str {
-    fn from(_: QueryId) -> Self {
-        QueryId::repr()
+impl FromStr for QueryId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut id = [0; 16];
+        hex::decode_to_slice(s, &mut id).map_err(|_| Error::path_parse_error(s))?;
+        Ok(Self(id))
     }
 }
 
@@ -71,11 +103,27 @@ impl TryFrom<&str> for QueryId {
     type Error = Error;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        if value == QueryId::repr() {
-            Ok(QueryId)
-        } else {
-            Err(Error::path_parse_error(value))
-        }
+        value.parse()
+    }
+}
+
+impl TryFrom<String> for QueryId {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Serializable for QueryId {
+    type Size = U16;
+
+    fn serialize(&self, buf: &mut GenericArray<u8, Self::Size>) {
+        buf.copy_from_slice(&self.0);
+    }
+
+    fn deserialize(buf: &GenericArray<u8, Self::Size>) -> Self {
+        Self((*buf).into())
     }
 }
 
@@ -158,3 +206,50 @@ pub struct NoRecord;
 impl RecordBinding for NoRecord {}
 
 impl RecordBinding for RecordId {}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use generic_array::GenericArray;
+
+    use super::{expected_collisions, MatchKey, QueryId};
+    use crate::{ff::Serializable, secret_sharing::SharedValue};
+
+    #[test]
+    fn birthday_bound() {
+        assert!(expected_collisions(0, 8).abs() < f64::EPSILON);
+        assert!(expected_collisions(1, 8).abs() < f64::EPSILON);
+        assert!((expected_collisions(2, 1) - 0.5).abs() < f64::EPSILON);
+        assert!((expected_collisions(1 << 16, 32) - 0.499_992_37).abs() < 1e-6);
+
+        // a million rows barely collide in the 40 bit match key space
+        let collisions = expected_collisions(1_000_000, MatchKey::BITS);
+        assert!(collisions > 0.45 && collisions < 0.46, "{collisions}");
+    }
+
+    #[test]
+    fn query_id_round_trip() {
+        const ID: &str = "00112233445566778899aabbccddeeff";
+
+        let query_id = ID.parse::<QueryId>().unwrap();
+        assert_eq!(ID, query_id.to_string());
+        assert_eq!(query_id, QueryId::try_from(ID.to_uppercase()).unwrap());
+
+        let mut buf = GenericArray::default();
+        query_id.serialize(&mut buf);
+        assert_eq!(hex::decode(ID).unwrap(), buf.as_slice());
+        assert_eq!(query_id, QueryId::deserialize(&buf));
+    }
+
+    #[test]
+    fn query_id_rejects_malformed() {
+        for id in [
+            "",
+            "0",
+            "00112233445566778899aabbccddeef",
+            "00112233445566778899aabbccddeeffaa",
+            "00112233445566778899aabbccddeefg",
+        ] {
+            assert!(id.parse::<QueryId>().is_err(), "{id} must not parse");
+        }
+    }
+}
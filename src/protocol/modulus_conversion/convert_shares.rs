@@ -35,7 +35,7 @@ use crate::{
     error::Error,
     exact::ExactSizeStream,
     ff::{Field, GaloisField, Gf2, PrimeField},
-    helpers::Role,
+    helpers::{Direction, Role},
     protocol::{
         basics::{SecureMul, ZeroPositions},
         boolean::xor_sparse,
@@ -95,6 +95,33 @@ impl<F: PrimeField> BitConversionTriple<Replicated<F>> {
     }
 }
 
+impl<F: Field> BitConversionTriple<Replicated<F>> {
+    /// Checks that this triple has the layout [`Self::new`] produces for `role`. Every helper
+    /// holds only two of the six shares, the rest must be zero. Converting a triple created for
+    /// another role produces garbage, which typically means that shares were wired to the
+    /// wrong helper.
+    ///
+    /// A triple for a bit that is zero in both shares is consistent with any role, so this check
+    /// can't catch every mismatch.
+    ///
+    /// ## Errors
+    /// If a share that `role` must not hold is not zero.
+    pub fn check_role(&self, role: Role) -> Result<(), Error> {
+        let own = &self.0[role];
+        let right = &self.0[role.peer(Direction::Right)];
+        let left = &self.0[role.peer(Direction::Left)];
+        if own.right() == F::ZERO
+            && right.left() == F::ZERO
+            && left.left() == F::ZERO
+            && left.right() == F::ZERO
+        {
+            Ok(())
+        } else {
+            Err(Error::RoleShareMismatch(role))
+        }
+    }
+}
+
 pub trait ToBitConversionTriples {
     /// Get the maximum number of bits that can be produced for this type.
     ///
@@ -450,4 +477,30 @@ mod tests {
                 .await;
         }
     }
+
+    #[tokio::test]
+    pub async fn role_mismatch() {
+        let world = TestWorld::default();
+        world
+            .semi_honest((), |ctx, ()| async move {
+                let wrong_role = ctx.role().peer(Direction::Right);
+                let triple = BitConversionTriple::<Replicated<Fp31>>::new(wrong_role, true, true);
+                assert!(matches!(
+                    triple.check_role(ctx.role()),
+                    Err(Error::RoleShareMismatch(role)) if role == ctx.role()
+                ));
+                BitConversionTriple::<Replicated<Fp31>>::new(ctx.role(), true, true)
+                    .check_role(ctx.role())
+                    .unwrap();
+
+                let v = ctx.validator();
+                let upgraded: Result<BitConversionTriple<Replicated<Fp31>>, _> = v
+                    .context()
+                    .set_total_records(1)
+                    .upgrade_for(RecordId::FIRST, triple)
+                    .await;
+                assert!(matches!(upgraded, Err(Error::RoleShareMismatch(_))));
+            })
+            .await;
+    }
 }
@@ -0,0 +1,120 @@
+use futures::future::try_join;
+use ipa_macros::Step;
+
+use crate::{
+    error::Error,
+    ff::PrimeField,
+    protocol::{
+        boolean::{greater_than_constant, random_bits_generator::RandomBitsGenerator},
+        context::UpgradedContext,
+        RecordId,
+    },
+    secret_sharing::replicated::{
+        semi_honest::AdditiveShare as Replicated, ReplicatedSecretSharing,
+    },
+};
+
+/// Converts shares of values in field `Source` into shares of the same values in the larger
+/// field `Target`, without revealing them. This lets protocols run early stages in a cheap field
+/// and move to a larger one before values can grow past its prime.
+///
+/// Read as integers, the three additive components of `x` sum up to `X = x + q * p`, where `p` is
+/// the prime of `Source` and `q ∈ {0, 1, 2}` is the number of times the sum wrapped around.
+/// Because `X < 3p`, helpers obtain a sharing of `X` in `Target` locally by reinterpreting their
+/// components. `q` is then computed with two comparisons (which bit-decompose `X` under a random
+/// mask) and subtracted.
+///
+/// This only works in the semi-honest setting.
+///
+/// # Errors
+/// Propagates errors from comparisons.
+///
+/// # Panics
+/// If `Target` is not at least three times larger than `Source`.
+pub async fn lift_field<Source, Target, C>(
+    ctx: C,
+    shares: &[Replicated<Source>],
+) -> Result<Vec<Replicated<Target>>, Error>
+where
+    Source: PrimeField,
+    Target: PrimeField,
+    C: UpgradedContext<Target, Share = Replicated<Target>>,
+{
+    let prime: u128 = Source::PRIME.into();
+    let target_prime: u128 = Target::PRIME.into();
+    assert!(
+        3 * prime <= target_prime,
+        "target field is too small to lift shares of values modulo {prime}"
+    );
+
+    let ctx = ctx.set_total_records(shares.len());
+    let wrapped_once_ctx = ctx.narrow(&Step::WrappedOnce);
+    let wrapped_twice_ctx = ctx.narrow(&Step::WrappedTwice);
+    let once_rbg = RandomBitsGenerator::new(ctx.narrow(&Step::RandomBitsForWrappedOnce));
+    let twice_rbg = RandomBitsGenerator::new(ctx.narrow(&Step::RandomBitsForWrappedTwice));
+    let (once_rbg, twice_rbg) = (&once_rbg, &twice_rbg);
+
+    ctx.try_join(shares.iter().enumerate().map(|(i, x)| {
+        let record_id = RecordId::from(i);
+        let sum = Replicated::new(
+            Target::truncate_from(x.left().as_u128()),
+            Target::truncate_from(x.right().as_u128()),
+        );
+        let once_ctx = wrapped_once_ctx.clone();
+        let twice_ctx = wrapped_twice_ctx.clone();
+        async move {
+            let (once, twice) = try_join(
+                greater_than_constant(once_ctx, record_id, once_rbg, &sum, prime - 1),
+                greater_than_constant(twice_ctx, record_id, twice_rbg, &sum, 2 * prime - 1),
+            )
+            .await?;
+            Ok(sum - &((once + &twice) * Target::truncate_from(prime)))
+        }
+    }))
+    .await
+}
+
+#[derive(Step)]
+pub(crate) enum Step {
+    WrappedOnce,
+    WrappedTwice,
+    RandomBitsForWrappedOnce,
+    RandomBitsForWrappedTwice,
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::lift_field;
+    use crate::{
+        ff::{Field, Fp31, Fp32BitPrime},
+        protocol::context::{UpgradableContext, Validator},
+        secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
+        test_fixture::{Reconstruct, Runner, TestWorld},
+    };
+
+    #[tokio::test]
+    async fn lifts_every_value() {
+        let world = TestWorld::default();
+        // Every value, several times over, so that the random shares wrap around the prime
+        // zero, one and two times.
+        let input = (0..4 * 31_u128)
+            .map(Fp31::truncate_from)
+            .collect::<Vec<_>>();
+
+        let result: Vec<Fp32BitPrime> = world
+            .semi_honest(
+                input.clone().into_iter(),
+                |ctx, shares: Vec<Replicated<Fp31>>| async move {
+                    let validator = ctx.validator::<Fp32BitPrime>();
+                    lift_field(validator.context(), &shares).await.unwrap()
+                },
+            )
+            .await
+            .reconstruct();
+
+        assert_eq!(
+            input.iter().map(Field::as_u128).collect::<Vec<_>>(),
+            result.iter().map(Field::as_u128).collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,7 +1,9 @@
 pub mod convert_shares;
+mod lift_field;
 
 // TODO: wean usage off convert_some_bits.
 pub(crate) use convert_shares::convert_some_bits;
 pub use convert_shares::{
     convert_bits, BitConversionTriple, LocalBitConverter, ToBitConversionTriples,
 };
+pub use lift_field::lift_field;
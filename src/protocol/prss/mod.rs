@@ -343,6 +343,26 @@ pub mod test {
         assert_eq!(r3_l, r2_r);
     }
 
+    /// Setting up PRSS again, as is done for every query, replaces the shared keys, so values
+    /// generated for the same gate and index no longer match those from the previous setup.
+    #[test]
+    fn rotated_keys_differ() {
+        const IDX: u128 = 7;
+        let step = Gate::default();
+        let before = participants().map(|p| p.indexed(&step).generate_values(IDX));
+        let after = participants().map(|p| p.indexed(&step).generate_values(IDX));
+
+        for (before, after) in before.iter().zip(&after) {
+            assert_ne!(before.0, after.0);
+            assert_ne!(before.1, after.1);
+        }
+
+        // Rotated keys are still consistent between neighbours.
+        assert_eq!(after[0].0, after[2].1);
+        assert_eq!(after[1].0, after[0].1);
+        assert_eq!(after[2].0, after[1].1);
+    }
+
     #[test]
     fn three_party_zero_u128() {
         const IDX: u128 = 7;
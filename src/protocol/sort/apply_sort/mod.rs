@@ -15,6 +15,15 @@ use crate::{
     },
 };
 
+/// Reorders `input` according to the sort permutation.
+///
+/// The sort is stable: rows with equal sort keys keep their relative order from `input`. The
+/// permutation generated by [`generate_permutation_and_reveal_shuffled`] is a composition of
+/// stable per-bit sorts, so no extra tie-break on the original index is needed. Attribution
+/// depends on this, as rows that share a match key must stay in the order they were submitted.
+///
+/// [`generate_permutation_and_reveal_shuffled`]: crate::protocol::sort::generate_permutation::generate_permutation_and_reveal_shuffled
+///
 /// # Errors
 /// Propagates errors from shuffle/reshare
 #[tracing::instrument(name = "apply_sort", skip_all, fields(gate = %ctx.gate().as_ref()))]
@@ -47,7 +56,7 @@ mod tests {
 
     use crate::{
         accumulation_test_input,
-        ff::{Fp32BitPrime, GaloisField},
+        ff::{Field, Fp32BitPrime},
         protocol::{
             attribution::input::AccumulateCreditInputRow,
             context::Context,
@@ -74,7 +83,7 @@ mod tests {
         match_keys.resize_with(COUNT, || rng.gen::<MatchKey>());
 
         let permutation =
-            permutation::sort(match_keys.iter().map(|mk| mk.as_u128()).collect::<Vec<_>>());
+            permutation::sort(match_keys.iter().map(Field::as_u128).collect::<Vec<_>>());
 
         let mut sidecar: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> =
             Vec::with_capacity(COUNT);
@@ -120,4 +129,61 @@ mod tests {
 
         assert_eq!(&expected[..], &result[..]);
     }
+
+    #[tokio::test]
+    pub async fn stable() {
+        const NUM_MULTI_BITS: u32 = 3;
+        const MATCH_KEYS: [u128; 6] = [5, 3, 5, 3, 5, 0];
+        // Rows are sorted by match key, rows with the same key keep their relative order.
+        const EXPECTED_CREDIT_ORDER: [u8; 6] = [6, 2, 4, 1, 3, 5];
+
+        let world = TestWorld::default();
+        let match_keys = MATCH_KEYS.map(MatchKey::truncate_from);
+        let sidecar: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = (1..=6)
+            .map(|credit: u8| {
+                accumulation_test_input!(
+                    {
+                        is_trigger_report: 0,
+                        helper_bit: 0,
+                        active_bit: 0,
+                        credit: credit,
+                    };
+                    (Fp32BitPrime, MatchKey, BreakdownKey)
+                )
+            })
+            .collect();
+        let expected = EXPECTED_CREDIT_ORDER
+            .iter()
+            .map(|&credit| sidecar[usize::from(credit - 1)])
+            .collect::<Vec<_>>();
+
+        let result: Vec<GenericReportTestInput<Fp32BitPrime, MatchKey, BreakdownKey>> = world
+            .semi_honest(
+                (match_keys.into_iter(), sidecar.into_iter()),
+                |ctx,
+                 (mk_shares, secret): (
+                    Vec<Replicated<MatchKey>>,
+                    Vec<AccumulateCreditInputRow<Fp32BitPrime, Replicated<_>>>,
+                )| async move {
+                    let ctx = ctx.narrow("apply_sort");
+                    let sort_permutation =
+                        generate_permutation_and_reveal_shuffled::<Fp32BitPrime, _, _, _>(
+                            ctx.narrow("convert_all_bits"),
+                            stream_iter(mk_shares),
+                            NUM_MULTI_BITS,
+                            MatchKey::BITS,
+                        )
+                        .await
+                        .unwrap();
+
+                    apply_sort_permutation(ctx, secret, &sort_permutation)
+                        .await
+                        .unwrap()
+                },
+            )
+            .await
+            .reconstruct();
+
+        assert_eq!(&expected[..], &result[..]);
+    }
 }
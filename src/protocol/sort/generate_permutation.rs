@@ -1,11 +1,15 @@
 use async_trait::async_trait;
 use futures::stream::Stream;
+#[cfg(any(test, feature = "test-fixture"))]
+use rand::{rngs::StdRng, SeedableRng};
 
+#[cfg(any(test, feature = "test-fixture"))]
+use crate::helpers::{Direction, Role};
 use crate::{
     error::Error,
     ff::PrimeField,
     protocol::{
-        basics::Reveal,
+        basics::{Reshare, Reveal},
         context::{
             Context, UpgradableContext, UpgradeContext, UpgradeToMalicious, UpgradedContext,
             UpgradedMaliciousContext, UpgradedSemiHonestContext, Validator,
@@ -44,6 +48,90 @@ pub struct ShuffledPermutationWrapper<T, C: Context> {
     pub ctx: C,
 }
 
+/// Source of the random permutations that hide a sort permutation before it is revealed.
+#[derive(Clone, Copy, Debug)]
+enum ShuffleRandomness {
+    /// Drawn from PRSS, so every pair of helpers shares permutations the third helper does not know.
+    Prss,
+    /// Drawn from a seed that every helper knows, which makes the revealed permutation
+    /// reproducible. This hides nothing, so it is only available to tests.
+    #[cfg(any(test, feature = "test-fixture"))]
+    Seeded(u64),
+}
+
+impl ShuffleRandomness {
+    fn permutations<C: Context>(self, ctx: &C, batch_size: u32) -> (Vec<u32>, Vec<u32>) {
+        match self {
+            Self::Prss => get_two_of_three_random_permutations(batch_size, ctx.prss_rng()),
+            #[cfg(any(test, feature = "test-fixture"))]
+            Self::Seeded(seed) => {
+                // Both helpers of a pair derive the same generator from the role of the one on
+                // the left.
+                let rng = |left: Role| {
+                    let mut rng_seed = [0_u8; 32];
+                    rng_seed[..8].copy_from_slice(&seed.to_le_bytes());
+                    rng_seed[8] = left as u8;
+                    StdRng::from_seed(rng_seed)
+                };
+                let role = ctx.role();
+                get_two_of_three_random_permutations(
+                    batch_size,
+                    (rng(role.peer(Direction::Left)), rng(role)),
+                )
+            }
+        }
+    }
+}
+
+/// Randomly permutes `rows` without revealing the permutation to any helper.
+///
+/// Every pair of helpers applies a permutation that the third one does not know, and rows are
+/// reshared in between, so no helper can link an output row to an input row. Other protocols
+/// can use this to break linkability before they reveal anything about the rows.
+///
+/// ## Errors
+/// If resharing fails.
+///
+/// ## Panics
+/// If there are more than `u32::MAX` rows.
+pub async fn oblivious_shuffle<C, T>(ctx: C, rows: Vec<T>) -> Result<Vec<T>, Error>
+where
+    C: Context,
+    T: Reshare<C, RecordId> + Send + Sync,
+{
+    let (shuffled, _) = shuffle_with(&ctx, rows, ShuffleRandomness::Prss).await?;
+    Ok(shuffled)
+}
+
+/// Shuffles `rows` like [`oblivious_shuffle`] and also returns the permutations this helper
+/// applied, so that they can be applied to other rows later on.
+async fn shuffle_with<C, T>(
+    ctx: &C,
+    rows: Vec<T>,
+    randomness: ShuffleRandomness,
+) -> Result<(Vec<T>, (Vec<u32>, Vec<u32>)), Error>
+where
+    C: Context,
+    T: Reshare<C, RecordId> + Send + Sync,
+{
+    let random_permutations = randomness.permutations(
+        &ctx.narrow(&ShuffleRevealPermutationStep::Generate),
+        rows.len().try_into().unwrap(),
+    );
+
+    let shuffled = shuffle_shares(
+        rows,
+        (
+            random_permutations.0.as_slice(),
+            random_permutations.1.as_slice(),
+        ),
+        ctx.narrow(&ShuffleRevealPermutationStep::Shuffle),
+    )
+    .await?;
+
+    Ok((shuffled, random_permutations))
+}
+
 /// This is an implementation of `OptApplyInv` (Algorithm 13) and `OptCompose` (Algorithm 14) described in:
 /// "An Efficient Secure Three-Party Sorting Protocol with an Honest Majority"
 /// by K. Chida, K. Hamada, D. Ikarashi, R. Kikuchi, N. Kiribuchi, and B. Pinkas
@@ -65,22 +153,30 @@ where
     S: SecretSharing<F> + BasicProtocols<C::UpgradedContext<F>, F>,
     ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
 {
-    let random_permutations_for_shuffle = get_two_of_three_random_permutations(
-        input_permutation.len().try_into().unwrap(),
-        m_ctx
-            .narrow(&ShuffleRevealPermutationStep::Generate)
-            .prss_rng(),
-    );
-
-    let shuffled_permutation = shuffle_shares(
+    shuffle_and_reveal_permutation_with::<C, _, _>(
+        m_ctx,
         input_permutation,
-        (
-            random_permutations_for_shuffle.0.as_slice(),
-            random_permutations_for_shuffle.1.as_slice(),
-        ),
-        m_ctx.narrow(&ShuffleRevealPermutationStep::Shuffle),
+        malicious_validator,
+        ShuffleRandomness::Prss,
     )
-    .await?;
+    .await
+}
+
+async fn shuffle_and_reveal_permutation_with<C, S, F>(
+    m_ctx: C::UpgradedContext<F>,
+    input_permutation: Vec<S>,
+    malicious_validator: C::Validator<F>,
+    randomness: ShuffleRandomness,
+) -> Result<RevealedAndRandomPermutations, Error>
+where
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    F: ExtendableField,
+    S: SecretSharing<F> + BasicProtocols<C::UpgradedContext<F>, F>,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+{
+    let (shuffled_permutation, random_permutations_for_shuffle) =
+        shuffle_with(&m_ctx, input_permutation, randomness).await?;
 
     let revealed_permutation = malicious_validator
         .validate(ShuffledPermutationWrapper {
@@ -88,6 +184,7 @@ where
             ctx: m_ctx,
         })
         .await?;
+    check_permutation(&revealed_permutation)?;
 
     Ok(RevealedAndRandomPermutations {
         revealed: revealed_permutation,
@@ -95,6 +192,33 @@ where
     })
 }
 
+/// Checks that `permutation` is a bijection over `0..permutation.len()`, i.e. every index appears
+/// in it exactly once. Applying anything else as a sort permutation silently corrupts the output.
+///
+/// ## Errors
+/// If some index is out of range or appears more than once.
+fn check_permutation(permutation: &[u32]) -> Result<(), Error> {
+    let mut seen = vec![false; permutation.len()];
+    for (i, &index) in permutation.iter().enumerate() {
+        match usize::try_from(index).ok().and_then(|index| seen.get_mut(index)) {
+            Some(seen) if !*seen => *seen = true,
+            Some(_) => {
+                return Err(Error::InvalidPermutation(format!(
+                    "index {index} at position {i} appears more than once"
+                )))
+            }
+            None => {
+                return Err(Error::InvalidPermutation(format!(
+                    "index {index} at position {i} is out of range 0..{}",
+                    permutation.len()
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// This function takes in a semihonest context and sort keys, generates a sort permutation, shuffles and reveals it and
 /// returns both shuffle-revealed permutation and 2/3 randoms which were used to shuffle the permutation
 /// The output of this can be applied to any of semihonest/malicious context
@@ -109,6 +233,75 @@ pub async fn generate_permutation_and_reveal_shuffled<F, C, S, I>(
     num_multi_bits: u32,
     max_bits: u32,
 ) -> Result<RevealedAndRandomPermutations, Error>
+where
+    F: PrimeField + ExtendableField,
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C::UpgradedContext<F>, F> + 'static,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+    I: Stream,
+    I::Item: ToBitConversionTriples + Clone + Send + Sync,
+    for<'u> UpgradeContext<'u, C::UpgradedContext<F>, F, RecordId>:
+        UpgradeToMalicious<'u, BitConversionTriple<Replicated<F>>, BitConversionTriple<S>>,
+{
+    generate_permutation_and_reveal_shuffled_with(
+        sh_ctx,
+        sort_keys,
+        num_multi_bits,
+        max_bits,
+        ShuffleRandomness::Prss,
+    )
+    .await
+}
+
+/// Same as [`generate_permutation_and_reveal_shuffled`], but the permutation is shuffled with
+/// permutations drawn from `shuffle_seed` rather than PRSS before it is revealed. Runs with the
+/// same seed and sort keys reveal the same shuffled permutation, so golden-vector tests can pin
+/// it down.
+///
+/// Every helper knows the seed, so the revealed permutation leaks the sort order. Production code
+/// must use [`generate_permutation_and_reveal_shuffled`].
+///
+/// # Panics
+/// If unable to convert sort keys length to u32
+/// # Errors
+/// If unable to convert sort keys length to u32
+#[cfg(any(test, feature = "test-fixture"))]
+pub async fn generate_permutation_with_shuffle_seed<F, C, S, I>(
+    sh_ctx: C,
+    sort_keys: I,
+    num_multi_bits: u32,
+    max_bits: u32,
+    shuffle_seed: u64,
+) -> Result<RevealedAndRandomPermutations, Error>
+where
+    F: PrimeField + ExtendableField,
+    C: UpgradableContext,
+    C::UpgradedContext<F>: UpgradedContext<F, Share = S>,
+    S: LinearSecretSharing<F> + BasicProtocols<C::UpgradedContext<F>, F> + 'static,
+    ShuffledPermutationWrapper<S, C::UpgradedContext<F>>: DowngradeMalicious<Target = Vec<u32>>,
+    I: Stream,
+    I::Item: ToBitConversionTriples + Clone + Send + Sync,
+    for<'u> UpgradeContext<'u, C::UpgradedContext<F>, F, RecordId>:
+        UpgradeToMalicious<'u, BitConversionTriple<Replicated<F>>, BitConversionTriple<S>>,
+{
+    generate_permutation_and_reveal_shuffled_with(
+        sh_ctx,
+        sort_keys,
+        num_multi_bits,
+        max_bits,
+        ShuffleRandomness::Seeded(shuffle_seed),
+    )
+    .await
+}
+
+async fn generate_permutation_and_reveal_shuffled_with<F, C, S, I>(
+    sh_ctx: C,
+    sort_keys: I,
+    num_multi_bits: u32,
+    max_bits: u32,
+    randomness: ShuffleRandomness,
+) -> Result<RevealedAndRandomPermutations, Error>
 where
     F: PrimeField + ExtendableField,
     C: UpgradableContext,
@@ -129,10 +322,11 @@ where
     .await?;
 
     let m_ctx = validator.context();
-    shuffle_and_reveal_permutation::<C, _, _>(
+    shuffle_and_reveal_permutation_with::<C, _, _>(
         m_ctx.narrow(&SortStep::ShuffleRevealPermutation),
         sort_permutation,
         validator,
+        randomness,
     )
     .await
 }
@@ -174,23 +368,30 @@ impl<'a, F: ExtendableField> DowngradeMalicious
 
 #[cfg(all(test, unit_test))]
 mod tests {
-    use std::iter::zip;
+    use std::{cmp::min, iter::zip};
 
     use futures::stream::iter as stream_iter;
-    use rand::seq::SliceRandom;
+    use rand::{
+        distributions::{Distribution, Standard},
+        seq::SliceRandom,
+    };
 
     use crate::{
-        ff::{Field, Fp31, GaloisField},
+        error::Error,
+        ff::{Field, Fp31, Fp32BitPrime, GaloisField, Gf32Bit, Gf40Bit, Gf5Bit, Gf8Bit},
         protocol::{
             context::{Context, SemiHonestContext, UpgradableContext, Validator},
             sort::{
-                generate_permutation::shuffle_and_reveal_permutation,
+                generate_permutation::{
+                    check_permutation, generate_permutation_with_shuffle_seed, oblivious_shuffle,
+                    shuffle_and_reveal_permutation,
+                },
                 generate_permutation_opt::generate_permutation_opt,
             },
             MatchKey,
         },
         rand::{thread_rng, Rng},
-        secret_sharing::SharedValue,
+        secret_sharing::{replicated::ReplicatedSecretSharing, SharedValue},
         test_fixture::{generate_shares, join3, Reconstruct, Runner, TestWorld},
     };
 
@@ -232,6 +433,68 @@ mod tests {
         assert_eq!(expected, mpc_sorted_list);
     }
 
+    /// Sorts `COUNT` random keys drawn from `0..key_space` and checks that the revealed order
+    /// places rows with equal keys next to each other.
+    async fn sort_groups_equal_keys<MK>(world: &TestWorld, key_space: u128)
+    where
+        MK: GaloisField,
+        Standard: Distribution<MK>,
+    {
+        const COUNT: usize = 50;
+        const NUM_MULTI_BITS: u32 = 3;
+        let mut rng = thread_rng();
+        let key_space = min(key_space, 1 << MK::BITS);
+
+        let match_keys = (0..COUNT)
+            .map(|_| MK::truncate_from(rng.gen_range(0..key_space)))
+            .collect::<Vec<_>>();
+
+        let mut expected = match_keys.iter().map(Field::as_u128).collect::<Vec<_>>();
+        expected.sort_unstable();
+
+        let result = world
+            .semi_honest(
+                match_keys.clone().into_iter(),
+                |ctx, mk_shares| async move {
+                    let (_validator, result) = generate_permutation_opt::<Fp32BitPrime, _, _, _>(
+                        ctx.narrow("sort"),
+                        stream_iter(mk_shares),
+                        NUM_MULTI_BITS,
+                        MK::BITS,
+                    )
+                    .await
+                    .unwrap();
+                    result
+                },
+            )
+            .await;
+
+        let mut mpc_sorted_list = vec![0; COUNT];
+        for (match_key, index) in zip(match_keys, result.reconstruct()) {
+            mpc_sorted_list[index.as_u128() as usize] = match_key.as_u128();
+        }
+
+        assert_eq!(
+            expected,
+            mpc_sorted_list,
+            "{}-bit keys from a space of {key_space}",
+            MK::BITS
+        );
+    }
+
+    #[tokio::test]
+    pub async fn groups_colliding_match_keys() {
+        let world = TestWorld::default();
+
+        // from almost every row sharing a key to collisions being unlikely
+        for key_space in [5, 50, u128::MAX] {
+            sort_groups_equal_keys::<Gf5Bit>(&world, key_space).await;
+            sort_groups_equal_keys::<Gf8Bit>(&world, key_space).await;
+            sort_groups_equal_keys::<Gf32Bit>(&world, key_space).await;
+            sort_groups_equal_keys::<Gf40Bit>(&world, key_space).await;
+        }
+    }
+
     #[tokio::test]
     pub async fn shuffle_and_reveal() {
         const BATCHSIZE: u32 = 25;
@@ -277,4 +540,93 @@ mod tests {
             perms_and_randoms[1].randoms_for_shuffle.1
         );
     }
+
+    #[tokio::test]
+    pub async fn oblivious_shuffle_permutes_rows() {
+        const COUNT: u128 = 25;
+
+        let tags = (0..COUNT)
+            .map(Fp32BitPrime::truncate_from)
+            .collect::<Vec<_>>();
+        let world = TestWorld::default();
+        let shuffled = world
+            .semi_honest(tags.clone().into_iter(), |ctx, shares| async move {
+                let input = shares.clone();
+                let shuffled = oblivious_shuffle(ctx, shares).await.unwrap();
+
+                // Rows are reshared after every permutation, so no helper can match its output
+                // shares to the input shares to learn the permutation.
+                assert!(!shuffled.iter().any(|x| input
+                    .iter()
+                    .any(|y| x.left() == y.left() && x.right() == y.right())));
+                shuffled
+            })
+            .await
+            .reconstruct();
+
+        assert_ne!(tags, shuffled);
+        let mut sorted = shuffled;
+        sorted.sort_by_key(Field::as_u128);
+        assert_eq!(tags, sorted);
+    }
+
+    #[tokio::test]
+    pub async fn seeded_shuffle_is_reproducible() {
+        const COUNT: usize = 20;
+        const NUM_MULTI_BITS: u32 = 3;
+        const SHUFFLE_SEED: u64 = 42;
+
+        let mut rng = thread_rng();
+        let mut match_keys = Vec::with_capacity(COUNT);
+        match_keys.resize_with(COUNT, || rng.gen::<MatchKey>());
+
+        let reveal = || async {
+            // every run gets its own PRSS, only the shuffle is seeded
+            TestWorld::default()
+                .semi_honest(
+                    match_keys.clone().into_iter(),
+                    |ctx, mk_shares| async move {
+                        generate_permutation_with_shuffle_seed::<Fp31, _, _, _>(
+                            ctx.narrow("sort"),
+                            stream_iter(mk_shares),
+                            NUM_MULTI_BITS,
+                            MatchKey::BITS,
+                            SHUFFLE_SEED,
+                        )
+                        .await
+                        .unwrap()
+                        .revealed
+                    },
+                )
+                .await
+        };
+
+        let [first, second] = [reveal().await, reveal().await];
+        for revealed in first.iter().chain(&second) {
+            assert_eq!(&first[0], revealed);
+        }
+        check_permutation(&first[0]).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_permutations() {
+        let mut permutation = (0..10).collect::<Vec<u32>>();
+        permutation.shuffle(&mut thread_rng());
+        check_permutation(&permutation).unwrap();
+        check_permutation(&[]).unwrap();
+
+        let mut duplicate = permutation.clone();
+        duplicate[7] = duplicate[3];
+        assert!(matches!(
+            check_permutation(&duplicate),
+            Err(Error::InvalidPermutation(_))
+        ));
+
+        let mut out_of_range = permutation;
+        out_of_range[0] = 10;
+        assert!(matches!(
+            check_permutation(&out_of_range),
+            Err(Error::InvalidPermutation(_))
+        ));
+    }
 }
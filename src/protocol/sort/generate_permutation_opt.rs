@@ -82,7 +82,8 @@ use crate::{
 /// ![Malicious sort permutation steps][malicious_sort]
 ///
 /// # Panics
-/// If sort keys dont have num of bits same as `num_bits`
+/// If sort keys dont have num of bits same as `num_bits`. In debug builds, also if `max_bits`
+/// exceeds the width of any sort key.
 /// # Errors
 pub async fn generate_permutation_opt<'a, F, C, S, I>(
     sh_ctx: C,
@@ -103,6 +104,12 @@ where
 {
     let mut malicious_validator = sh_ctx.clone().validator();
     let sort_keys = sort_keys.collect::<Vec<_>>().await;
+    // Nothing ties `max_bits` to the type of the sort keys, so catch callers that ask to sort on
+    // bits the keys do not have.
+    debug_assert!(
+        sort_keys.iter().all(|key| max_bits <= key.bits()),
+        "cannot sort on {max_bits} bits, sort keys are narrower than that"
+    );
     if sort_keys.is_empty() {
         return Ok((malicious_validator, Vec::new()));
     }
@@ -248,6 +255,29 @@ mod tests {
         assert_eq!(expected, mpc_sorted_list);
     }
 
+    #[tokio::test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "sort keys are narrower than that")]
+    async fn more_bits_than_keys_have() {
+        let world = TestWorld::default();
+        let mut rng = thread_rng();
+        let match_keys = (0..4).map(|_| rng.gen::<MatchKey>()).collect::<Vec<_>>();
+
+        world
+            .semi_honest(match_keys.into_iter(), |ctx, mk_shares| async move {
+                generate_permutation_opt::<Fp32BitPrime, _, _, _>(
+                    ctx.narrow("sort"),
+                    stream_iter(mk_shares),
+                    3,
+                    MatchKey::BITS + 1,
+                )
+                .await
+                .unwrap()
+                .1
+            })
+            .await;
+    }
+
     async fn sortn(count: usize) {
         const NUM_MULTI_BITS: u32 = 3;
         let world = TestWorld::default();
@@ -59,7 +59,7 @@ pub(super) fn shuffle_for_helper(which_step: ShuffleStep) -> Role {
 /// i)   2 helpers receive permutation pair and choose the permutation to be applied
 /// ii)  2 helpers apply the permutation to their shares
 /// iii) reshare to `to_helper`
-async fn shuffle_or_unshuffle_once<F, S, C>(
+async fn shuffle_or_unshuffle_once<S, C>(
     mut input: Vec<S>,
     random_permutations: (&[u32], &[u32]),
     shuffle_or_unshuffle: ShuffleOrUnshuffle,
@@ -67,8 +67,6 @@ async fn shuffle_or_unshuffle_once<F, S, C>(
     which_step: ShuffleStep,
 ) -> Result<Vec<S>, Error>
 where
-    F: Field,
-    S: SecretSharing<F>,
     C: Context,
     S: Reshare<C, RecordId> + Send + Sync,
 {
@@ -98,7 +96,7 @@ where
 /// For this, we have three shuffle steps one per `shuffle_or_unshuffle_once` i.e. Step1, Step2 and Step3.
 /// The Shuffle object receives a step function and appends a `ShuffleStep` to form a concrete step
 /// ![Shuffle steps][shuffle]
-pub async fn shuffle_shares<F: Field, S: SecretSharing<F> + Reshare<C, RecordId>, C: Context>(
+pub async fn shuffle_shares<C: Context, S: Reshare<C, RecordId> + Send + Sync>(
     input: Vec<S>,
     random_permutations: (&[u32], &[u32]),
     ctx: C,
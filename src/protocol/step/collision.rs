@@ -0,0 +1,117 @@
+use std::{any::type_name, collections::HashMap, sync::Mutex};
+
+/// Keeps track of the step types that produced every name a gate has been narrowed with.
+///
+/// Gates are identified by their string value only, so two different steps that map to the same
+/// name at the same position in the tree end up sharing PRSS randomness and channels. This is easy
+/// to do by copy-pasting a step name, and nothing else notices it.
+///
+/// Every tree of gates has its own registry, shared by all gates narrowed from the same root. A
+/// query starts from a fresh root gate, so steps of different queries (or tests) never collide.
+///
+/// It is only compiled into debug builds.
+#[derive(Default)]
+pub(super) struct StepNames {
+    /// Step type for every `parent/name` that a gate in the tree has been narrowed to.
+    names: Mutex<HashMap<String, &'static str>>,
+}
+
+impl StepNames {
+    /// Records that `parent` has been narrowed to `name` by a step of type `S`.
+    ///
+    /// ## Panics
+    /// If `parent` has already been narrowed to `name` by a step of a different type.
+    pub(super) fn register<S: ?Sized>(&self, parent: &str, name: &str) {
+        let step_type = step_type::<S>();
+        // Do not panic while holding the lock, it would poison the registry for the whole tree.
+        let existing = *self
+            .names
+            .lock()
+            .unwrap()
+            .entry([parent, "/", name].concat())
+            .or_insert(step_type);
+        assert_eq!(
+            existing, step_type,
+            "Step '{name}' of {step_type} collides with a step of {existing} in gate '{parent}'"
+        );
+    }
+}
+
+/// Type of a step for the purposes of collision detection. Strings are allowed as steps in tests
+/// and it does not matter whether they are owned.
+fn step_type<S: ?Sized>() -> &'static str {
+    let name = type_name::<S>();
+    if name == type_name::<String>() {
+        type_name::<str>()
+    } else {
+        name
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::StepNames;
+    use crate::protocol::step::{Gate, Step, StepNarrow};
+
+    enum Original {
+        Compute,
+    }
+
+    impl Step for Original {}
+
+    impl AsRef<str> for Original {
+        fn as_ref(&self) -> &'static str {
+            "compute"
+        }
+    }
+
+    /// A copy of [`Original`] that forgot to rename its step.
+    enum Copied {
+        Compute,
+    }
+
+    impl Step for Copied {}
+
+    impl AsRef<str> for Copied {
+        fn as_ref(&self) -> &'static str {
+            "compute"
+        }
+    }
+
+    #[test]
+    fn same_type_can_be_registered_again() {
+        let names = StepNames::default();
+        names.register::<Original>("protocol", "compute");
+        names.register::<Original>("protocol", "compute");
+        names.register::<Copied>("protocol/other", "compute");
+    }
+
+    #[test]
+    fn owned_and_borrowed_strings_are_the_same_step() {
+        let names = StepNames::default();
+        names.register::<str>("protocol", "compute");
+        names.register::<String>("protocol", "compute");
+    }
+
+    #[test]
+    #[should_panic(expected = "collides with a step of")]
+    fn detects_collision() {
+        let names = StepNames::default();
+        names.register::<Original>("protocol", "compute");
+        names.register::<Copied>("protocol", "compute");
+    }
+
+    #[test]
+    fn separate_gate_trees_do_not_collide() {
+        let _ = Gate::default().narrow(&Original::Compute);
+        let _ = Gate::default().narrow(&Copied::Compute);
+    }
+
+    #[test]
+    #[should_panic(expected = "collides with a step of")]
+    fn narrow_detects_collision() {
+        let gate = Gate::default().narrow("protocol");
+        let _ = gate.narrow(&Original::Compute);
+        let _ = gate.narrow(&Copied::Compute);
+    }
+}
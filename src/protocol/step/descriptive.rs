@@ -1,5 +1,12 @@
-use std::fmt::{Debug, Display, Formatter};
+#[cfg(debug_assertions)]
+use std::sync::Arc;
+use std::{
+    fmt::{Debug, Display, Formatter},
+    hash::{Hash, Hasher},
+};
 
+#[cfg(debug_assertions)]
+use super::collision::StepNames;
 use super::{Step, StepNarrow};
 #[cfg(feature = "step-trace")]
 use crate::telemetry::{labels::STEP, metrics::STEP_NARROWED};
@@ -22,7 +29,7 @@ use crate::telemetry::{labels::STEP, metrics::STEP_NARROWED};
 /// Step "a" would be executed with a context identifier of "protocol/a", which it
 ///  would `narrow()` into "protocol/a/x" and "protocol/a/y" to produce a final set
 /// of identifiers: ".../a/x", ".../a/y", ".../b", and ".../c".
-#[derive(Clone, Hash, PartialEq, Eq)]
+#[derive(Clone)]
 #[cfg_attr(
     feature = "enable-serde",
     derive(serde::Deserialize),
@@ -30,6 +37,34 @@ use crate::telemetry::{labels::STEP, metrics::STEP_NARROWED};
 )]
 pub struct Descriptive {
     id: String,
+    /// Step names used in the tree of gates that this one belongs to.
+    #[cfg(debug_assertions)]
+    names: Arc<StepNames>,
+}
+
+impl Descriptive {
+    fn new(id: String) -> Self {
+        Self {
+            id,
+            #[cfg(debug_assertions)]
+            names: Arc::default(),
+        }
+    }
+}
+
+// Gates are identified by their string value, no matter which tree they come from.
+impl PartialEq for Descriptive {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Descriptive {}
+
+impl Hash for Descriptive {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
 }
 
 impl Display for Descriptive {
@@ -41,13 +76,15 @@ impl Display for Descriptive {
 impl<S: Step + ?Sized> StepNarrow<S> for Descriptive {
     /// Narrow the scope of the step identifier.
     /// # Panics
-    /// In a debug build, this checks that the same refine call isn't run twice and that the string
-    /// value of the step doesn't include '/' (which would lead to a bad outcome).
+    /// In a debug build, this checks that the same refine call isn't run twice, that the string
+    /// value of the step doesn't include '/' (which would lead to a bad outcome) and that no step of
+    /// another type has narrowed this gate to the same string.
     fn narrow(&self, step: &S) -> Self {
         #[cfg(debug_assertions)]
         {
-            let s = String::from(step.as_ref());
+            let s = step.as_ref();
             assert!(!s.contains('/'), "The string for a step cannot contain '/'");
+            self.names.register::<S>(&self.id, s);
         }
 
         let mut id = self.id.clone() + "/";
@@ -61,7 +98,11 @@ impl<S: Step + ?Sized> StepNarrow<S> for Descriptive {
             metrics::increment_counter!(STEP_NARROWED, STEP => id.clone());
         }
 
-        Self { id }
+        Self {
+            id,
+            #[cfg(debug_assertions)]
+            names: Arc::clone(&self.names),
+        }
     }
 }
 
@@ -69,9 +110,7 @@ impl Default for Descriptive {
     // TODO(mt): this should might be better if it were to be constructed from
     // a QueryId rather than using a default.
     fn default() -> Self {
-        Self {
-            id: String::from("protocol"),
-        }
+        Self::new(String::from("protocol"))
     }
 }
 
@@ -84,7 +123,7 @@ impl AsRef<str> for Descriptive {
 impl From<&str> for Descriptive {
     fn from(id: &str) -> Self {
         let id = id.strip_prefix('/').unwrap_or(id);
-        Descriptive { id: id.to_owned() }
+        Descriptive::new(id.to_owned())
     }
 }
 
@@ -1,3 +1,5 @@
+#[cfg(all(feature = "descriptive-gate", debug_assertions))]
+mod collision;
 #[cfg(feature = "compact-gate")]
 mod compact;
 #[cfg(feature = "descriptive-gate")]
@@ -75,6 +77,31 @@ impl From<usize> for BitOpStep {
     }
 }
 
+/// A step that isolates a sub-protocol from its siblings, see [`Context::scoped`].
+///
+/// [`Context::scoped`]: crate::protocol::context::Context::scoped
+#[derive(Step)]
+pub enum ScopeStep {
+    #[dynamic]
+    Scope(usize),
+}
+
+impl ScopeStep {
+    /// Number of scopes that a context can be split into.
+    pub const MAX_SCOPES: usize = 64;
+}
+
+impl From<usize> for ScopeStep {
+    fn from(v: usize) -> Self {
+        assert!(
+            v < Self::MAX_SCOPES,
+            "scope index {v} is out of range, there can be at most {} scopes",
+            Self::MAX_SCOPES
+        );
+        Self::Scope(v)
+    }
+}
+
 /// Set of steps that define the IPA protocol.
 #[derive(Step)]
 pub(crate) enum IpaProtocolStep {
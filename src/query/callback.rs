@@ -0,0 +1,107 @@
+use std::fmt::{Debug, Formatter};
+
+use ::tokio::sync::oneshot;
+use async_trait::async_trait;
+#[cfg(all(feature = "shuttle", test))]
+use shuttle::future as tokio;
+use tracing::Instrument;
+
+use crate::{
+    helpers::query::{CallbackUrl, QueryConfig, QueryType},
+    protocol::QueryId,
+    query::state::RunningQuery,
+    sync::Arc,
+};
+
+/// Delivers query completion notifications to the callback URL requested by the client.
+#[async_trait]
+pub trait CompletionNotifier: Send + Sync {
+    /// Tells `url` that `query_id` has completed and its results can be fetched from this helper.
+    /// Notifications are best effort: failures are expected to be logged rather than reported.
+    async fn notify(&self, url: CallbackUrl, query_id: QueryId);
+}
+
+/// Completion callbacks that [`Processor`] is allowed to make. Clients choose the URL, so only
+/// hosts from the allowlist can be called back. Otherwise, clients could make helpers send
+/// requests to arbitrary hosts from inside the helper network.
+///
+/// [`Processor`]: crate::query::QueryProcessor
+#[derive(Clone)]
+pub struct CompletionCallbacks {
+    allowed_hosts: Vec<String>,
+    notifier: Arc<dyn CompletionNotifier>,
+}
+
+impl Debug for CompletionCallbacks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompletionCallbacks")
+            .field("allowed_hosts", &self.allowed_hosts)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CompletionCallbacks {
+    #[must_use]
+    pub fn new<I: IntoIterator<Item = String>>(
+        allowed_hosts: I,
+        notifier: Arc<dyn CompletionNotifier>,
+    ) -> Self {
+        Self {
+            allowed_hosts: allowed_hosts.into_iter().collect(),
+            notifier,
+        }
+    }
+
+    pub(super) fn is_allowed(&self, url: &CallbackUrl) -> bool {
+        self.allowed_hosts
+            .iter()
+            .any(|host| host.eq_ignore_ascii_case(url.host()))
+    }
+
+    /// Makes the callback requested in `config` once `running` completes, whether it succeeded
+    /// or not. The result becomes available to the returned query before the notification is
+    /// sent, so clients can fetch it as soon as they are notified.
+    pub(super) fn on_completion(
+        &self,
+        query_id: QueryId,
+        config: &QueryConfig,
+        running: RunningQuery,
+    ) -> RunningQuery {
+        let Some(url) = callback_url(config).filter(|url| self.is_allowed(url)) else {
+            return running;
+        };
+
+        let RunningQuery {
+            result,
+            join_handle,
+        } = running;
+        let (tx, rx) = oneshot::channel();
+        let notifier = Arc::clone(&self.notifier);
+        tokio::spawn(
+            async move {
+                // If the query task is aborted, it never completes and there is nothing to report.
+                if let Ok(result) = result.await {
+                    if tx.send(result).is_ok() {
+                        notifier.notify(url, query_id).await;
+                    }
+                }
+            }
+            .in_current_span(),
+        );
+
+        RunningQuery {
+            result: rx,
+            join_handle,
+        }
+    }
+}
+
+/// Callback requested by the client for the query, if any.
+pub(super) fn callback_url(config: &QueryConfig) -> Option<CallbackUrl> {
+    match &config.query_type {
+        QueryType::SemiHonestIpa(ipa_config) | QueryType::MaliciousIpa(ipa_config) => {
+            ipa_config.callback_url.clone()
+        }
+        _ => None,
+    }
+}
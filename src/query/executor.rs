@@ -12,6 +12,7 @@ use rand::rngs::StdRng;
 use rand_core::SeedableRng;
 #[cfg(all(feature = "shuttle", test))]
 use shuttle::future as tokio;
+use tracing::Instrument;
 use typenum::Unsigned;
 
 #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
@@ -21,7 +22,7 @@ use crate::{
     helpers::{
         negotiate_prss,
         query::{QueryConfig, QueryType},
-        BodyStream, Gateway,
+        AbortReason, BodyStream, Gateway,
     },
     hpke::{KeyPair, KeyRegistry},
     protocol::{
@@ -31,6 +32,7 @@ use crate::{
     },
     query::{
         runner::{IpaQuery, QueryResult, SparseAggregateQuery},
+        slots::SlotPermit,
         state::RunningQuery,
     },
 };
@@ -62,27 +64,37 @@ pub fn execute(
     key_registry: Arc<KeyRegistry<KeyPair>>,
     gateway: Gateway,
     input: BodyStream,
+    permit: Option<SlotPermit>,
 ) -> RunningQuery {
-    match (config.query_type, config.field_type) {
+    match (config.query_type.clone(), config.field_type) {
         #[cfg(any(test, feature = "weak-field"))]
-        (QueryType::TestMultiply, FieldType::Fp31) => {
-            do_query(config, gateway, input, |prss, gateway, _config, input| {
+        (QueryType::TestMultiply, FieldType::Fp31) => do_query(
+            config,
+            gateway,
+            input,
+            permit,
+            |prss, gateway, _config, input| {
                 Box::pin(execute_test_multiply::<crate::ff::Fp31>(
                     prss, gateway, input,
                 ))
-            })
-        }
+            },
+        ),
         #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
-        (QueryType::TestMultiply, FieldType::Fp32BitPrime) => {
-            do_query(config, gateway, input, |prss, gateway, _config, input| {
+        (QueryType::TestMultiply, FieldType::Fp32BitPrime) => do_query(
+            config,
+            gateway,
+            input,
+            permit,
+            |prss, gateway, _config, input| {
                 Box::pin(execute_test_multiply::<Fp32BitPrime>(prss, gateway, input))
-            })
-        }
+            },
+        ),
         #[cfg(any(test, feature = "weak-field"))]
         (QueryType::SemiHonestIpa(ipa_config), FieldType::Fp31) => do_query(
             config,
             gateway,
             input,
+            permit,
             move |prss, gateway, config, input| {
                 let ctx = SemiHonestContext::new(prss, gateway);
                 Box::pin(
@@ -96,6 +108,7 @@ pub fn execute(
             config,
             gateway,
             input,
+            permit,
             move |prss, gateway, config, input| {
                 let ctx = SemiHonestContext::new(prss, gateway);
                 Box::pin(
@@ -110,6 +123,7 @@ pub fn execute(
             config,
             gateway,
             input,
+            permit,
             move |prss, gateway, config, input| {
                 let ctx = MaliciousContext::new(prss, gateway);
                 Box::pin(
@@ -123,6 +137,7 @@ pub fn execute(
             config,
             gateway,
             input,
+            permit,
             move |prss, gateway, config, input| {
                 let ctx = MaliciousContext::new(prss, gateway);
                 Box::pin(
@@ -137,6 +152,7 @@ pub fn execute(
             config,
             gateway,
             input,
+            permit,
             move |prss, gateway, config, input| {
                 let ctx = SemiHonestContext::new(prss, gateway);
                 Box::pin(
@@ -154,6 +170,7 @@ pub fn execute(
                 config,
                 gateway,
                 input,
+                permit,
                 move |prss, gateway, config, input| {
                     let ctx = SemiHonestContext::new(prss, gateway);
                     Box::pin(
@@ -172,6 +189,7 @@ pub fn execute(
             config,
             gateway,
             input,
+            permit,
             move |prss, gateway, config, input| {
                 let ctx = MaliciousContext::new(prss, gateway);
                 Box::pin(
@@ -189,6 +207,7 @@ pub fn execute(
                 config,
                 gateway,
                 input,
+                permit,
                 move |prss, gateway, config, input| {
                     let ctx = MaliciousContext::new(prss, gateway);
                     Box::pin(
@@ -205,10 +224,14 @@ pub fn execute(
     }
 }
 
+/// Spawns a task that executes the query. The execution slot that the coordinator of the query
+/// took for it, if this helper is the coordinator, is held by the task until the query is done.
+/// The task runs inside the tracing span that is current at the time this function is called.
 pub fn do_query<F>(
     config: QueryConfig,
     gateway: Gateway,
     input_stream: BodyStream,
+    permit: Option<SlotPermit>,
     query_impl: F,
 ) -> RunningQuery
 where
@@ -223,16 +246,35 @@ where
 {
     let (tx, rx) = oneshot::channel();
 
-    let join_handle = tokio::spawn(async move {
-        // TODO: make it a generic argument for this function
-        let mut rng = StdRng::from_entropy();
-        // Negotiate PRSS first
-        let step = Gate::default().narrow(&config.query_type);
-        let prss = negotiate_prss(&gateway, &step, &mut rng).await.unwrap();
-
-        tx.send(query_impl(&prss, &gateway, &config, input_stream).await)
-            .unwrap();
-    });
+    let join_handle = tokio::spawn(
+        async move {
+            let _permit = permit;
+            tracing::info!("starting query execution");
+            // TODO: make it a generic argument for this function
+            let mut rng = StdRng::from_entropy();
+            // Negotiate PRSS first. Keys are never reused across queries: every query runs its own
+            // key agreement, so helpers rotate PRSS between queries without disrupting the
+            // ones that are still in flight.
+            let step = Gate::default().narrow(&config.query_type);
+            // Peers that never show up fail the query the same way as any other protocol error,
+            // so a query deadline reports a timeout even before PRSS is set up.
+            let result = match negotiate_prss(&gateway, &step, &mut rng).await {
+                Ok(prss) => query_impl(&prss, &gateway, &config, input_stream).await,
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = &result {
+                // Peers may still be sending records for this query, but nothing is going to
+                // receive them anymore.
+                gateway.abort(if e.is_timeout() {
+                    AbortReason::Timeout
+                } else {
+                    AbortReason::Failed
+                });
+            }
+            tx.send(result).unwrap();
+        }
+        .in_current_span(),
+    );
 
     RunningQuery {
         result: rx,
@@ -0,0 +1,303 @@
+use std::{
+    cmp::{max, min},
+    fmt::Debug,
+    iter::zip,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use tracing::{
+    callsite::rebuild_interest_cache,
+    field::{display, Field, Visit},
+    metadata::LevelFilter,
+    span,
+    subscriber::Interest,
+    Level, Metadata, Span, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::{
+    helpers::{
+        query::{QueryConfig, QueryLogLevel, QueryType},
+        Role,
+    },
+    protocol::QueryId,
+};
+
+const QUERY_SPAN: &str = "query";
+const LOG_LEVEL_FIELD: &str = "log_level";
+
+/// Creates the span that a query runs in. Every log line emitted inside of it carries the query
+/// id and the role of this helper. If the client asked for a log level in `config`,
+/// [`QueryLevelFilter`] applies it to everything inside of this span.
+pub(super) fn query_span(query_id: QueryId, role: Role, config: &QueryConfig) -> Span {
+    let span = tracing::info_span!(
+        QUERY_SPAN,
+        id = %query_id,
+        role = ?role,
+        log_level = tracing::field::Empty
+    );
+    if let Some(level) = log_level(config) {
+        span.record(LOG_LEVEL_FIELD, display(Level::from(level)));
+    }
+
+    span
+}
+
+/// Log level requested by the client for the query, if any.
+fn log_level(config: &QueryConfig) -> Option<QueryLogLevel> {
+    match &config.query_type {
+        QueryType::SemiHonestIpa(ipa_config) | QueryType::MaliciousIpa(ipa_config) => {
+            ipa_config.log_level
+        }
+        _ => None,
+    }
+}
+
+/// Levels that queries can ask for, from the least to the most verbose.
+const LEVELS: [Level; 5] = [
+    Level::ERROR,
+    Level::WARN,
+    Level::INFO,
+    Level::DEBUG,
+    Level::TRACE,
+];
+
+/// Level filter for the whole tracing subscriber that lets individual queries log more than the
+/// rest of the helper. Events at or above the default level are always enabled. More verbose
+/// events are only enabled inside a query span that asked for them, see [`query_span`]. Operators
+/// set the most verbose level that queries may ask for, more verbose requests are lowered to it.
+///
+/// The query span itself is an `INFO` span, so helpers that log less than that ignore the log
+/// levels requested by queries.
+pub struct QueryLevelFilter {
+    default: LevelFilter,
+    max_query_level: LevelFilter,
+    /// Number of open query spans with a log level, for every level in [`LEVELS`]. The most
+    /// verbose of them is reported in [`Layer::max_level_hint`], so events that no running query
+    /// asked for are disabled without looking up their spans.
+    overrides: [AtomicUsize; LEVELS.len()],
+}
+
+/// Log level of a query span, stored in its extensions.
+struct QueryLevel(Level);
+
+impl QueryLevelFilter {
+    #[must_use]
+    pub fn new(default: LevelFilter, max_query_level: LevelFilter) -> Self {
+        Self {
+            default,
+            max_query_level,
+            overrides: Default::default(),
+        }
+    }
+
+    /// Most verbose level that an open query span asked for.
+    fn max_override(&self) -> LevelFilter {
+        zip(LEVELS, &self.overrides)
+            .rev()
+            .find(|(_, count)| count.load(Ordering::Acquire) > 0)
+            .map_or(LevelFilter::OFF, |(level, _)| {
+                LevelFilter::from_level(level)
+            })
+    }
+
+    fn add_override(&self, level: Level) {
+        if self.overrides[level_index(level)].fetch_add(1, Ordering::AcqRel) == 0 {
+            // the max level hint may have changed
+            rebuild_interest_cache();
+        }
+    }
+
+    fn remove_override(&self, level: Level) {
+        if self.overrides[level_index(level)].fetch_sub(1, Ordering::AcqRel) == 1 {
+            rebuild_interest_cache();
+        }
+    }
+
+    /// Log level of the innermost query span that the current span is nested in.
+    fn current_query_level<S>(ctx: &Context<'_, S>) -> Option<Level>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        ctx.lookup_current()?
+            .scope()
+            .find_map(|span| span.extensions().get::<QueryLevel>().map(|level| level.0))
+    }
+}
+
+fn level_index(level: Level) -> usize {
+    LEVELS.iter().position(|l| *l == level).unwrap()
+}
+
+impl<S> Layer<S> for QueryLevelFilter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if self.default >= *metadata.level() {
+            Interest::always()
+        } else if self.max_query_level >= *metadata.level() {
+            // depends on the span it is called from
+            Interest::sometimes()
+        } else {
+            Interest::never()
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        if self.default >= *metadata.level() {
+            return true;
+        }
+
+        self.max_override() >= *metadata.level()
+            && Self::current_query_level(&ctx).map_or(false, |level| level >= *metadata.level())
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(max(self.default, self.max_override()))
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if span.name() != QUERY_SPAN || span.metadata().target() != module_path!() {
+            return;
+        }
+
+        let mut visitor = LevelVisitor(None);
+        values.record(&mut visitor);
+        let Some(level) = visitor.0.and_then(|level| {
+            min(LevelFilter::from_level(level), self.max_query_level).into_level()
+        }) else {
+            return;
+        };
+        if self.default >= level {
+            // enabled anyway
+            return;
+        }
+
+        let previous = span.extensions_mut().replace(QueryLevel(level));
+        self.add_override(level);
+        if let Some(QueryLevel(previous)) = previous {
+            self.remove_override(previous);
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let level = span.extensions().get::<QueryLevel>().map(|level| level.0);
+        if let Some(level) = level {
+            self.remove_override(level);
+        }
+    }
+}
+
+/// Reads the log level that [`query_span`] records.
+struct LevelVisitor(Option<Level>);
+
+impl Visit for LevelVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == LOG_LEVEL_FIELD {
+            self.0 = Level::from_str(&format!("{value:?}")).ok();
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::metadata::LevelFilter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::{query_span, QueryLevelFilter};
+    use crate::{
+        ff::FieldType,
+        helpers::{
+            query::{IpaQueryConfig, QueryConfig, QueryLogLevel, QueryType},
+            Role,
+        },
+        protocol::QueryId,
+    };
+
+    /// Logs a debug and a trace line inside of a query that asked for `log_level` and a debug
+    /// line outside of it. Returns the ones that the helper logged.
+    fn logged(max_query_level: LevelFilter, log_level: Option<QueryLogLevel>) -> Vec<&'static str> {
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry()
+            .with(QueryLevelFilter::new(LevelFilter::INFO, max_query_level))
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer({
+                        let logs = Arc::clone(&logs);
+                        move || CapturedLogs(Arc::clone(&logs))
+                    }),
+            );
+        let config = QueryConfig::new(
+            QueryType::SemiHonestIpa(IpaQueryConfig {
+                log_level,
+                ..IpaQueryConfig::default()
+            }),
+            FieldType::Fp31,
+            1,
+        )
+        .unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            query_span(QueryId::default(), Role::H1, &config).in_scope(|| {
+                tracing::debug!("query debug");
+                tracing::trace!("query trace");
+            });
+            tracing::debug!("helper debug");
+        });
+
+        let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+        logs.lines()
+            .filter_map(|line| {
+                ["query debug", "query trace", "helper debug"]
+                    .into_iter()
+                    .find(|message| line.contains(message))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn query_level_applies_inside_query_only() {
+        assert_eq!(
+            vec!["query debug"],
+            logged(LevelFilter::TRACE, Some(QueryLogLevel::Debug))
+        );
+        assert_eq!(
+            vec!["query debug", "query trace"],
+            logged(LevelFilter::TRACE, Some(QueryLogLevel::Trace))
+        );
+        assert!(logged(LevelFilter::TRACE, Some(QueryLogLevel::Info)).is_empty());
+        assert!(logged(LevelFilter::TRACE, None).is_empty());
+    }
+
+    #[test]
+    fn query_level_is_capped() {
+        assert_eq!(
+            vec!["query debug"],
+            logged(LevelFilter::DEBUG, Some(QueryLogLevel::Trace))
+        );
+        assert!(logged(LevelFilter::INFO, Some(QueryLogLevel::Debug)).is_empty());
+    }
+
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -1,13 +1,18 @@
+mod callback;
 mod completion;
 mod executor;
+mod logging;
 mod processor;
 mod runner;
+mod slots;
 mod state;
 
+pub use callback::{CompletionCallbacks, CompletionNotifier};
 use completion::Handle as CompletionHandle;
 pub use executor::Result as ProtocolResult;
+pub use logging::QueryLevelFilter;
 pub use processor::{
     NewQueryError, PrepareQueryError, Processor as QueryProcessor, QueryCompletionError,
-    QueryInputError, QueryStatusError,
+    QueryInputError, QueryLimits, QueryStatusError, WarmupError,
 };
-pub use state::QueryStatus;
+pub use state::{QueryStatus, StateError};
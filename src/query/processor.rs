@@ -1,24 +1,36 @@
 use std::{
-    collections::hash_map::Entry,
+    collections::{hash_map::Entry, HashMap},
     fmt::{Debug, Formatter},
+    num::NonZeroUsize,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
-use futures::{future::try_join, stream};
+use futures::{
+    future::{ready, try_join, BoxFuture, Shared},
+    stream, FutureExt,
+};
+#[cfg(all(feature = "shuttle", test))]
+use shuttle::future as tokio;
 
 use crate::{
     error::Error as ProtocolError,
     helpers::{
-        query::{PrepareQuery, QueryConfig, QueryInput},
-        Gateway, GatewayConfig, Role, RoleAssignment, Transport, TransportError, TransportImpl,
+        query::{CallbackUrl, PrepareQuery, QueryConfig, QueryInput, ResultPage},
+        Gateway, GatewayConfig, HelperIdentity, PeerState, Role, RoleAssignment, Transport,
+        TransportError, TransportImpl,
     },
     hpke::{KeyPair, KeyRegistry},
     protocol::QueryId,
     query::{
+        callback::{callback_url, CompletionCallbacks},
         executor,
+        logging::query_span,
+        slots::Slots,
         state::{QueryState, QueryStatus, RemoveQuery, RunningQueries, StateError},
         CompletionHandle, ProtocolResult,
     },
+    sync::Mutex,
 };
 
 /// `Processor` accepts and tracks requests to initiate new queries on this helper party
@@ -41,23 +53,82 @@ use crate::{
 pub struct Processor {
     queries: RunningQueries,
     key_registry: Arc<KeyRegistry<KeyPair>>,
+    limits: QueryLimits,
+    slots: Arc<Slots>,
+    callbacks: Option<CompletionCallbacks>,
+    retained_results: Arc<Mutex<HashMap<QueryId, RetainedResult>>>,
+    result_ttl: Duration,
+}
+
+/// How long [`Processor`] keeps the results of queries fetched in pages, unless configured
+/// otherwise.
+const DEFAULT_RESULT_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Result of a query fetched in pages. The first request for a page hands the query over to a
+/// task that waits for it to complete, and every request shares the result of that task.
+struct RetainedResult {
+    result: Shared<BoxFuture<'static, PagedResult>>,
+    /// Set when the query completes, the result is kept until then.
+    expires_at: Option<Instant>,
 }
 
+/// Serialized result of a query, or the error it failed with.
+type PagedResult = Result<Arc<Vec<u8>>, Arc<ProtocolError>>;
+
 impl Default for Processor {
+    fn default() -> Self {
+        Self::with_limits(KeyRegistry::<KeyPair>::empty(), QueryLimits::default())
+    }
+}
+
+/// Admission control settings for [`Processor`]. At most `max_in_flight` queries coordinated by
+/// this helper are admitted concurrently and up to `max_backlog` more can be accepted and wait
+/// for an execution slot. Slots are given to waiting queries in the order of their
+/// [`QueryPriority`]. Anything submitted beyond that is rejected with `TooManyQueries` error.
+///
+/// Only the coordinator of a query waits for a slot. It sends the prepare request to the other
+/// helpers once the query is admitted, and they run it as soon as its inputs arrive. Helpers
+/// therefore never wait for each other's slots, and all of them run the queries that the
+/// coordinator admitted.
+///
+/// [`QueryPriority`]: crate::helpers::query::QueryPriority
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QueryLimits {
+    pub max_in_flight: NonZeroUsize,
+    pub max_backlog: usize,
+}
+
+impl Default for QueryLimits {
     fn default() -> Self {
         Self {
-            queries: RunningQueries::default(),
-            key_registry: Arc::new(KeyRegistry::<KeyPair>::empty()),
+            max_in_flight: NonZeroUsize::new(4).unwrap(),
+            max_backlog: 16,
         }
     }
 }
 
+impl QueryLimits {
+    /// Total number of queries this helper is willing to keep track of at any given time.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.max_in_flight.get() + self.max_backlog
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum NewQueryError {
     #[error(transparent)]
     State(#[from] StateError),
     #[error(transparent)]
     Transport(#[from] TransportError),
+    #[error("Too many queries: this helper can only accept {0} queries at a time")]
+    TooManyQueries(usize),
+    #[error("Helper {0:?} is unreachable")]
+    PeerUnreachable(HelperIdentity),
+    #[error("Role assignment {0:?} must assign a role to every helper")]
+    InvalidRoleAssignment([HelperIdentity; 3]),
+    #[error("Callback URL {0} is not allowed by this helper")]
+    CallbackNotAllowed(CallbackUrl),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -66,6 +137,10 @@ pub enum PrepareQueryError {
     WrongTarget,
     #[error("Query is already running")]
     AlreadyRunning,
+    #[error("Too many queries: this helper can only accept {0} queries at a time")]
+    TooManyQueries(usize),
+    #[error("Callback URL {0} is not allowed by this helper")]
+    CallbackNotAllowed(CallbackUrl),
     #[error(transparent)]
     StateError {
         #[from]
@@ -101,6 +176,15 @@ pub enum QueryCompletionError {
     },
     #[error("query execution failed: {0}")]
     ExecutionError(#[from] ProtocolError),
+    /// Same as `ExecutionError`, but shared by all requests for the pages of the query result.
+    #[error("query execution failed: {0}")]
+    PagedExecutionError(Arc<ProtocolError>),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum WarmupError {
+    #[error("Helper {0:?} is unreachable")]
+    PeerUnreachable(HelperIdentity),
 }
 
 impl Debug for Processor {
@@ -112,44 +196,146 @@ impl Debug for Processor {
 impl Processor {
     #[must_use]
     pub fn new(key_registry: KeyRegistry<KeyPair>) -> Self {
+        Self::with_limits(key_registry, QueryLimits::default())
+    }
+
+    #[must_use]
+    pub fn with_limits(key_registry: KeyRegistry<KeyPair>, limits: QueryLimits) -> Self {
         Self {
             queries: RunningQueries::default(),
             key_registry: Arc::new(key_registry),
+            limits,
+            slots: Arc::new(Slots::new(limits.max_in_flight.get())),
+            callbacks: None,
+            retained_results: Arc::default(),
+            result_ttl: DEFAULT_RESULT_TTL,
+        }
+    }
+
+    /// Lets clients ask this helper to notify them when their queries complete. Queries that
+    /// request a callback to a host that is not allowed by `callbacks` are rejected. Without
+    /// this, every query that requests a callback is rejected.
+    #[must_use]
+    pub fn with_completion_callbacks(mut self, callbacks: CompletionCallbacks) -> Self {
+        self.callbacks = Some(callbacks);
+        self
+    }
+
+    /// Sets how long the results of queries fetched with [`Self::result_page`] are kept after the
+    /// first page was requested. Clients must fetch all pages within that time.
+    #[must_use]
+    pub fn with_result_ttl(mut self, ttl: Duration) -> Self {
+        self.result_ttl = ttl;
+        self
+    }
+
+    /// Returns the callback URL requested by `config` if this helper is not allowed to call it.
+    fn disallowed_callback(&self, config: &QueryConfig) -> Option<CallbackUrl> {
+        callback_url(config).filter(|url| {
+            !self
+                .callbacks
+                .as_ref()
+                .map_or(false, |callbacks| callbacks.is_allowed(url))
+        })
+    }
+
+    /// Connects to the other helpers, so that the first query after startup does not pay for
+    /// establishing connections. Peer states are refreshed along the way.
+    ///
+    /// PRSS is not set up here. Every query agrees on fresh PRSS keys when it starts, because
+    /// sharing them between queries would correlate their randomness.
+    ///
+    /// ## Errors
+    /// If another helper cannot be reached. Connections to it are then established lazily, when
+    /// it comes up.
+    pub async fn warmup(&self, transport: &TransportImpl) -> Result<(), WarmupError> {
+        transport.connect_peers().await;
+        match transport
+            .identity()
+            .others()
+            .into_iter()
+            .find(|&peer| transport.peer_state(peer) == PeerState::Unreachable)
+        {
+            Some(peer) => Err(WarmupError::PeerUnreachable(peer)),
+            None => Ok(()),
         }
     }
 
     /// Upon receiving a new query request:
     /// * processor generates new query id
     /// * assigns roles to helpers in the ring. Helper that received new query request becomes `Role::H1` (aka coordinator).
-    /// The coordinator is in theory free to choose helpers for `Role::H2` and `Role::H3` arbitrarily (aka followers), however, this is not currently exercised.
+    ///   Use [`Self::new_query_with_role_assignment`] to choose the roles for a query explicitly.
     /// * Requests Infra and Network layer to create resources for this query
+    /// * waits for an execution slot, see [`QueryLimits`]
     /// * sends `prepare` request that describes the query configuration (query id, query type, field type, roles -> endpoints or reverse) to followers and waits for the confirmation
     /// * records newly created query id internally and sets query state to awaiting data
     /// * returns query configuration
     ///
     /// ## Errors
-    /// When other peers failed to acknowledge this query
-    #[allow(clippy::missing_panics_doc)]
+    /// When other peers failed to acknowledge this query or this helper is at capacity.
     pub async fn new_query(
         &self,
         transport: TransportImpl,
         req: QueryConfig,
     ) -> Result<PrepareQuery, NewQueryError> {
-        let query_id = QueryId;
+        let id = transport.identity();
+        let [right, left] = id.others();
+        self.new_query_with_role_assignment(transport, req, [id, right, left])
+            .await
+    }
+
+    /// Same as [`Self::new_query`], but helper `assignment[i]` plays role `Role::all()[i]` in
+    /// this query. This helper still coordinates the query, but it does not have to be `Role::H1`,
+    /// so deployments can rotate the roles that hosts play from one query to the next.
+    ///
+    /// ## Errors
+    /// If `assignment` does not assign a role to every helper, the query requests a callback that
+    /// is not allowed, when other peers failed to acknowledge this query or this helper is at
+    /// capacity.
+    pub async fn new_query_with_role_assignment(
+        &self,
+        transport: TransportImpl,
+        req: QueryConfig,
+        assignment: [HelperIdentity; 3],
+    ) -> Result<PrepareQuery, NewQueryError> {
+        if !HelperIdentity::make_three()
+            .iter()
+            .all(|helper| assignment.contains(helper))
+        {
+            return Err(NewQueryError::InvalidRoleAssignment(assignment));
+        }
+        if let Some(url) = self.disallowed_callback(&req) {
+            return Err(NewQueryError::CallbackNotAllowed(url));
+        }
+
+        let query_id = QueryId::random();
         let handle = self.queries.handle(query_id);
-        handle.set_state(QueryState::Preparing(req))?;
+        if !handle
+            .set_state_within_capacity(QueryState::Preparing(req.clone()), self.limits.capacity())?
+        {
+            return Err(NewQueryError::TooManyQueries(self.limits.capacity()));
+        }
         let guard = handle.remove_query_on_drop();
 
-        let id = transport.identity();
-        let [right, left] = id.others();
+        let [right, left] = transport.identity().others();
+        if let Some(peer) = [right, left]
+            .into_iter()
+            .find(|&peer| transport.peer_state(peer) == PeerState::Unreachable)
+        {
+            return Err(NewQueryError::PeerUnreachable(peer));
+        }
 
-        let roles = RoleAssignment::try_from([(id, Role::H1), (right, Role::H2), (left, Role::H3)])
-            .unwrap();
+        // The query is admitted once it gets a slot. Other helpers learn about it only then, so
+        // they never hold resources for a query that is still waiting.
+        let permit = self.slots.acquire(req.priority).await;
+
+        let roles = RoleAssignment::new(assignment);
 
         let prepare_request = PrepareQuery {
             query_id,
-            config: req,
+            config: req.clone(),
             roles: roles.clone(),
+            coordinator: transport.identity(),
         };
 
         // Inform other parties about new query. If any of them rejects it, this join will fail
@@ -160,40 +346,56 @@ impl Processor {
         .await
         .map_err(NewQueryError::Transport)?;
 
-        handle.set_state(QueryState::AwaitingInputs(query_id, req, roles))?;
+        handle.set_state(QueryState::AwaitingInputs(
+            query_id,
+            req,
+            roles,
+            Some(permit),
+        ))?;
 
         guard.restore();
         Ok(prepare_request)
     }
 
     /// On prepare, each follower:
-    /// * ensures that it is not the leader on this query
+    /// * ensures that it is not the coordinator of this query. The coordinator can play any role,
+    ///   but it is the only helper that has the query in [`QueryStatus::Preparing`] state.
     /// * query is not registered yet
     /// * creates gateway and network
     /// * registers query
     ///
     /// ## Errors
-    /// if query is already running or this helper cannot be a follower in it
+    /// if query is already running, this helper coordinates it or has no role in it, it is at
+    /// capacity or the query requests a callback that is not allowed.
     pub fn prepare(
         &self,
         transport: &TransportImpl,
         req: PrepareQuery,
     ) -> Result<(), PrepareQueryError> {
-        let my_role = req.roles.role(transport.identity());
-
-        if my_role == Role::H1 {
+        let id = transport.identity();
+        if Role::all()
+            .iter()
+            .all(|&role| req.roles.identity(role) != id)
+        {
             return Err(PrepareQueryError::WrongTarget);
         }
         let handle = self.queries.handle(req.query_id);
-        if handle.status().is_some() {
-            return Err(PrepareQueryError::AlreadyRunning);
+        match handle.status() {
+            Some(QueryStatus::Preparing) => return Err(PrepareQueryError::WrongTarget),
+            Some(_) => return Err(PrepareQueryError::AlreadyRunning),
+            None => {}
+        }
+        if let Some(url) = self.disallowed_callback(&req.config) {
+            return Err(PrepareQueryError::CallbackNotAllowed(url));
         }
 
-        handle.set_state(QueryState::AwaitingInputs(
-            req.query_id,
-            req.config,
-            req.roles,
-        ))?;
+        if !handle.set_state_within_capacity(
+            // the coordinator admitted this query, so it can run right away
+            QueryState::AwaitingInputs(req.query_id, req.config, req.roles, None),
+            self.limits.capacity(),
+        )? {
+            return Err(PrepareQueryError::TooManyQueries(self.limits.capacity()));
+        }
 
         Ok(())
     }
@@ -214,26 +416,38 @@ impl Processor {
         match queries.entry(input.query_id) {
             Entry::Occupied(entry) => {
                 let state = entry.remove();
-                if let QueryState::AwaitingInputs(query_id, config, role_assignment) = state {
+                if let QueryState::AwaitingInputs(query_id, config, role_assignment, permit) = state
+                {
                     assert_eq!(
                         input.query_id, query_id,
                         "received inputs for a different query"
                     );
-                    let gateway = Gateway::new(
-                        query_id,
-                        GatewayConfig::from(&config),
-                        role_assignment,
-                        transport,
-                    );
-                    queries.insert(
-                        input.query_id,
-                        QueryState::Running(executor::execute(
-                            config,
+                    let mut gateway_config = GatewayConfig::from(&config);
+                    if let Some(batch_records) = transport.batch_records() {
+                        gateway_config = gateway_config.with_batch_records(batch_records);
+                    }
+                    if let Some(timeout) = config.timeout_seconds {
+                        gateway_config = gateway_config.with_deadline(
+                            Instant::now() + Duration::from_secs(timeout.get().into()),
+                        );
+                    }
+                    let gateway =
+                        Gateway::new(query_id, gateway_config, role_assignment, transport);
+                    let span = query_span(query_id, gateway.role(), &config);
+                    let running = span.in_scope(|| {
+                        let running = executor::execute(
+                            config.clone(),
                             Arc::clone(&self.key_registry),
                             gateway,
                             input.input_stream,
-                        )),
-                    );
+                            permit,
+                        );
+                        match &self.callbacks {
+                            Some(callbacks) => callbacks.on_completion(query_id, &config, running),
+                            None => running,
+                        }
+                    });
+                    queries.insert(input.query_id, QueryState::Running(running));
                     Ok(())
                 } else {
                     let error = StateError::InvalidState {
@@ -256,20 +470,55 @@ impl Processor {
     /// ## Panics
     /// If the query collection mutex is poisoned.
     pub fn query_status(&self, query_id: QueryId) -> Result<QueryStatus, QueryStatusError> {
+        let status = self
+            .queries
+            .inner
+            .lock()
+            .unwrap()
+            .get_mut(&query_id)
+            .map(Self::refresh_status);
+
+        match status {
+            Some(status) => Ok(status),
+            // queries fetched in pages are completed by the task that retains their results
+            None if self
+                .retained_results
+                .lock()
+                .unwrap()
+                .get(&query_id)
+                .map_or(false, |result| result.expires_at.is_none()) =>
+            {
+                Ok(QueryStatus::AwaitingCompletion)
+            }
+            None => Err(QueryStatusError::NoSuchQuery(query_id)),
+        }
+    }
+
+    /// Lists queries tracked by this helper along with their status, in no particular order.
+    /// Queries that are still being set up are not included.
+    ///
+    /// ## Panics
+    /// If the query collection mutex is poisoned.
+    #[must_use]
+    pub fn active_queries(&self) -> Vec<(QueryId, QueryStatus)> {
         let mut queries = self.queries.inner.lock().unwrap();
-        let Some(mut state) = queries.remove(&query_id) else {
-            return Err(QueryStatusError::NoSuchQuery(query_id));
-        };
+        queries
+            .iter_mut()
+            .filter(|(_, state)| !matches!(state, QueryState::Empty))
+            .map(|(&query_id, state)| (query_id, Self::refresh_status(state)))
+            .collect()
+    }
 
-        if let QueryState::Running(ref mut running) = state {
+    /// Moves a running query whose execution has finished to the completed state and returns its
+    /// current status.
+    fn refresh_status(state: &mut QueryState) -> QueryStatus {
+        if let QueryState::Running(running) = state {
             if let Some(result) = running.try_complete() {
-                state = QueryState::Completed(result);
+                *state = QueryState::Completed(result);
             }
         }
 
-        let status = QueryStatus::from(&state);
-        queries.insert(query_id, state);
-        Ok(status)
+        QueryStatus::from(&*state)
     }
 
     /// Awaits the query completion
@@ -308,23 +557,110 @@ impl Processor {
 
         Ok(handle.await?)
     }
+
+    /// Returns a page of the query result. Pages that start past the end of the result are
+    /// empty, so clients can keep fetching until they get a page shorter than they asked for.
+    ///
+    /// The first request drives the query to completion the same way [`Self::complete`] does.
+    /// Unlike [`Self::complete`], the result is kept for the configured TTL, so clients can fetch
+    /// it in any number of requests and resume where they stopped if one of them fails.
+    ///
+    /// ## Errors
+    /// If query is not registered on this helper, its result has expired, or it is being
+    /// completed by [`Self::complete`].
+    ///
+    /// ## Panics
+    /// If failed to obtain an exclusive access to the query collection.
+    pub async fn result_page(&self, page: ResultPage) -> Result<Vec<u8>, QueryCompletionError> {
+        let bytes = self
+            .retained_result(page.query_id)?
+            .await
+            .map_err(QueryCompletionError::PagedExecutionError)?;
+
+        let start = page.offset.min(bytes.len());
+        let end = start.saturating_add(page.limit).min(bytes.len());
+        Ok(bytes[start..end].to_vec())
+    }
+
+    /// Returns the retained result of `query_id`. If there is none, the query is taken over by
+    /// a task that waits for it to complete and retains its result. Expired results of all
+    /// queries are dropped along the way.
+    fn retained_result(
+        &self,
+        query_id: QueryId,
+    ) -> Result<Shared<BoxFuture<'static, PagedResult>>, QueryCompletionError> {
+        let mut retained = self.retained_results.lock().unwrap();
+        let now = Instant::now();
+        retained.retain(|_, result| result.expires_at.map_or(true, |at| at > now));
+        if let Some(result) = retained.get(&query_id) {
+            return Ok(result.result.clone());
+        }
+
+        // Requests for pages of the same query hold the lock on retained results while they
+        // take the query over, so only one of them does.
+        let completion = {
+            let mut queries = self.queries.inner.lock().unwrap();
+            match queries.remove(&query_id) {
+                Some(QueryState::Completed(result)) => ready(result).boxed(),
+                Some(QueryState::Running(handle)) => handle.boxed(),
+                Some(state) => {
+                    let state_error = StateError::InvalidState {
+                        from: QueryStatus::from(&state),
+                        to: QueryStatus::Running,
+                    };
+                    queries.insert(query_id, state);
+                    return Err(QueryCompletionError::StateError {
+                        source: state_error,
+                    });
+                }
+                None => return Err(QueryCompletionError::NoSuchQuery(query_id)),
+            }
+        };
+
+        let retained_results = Arc::clone(&self.retained_results);
+        let ttl = self.result_ttl;
+        let result = async move {
+            let result = completion
+                .await
+                .map(|result| Arc::new(result.into_bytes()))
+                .map_err(Arc::new);
+            if let Some(retained) = retained_results.lock().unwrap().get_mut(&query_id) {
+                retained.expires_at = Some(Instant::now() + ttl);
+            }
+            result
+        }
+        .boxed()
+        .shared();
+        retained.insert(
+            query_id,
+            RetainedResult {
+                result: result.clone(),
+                expires_at: None,
+            },
+        );
+        // The task completes the query even if the request that started it goes away.
+        tokio::spawn(result.clone());
+
+        Ok(result)
+    }
 }
 
 #[cfg(all(test, unit_test))]
 mod tests {
-    use std::{array, future::Future, sync::Arc};
+    use std::{array, future::Future, num::NonZeroU32, sync::Arc};
 
-    use futures::pin_mut;
+    use futures::{future::join_all, pin_mut};
     use futures_util::future::poll_immediate;
-    use tokio::sync::Barrier;
+    use tokio::sync::{oneshot, Barrier};
 
     use super::*;
     use crate::{
-        ff::FieldType,
+        ff::{Field, FieldType, Fp31},
         helpers::{
-            query::{QueryType, QueryType::TestMultiply},
+            query::{QueryPriority, QueryType, QueryType::TestMultiply},
             HelperIdentity, InMemoryNetwork, PrepareQueryCallback, TransportCallbacks,
         },
+        query::state::RunningQuery,
     };
 
     fn prepare_query_callback<T, F, Fut>(cb: F) -> Box<dyn PrepareQueryCallback<T>>
@@ -342,40 +678,36 @@ mod tests {
     #[tokio::test]
     async fn new_query() {
         let barrier = Arc::new(Barrier::new(3));
-        let cb2_barrier = Arc::clone(&barrier);
-        let cb3_barrier = Arc::clone(&barrier);
-        let cb2 = TransportCallbacks {
-            prepare_query: prepare_query_callback(move |_, _| {
-                let barrier = Arc::clone(&cb2_barrier);
-                async move {
-                    barrier.wait().await;
-                    Ok(())
-                }
-            }),
-            ..Default::default()
-        };
-        let cb3 = TransportCallbacks {
-            prepare_query: prepare_query_callback(move |_, _| {
-                let barrier = Arc::clone(&cb3_barrier);
-                async move {
-                    barrier.wait().await;
-                    Ok(())
-                }
-            }),
-            ..Default::default()
-        };
+        let prepared = Arc::new(Mutex::new(Vec::new()));
+        let [cb2, cb3] = array::from_fn(|_| {
+            let barrier = Arc::clone(&barrier);
+            let prepared = Arc::clone(&prepared);
+            TransportCallbacks {
+                prepare_query: prepare_query_callback(move |_, req| {
+                    let barrier = Arc::clone(&barrier);
+                    prepared.lock().unwrap().push(req.query_id);
+                    async move {
+                        barrier.wait().await;
+                        Ok(())
+                    }
+                }),
+                ..Default::default()
+            }
+        });
         let network = InMemoryNetwork::new([TransportCallbacks::default(), cb2, cb3]);
         let [t0, _, _] = network.transports();
         let p0 = Processor::default();
         let request = test_multiply_config();
 
-        let qc_future = p0.new_query(t0, request);
+        let qc_future = p0.new_query(t0, request.clone());
         pin_mut!(qc_future);
 
         // poll future once to trigger query status change
         let _qc = poll_immediate(&mut qc_future).await;
 
-        assert_eq!(QueryStatus::Preparing, p0.query_status(QueryId).unwrap());
+        let [(query_id, QueryStatus::Preparing)] = p0.active_queries()[..] else {
+            panic!("expected one query in preparing state");
+        };
         // unblock sends
         barrier.wait().await;
 
@@ -384,20 +716,23 @@ mod tests {
 
         assert_eq!(
             PrepareQuery {
-                query_id: QueryId,
+                query_id,
                 config: request,
                 roles: expected_assignment,
+                coordinator: HelperIdentity::ONE,
             },
             qc
         );
+        // followers are told the id picked by the coordinator
+        assert_eq!(vec![query_id; 2], *prepared.lock().unwrap());
         assert_eq!(
             QueryStatus::AwaitingInputs,
-            p0.query_status(QueryId).unwrap()
+            p0.query_status(query_id).unwrap()
         );
     }
 
     #[tokio::test]
-    async fn rejects_duplicate_query_id() {
+    async fn new_query_with_role_assignment() {
         let cb = array::from_fn(|_| TransportCallbacks {
             prepare_query: prepare_query_callback(|_, _| async { Ok(()) }),
             ..Default::default()
@@ -406,15 +741,167 @@ mod tests {
         let [t0, _, _] = network.transports();
         let p0 = Processor::default();
         let request = test_multiply_config();
+        let [one, two, three] = HelperIdentity::make_three();
+
+        assert!(matches!(
+            p0.new_query_with_role_assignment(t0.clone_ref(), request.clone(), [one, two, two])
+                .await,
+            Err(NewQueryError::InvalidRoleAssignment(_))
+        ));
 
-        let _qc = p0
-            .new_query(Transport::clone_ref(&t0), request)
+        let qc = p0
+            .new_query_with_role_assignment(t0, request, [three, one, two])
             .await
             .unwrap();
+        assert_eq!(Role::H2, qc.roles.role(one));
+        assert_eq!(
+            QueryStatus::AwaitingInputs,
+            p0.query_status(qc.query_id).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn new_queries_get_distinct_ids() {
+        let cb = array::from_fn(|_| TransportCallbacks {
+            prepare_query: prepare_query_callback(|_, _| async { Ok(()) }),
+            ..Default::default()
+        });
+        let network = InMemoryNetwork::new(cb);
+        let [t0, _, _] = network.transports();
+        let p0 = Processor::default();
+        let request = test_multiply_config();
+
+        let first = p0
+            .new_query(Transport::clone_ref(&t0), request.clone())
+            .await
+            .unwrap();
+        let second = p0.new_query(t0, request).await.unwrap();
+        assert_ne!(first.query_id, second.query_id);
+        for query_id in [first.query_id, second.query_id] {
+            assert_eq!(
+                QueryStatus::AwaitingInputs,
+                p0.query_status(query_id).unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_queries_over_capacity() {
+        let cb = array::from_fn(|_| TransportCallbacks {
+            prepare_query: prepare_query_callback(|_, _| async { Ok(()) }),
+            ..Default::default()
+        });
+        let network = InMemoryNetwork::new(cb);
+        let [t0, t1, _] = network.transports();
+        let p0 = Processor::with_limits(
+            KeyRegistry::empty(),
+            QueryLimits {
+                max_in_flight: NonZeroUsize::new(2).unwrap(),
+                max_backlog: 0,
+            },
+        );
+        let request = test_multiply_config();
+
+        // more queries than the helper has room for arrive at the same time
+        let results =
+            join_all((0..5).map(|_| p0.new_query(Transport::clone_ref(&t0), request.clone())))
+                .await;
+        let accepted = results
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .map(|qc| qc.query_id)
+            .collect::<Vec<_>>();
+        assert_eq!(2, accepted.len());
+        assert!(results
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .all(|e| matches!(e, NewQueryError::TooManyQueries(2))));
+
+        // queries coordinated by other helpers count towards the same limit
         assert!(matches!(
-            p0.new_query(t0, request).await,
-            Err(NewQueryError::State(StateError::AlreadyRunning)),
+            p0.prepare(
+                &t1,
+                PrepareQuery {
+                    query_id: QueryId::random(),
+                    config: request,
+                    roles: RoleAssignment::new(HelperIdentity::make_three()),
+                    coordinator: HelperIdentity::TWO,
+                },
+            ),
+            Err(PrepareQueryError::TooManyQueries(2)),
         ));
+
+        // the accepted queries are not affected by rejected ones
+        for query_id in accepted {
+            assert_eq!(
+                QueryStatus::AwaitingInputs,
+                p0.query_status(query_id).unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn coordinator_admits_queries_by_priority() {
+        let prepared = Arc::new(Mutex::new(Vec::new()));
+        let cb = array::from_fn(|_| {
+            let prepared = Arc::clone(&prepared);
+            TransportCallbacks {
+                prepare_query: prepare_query_callback(move |_, req| {
+                    prepared.lock().unwrap().push(req.query_id);
+                    async { Ok(()) }
+                }),
+                ..Default::default()
+            }
+        });
+        let network = InMemoryNetwork::new(cb);
+        let [t0, _, _] = network.transports();
+        let p0 = Processor::with_limits(
+            KeyRegistry::empty(),
+            QueryLimits {
+                max_in_flight: NonZeroUsize::new(1).unwrap(),
+                max_backlog: 2,
+            },
+        );
+        let request = |priority| test_multiply_config().with_priority(priority);
+        // Admitted queries hold their slot until they finish or are dropped.
+        let finish = |query_id| {
+            p0.queries.inner.lock().unwrap().remove(&query_id);
+        };
+
+        let running = p0
+            .new_query(Transport::clone_ref(&t0), request(QueryPriority::Normal))
+            .await
+            .unwrap();
+
+        let low = p0.new_query(Transport::clone_ref(&t0), request(QueryPriority::Low));
+        let high = p0.new_query(Transport::clone_ref(&t0), request(QueryPriority::High));
+        pin_mut!(low, high);
+        assert!(poll_immediate(&mut low).await.is_none());
+        assert!(poll_immediate(&mut high).await.is_none());
+        // other helpers do not hear about queries that wait for a slot
+        assert_eq!(vec![running.query_id; 2], *prepared.lock().unwrap());
+
+        finish(running.query_id);
+        let high = high.await.unwrap();
+        assert!(poll_immediate(&mut low).await.is_none());
+        assert_eq!(
+            QueryStatus::AwaitingInputs,
+            p0.query_status(high.query_id).unwrap()
+        );
+
+        finish(high.query_id);
+        let low = low.await.unwrap();
+        assert_eq!(
+            vec![
+                running.query_id,
+                running.query_id,
+                high.query_id,
+                high.query_id,
+                low.query_id,
+                low.query_id
+            ],
+            *prepared.lock().unwrap()
+        );
     }
 
     #[tokio::test]
@@ -456,7 +943,9 @@ mod tests {
         let [t0, _, _] = network.transports();
         let p0 = Processor::default();
         let request = test_multiply_config();
-        p0.new_query(t0.clone_ref(), request).await.unwrap_err();
+        p0.new_query(t0.clone_ref(), request.clone())
+            .await
+            .unwrap_err();
 
         assert!(matches!(
             p0.new_query(t0, request).await.unwrap_err(),
@@ -464,14 +953,121 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn complete_reports_timeout() {
+        let cb = array::from_fn(|_| TransportCallbacks {
+            prepare_query: prepare_query_callback(|_, _| async { Ok(()) }),
+            ..Default::default()
+        });
+        let network = InMemoryNetwork::new(cb);
+        let [t0, _, _] = network.transports();
+        let processor = Processor::default();
+        let request = test_multiply_config().with_timeout_seconds(NonZeroU32::new(1).unwrap());
+        let query_id = processor
+            .new_query(t0.clone_ref(), request)
+            .await
+            .unwrap()
+            .query_id;
+
+        // only this helper gets its inputs, so it waits for the others until the deadline
+        processor
+            .receive_inputs(
+                t0,
+                QueryInput {
+                    query_id,
+                    input_stream: vec![0_u8; 4].into(),
+                },
+            )
+            .unwrap();
+
+        let err = processor.complete(query_id).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                QueryCompletionError::ExecutionError(ProtocolError::Timeout { .. })
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn result_pages_share_completion() {
+        let processor = Processor::default();
+        let query_id = QueryId::default();
+        let (tx, rx) = oneshot::channel();
+        processor.queries.inner.lock().unwrap().insert(
+            query_id,
+            QueryState::Running(RunningQuery {
+                result: rx,
+                join_handle: tokio::spawn(async {}),
+            }),
+        );
+        let page = |offset| {
+            processor.result_page(ResultPage {
+                query_id,
+                offset,
+                limit: 2,
+            })
+        };
+
+        // both requests arrive before the query completes
+        let first = page(0);
+        let second = page(2);
+        pin_mut!(first, second);
+        assert!(poll_immediate(&mut first).await.is_none());
+        assert!(poll_immediate(&mut second).await.is_none());
+        assert_eq!(
+            QueryStatus::AwaitingCompletion,
+            processor.query_status(query_id).unwrap()
+        );
+
+        let result = [1_u128, 2, 3].map(Fp31::truncate_from).to_vec();
+        tx.send(Ok(Box::new(result))).unwrap();
+        assert_eq!(vec![1, 2], first.await.unwrap());
+        assert_eq!(vec![3], second.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn lists_active_queries() {
+        let cb = array::from_fn(|_| TransportCallbacks {
+            prepare_query: prepare_query_callback(|_, _| async { Ok(()) }),
+            ..Default::default()
+        });
+        let network = InMemoryNetwork::new(cb);
+        let [t0, _, _] = network.transports();
+        let processor = Processor::default();
+        assert!(processor.active_queries().is_empty());
+
+        let qc = processor
+            .new_query(t0, test_multiply_config())
+            .await
+            .unwrap();
+        let other_query = QueryId::from([1; 16]);
+        processor.queries.inner.lock().unwrap().insert(
+            other_query,
+            QueryState::Completed(Err(ProtocolError::timeout("receive records"))),
+        );
+
+        let mut active = processor.active_queries();
+        active.sort_by_key(|(query_id, _)| *query_id != qc.query_id);
+        assert_eq!(
+            vec![
+                (qc.query_id, QueryStatus::AwaitingInputs),
+                (other_query, QueryStatus::Completed),
+            ],
+            active
+        );
+    }
+
     mod prepare {
         use super::*;
 
         fn prepare_query(identities: [HelperIdentity; 3]) -> PrepareQuery {
             PrepareQuery {
-                query_id: QueryId,
+                query_id: QueryId::default(),
                 config: test_multiply_config(),
                 roles: RoleAssignment::new(identities),
+                coordinator: identities[0],
             }
         }
 
@@ -484,13 +1080,29 @@ mod tests {
             let processor = Processor::default();
 
             assert!(matches!(
-                processor.query_status(QueryId).unwrap_err(),
+                processor.query_status(QueryId::default()).unwrap_err(),
                 QueryStatusError::NoSuchQuery(_)
             ));
             processor.prepare(&transport, req).unwrap();
             assert_eq!(
                 QueryStatus::AwaitingInputs,
-                processor.query_status(QueryId).unwrap()
+                processor.query_status(QueryId::default()).unwrap()
+            );
+        }
+
+        #[tokio::test]
+        async fn accepts_any_role() {
+            let network = InMemoryNetwork::default();
+            let [one, two, three] = HelperIdentity::make_three();
+            // the coordinator plays `H2`, so this follower plays `H1`
+            let req = prepare_query([three, one, two]);
+            let transport = network.transport(three);
+            let processor = Processor::default();
+
+            processor.prepare(&transport, req).unwrap();
+            assert_eq!(
+                QueryStatus::AwaitingInputs,
+                processor.query_status(QueryId::default()).unwrap()
             );
         }
 
@@ -501,6 +1113,11 @@ mod tests {
             let req = prepare_query(identities);
             let transport = network.transport(identities[0]);
             let processor = Processor::default();
+            processor
+                .queries
+                .handle(req.query_id)
+                .set_state(QueryState::Preparing(req.config.clone()))
+                .unwrap();
 
             assert!(matches!(
                 processor.prepare(&transport, req),
@@ -527,14 +1144,21 @@ mod tests {
         use std::time::Duration;
 
         use tokio::time::sleep;
+        use tracing::metadata::LevelFilter;
+        use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt};
 
         use super::*;
         use crate::{
+            app::Error as AppError,
             error::BoxError,
             ff::{Field, Fp31},
-            helpers::query::IpaQueryConfig,
+            helpers::{
+                query::{IpaQueryConfig, QueryLogLevel, QueryPriority},
+                WireCompression,
+            },
             ipa_test_input,
             protocol::{ipa::IPAInputRow, BreakdownKey, MatchKey},
+            query::QueryLevelFilter,
             secret_sharing::replicated::semi_honest,
             test_fixture::{input::GenericReportTestInput, Reconstruct, TestApp},
         };
@@ -558,6 +1182,88 @@ mod tests {
             ))
         }
 
+        /// Collects everything written by the tracing subscriber.
+        #[derive(Clone, Default)]
+        struct CapturedLogs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for CapturedLogs {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl<'a> MakeWriter<'a> for CapturedLogs {
+            type Writer = Self;
+
+            fn make_writer(&'a self) -> Self::Writer {
+                self.clone()
+            }
+        }
+
+        #[tokio::test]
+        async fn query_span_has_id_and_role() -> Result<(), BoxError> {
+            let logs = CapturedLogs::default();
+            let subscriber = tracing_subscriber::fmt()
+                .with_writer(logs.clone())
+                .with_ansi(false)
+                .finish();
+            let _guard = tracing::subscriber::set_default(subscriber);
+
+            let app = TestApp::default();
+            let a = Fp31::truncate_from(4u128);
+            let b = Fp31::truncate_from(5u128);
+            let query_id = app
+                .start_query(vec![a, b].into_iter(), test_multiply_config())
+                .await?;
+            app.complete_query(query_id).await?;
+
+            let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+            for role in Role::all() {
+                let span = format!("query{{id={query_id} role={role:?}}}");
+                assert!(logs.contains(&span), "{span} is missing from logs:\n{logs}");
+            }
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn query_log_level_is_recorded() -> Result<(), BoxError> {
+            let logs = CapturedLogs::default();
+            let subscriber = tracing_subscriber::registry()
+                .with(QueryLevelFilter::new(LevelFilter::INFO, LevelFilter::DEBUG))
+                .with(
+                    tracing_subscriber::fmt::layer()
+                        .with_writer(logs.clone())
+                        .with_ansi(false),
+                );
+            let _guard = tracing::subscriber::set_default(subscriber);
+
+            let mut config = ipa_config(None);
+            if let QueryType::SemiHonestIpa(ipa) = &mut config.query_type {
+                ipa.log_level = Some(QueryLogLevel::Debug);
+            }
+            TestApp::default()
+                .execute_query::<_, Vec<IPAInputRow<_, _, _>>>(ipa_records().into_iter(), config)
+                .await?;
+
+            let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+            let started = logs
+                .lines()
+                .filter(|line| line.contains("starting query execution"))
+                .collect::<Vec<_>>();
+            assert_eq!(3, started.len(), "{logs}");
+            for line in started {
+                assert!(line.contains("log_level=DEBUG"), "{line}");
+            }
+
+            Ok(())
+        }
+
         #[tokio::test]
         async fn complete_query_status_poll() -> Result<(), BoxError> {
             let app = TestApp::default();
@@ -591,6 +1297,12 @@ mod tests {
             ipa_query(&app).await
         }
 
+        #[tokio::test]
+        async fn complete_query_ipa_framed() -> Result<(), BoxError> {
+            let app = TestApp::framed();
+            ipa_query(&app).await
+        }
+
         #[tokio::test]
         async fn complete_query_twice() -> Result<(), BoxError> {
             let app = TestApp::default();
@@ -598,8 +1310,179 @@ mod tests {
             ipa_query(&app).await
         }
 
+        #[tokio::test]
+        async fn complete_query_ipa_with_rotated_roles() -> Result<(), BoxError> {
+            let app = TestApp::default();
+            let [one, two, three] = HelperIdentity::make_three();
+            let default_roles = ipa_query_with_role_assignment(&app, [one, two, three]).await?;
+            let rotated_roles = ipa_query_with_role_assignment(&app, [three, one, two]).await?;
+            assert_eq!(default_roles, rotated_roles);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn result_pages_concatenate_to_histogram() -> Result<(), BoxError> {
+            const PAGE_SIZE: usize = 4;
+
+            let app = TestApp::default();
+            let query_id = app
+                .start_query::<_, Vec<IPAInputRow<_, _, _>>>(
+                    ipa_records().into_iter(),
+                    ipa_config(None),
+                )
+                .await?;
+
+            let mut results = [Vec::new(), Vec::new(), Vec::new()];
+            let mut pages = 0;
+            loop {
+                let page = app
+                    .result_page(query_id, results[0].len(), PAGE_SIZE)
+                    .await?;
+                pages += 1;
+                let last = page[0].len() < PAGE_SIZE;
+                for (result, page) in results.iter_mut().zip(page) {
+                    result.extend(page);
+                }
+                if last {
+                    break;
+                }
+            }
+            assert!(pages > 1, "result should span several pages");
+
+            let histogram = results
+                .clone()
+                .map(|bytes| {
+                    semi_honest::AdditiveShare::<Fp31>::from_byte_slice(&bytes).collect::<Vec<_>>()
+                })
+                .reconstruct();
+            assert_eq!([0_u128, 2, 3].map(Fp31::truncate_from).to_vec(), histogram);
+
+            // pages can be fetched again, e.g. to resume a failed download
+            let page = app.result_page(query_id, 0, usize::MAX).await?;
+            assert_eq!(results, page);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn result_pages_expire() {
+            let processor = Processor::default();
+            let query_id = QueryId::default();
+            let retain = |expires_at| {
+                let result: PagedResult = Ok(Arc::new(vec![1, 2, 3]));
+                processor.retained_results.lock().unwrap().insert(
+                    query_id,
+                    RetainedResult {
+                        result: ready(result).boxed().shared(),
+                        expires_at: Some(expires_at),
+                    },
+                );
+            };
+            let page = ResultPage {
+                query_id,
+                offset: 1,
+                limit: 5,
+            };
+
+            retain(Instant::now() + Duration::from_secs(60));
+            assert_eq!(vec![2, 3], processor.result_page(page).await.unwrap());
+
+            retain(Instant::now());
+            assert!(matches!(
+                processor.result_page(page).await,
+                Err(QueryCompletionError::NoSuchQuery(_))
+            ));
+        }
+
+        #[cfg(feature = "web-app")]
+        #[tokio::test]
+        async fn completion_callback() -> Result<(), BoxError> {
+            use axum::{routing::post, Router, Server};
+            use tokio::sync::mpsc;
+
+            use crate::{net::HttpCompletionNotifier, query::CompletionCallbacks};
+
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let router = Router::new().route(
+                "/done",
+                post(move |body: String| async move { tx.send(body).unwrap() }),
+            );
+            let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+            let addr = listener.local_addr()?;
+            tokio::spawn(Server::from_tcp(listener)?.serve(router.into_make_service()));
+
+            let callbacks = CompletionCallbacks::new(
+                ["127.0.0.1".to_owned()],
+                Arc::new(HttpCompletionNotifier::default()),
+            );
+            let app = TestApp::with_completion_callbacks(&callbacks);
+            let url = format!("http://{addr}/done").parse::<CallbackUrl>()?;
+            ipa_query_with_callback(&app, HelperIdentity::make_three(), Some(url)).await?;
+
+            // every helper holds a share of the results, so each of them calls back
+            let mut query_ids = Vec::new();
+            for _ in 0..3 {
+                let body: serde_json::Value = serde_json::from_str(&rx.recv().await.unwrap())?;
+                query_ids.push(serde_json::from_value::<QueryId>(body["query_id"].clone())?);
+            }
+            assert!(query_ids.iter().all(|&query_id| query_id == query_ids[0]));
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn callback_host_not_allowed() {
+            let app = TestApp::default();
+            let url = "http://169.254.169.254/latest".parse().unwrap();
+            let err = ipa_query_with_callback(&app, HelperIdentity::make_three(), Some(url))
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<AppError>(),
+                Some(AppError::NewQuery(NewQueryError::CallbackNotAllowed(_)))
+            ));
+        }
+
         async fn ipa_query(app: &TestApp) -> Result<(), BoxError> {
-            let records: Vec<GenericReportTestInput<Fp31, MatchKey, BreakdownKey>> = ipa_test_input!(
+            ipa_query_with_role_assignment(app, HelperIdentity::make_three()).await?;
+            Ok(())
+        }
+
+        async fn ipa_query_with_role_assignment(
+            app: &TestApp,
+            assignment: [HelperIdentity; 3],
+        ) -> Result<Vec<Fp31>, BoxError> {
+            ipa_query_with_callback(app, assignment, None).await
+        }
+
+        async fn ipa_query_with_callback(
+            app: &TestApp,
+            assignment: [HelperIdentity; 3],
+            callback_url: Option<CallbackUrl>,
+        ) -> Result<Vec<Fp31>, BoxError> {
+            // rows are serialized into a byte stream and deserialized by the helpers as they
+            // arrive, so this exercises the same ingest path as an HTTP upload
+            let results = app
+                .execute_query_with_role_assignment::<_, Vec<IPAInputRow<_, _, _>>>(
+                    ipa_records().into_iter(),
+                    ipa_config(callback_url),
+                    assignment,
+                )
+                .await?;
+
+            let results = results
+                .map(|bytes| {
+                    semi_honest::AdditiveShare::<Fp31>::from_byte_slice(&bytes).collect::<Vec<_>>()
+                })
+                .reconstruct();
+            assert_eq!([0_u128, 2, 3].map(Fp31::truncate_from).to_vec(), results);
+
+            Ok(results)
+        }
+
+        fn ipa_records() -> Vec<GenericReportTestInput<Fp31, MatchKey, BreakdownKey>> {
+            ipa_test_input!(
                 [
                     { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
                     { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
@@ -608,27 +1491,35 @@ mod tests {
                     { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
                 ];
                 (Fp31, MatchKey, BreakdownKey)
-            );
-            let record_count = records.len();
-
-            let _results = app
-                .execute_query::<_, Vec<IPAInputRow<_, _, _>>>(
-                    records.into_iter(),
-                    QueryConfig {
-                        size: record_count.try_into().unwrap(),
-                        field_type: FieldType::Fp31,
-                        query_type: QueryType::SemiHonestIpa(IpaQueryConfig {
-                            per_user_credit_cap: 3,
-                            max_breakdown_key: 3,
-                            attribution_window_seconds: None,
-                            num_multi_bits: 3,
-                            plaintext_match_keys: true,
-                        }),
-                    },
-                )
-                .await?;
+            )
+        }
 
-            Ok(())
+        fn ipa_config(callback_url: Option<CallbackUrl>) -> QueryConfig {
+            QueryConfig {
+                size: ipa_records().len().try_into().unwrap(),
+                field_type: FieldType::Fp31,
+                query_type: QueryType::SemiHonestIpa(IpaQueryConfig {
+                    per_user_credit_cap: 3,
+                    max_breakdown_key: 3,
+                    attribution_window_seconds: None,
+                    num_multi_bits: 3,
+                    plaintext_match_keys: true,
+                    clamp_breakdown_keys: false,
+                    detect_max_breakdown_key: false,
+                    dedup: false,
+                    drop_zero_match_keys: false,
+                    signed_trigger_values: false,
+                    max_trigger_value: None,
+                    check_histogram_total: false,
+                    first_row_continues_previous: false,
+                    max_input_rows: None,
+                    callback_url,
+                    log_level: None,
+                }),
+                priority: QueryPriority::default(),
+                wire_compression: WireCompression::None,
+                timeout_seconds: None,
+            }
         }
     }
 }
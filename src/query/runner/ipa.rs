@@ -1,6 +1,7 @@
-use std::marker::PhantomData;
+use std::{io, marker::PhantomData, num::NonZeroU32};
 
 use futures::{
+    future::ready,
     stream::{iter, repeat},
     Stream, StreamExt, TryStreamExt,
 };
@@ -87,22 +88,29 @@ where
         } = self;
         tracing::info!("New query: {config:?}");
         let sz = usize::from(query_size);
+        if let Some(limit) = config.max_input_rows {
+            let limit = usize::try_from(limit.get()).unwrap();
+            if sz > limit {
+                return Err(Error::InputTooLarge { rows: sz, limit });
+            }
+        }
 
         let input = if config.plaintext_match_keys {
-            let mut v = assert_stream_send(RecordsStream::<
-                IPAInputRow<F, MatchKey, BreakdownKey>,
-                _,
-            >::new(input_stream))
+            let mut v = assert_stream_send(limit_rows(
+                RecordsStream::<IPAInputRow<F, MatchKey, BreakdownKey>, _>::new(input_stream),
+                config.max_input_rows,
+            ))
             .try_concat()
             .await?;
             v.truncate(sz);
             v
         } else {
-            assert_stream_send(LengthDelimitedStream::<
-                EncryptedReport<F, MatchKey, BreakdownKey, _>,
-                _,
-            >::new(input_stream))
-            .map_err(Into::<Error>::into)
+            assert_stream_send(limit_rows(
+                LengthDelimitedStream::<EncryptedReport<F, MatchKey, BreakdownKey, _>, _>::new(
+                    input_stream,
+                ),
+                config.max_input_rows,
+            ))
             .map_ok(|enc_reports| {
                 iter(enc_reports.into_iter().map(|enc_report| {
                     enc_report
@@ -147,6 +155,26 @@ where
     }
 }
 
+/// Fails with [`Error::InputTooLarge`] as soon as more than `limit` rows have arrived on `input`,
+/// so that the rest of an oversized input is never read. Rows arrive in chunks, so the reported
+/// number of rows may be larger than `limit + 1`.
+fn limit_rows<T: Send>(
+    input: impl Stream<Item = Result<Vec<T>, io::Error>> + Send,
+    limit: Option<NonZeroU32>,
+) -> impl Stream<Item = Result<Vec<T>, Error>> + Send {
+    let limit = limit.map(|limit| usize::try_from(limit.get()).unwrap());
+    input.scan(0, move |rows, chunk| {
+        let chunk = chunk.map_err(Error::from).and_then(|chunk| {
+            *rows += chunk.len();
+            match limit {
+                Some(limit) if *rows > limit => Err(Error::InputTooLarge { rows: *rows, limit }),
+                _ => Ok(chunk),
+            }
+        });
+        ready(Some(chunk))
+    })
+}
+
 /// Helps to convince the compiler that things are `Send`. Like `seq_join::assert_send`, but for
 /// streams.
 ///
@@ -162,6 +190,7 @@ pub fn assert_stream_send<'a, T>(
 mod tests {
     use std::iter::zip;
 
+    use futures::future::join_all;
     use generic_array::GenericArray;
     use rand::rngs::StdRng;
     use rand_core::SeedableRng;
@@ -225,6 +254,17 @@ mod tests {
                 attribution_window_seconds: None,
                 max_breakdown_key: 3,
                 plaintext_match_keys: true,
+                clamp_breakdown_keys: false,
+                detect_max_breakdown_key: false,
+                dedup: false,
+                drop_zero_match_keys: false,
+                signed_trigger_values: false,
+                max_trigger_value: None,
+                check_histogram_total: false,
+                first_row_continues_previous: false,
+                max_input_rows: None,
+                callback_url: None,
+                log_level: None,
             };
             let input = BodyStream::from(shares);
             // Note that we ignore the last 2 records to test that runner follows the rule
@@ -237,6 +277,64 @@ mod tests {
         assert_eq!(results.reconstruct(), EXPECTED);
     }
 
+    #[tokio::test]
+    async fn rejects_input_over_limit() {
+        let records: Vec<GenericReportTestInput<Fp31, MatchKey, BreakdownKey>> = ipa_test_input!(
+            [
+                { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+                { timestamp: 0, match_key: 68362, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                { timestamp: 0, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 5 },
+                { timestamp: 0, match_key: 68362, is_trigger_report: 1, breakdown_key: 0, trigger_value: 2 },
+            ];
+            (Fp31, MatchKey, BreakdownKey)
+        );
+        let shares = records.into_iter().share().map(
+            |shares: Vec<IPAInputRow<Fp31, MatchKey, BreakdownKey>>| {
+                shares
+                    .into_iter()
+                    .flat_map(|share| {
+                        let mut buf = [0u8; <IPAInputRow<
+                            Fp31,
+                            MatchKey,
+                            BreakdownKey,
+                        > as Serializable>::Size::USIZE];
+                        share.serialize(GenericArray::from_mut_slice(&mut buf));
+
+                        buf
+                    })
+                    .collect::<Vec<_>>()
+            },
+        );
+
+        // A query that declares more rows than the limit is rejected before reading its input.
+        // A query that declares few enough rows is rejected once the extra rows arrive.
+        for query_size in [5_u32, 3] {
+            let world = TestWorld::default();
+            #[allow(clippy::large_futures)]
+            let results = join_all(zip(shares.clone(), world.contexts()).map(|(shares, ctx)| {
+                let query_config = IpaQueryConfig {
+                    plaintext_match_keys: true,
+                    max_input_rows: NonZeroU32::new(3),
+                    ..IpaQueryConfig::no_window(3, 3, 3)
+                };
+                IpaQuery::<Fp31, _, _>::new(query_config, Arc::new(KeyRegistry::empty())).execute(
+                    ctx,
+                    QuerySize::try_from(query_size).unwrap(),
+                    BodyStream::from(shares),
+                )
+            }))
+            .await;
+
+            for result in results {
+                assert!(matches!(
+                    result,
+                    Err(Error::InputTooLarge { rows: 5, limit: 3 })
+                ));
+            }
+        }
+    }
+
     #[tokio::test]
     async fn malicious_ipa() {
         const EXPECTED: &[u128] = &[0, 2, 3];
@@ -284,6 +382,17 @@ mod tests {
                 attribution_window_seconds: None,
                 max_breakdown_key: 3,
                 plaintext_match_keys: true,
+                clamp_breakdown_keys: false,
+                detect_max_breakdown_key: false,
+                dedup: false,
+                drop_zero_match_keys: false,
+                signed_trigger_values: false,
+                max_trigger_value: None,
+                check_histogram_total: false,
+                first_row_continues_previous: false,
+                max_input_rows: None,
+                callback_url: None,
+                log_level: None,
             };
             IpaQuery::<Fp31, _, _>::new(query_config, Arc::new(KeyRegistry::empty())).execute(
                 ctx,
@@ -340,6 +449,17 @@ mod tests {
                 attribution_window_seconds: None,
                 max_breakdown_key: 3,
                 plaintext_match_keys: false,
+                clamp_breakdown_keys: false,
+                detect_max_breakdown_key: false,
+                dedup: false,
+                drop_zero_match_keys: false,
+                signed_trigger_values: false,
+                max_trigger_value: None,
+                check_histogram_total: false,
+                first_row_continues_previous: false,
+                max_input_rows: None,
+                callback_url: None,
+                log_level: None,
             };
             let input = BodyStream::from(buffer);
             IpaQuery::<Fp31, _, _>::new(query_config, Arc::clone(&key_registry))
@@ -0,0 +1,193 @@
+use std::time::{Duration, Instant};
+
+use ::tokio::sync::oneshot;
+
+use crate::{
+    helpers::query::QueryPriority,
+    sync::{Arc, Mutex},
+};
+
+/// Waiting queries move up by one priority level every time they wait this long.
+const AGING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Execution slots shared by all queries on this helper. At most as many queries as there are
+/// slots run concurrently, the others wait in line until a slot frees up.
+///
+/// Unlike a semaphore, the next free slot goes to the waiting query with the highest priority,
+/// and queries of the same priority are served in the order they arrived. To prevent a steady
+/// flow of high priority queries from starving the others, waiting queries are aged: every
+/// [`AGING_INTERVAL`] spent in line counts as one priority level.
+#[derive(Debug)]
+pub struct Slots {
+    inner: Mutex<SlotsInner>,
+}
+
+#[derive(Debug)]
+struct SlotsInner {
+    available: usize,
+    waiting: Vec<Waiter>,
+    next_seq: u64,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    priority: QueryPriority,
+    since: Instant,
+    seq: u64,
+    tx: oneshot::Sender<SlotPermit>,
+}
+
+impl Waiter {
+    /// Priority level of this waiter, including the levels it gained while waiting.
+    fn effective_priority(&self, now: Instant) -> u128 {
+        let aged = now.duration_since(self.since).as_nanos() / AGING_INTERVAL.as_nanos();
+        u128::from(self.priority as u8) + aged
+    }
+}
+
+/// Slot held by a running query. The slot is handed over to the next waiting query when this is
+/// dropped.
+#[derive(Debug)]
+pub struct SlotPermit {
+    slots: Option<Arc<Slots>>,
+}
+
+impl Drop for SlotPermit {
+    fn drop(&mut self) {
+        if let Some(slots) = self.slots.take() {
+            slots.release();
+        }
+    }
+}
+
+impl Slots {
+    #[must_use]
+    pub fn new(count: usize) -> Self {
+        Self {
+            inner: Mutex::new(SlotsInner {
+                available: count,
+                waiting: Vec::new(),
+                next_seq: 0,
+            }),
+        }
+    }
+
+    /// Waits for a free slot. The slot is held until the returned permit is dropped.
+    ///
+    /// ## Panics
+    /// If the mutex is poisoned.
+    pub async fn acquire(self: &Arc<Self>, priority: QueryPriority) -> SlotPermit {
+        let rx = {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.available > 0 && inner.waiting.is_empty() {
+                inner.available -= 1;
+                return SlotPermit {
+                    slots: Some(Arc::clone(self)),
+                };
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let seq = inner.next_seq;
+            inner.next_seq += 1;
+            inner.waiting.push(Waiter {
+                priority,
+                since: Instant::now(),
+                seq,
+                tx,
+            });
+            rx
+        };
+
+        // Waiters are only dropped after they were sent a permit. If the waiting task goes away
+        // before receiving it, the permit is dropped with the channel and passed on.
+        rx.await
+            .expect("waiters are never dropped without a permit")
+    }
+
+    /// Hands the slot over to the waiting query that should run next, or makes it available if
+    /// no query is waiting.
+    fn release(self: Arc<Self>) {
+        loop {
+            let waiter = {
+                let mut inner = self.inner.lock().unwrap();
+                let now = Instant::now();
+                let next = inner
+                    .waiting
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|(_, w)| (w.effective_priority(now), std::cmp::Reverse(w.seq)))
+                    .map(|(i, _)| i);
+                if let Some(i) = next {
+                    inner.waiting.swap_remove(i)
+                } else {
+                    inner.available += 1;
+                    return;
+                }
+            };
+
+            // Send outside of the lock, dropping a permit that could not be delivered takes it.
+            let permit = SlotPermit {
+                slots: Some(Arc::clone(&self)),
+            };
+            match waiter.tx.send(permit) {
+                Ok(()) => return,
+                // The waiting query is gone, try the next one.
+                Err(mut permit) => permit.slots = None,
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use futures::{future::poll_immediate, FutureExt};
+
+    use super::Slots;
+    use crate::{helpers::query::QueryPriority, sync::Arc};
+
+    #[tokio::test]
+    async fn higher_priority_goes_first() {
+        let slots = Arc::new(Slots::new(1));
+        let running = slots.acquire(QueryPriority::Normal).await;
+
+        let mut low = Box::pin(slots.acquire(QueryPriority::Low));
+        let mut high = Box::pin(slots.acquire(QueryPriority::High));
+        assert!(poll_immediate(&mut low).await.is_none());
+        assert!(poll_immediate(&mut high).await.is_none());
+
+        drop(running);
+        let high = high.now_or_never().unwrap();
+        assert!(poll_immediate(&mut low).await.is_none());
+
+        drop(high);
+        low.await;
+    }
+
+    #[tokio::test]
+    async fn same_priority_is_fifo() {
+        let slots = Arc::new(Slots::new(1));
+        let running = slots.acquire(QueryPriority::Normal).await;
+
+        let mut first = Box::pin(slots.acquire(QueryPriority::Normal));
+        let mut second = Box::pin(slots.acquire(QueryPriority::Normal));
+        assert!(poll_immediate(&mut first).await.is_none());
+        assert!(poll_immediate(&mut second).await.is_none());
+
+        drop(running);
+        let _first = first.now_or_never().unwrap();
+        assert!(poll_immediate(&mut second).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn abandoned_waiter_does_not_lose_slot() {
+        let slots = Arc::new(Slots::new(1));
+        let running = slots.acquire(QueryPriority::Normal).await;
+
+        let mut abandoned = Box::pin(slots.acquire(QueryPriority::High));
+        assert!(poll_immediate(&mut abandoned).await.is_none());
+        drop(abandoned);
+
+        drop(running);
+        slots.acquire(QueryPriority::Low).await;
+    }
+}
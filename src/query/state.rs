@@ -12,7 +12,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     helpers::{query::QueryConfig, RoleAssignment},
     protocol::QueryId,
-    query::runner::QueryResult,
+    query::{runner::QueryResult, slots::SlotPermit},
     sync::Mutex,
     task::JoinHandle,
 };
@@ -41,7 +41,7 @@ impl From<&QueryState> for QueryStatus {
         match source {
             QueryState::Empty => panic!("Query cannot be in the empty state"),
             QueryState::Preparing(_) => QueryStatus::Preparing,
-            QueryState::AwaitingInputs(_, _, _) => QueryStatus::AwaitingInputs,
+            QueryState::AwaitingInputs(..) => QueryStatus::AwaitingInputs,
             QueryState::Running(_) => QueryStatus::Running,
             QueryState::AwaitingCompletion => QueryStatus::AwaitingCompletion,
             QueryState::Completed(_) => QueryStatus::Completed,
@@ -53,7 +53,8 @@ impl From<&QueryState> for QueryStatus {
 pub enum QueryState {
     Empty,
     Preparing(QueryConfig),
-    AwaitingInputs(QueryId, QueryConfig, RoleAssignment),
+    /// The permit is held by the coordinator of the query, which decides when it may run.
+    AwaitingInputs(QueryId, QueryConfig, RoleAssignment, Option<SlotPermit>),
     Running(RunningQuery),
     AwaitingCompletion,
     Completed(QueryResult),
@@ -66,8 +67,9 @@ impl QueryState {
         match (cur_state, &new_state) {
             // If query is not running, coordinator initial state is preparing
             // and followers initial state is awaiting inputs
-            (Empty, Preparing(_) | AwaitingInputs(_, _, _))
-            | (Preparing(_), AwaitingInputs(_, _, _)) => Ok(new_state),
+            (Empty, Preparing(_) | AwaitingInputs(..)) | (Preparing(_), AwaitingInputs(..)) => {
+                Ok(new_state)
+            }
             (_, Preparing(_)) => Err(StateError::AlreadyRunning),
             (_, _) => Err(StateError::InvalidState {
                 from: cur_state.into(),
@@ -154,6 +156,30 @@ pub struct QueryHandle<'a> {
 impl QueryHandle<'_> {
     pub fn set_state(&self, new_state: QueryState) -> Result<(), StateError> {
         let mut inner = self.queries.inner.lock().unwrap();
+        self.set_state_locked(&mut inner, new_state)
+    }
+
+    /// Same as [`Self::set_state`], but a query that is not tracked yet is only added if fewer
+    /// than `capacity` queries are. Returns `false` if there is no room for it. Queries are
+    /// counted and added under the same lock, so concurrent requests cannot exceed `capacity`.
+    pub fn set_state_within_capacity(
+        &self,
+        new_state: QueryState,
+        capacity: usize,
+    ) -> Result<bool, StateError> {
+        let mut inner = self.queries.inner.lock().unwrap();
+        if !inner.contains_key(&self.query_id) && inner.len() >= capacity {
+            return Ok(false);
+        }
+        self.set_state_locked(&mut inner, new_state)?;
+        Ok(true)
+    }
+
+    fn set_state_locked(
+        &self,
+        inner: &mut HashMap<QueryId, QueryState>,
+        new_state: QueryState,
+    ) -> Result<(), StateError> {
         let entry = inner.entry(self.query_id);
         match entry {
             Entry::Occupied(mut entry) => {
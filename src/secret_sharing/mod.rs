@@ -3,6 +3,7 @@ pub mod replicated;
 mod decomposed;
 mod into_shares;
 mod scheme;
+mod share_stream;
 
 use std::{
     fmt::Debug,
@@ -20,6 +21,7 @@ use rand::{
 #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
 use replicated::{semi_honest::AdditiveShare, ReplicatedSecretSharing};
 pub use scheme::{Bitwise, Linear, LinearRefOps, SecretSharing};
+pub use share_stream::{share_stream, ShareStream};
 
 use crate::ff::{AddSub, AddSubAssign, Serializable};
 
@@ -3,11 +3,12 @@ use std::{
     ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign},
 };
 
+use bitvec::{field::BitField, order::Lsb0, slice::BitSlice, vec::BitVec, view::BitView};
 use generic_array::{ArrayLength, GenericArray};
 use typenum::Unsigned;
 
 use crate::{
-    ff::Serializable,
+    ff::{GaloisField, Serializable},
     secret_sharing::{
         replicated::ReplicatedSecretSharing, Linear as LinearSecretSharing, SecretSharing,
         SharedValue,
@@ -73,6 +74,68 @@ where
     }
 }
 
+impl<V: GaloisField> AdditiveShare<V> {
+    /// Number of bytes required to store `count` shares packed with [`Self::pack`].
+    ///
+    /// ## Panics
+    /// If `V::BITS` does not fit into `usize`.
+    #[must_use]
+    pub fn packed_len(count: usize) -> usize {
+        (2 * count * usize::try_from(V::BITS).unwrap() + 7) / 8
+    }
+
+    /// Serializes `shares` without padding each value to a whole number of bytes. Every share
+    /// takes exactly `2 * V::BITS` bits, so shares of consecutive rows may share a byte. This
+    /// saves space for values whose width is not a multiple of 8, for example 20 bit match keys
+    /// take 5 bytes per share instead of 6.
+    #[must_use]
+    pub fn pack(shares: &[Self]) -> Vec<u8> {
+        let mut bits = BitVec::<u8, Lsb0>::with_capacity(8 * Self::packed_len(shares.len()));
+        for share in shares {
+            share.pack_into(&mut bits);
+        }
+
+        bits.into_vec()
+    }
+
+    /// Deserializes `count` shares written by [`Self::pack`].
+    ///
+    /// ## Panics
+    /// If `from` is too short to hold `count` packed shares.
+    pub fn unpack(from: &[u8], count: usize) -> impl Iterator<Item = Self> + '_ {
+        assert!(from.len() >= Self::packed_len(count));
+        let width = usize::try_from(V::BITS).unwrap();
+
+        from.view_bits::<Lsb0>()
+            .chunks_exact(2 * width)
+            .take(count)
+            .map(Self::unpack_from)
+    }
+
+    /// Appends the `2 * V::BITS` bits of this share to `bits`, left value first. Unlike
+    /// [`Self::pack`], this lets callers pack shares next to other data.
+    pub fn pack_into(&self, bits: &mut BitVec<u8, Lsb0>) {
+        for value in [self.left(), self.right()] {
+            bits.extend((0..V::BITS).map(|i| value[i]));
+        }
+    }
+
+    /// Reads a share written by [`Self::pack_into`] from the start of `bits`, which does not have
+    /// to be byte aligned.
+    ///
+    /// ## Panics
+    /// If `bits` is shorter than `2 * V::BITS`.
+    #[must_use]
+    pub fn unpack_from(bits: &BitSlice<u8, Lsb0>) -> Self {
+        let width = usize::try_from(V::BITS).unwrap();
+        let (left, right) = bits[..2 * width].split_at(width);
+        Self::new(
+            V::truncate_from(left.load_le::<u128>()),
+            V::truncate_from(right.load_le::<u128>()),
+        )
+    }
+}
+
 impl<'a, 'b, V: SharedValue> Add<&'b AdditiveShare<V>> for &'a AdditiveShare<V> {
     type Output = AdditiveShare<V>;
 
@@ -240,9 +303,12 @@ where
 
 #[cfg(all(test, unit_test))]
 mod tests {
+    use typenum::Unsigned;
+
     use super::AdditiveShare;
     use crate::{
-        ff::{Field, Fp31},
+        ff::{Field, Fp31, Gf20Bit, Serializable},
+        rand::{thread_rng, Rng},
         secret_sharing::replicated::ReplicatedSecretSharing,
     };
 
@@ -380,4 +446,25 @@ mod tests {
         mult_by_constant_test_case((0, 0, 1), 2, 2);
         mult_by_constant_test_case((0, 0, 0), 2, 0);
     }
+
+    #[test]
+    fn pack_unaligned_values() {
+        // odd number of shares, so the last one ends in the middle of a byte
+        const COUNT: usize = 7;
+
+        let mut rng = thread_rng();
+        let shares = (0..COUNT)
+            .map(|_| AdditiveShare::<Gf20Bit>::new(rng.gen(), rng.gen()))
+            .collect::<Vec<_>>();
+
+        let packed = AdditiveShare::pack(&shares);
+        assert_eq!(35, packed.len());
+        assert_eq!(AdditiveShare::<Gf20Bit>::packed_len(COUNT), packed.len());
+        assert!(packed.len() < COUNT * <AdditiveShare<Gf20Bit> as Serializable>::Size::USIZE);
+
+        assert_eq!(
+            shares,
+            AdditiveShare::<Gf20Bit>::unpack(&packed, COUNT).collect::<Vec<_>>()
+        );
+    }
 }
@@ -0,0 +1,192 @@
+use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use futures::{Stream, StreamExt};
+
+use crate::{
+    rand::Rng,
+    secret_sharing::IntoShares,
+    sync::{Arc, Mutex},
+};
+
+/// Secret-shares values produced by `input` incrementally and returns one stream per helper.
+///
+/// Unlike [`IntoShares`] for iterators, this does not materialize shares for all three helpers
+/// upfront. A row is pulled from `input` and shared only when one of the helper streams asks for
+/// it, and each helper stream only ever yields its own share. Shares destined for helpers that are
+/// slower to consume are buffered inside their own queue until requested.
+///
+/// Every queue holds at most `capacity` shares. Once the queue of the slowest helper is full, the
+/// other streams stop pulling rows from `input` until that helper catches up. All three streams
+/// must therefore be consumed concurrently, otherwise the faster ones stall.
+///
+/// Streams are returned in helper order, i.e. the first stream yields shares for `H1`.
+pub fn share_stream<S, U, T, R>(
+    input: S,
+    rng: R,
+    capacity: NonZeroUsize,
+) -> [ShareStream<S, T, R>; 3]
+where
+    S: Stream<Item = U> + Unpin,
+    U: IntoShares<T>,
+    R: Rng,
+{
+    let inner = Arc::new(Mutex::new(Shared {
+        source: input,
+        rng,
+        pending: [(); 3].map(|()| VecDeque::with_capacity(capacity.get())),
+        capacity: capacity.get(),
+        wakers: [None, None, None],
+        done: false,
+    }));
+
+    [0, 1, 2].map(|index| ShareStream {
+        index,
+        inner: Arc::clone(&inner),
+    })
+}
+
+/// Stream of shares for a single helper, created by [`share_stream`].
+pub struct ShareStream<S, T, R> {
+    index: usize,
+    inner: Arc<Mutex<Shared<S, T, R>>>,
+}
+
+struct Shared<S, T, R> {
+    source: S,
+    rng: R,
+    /// Shares that were generated but not yet consumed, one queue per helper.
+    pending: [VecDeque<T>; 3],
+    /// Maximum number of shares in each of the `pending` queues.
+    capacity: usize,
+    /// Helpers waiting for the source stream to produce the next value or for a full queue to
+    /// make room for it.
+    wakers: [Option<Waker>; 3],
+    done: bool,
+}
+
+impl<S, T, R> Shared<S, T, R> {
+    fn wake_all(&mut self) {
+        for waker in self.wakers.iter_mut().filter_map(Option::take) {
+            waker.wake();
+        }
+    }
+}
+
+impl<S, U, T, R> Stream for ShareStream<S, T, R>
+where
+    S: Stream<Item = U> + Unpin,
+    U: IntoShares<T>,
+    R: Rng,
+{
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let index = self.index;
+        let mut shared = self.inner.lock().unwrap();
+        let was_full = shared.pending[index].len() == shared.capacity;
+        if let Some(share) = shared.pending[index].pop_front() {
+            if was_full {
+                // other helpers may be waiting for room in this queue
+                shared.wake_all();
+            }
+            return Poll::Ready(Some(share));
+        }
+        if shared.done {
+            return Poll::Ready(None);
+        }
+        if shared
+            .pending
+            .iter()
+            .any(|queue| queue.len() >= shared.capacity)
+        {
+            shared.wakers[index] = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        match shared.source.poll_next_unpin(cx) {
+            Poll::Ready(Some(value)) => {
+                let Shared { rng, pending, .. } = &mut *shared;
+                let [s0, s1, s2] = value.share_with(rng);
+                pending[0].push_back(s0);
+                pending[1].push_back(s1);
+                pending[2].push_back(s2);
+                shared.wake_all();
+
+                Poll::Ready(shared.pending[index].pop_front())
+            }
+            Poll::Ready(None) => {
+                shared.done = true;
+                shared.wake_all();
+                Poll::Ready(None)
+            }
+            Poll::Pending => {
+                shared.wakers[index] = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use futures::{
+        future::join3,
+        stream::{self, poll_immediate},
+        StreamExt,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::share_stream;
+    use crate::{
+        ff::{Field, Fp31},
+        secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
+        test_fixture::Reconstruct,
+    };
+
+    #[tokio::test]
+    async fn reconstructs_streamed_shares() {
+        const ROWS: u128 = 1000;
+
+        let input = (0..ROWS).map(Fp31::truncate_from).collect::<Vec<_>>();
+        let [h1, h2, h3] = share_stream::<_, _, Replicated<Fp31>, _>(
+            stream::iter(input.clone()),
+            StdRng::seed_from_u64(42),
+            NonZeroUsize::new(16).unwrap(),
+        );
+
+        let (s1, s2, s3) = join3(
+            h1.collect::<Vec<_>>(),
+            h2.collect::<Vec<_>>(),
+            h3.collect::<Vec<_>>(),
+        )
+        .await;
+
+        assert_eq!(input, [s1, s2, s3].reconstruct());
+    }
+
+    #[tokio::test]
+    async fn slow_helper_stalls_others() {
+        const CAPACITY: usize = 4;
+
+        let [mut h1, mut h2, _h3] = share_stream::<_, _, Replicated<Fp31>, _>(
+            stream::iter((0..100_u128).map(Fp31::truncate_from)),
+            StdRng::seed_from_u64(42),
+            NonZeroUsize::new(CAPACITY).unwrap(),
+        );
+
+        // nobody reads shares for H3, so H1 and H2 get as many rows as its queue holds
+        let ready = poll_immediate(&mut h1)
+            .take_while(|poll| futures::future::ready(poll.is_ready()))
+            .count()
+            .await;
+        assert_eq!(CAPACITY, ready);
+        assert!(poll_immediate(&mut h2).next().await.unwrap().is_ready());
+    }
+}
@@ -17,6 +17,7 @@ pub mod metrics {
     pub const INDEXED_PRSS_GENERATED: &str = "i.prss.gen";
     pub const SEQUENTIAL_PRSS_GENERATED: &str = "s.prss.gen";
     pub const STEP_NARROWED: &str = "step.narrowed";
+    pub const MULTIPLICATIONS: &str = "multiplications";
 
     #[cfg(feature = "web-app")]
     pub mod web {
@@ -105,5 +106,12 @@ pub mod metrics {
             Unit::Count,
             "Number of times the step is narrowed"
         );
+
+        describe_counter!(
+            MULTIPLICATIONS,
+            Unit::Count,
+            "Number of secure multiplications performed by the protocols, if the count-multiplications \
+             feature is enabled"
+        );
     }
 }
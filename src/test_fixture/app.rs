@@ -1,4 +1,4 @@
-use std::iter::zip;
+use std::{array, iter::zip};
 
 use generic_array::GenericArray;
 use typenum::Unsigned;
@@ -7,11 +7,12 @@ use crate::{
     app::Error,
     ff::Serializable,
     helpers::{
-        query::{QueryConfig, QueryInput},
-        InMemoryNetwork, InMemoryTransport,
+        query::{QueryConfig, QueryInput, ResultPage},
+        HelperIdentity, InMemoryNetwork, InMemoryTransport, TransportCallbacks,
     },
+    hpke::KeyRegistry,
     protocol::QueryId,
-    query::QueryStatus,
+    query::{CompletionCallbacks, QueryStatus},
     secret_sharing::IntoShares,
     test_fixture::try_join3_array,
     AppSetup, HelperApp,
@@ -60,10 +61,45 @@ fn unzip_tuple_array<T, U>(input: [(T, U); 3]) -> ([T; 3], [U; 3]) {
 
 impl Default for TestApp {
     fn default() -> Self {
-        let (setup, callbacks) =
-            unzip_tuple_array([AppSetup::new(), AppSetup::new(), AppSetup::new()]);
+        Self::with_setup(
+            [AppSetup::new(), AppSetup::new(), AppSetup::new()],
+            InMemoryNetwork::new,
+        )
+    }
+}
+
+impl TestApp {
+    /// Creates helpers that notify clients about completed queries through `callbacks`.
+    #[must_use]
+    pub fn with_completion_callbacks(callbacks: &CompletionCallbacks) -> Self {
+        Self::with_setup(
+            array::from_fn(|_| {
+                AppSetup::with_completion_callbacks(KeyRegistry::empty(), callbacks.clone())
+            }),
+            InMemoryNetwork::new,
+        )
+    }
+
+    /// Creates helpers connected by [`InMemoryNetwork::framed`], which delivers the records they
+    /// exchange in small frames, the same way a byte stream transport would.
+    #[must_use]
+    pub fn framed() -> Self {
+        Self::with_setup(
+            [AppSetup::new(), AppSetup::new(), AppSetup::new()],
+            InMemoryNetwork::framed,
+        )
+    }
 
-        let network = InMemoryNetwork::new(callbacks);
+    fn with_setup<F>(
+        setup: [(AppSetup, TransportCallbacks<InMemoryTransport>); 3],
+        network: F,
+    ) -> Self
+    where
+        F: FnOnce([TransportCallbacks<InMemoryTransport>; 3]) -> InMemoryNetwork,
+    {
+        let (setup, callbacks) = unzip_tuple_array(setup);
+
+        let network = network(callbacks);
         let drivers = network
             .transports()
             .iter()
@@ -76,9 +112,7 @@ impl Default for TestApp {
 
         Self { drivers, network }
     }
-}
 
-impl TestApp {
     /// Initiates a new query on all helpers and drives it to completion.
     ///
     /// ## Errors
@@ -89,6 +123,25 @@ impl TestApp {
         input: I,
         query_config: QueryConfig,
     ) -> Result<QueryId, Error>
+    where
+        I: IntoShares<A>,
+        A: IntoBuf,
+    {
+        self.start_query_with_role_assignment(input, query_config, HelperIdentity::make_three())
+            .await
+    }
+
+    /// Same as [`Self::start_query`], but helper `assignment[i]` plays role `Role::all()[i]`.
+    /// Helper 1 coordinates the query regardless of the role it plays.
+    ///
+    /// ## Errors
+    /// Returns an error if it can't start a query or send query input.
+    pub async fn start_query_with_role_assignment<I, A>(
+        &self,
+        input: I,
+        query_config: QueryConfig,
+        assignment: [HelperIdentity; 3],
+    ) -> Result<QueryId, Error>
     where
         I: IntoShares<A>,
         A: IntoBuf,
@@ -96,14 +149,14 @@ impl TestApp {
         let helpers_input = input.share().map(IntoBuf::into_buf);
 
         // helper 1 initiates the query
-        let query_id = self.drivers[0].start_query(query_config).await?;
-
-        // Send inputs
-        helpers_input
-            .into_iter()
-            .enumerate()
-            .map(|(i, input)| {
-                self.drivers[i].execute_query(QueryInput {
+        let query_id = self.drivers[0]
+            .start_query_with_role_assignment(query_config, assignment)
+            .await?;
+
+        // Send inputs, every helper gets the share for the role it plays
+        zip(assignment, helpers_input)
+            .map(|(helper, input)| {
+                self.drivers[helper].execute_query(QueryInput {
                     query_id,
                     input_stream: input.into(),
                 })
@@ -136,6 +189,27 @@ impl TestApp {
         results
     }
 
+    /// Fetches the same page of the query result from every helper.
+    ///
+    /// ## Errors
+    /// Returns an error if one or more helpers can't finish the processing.
+    pub async fn result_page(
+        &self,
+        query_id: QueryId,
+        offset: usize,
+        limit: usize,
+    ) -> Result<[Vec<u8>; 3], Error> {
+        let page = ResultPage {
+            query_id,
+            offset,
+            limit,
+        };
+        let results =
+            try_join3_array([0, 1, 2].map(|i| self.drivers[i].query_result_page(page))).await;
+        self.network.reset();
+        results
+    }
+
     /// Initiates a new query on all helpers and drives it to completion.
     ///
     /// ## Errors
@@ -153,4 +227,29 @@ impl TestApp {
         let query_id = self.start_query(input, query_config).await?;
         self.complete_query(query_id).await
     }
+
+    /// Same as [`Self::execute_query`], but helper `assignment[i]` plays role `Role::all()[i]`.
+    /// Results are returned in the order of roles, so they can be reconstructed the same way.
+    ///
+    /// ## Errors
+    /// Returns an error if it can't start a query or one or more helpers can't finish the processing.
+    ///
+    /// ## Panics
+    /// If `assignment` does not contain every helper exactly once.
+    pub async fn execute_query_with_role_assignment<I, A>(
+        &self,
+        input: I,
+        query_config: QueryConfig,
+        assignment: [HelperIdentity; 3],
+    ) -> Result<[Vec<u8>; 3], Error>
+    where
+        I: IntoShares<A>,
+        A: IntoBuf,
+    {
+        let query_id = self
+            .start_query_with_role_assignment(input, query_config, assignment)
+            .await?;
+        let mut results = self.complete_query(query_id).await?.map(Some);
+        Ok(assignment.map(|helper| results[helper].take().unwrap()))
+    }
 }
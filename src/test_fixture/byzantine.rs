@@ -0,0 +1,261 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    helpers::{HelperIdentity, StreamInterceptor},
+    protocol::step::Gate,
+    sync::Mutex,
+};
+
+/// What a malicious helper does to a record it sends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// XOR every byte of the record with the given mask.
+    FlipBits(u8),
+    /// Do not send the record at all. Note that the receiving helper will wait for it forever
+    /// unless the stream is closed.
+    Drop,
+    /// Send the record twice.
+    Duplicate,
+    /// Send the record after the one that follows it.
+    Swap,
+}
+
+struct ScheduledFault {
+    from: HelperIdentity,
+    gate: Gate,
+    records: Range<usize>,
+    record_size: usize,
+    fault: Fault,
+}
+
+impl ScheduledFault {
+    fn applies_to(&self, from: HelperIdentity, gate: &Gate) -> bool {
+        self.from == from && &self.gate == gate
+    }
+}
+
+/// Records of a single channel that were received from the sender but not yet passed on.
+#[derive(Default)]
+struct ChannelState {
+    /// Trailing bytes of a record that is split across chunks.
+    partial: Vec<u8>,
+    /// Index of the next complete record.
+    next_record: usize,
+    /// Record held back by [`Fault::Swap`].
+    swapped: Option<Vec<u8>>,
+}
+
+/// Programmable fault schedule for the in-memory transport. Install it with
+/// [`TestWorldConfig::with_stream_interceptor`] to make a helper tamper with the records it sends
+/// on a given gate, for example to check that malicious validation catches it.
+///
+/// Faults are keyed by the sending helper, the exact gate and the range of record indices. Records
+/// on channels without any faults are passed through untouched.
+///
+/// [`TestWorldConfig::with_stream_interceptor`]: crate::test_fixture::TestWorldConfig::with_stream_interceptor
+#[derive(Default)]
+pub struct ByzantineSchedule {
+    faults: Vec<ScheduledFault>,
+    channels: Mutex<HashMap<(HelperIdentity, HelperIdentity, Gate), ChannelState>>,
+}
+
+impl ByzantineSchedule {
+    /// Makes helper `from` apply `fault` to `records` it sends on `gate`, where every record
+    /// occupies `record_size` bytes on the wire.
+    ///
+    /// ## Panics
+    /// If `record_size` is zero.
+    #[must_use]
+    pub fn with_fault(
+        mut self,
+        from: HelperIdentity,
+        gate: Gate,
+        records: Range<usize>,
+        record_size: usize,
+        fault: Fault,
+    ) -> Self {
+        assert_ne!(0, record_size, "records must not be empty");
+        self.faults.push(ScheduledFault {
+            from,
+            gate,
+            records,
+            record_size,
+            fault,
+        });
+        self
+    }
+}
+
+impl StreamInterceptor for ByzantineSchedule {
+    fn intercept(
+        &self,
+        from: HelperIdentity,
+        to: HelperIdentity,
+        gate: &Gate,
+        _offset: usize,
+        chunk: &mut Vec<u8>,
+    ) {
+        let faults = self
+            .faults
+            .iter()
+            .filter(|f| f.applies_to(from, gate))
+            .collect::<Vec<_>>();
+        let Some(record_size) = faults.first().map(|f| f.record_size) else {
+            return;
+        };
+
+        let mut channels = self.channels.lock().unwrap();
+        let state = channels.entry((from, to, gate.clone())).or_default();
+        state.partial.append(chunk);
+
+        let mut output = Vec::with_capacity(state.partial.len());
+        let mut emit = |state: &mut ChannelState, record: &[u8]| {
+            output.extend_from_slice(record);
+            if let Some(swapped) = state.swapped.take() {
+                output.extend(swapped);
+            }
+        };
+
+        let complete = state.partial.len() - state.partial.len() % record_size;
+        let records = state.partial.drain(..complete).collect::<Vec<_>>();
+        for mut record in records.chunks(record_size).map(<[u8]>::to_vec) {
+            let index = state.next_record;
+            state.next_record += 1;
+
+            match faults
+                .iter()
+                .find(|f| f.records.contains(&index))
+                .map(|f| f.fault)
+            {
+                None => emit(state, &record),
+                Some(Fault::FlipBits(mask)) => {
+                    for b in &mut record {
+                        *b ^= mask;
+                    }
+                    emit(state, &record);
+                }
+                Some(Fault::Drop) => {}
+                Some(Fault::Duplicate) => {
+                    emit(state, &record);
+                    emit(state, &record);
+                }
+                Some(Fault::Swap) => state.swapped = Some(record),
+            }
+        }
+
+        *chunk = output;
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use typenum::Unsigned;
+
+    use super::{ByzantineSchedule, Fault};
+    use crate::{
+        error::Error,
+        ff::{Field, Fp32BitPrime, Serializable},
+        helpers::{HelperIdentity, StreamInterceptor},
+        protocol::{
+            basics::SecureMul,
+            context::{
+                validator::{Step as ValidatorStep, Validator},
+                Context, UpgradableContext, UpgradedContext,
+            },
+            step::{Gate, StepNarrow},
+            RecordId,
+        },
+        sync::Arc,
+        test_fixture::{Runner, TestWorld, TestWorldConfig},
+    };
+
+    fn gate() -> Gate {
+        Gate::default().narrow("test")
+    }
+
+    fn intercept(schedule: &ByzantineSchedule, chunks: &[&[u8]]) -> Vec<u8> {
+        chunks
+            .iter()
+            .scan(0, |offset, chunk| {
+                let mut chunk = chunk.to_vec();
+                let len = chunk.len();
+                schedule.intercept(
+                    HelperIdentity::ONE,
+                    HelperIdentity::TWO,
+                    &gate(),
+                    *offset,
+                    &mut chunk,
+                );
+                *offset += len;
+                Some(chunk)
+            })
+            .flatten()
+            .collect()
+    }
+
+    #[test]
+    fn applies_faults_to_records() {
+        let schedule = ByzantineSchedule::default()
+            .with_fault(HelperIdentity::ONE, gate(), 0..1, 2, Fault::FlipBits(0xFF))
+            .with_fault(HelperIdentity::ONE, gate(), 1..2, 2, Fault::Drop)
+            .with_fault(HelperIdentity::ONE, gate(), 2..3, 2, Fault::Duplicate)
+            .with_fault(HelperIdentity::ONE, gate(), 3..4, 2, Fault::Swap);
+
+        // records are split across chunks on purpose
+        let output = intercept(&schedule, &[&[0, 1, 2], &[3, 4, 5, 6], &[7, 8, 9]]);
+        assert_eq!(vec![255, 254, 4, 5, 4, 5, 8, 9, 6, 7], output);
+    }
+
+    #[test]
+    fn ignores_other_channels() {
+        let schedule = ByzantineSchedule::default().with_fault(
+            HelperIdentity::TWO,
+            gate(),
+            0..10,
+            1,
+            Fault::Drop,
+        );
+
+        assert_eq!(vec![0, 1, 2], intercept(&schedule, &[&[0, 1, 2]]));
+    }
+
+    #[tokio::test]
+    async fn corrupted_multiplication_fails_validation() {
+        // Malicious multiplication sends the product of `x` shares on the gate of the context it
+        // was given, so flipping bits there corrupts the first record of H1's share.
+        let gate = Gate::default()
+            .narrow(&TestWorld::execution_step(0))
+            .narrow(&ValidatorStep::MaliciousProtocol)
+            .narrow("mul");
+        let schedule = ByzantineSchedule::default().with_fault(
+            HelperIdentity::ONE,
+            gate,
+            0..1,
+            <Fp32BitPrime as Serializable>::Size::USIZE,
+            Fault::FlipBits(0x01),
+        );
+        let world = TestWorld::new_with(
+            TestWorldConfig::default().with_stream_interceptor(Arc::new(schedule)),
+        );
+
+        let (a, b) = (
+            Fp32BitPrime::truncate_from(3_u128),
+            Fp32BitPrime::truncate_from(5_u128),
+        );
+        world
+            .malicious((a, b), |ctx, (a, b)| async move {
+                let v = ctx.validator();
+                let m = v.context();
+                let (a, b) = m.clone().upgrade((a, b)).await.unwrap();
+                let ab = a
+                    .multiply(&b, m.narrow("mul").set_total_records(1), RecordId::FIRST)
+                    .await
+                    .unwrap();
+                match v.validate(ab).await {
+                    Ok(result) => panic!("Got a result {result:?}"),
+                    Err(err) => assert!(matches!(err, Error::MaliciousSecurityCheckFailed)),
+                }
+            })
+            .await;
+    }
+}
@@ -30,6 +30,11 @@ pub struct GenericReportTestInput<F: Field, MK: GaloisField, BK: GaloisField> {
     pub helper_bit: Option<F>,
     pub aggregation_bit: Option<F>,
     pub active_bit: Option<F>,
+    /// Identifier of the source report. It is not secret-shared; every helper receives it as is
+    /// when this input is shared into [`WithReportId`].
+    ///
+    /// [`WithReportId`]: crate::protocol::ipa::WithReportId
+    pub report_id: Option<u64>,
 }
 
 #[macro_export]
@@ -45,6 +50,7 @@ macro_rules! ipa_test_input {
             helper_bit: None,
             aggregation_bit: None,
             active_bit: None,
+            report_id: None,
         }
     };
 
@@ -68,6 +74,7 @@ macro_rules! attribution_window_test_input {
             helper_bit: Some(<$field as $crate::ff::Field>::truncate_from(u128::try_from($hb).unwrap())),
             aggregation_bit: None,
             active_bit: None,
+            report_id: None,
         }
     };
 
@@ -91,6 +98,7 @@ macro_rules! accumulation_test_input {
             helper_bit: Some(<$field as $crate::ff::Field>::truncate_from(u128::try_from($hb).unwrap())),
             aggregation_bit: None,
             active_bit: Some(<$field as $crate::ff::Field>::truncate_from(u128::try_from($ab).unwrap())),
+            report_id: None,
         }
     };
 
@@ -114,6 +122,7 @@ macro_rules! credit_capping_test_input {
             helper_bit: Some(<$field as $crate::ff::Field>::truncate_from(u128::try_from($hb).unwrap())),
             aggregation_bit: None,
             active_bit: None,
+            report_id: None,
         }
     };
 
@@ -137,6 +146,7 @@ macro_rules! aggregation_test_input {
             helper_bit: Some(<$field as $crate::ff::Field>::truncate_from(u128::try_from($hb).unwrap())),
             aggregation_bit: None,
             active_bit: None,
+            report_id: None,
         }
     };
 
@@ -1,23 +1,30 @@
 use std::iter::zip;
 
-use rand::{distributions::Standard, prelude::Distribution};
+use rand::{distributions::Standard, prelude::Distribution, rngs::StdRng, SeedableRng};
 
 use crate::{
     ff::{Field, GaloisField, PrimeField, Serializable},
+    helpers::Role,
     protocol::{
         attribution::input::{
             AccumulateCreditInputRow, ApplyAttributionWindowInputRow, CreditCappingInputRow,
         },
-        ipa::IPAInputRow,
+        ipa::{ArithmeticallySharedIPAInputs, IPAInputRow, WithReportId},
         BreakdownKey, MatchKey,
     },
     rand::Rng,
     report::{EventType, Report},
-    secret_sharing::{replicated::semi_honest::AdditiveShare as Replicated, IntoShares},
+    secret_sharing::{
+        replicated::{
+            malicious::{AdditiveShare as MaliciousReplicated, ExtendableField},
+            semi_honest::AdditiveShare as Replicated,
+        },
+        IntoShares,
+    },
     test_fixture::{
         input::{GenericReportShare, GenericReportTestInput},
         ipa::TestRawDataRecord,
-        Reconstruct,
+        Reconstruct, WithMacKey,
     },
 };
 
@@ -39,6 +46,7 @@ where
             helper_bit,
             aggregation_bit,
             active_bit,
+            report_id: _,
         } = self;
 
         let [match_key0, match_key1, match_key2] = match_key.share_with(rng);
@@ -230,6 +238,95 @@ where
     }
 }
 
+impl<F, MK, BK> IntoShares<WithReportId<IPAInputRow<F, MK, BK>>>
+    for GenericReportTestInput<F, MK, BK>
+where
+    F: Field + IntoShares<Replicated<F>>,
+    MK: GaloisField + IntoShares<Replicated<MK>>,
+    BK: GaloisField + IntoShares<Replicated<BK>>,
+    Standard: Distribution<F>,
+{
+    fn share_with<R: Rng>(self, rng: &mut R) -> [WithReportId<IPAInputRow<F, MK, BK>>; 3] {
+        let report_id = self.report_id;
+        let rows: [IPAInputRow<F, MK, BK>; 3] = self.share_with(rng);
+        rows.map(|row| WithReportId { report_id, row })
+    }
+}
+
+/// Shares the arithmetic fields of an IPA input row directly into MAC'd shares under the key `r`,
+/// which is what the malicious [`ipa`] upgrades them into. Match key and breakdown key are binary
+/// shares that are converted rather than upgraded, so they are left out.
+///
+/// [`ipa`]: crate::protocol::ipa::ipa
+impl<F, MK, BK> IntoShares<ArithmeticallySharedIPAInputs<F, MaliciousReplicated<F>>>
+    for WithMacKey<GenericReportTestInput<F, MK, BK>, F::ExtendedField>
+where
+    F: ExtendableField + IntoShares<Replicated<F>>,
+    F::ExtendedField: IntoShares<Replicated<F::ExtendedField>>,
+    MK: GaloisField,
+    BK: GaloisField,
+{
+    fn share_with<R: Rng>(
+        self,
+        rng: &mut R,
+    ) -> [ArithmeticallySharedIPAInputs<F, MaliciousReplicated<F>>; 3] {
+        let WithMacKey { value, r } = self;
+        let [t0, t1, t2] = WithMacKey {
+            value: value.timestamp.unwrap(),
+            r,
+        }
+        .share_with(rng);
+        let [i0, i1, i2] = WithMacKey {
+            value: value.is_trigger_report.unwrap(),
+            r,
+        }
+        .share_with(rng);
+        let [v0, v1, v2] = WithMacKey {
+            value: value.trigger_value,
+            r,
+        }
+        .share_with(rng);
+
+        [
+            ArithmeticallySharedIPAInputs::new(t0, i0, v0),
+            ArithmeticallySharedIPAInputs::new(t1, i1, v1),
+            ArithmeticallySharedIPAInputs::new(t2, i2, v2),
+        ]
+    }
+}
+
+/// Shares `plaintext` and returns only the shares held by `role`, so that a single helper's
+/// input handling can be tested without running the other two.
+///
+/// Shares are generated from a fixed seed, so calling this for each of the three roles yields
+/// shares that are consistent with each other and reconstruct to `plaintext`.
+#[must_use]
+pub fn local_shares_for<F, MK, BK>(
+    role: Role,
+    plaintext: &[GenericReportTestInput<F, MK, BK>],
+) -> Vec<IPAInputRow<F, MK, BK>>
+where
+    F: Field + IntoShares<Replicated<F>>,
+    MK: GaloisField + IntoShares<Replicated<MK>>,
+    BK: GaloisField + IntoShares<Replicated<BK>>,
+    Standard: Distribution<F>,
+{
+    const SEED: u64 = 42;
+
+    let mut rng = StdRng::seed_from_u64(SEED);
+    plaintext
+        .iter()
+        .map(|row| {
+            let [h1, h2, h3]: [IPAInputRow<F, MK, BK>; 3] = row.share_with(&mut rng);
+            match role {
+                Role::H1 => h1,
+                Role::H2 => h2,
+                Role::H3 => h3,
+            }
+        })
+        .collect()
+}
+
 const DOMAINS: &[&str] = &[
     "mozilla.com",
     "facebook.com",
@@ -351,6 +448,59 @@ where
             timestamp: None,
             aggregation_bit: None,
             active_bit: Some(active_bit),
+            report_id: None,
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::local_shares_for;
+    use crate::{
+        ff::Fp31,
+        helpers::Role,
+        ipa_test_input,
+        protocol::{BreakdownKey, MatchKey},
+        test_fixture::{input::GenericReportTestInput, Reconstruct},
+    };
+
+    #[test]
+    fn local_shares_reconstruct() {
+        let plaintext: Vec<GenericReportTestInput<Fp31, MatchKey, BreakdownKey>> = ipa_test_input!(
+            [
+                { timestamp: 0, match_key: 12345, is_trigger_report: 0, breakdown_key: 1, trigger_value: 0 },
+                { timestamp: 5, match_key: 12345, is_trigger_report: 1, breakdown_key: 0, trigger_value: 3 },
+                { timestamp: 9, match_key: 68362, is_trigger_report: 0, breakdown_key: 2, trigger_value: 0 },
+            ];
+            (Fp31, MatchKey, BreakdownKey)
+        );
+
+        let [h1, h2, h3] =
+            [Role::H1, Role::H2, Role::H3].map(|role| local_shares_for(role, &plaintext));
+        // generation is deterministic
+        assert_eq!(h1, local_shares_for(Role::H1, &plaintext));
+
+        for (expected, ((s1, s2), s3)) in plaintext.iter().zip(h1.iter().zip(&h2).zip(&h3)) {
+            assert_eq!(
+                expected.timestamp.unwrap(),
+                [&s1.timestamp, &s2.timestamp, &s3.timestamp].reconstruct()
+            );
+            assert_eq!(
+                expected.match_key.unwrap(),
+                [&s1.mk_shares, &s2.mk_shares, &s3.mk_shares].reconstruct()
+            );
+            assert_eq!(
+                expected.is_trigger_report.unwrap(),
+                [&s1.is_trigger_bit, &s2.is_trigger_bit, &s3.is_trigger_bit].reconstruct()
+            );
+            assert_eq!(
+                expected.breakdown_key.unwrap(),
+                [&s1.breakdown_key, &s2.breakdown_key, &s3.breakdown_key].reconstruct()
+            );
+            assert_eq!(
+                expected.trigger_value,
+                [&s1.trigger_value, &s2.trigger_value, &s3.trigger_value].reconstruct()
+            );
         }
     }
 }
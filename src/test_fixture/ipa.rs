@@ -1,5 +1,6 @@
-use std::{collections::HashMap, num::NonZeroU32, ops::Deref};
+use std::{cmp::min, collections::HashMap, num::NonZeroU32, ops::Deref};
 
+use crate::{ff::Field, protocol::attribution::AttributionTrace};
 #[cfg(feature = "in-memory-infra")]
 use crate::{
     ff::{PrimeField, Serializable},
@@ -83,6 +84,70 @@ pub fn ipa_in_the_clear(
     breakdowns
 }
 
+/// Computes the intermediate values of attribution in the clear, the way [`ipa_with_trace`]
+/// returns them. Rows are sorted by user id, keeping rows of the same user in input order, like
+/// the match key sort of IPA does.
+///
+/// This mirrors the MPC circuit step by step rather than the attribution rules, so that tests can
+/// tell which stage went wrong. Only the default configuration is supported: no attribution window,
+/// no deduplication and a per-user cap greater than one.
+///
+/// ## Panics
+/// If `per_user_cap` is less than two.
+///
+/// [`ipa_with_trace`]: crate::protocol::ipa::ipa_with_trace
+#[must_use]
+pub fn attribution_trace_in_the_clear<F: Field>(
+    input: &[TestRawDataRecord],
+    per_user_cap: u32,
+) -> AttributionTrace<F> {
+    assert!(per_user_cap > 1, "cap of one is attributed differently");
+
+    let mut sorted = input.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|row| row.user_id);
+    let same_user = |i: usize| i > 0 && sorted[i - 1].user_id == sorted[i].user_id;
+
+    // Every row accumulates the credit of the trigger events of the same user that immediately
+    // follow it.
+    let mut accumulated = vec![0; sorted.len()];
+    for i in (0..sorted.len()).rev() {
+        accumulated[i] = sorted[i].trigger_value;
+        if i + 1 < sorted.len() && same_user(i + 1) && sorted[i + 1].is_trigger_report {
+            accumulated[i] += accumulated[i + 1];
+        }
+    }
+
+    // Only source events keep their credit, the most recent ones get it first.
+    let mut capped = vec![0; sorted.len()];
+    let mut later_credit = 0;
+    for i in (0..sorted.len()).rev() {
+        if i + 1 == sorted.len() || !same_user(i + 1) {
+            later_credit = 0;
+        }
+        let credit = if sorted[i].is_trigger_report {
+            0
+        } else {
+            min(accumulated[i], per_user_cap)
+        };
+        capped[i] = min(credit, per_user_cap.saturating_sub(later_credit));
+        later_credit += credit;
+    }
+
+    let to_field = |values: Vec<u32>| values.into_iter().map(F::truncate_from).collect();
+    AttributionTrace {
+        is_trigger_bits: to_field(
+            sorted
+                .iter()
+                .map(|row| u32::from(row.is_trigger_report))
+                .collect(),
+        ),
+        trigger_values: to_field(sorted.iter().map(|row| row.trigger_value).collect()),
+        helper_bits: to_field((0..sorted.len()).map(|i| u32::from(same_user(i))).collect()),
+        accumulated_credits: to_field(accumulated),
+        capped_credits: to_field(capped),
+    }
+}
+
 /// Assumes records all belong to the same user, and are in reverse chronological order
 /// Will give incorrect results if this is not true
 #[allow(clippy::missing_panics_doc)]
@@ -167,10 +232,11 @@ pub async fn test_ipa<F>(
         })
         .collect::<Vec<_>>();
 
+    let config = &config;
     let result: Vec<F> = match security_model {
         IpaSecurityModel::Malicious => world
             .malicious(records.into_iter(), |ctx, input_rows| async move {
-                ipa::<_, _, _, F, MatchKey, BreakdownKey>(ctx, &input_rows, config)
+                ipa::<_, _, _, F, MatchKey, BreakdownKey>(ctx, &input_rows, config.clone())
                     .await
                     .unwrap()
             })
@@ -178,7 +244,7 @@ pub async fn test_ipa<F>(
             .reconstruct(),
         IpaSecurityModel::SemiHonest => world
             .semi_honest(records.into_iter(), |ctx, input_rows| async move {
-                ipa::<_, _, _, F, MatchKey, BreakdownKey>(ctx, &input_rows, config)
+                ipa::<_, _, _, F, MatchKey, BreakdownKey>(ctx, &input_rows, config.clone())
                     .await
                     .unwrap()
             })
@@ -1,3 +1,5 @@
+#[cfg(feature = "in-memory-infra")]
+pub mod byzantine;
 pub mod input;
 mod sharing;
 #[cfg(feature = "in-memory-infra")]
@@ -23,7 +25,10 @@ pub use event_gen::{Config as EventGeneratorConfig, EventGenerator};
 use futures::TryFuture;
 use rand::{distributions::Standard, prelude::Distribution, rngs::mock::StepRng};
 use rand_core::{CryptoRng, RngCore};
-pub use sharing::{get_bits, into_bits, Reconstruct};
+pub use sharing::{
+    get_bits, into_bits, reconstruct_histogram, reconstruct_iter, Reconstruct, ReconstructFromTwo,
+    ValidateConsistency, ValidateMalicious, WithMacKey,
+};
 #[cfg(feature = "in-memory-infra")]
 pub use world::{Runner, TestWorld, TestWorldConfig};
 
@@ -1,15 +1,31 @@
-use std::{borrow::Borrow, iter::zip, ops::Deref};
+use std::{
+    borrow::Borrow,
+    io,
+    iter::{from_fn, zip},
+    ops::Deref,
+};
+
+use futures::{
+    future::join3,
+    stream::{iter as stream_iter, unfold},
+    Stream, StreamExt, TryStreamExt,
+};
 
 use crate::{
-    ff::{Field, PrimeField},
-    protocol::boolean::RandomBitsShare,
+    ff::{Field, PrimeField, Serializable},
+    helpers::{BytesStream, Direction, RecordsStream, Role},
+    protocol::{
+        attribution::{AttributionTrace, CreditReconciliation},
+        boolean::RandomBitsShare,
+    },
+    rand::Rng,
     secret_sharing::{
         replicated::{
             malicious::{AdditiveShare as MaliciousReplicated, ExtendableField},
             semi_honest::AdditiveShare as Replicated,
             ReplicatedSecretSharing,
         },
-        BitDecomposed, SecretSharing,
+        BitDecomposed, IntoShares, SecretSharing,
     },
 };
 
@@ -62,6 +78,28 @@ impl<F: Field> Reconstruct<F> for [Replicated<F>; 3] {
     }
 }
 
+impl<F: Field> Reconstruct<CreditReconciliation<F>> for [&CreditReconciliation<Replicated<F>>; 3] {
+    fn reconstruct(&self) -> CreditReconciliation<F> {
+        CreditReconciliation {
+            total_input_credit: self.map(|r| &r.total_input_credit).reconstruct(),
+            total_attributed_credit: self.map(|r| &r.total_attributed_credit).reconstruct(),
+            total_capped: self.map(|r| &r.total_capped).reconstruct(),
+        }
+    }
+}
+
+impl<F: Field> Reconstruct<AttributionTrace<F>> for [&AttributionTrace<Replicated<F>>; 3] {
+    fn reconstruct(&self) -> AttributionTrace<F> {
+        AttributionTrace {
+            is_trigger_bits: self.map(|t| &t.is_trigger_bits).reconstruct(),
+            trigger_values: self.map(|t| &t.trigger_values).reconstruct(),
+            helper_bits: self.map(|t| &t.helper_bits).reconstruct(),
+            accumulated_credits: self.map(|t| &t.accumulated_credits).reconstruct(),
+            capped_credits: self.map(|t| &t.capped_credits).reconstruct(),
+        }
+    }
+}
+
 impl<T, U, V, W> Reconstruct<(V, W)> for [(T, U); 3]
 where
     for<'t> [&'t T; 3]: Reconstruct<V>,
@@ -117,6 +155,126 @@ where
     }
 }
 
+/// Reconstruction of values from the shares of only two helpers, e.g. when the third one crashed
+/// after computing the result. Every share of replicated secret sharing is held by two helpers,
+/// so any two of them know all three shares.
+pub trait ReconstructFromTwo<T> {
+    /// Reconstructs a value from the shares `a` and `b` of the two helpers other than `missing`,
+    /// given in the order of [`Role::all`].
+    ///
+    /// # Panics
+    /// Panics if the shares of the two helpers are not consistent.
+    fn from_two(a: &Self, b: &Self, missing: Role) -> T;
+}
+
+impl<F: Field> ReconstructFromTwo<F> for Replicated<F> {
+    fn from_two(a: &Self, b: &Self, missing: Role) -> F {
+        // The right share of a helper is the left share of its right peer. H3 is the left peer
+        // of H1, so the order flips when H2 is missing.
+        let (first, second) = if missing == Role::H2 { (b, a) } else { (a, b) };
+        assert_eq!(first.right(), second.left());
+
+        first.left() + first.right() + second.right()
+    }
+}
+
+impl<I, T> ReconstructFromTwo<Vec<T>> for [I]
+where
+    I: ReconstructFromTwo<T>,
+{
+    fn from_two(a: &Self, b: &Self, missing: Role) -> Vec<T> {
+        assert_eq!(a.len(), b.len());
+        zip(a, b).map(|(a, b)| I::from_two(a, b, missing)).collect()
+    }
+}
+
+impl<I, T> ReconstructFromTwo<Vec<T>> for Vec<I>
+where
+    I: ReconstructFromTwo<T>,
+{
+    fn from_two(a: &Self, b: &Self, missing: Role) -> Vec<T> {
+        <[I]>::from_two(a, b, missing)
+    }
+}
+
+/// Reconstructs values from per-helper sequences of shares one at a time, without collecting them
+/// first. Useful for large outputs that tests want to check row by row.
+///
+/// # Panics
+/// If sequences have different lengths or the shares are not valid replicated secret shares.
+pub fn reconstruct_iter<I, T>(shares: [I; 3]) -> impl Iterator<Item = T>
+where
+    I: IntoIterator,
+    [I::Item; 3]: Reconstruct<T>,
+{
+    let [mut s0, mut s1, mut s2] = shares.map(IntoIterator::into_iter);
+    from_fn(move || match (s0.next(), s1.next(), s2.next()) {
+        (Some(x0), Some(x1), Some(x2)) => Some([x0, x1, x2].reconstruct()),
+        (None, None, None) => None,
+        _ => panic!("helpers produced a different number of shares"),
+    })
+}
+
+/// Streaming counterpart of [`reconstruct_iter`] for query results. Takes the serialized result
+/// shares that every helper produced, e.g. read from three result files, and lazily yields the
+/// reconstructed `(breakdown_key, credit)` pairs of the histogram. Streams do not need to be
+/// chunked the same way, so the result never has to be loaded into memory in full.
+///
+/// # Errors
+/// The stream yields an error and stops if one of the inputs fails or ends in the middle of a row,
+/// if streams hold a different number of rows, or if the shares of a row are not consistent with
+/// each other. Result files come from outside of the test, so none of these panic.
+pub fn reconstruct_histogram<F, S>(
+    shares: [S; 3],
+) -> impl Stream<Item = Result<(u32, F), io::Error>>
+where
+    F: Field,
+    Replicated<F>: Serializable,
+    S: BytesStream,
+{
+    let rows = shares.map(|stream| {
+        Box::pin(
+            RecordsStream::<Replicated<F>, _>::new(stream)
+                .map_ok(|rows| stream_iter(rows.into_iter().map(Ok)))
+                .try_flatten(),
+        )
+    });
+
+    unfold(Some((rows, 0_u32)), |state| async move {
+        let ([mut s0, mut s1, mut s2], breakdown_key) = state?;
+        let invalid = |reason| {
+            Some((
+                Err(io::Error::new(io::ErrorKind::InvalidData, reason)),
+                None,
+            ))
+        };
+        let row = match join3(s0.next(), s1.next(), s2.next()).await {
+            (Some(Ok(x0)), Some(Ok(x1)), Some(Ok(x2))) => {
+                let shares = [x0, x1, x2];
+                if let Some(reason) = shares.first_inconsistency() {
+                    return invalid(format!("breakdown key {breakdown_key}: {reason}"));
+                }
+                shares.reconstruct()
+            }
+            (None, None, None) => return None,
+            (Some(Err(e)), _, _) | (_, Some(Err(e)), _) | (_, _, Some(Err(e))) => {
+                return Some((Err(e), None));
+            }
+            _ => {
+                return invalid(format!(
+                    "helpers produced a different number of shares, some of them end at \
+                     breakdown key {breakdown_key}"
+                ));
+            }
+        };
+
+        Some((
+            Ok((breakdown_key, row)),
+            Some(([s0, s1, s2], breakdown_key + 1)),
+        ))
+    })
+}
+
 impl<F, S> Reconstruct<F> for [RandomBitsShare<F, S>; 3]
 where
     F: Field,
@@ -137,6 +295,74 @@ where
     }
 }
 
+/// Checks that the shares held by the three helpers agree with each other: every component of a
+/// replicated share is known to two helpers and must have the same value at both of them.
+///
+/// [`Reconstruct`] panics on inconsistent shares too, but this reports where the shares diverge,
+/// which helps tracking down PRSS or step mismatches between helpers.
+pub trait ValidateConsistency {
+    /// Returns a description of the first inconsistency, or `None` if shares are consistent.
+    fn first_inconsistency(&self) -> Option<String>;
+}
+
+impl<F: Field> ValidateConsistency for [&Replicated<F>; 3] {
+    fn first_inconsistency(&self) -> Option<String> {
+        Role::all().iter().find_map(|&role| {
+            let (share, right) = (self[role], self[role.peer(Direction::Right)]);
+            (share.right() != right.left()).then(|| {
+                format!(
+                    "right share of {role:?} is {:?}, but left share of {:?} is {:?}",
+                    share.right(),
+                    role.peer(Direction::Right),
+                    right.left(),
+                )
+            })
+        })
+    }
+}
+
+impl<F: Field> ValidateConsistency for [Replicated<F>; 3] {
+    fn first_inconsistency(&self) -> Option<String> {
+        [&self[0], &self[1], &self[2]].first_inconsistency()
+    }
+}
+
+impl<I> ValidateConsistency for [&[I]; 3]
+where
+    for<'i> [&'i I; 3]: ValidateConsistency,
+{
+    fn first_inconsistency(&self) -> Option<String> {
+        if self[0].len() != self[1].len() || self[0].len() != self[2].len() {
+            return Some(format!(
+                "helpers have different number of shares: {:?}",
+                self.map(<[I]>::len)
+            ));
+        }
+        zip(self[0].iter(), zip(self[1].iter(), self[2].iter()))
+            .map(|(x0, (x1, x2))| [x0, x1, x2].first_inconsistency())
+            .enumerate()
+            .find_map(|(i, e)| e.map(|e| format!("at index {i}: {e}")))
+    }
+}
+
+impl<I> ValidateConsistency for [&Vec<I>; 3]
+where
+    for<'i> [&'i [I]; 3]: ValidateConsistency,
+{
+    fn first_inconsistency(&self) -> Option<String> {
+        self.map(Deref::deref).first_inconsistency()
+    }
+}
+
+impl<I> ValidateConsistency for [Vec<I>; 3]
+where
+    for<'i> [&'i [I]; 3]: ValidateConsistency,
+{
+    fn first_inconsistency(&self) -> Option<String> {
+        [&self[0], &self[1], &self[2]].first_inconsistency()
+    }
+}
+
 pub trait ValidateMalicious<F: ExtendableField> {
     fn validate(&self, r: F::ExtendedField);
 }
@@ -200,3 +426,235 @@ impl<F: ExtendableField> ValidateMalicious<F>
         [v0.clone(), v1.clone(), v2.clone()].validate(r);
     }
 }
+
+/// A value to be shared into malicious shares that are authenticated with the MAC key `r`.
+///
+/// Malicious protocols normally upgrade semi-honest inputs using the key held by their validator.
+/// This lets tests produce MAC'd shares directly instead, for protocols that take malicious
+/// shares as input. The shares are only consistent with validators that use the same key: share
+/// `r` as well and pass it to [`validator_with_mac_key`]. They can also be checked with
+/// [`ValidateMalicious`] given `r`.
+///
+/// [`validator_with_mac_key`]: crate::protocol::context::MaliciousContext::validator_with_mac_key
+#[derive(Clone, Copy, Debug)]
+pub struct WithMacKey<T, R> {
+    pub value: T,
+    pub r: R,
+}
+
+impl<F> IntoShares<MaliciousReplicated<F>> for WithMacKey<F, F::ExtendedField>
+where
+    F: ExtendableField + IntoShares<Replicated<F>>,
+    F::ExtendedField: IntoShares<Replicated<F::ExtendedField>>,
+{
+    fn share_with<R: Rng>(self, rng: &mut R) -> [MaliciousReplicated<F>; 3] {
+        let [x0, x1, x2] = self.value.share_with(rng);
+        let [rx0, rx1, rx2] = (self.value.to_extended() * self.r).share_with(rng);
+        [
+            MaliciousReplicated::new(x0, rx0),
+            MaliciousReplicated::new(x1, rx1),
+            MaliciousReplicated::new(x2, rx2),
+        ]
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::{io, iter::zip};
+
+    use bytes::Bytes;
+    use futures::{stream, TryStreamExt};
+    use generic_array::GenericArray;
+    use typenum::Unsigned;
+
+    use super::{
+        reconstruct_histogram, reconstruct_iter, Reconstruct, ReconstructFromTwo,
+        ValidateMalicious, WithMacKey,
+    };
+    use crate::{
+        error::BoxError,
+        ff::{Field, Fp31, Fp32BitPrime, Serializable},
+        helpers::{BytesStream, Role},
+        rand::{thread_rng, Rng},
+        secret_sharing::{
+            replicated::{
+                malicious::{
+                    AdditiveShare as MaliciousReplicated,
+                    ThisCodeIsAuthorizedToDowngradeFromMalicious,
+                },
+                semi_honest::AdditiveShare as Replicated,
+                ReplicatedSecretSharing,
+            },
+            IntoShares,
+        },
+    };
+
+    #[test]
+    fn reconstruct_iter_matches_eager() {
+        const ROWS: u128 = 10_000;
+
+        let histogram = (0..ROWS)
+            .map(Fp32BitPrime::truncate_from)
+            .collect::<Vec<_>>();
+        let shares: [Vec<Replicated<Fp32BitPrime>>; 3] =
+            histogram.clone().into_iter().share_with(&mut thread_rng());
+
+        let mut rows = 0;
+        for (i, value) in reconstruct_iter(shares.clone()).enumerate() {
+            assert_eq!(histogram[i], value);
+            rows += 1;
+        }
+        assert_eq!(histogram.len(), rows);
+        let eager: Vec<Fp32BitPrime> = shares.reconstruct();
+        assert_eq!(eager, reconstruct_iter(shares).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[should_panic(expected = "different number of shares")]
+    fn reconstruct_iter_length_mismatch() {
+        let [s0, s1, mut s2]: [Vec<Replicated<Fp32BitPrime>>; 3] = (0..4_u128)
+            .map(Fp32BitPrime::truncate_from)
+            .share_with(&mut thread_rng());
+        s2.pop();
+        reconstruct_iter::<_, Fp32BitPrime>([s0, s1, s2]).for_each(drop);
+    }
+
+    #[tokio::test]
+    async fn reconstruct_histogram_from_streams() {
+        const BREAKDOWN_KEYS: u128 = 100;
+
+        let histogram = (0..BREAKDOWN_KEYS)
+            .map(|credit| Fp32BitPrime::truncate_from(credit * 7))
+            .collect::<Vec<_>>();
+        let shares: [Vec<Replicated<Fp32BitPrime>>; 3] =
+            histogram.clone().into_iter().share_with(&mut thread_rng());
+
+        let actual = reconstruct_histogram::<Fp32BitPrime, _>(result_streams(shares))
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            histogram
+                .into_iter()
+                .enumerate()
+                .map(|(bk, credit)| (u32::try_from(bk).unwrap(), credit))
+                .collect::<Vec<_>>(),
+            actual
+        );
+    }
+
+    #[tokio::test]
+    async fn reconstruct_histogram_reports_malformed_results() {
+        async fn reconstruct(shares: [Vec<Replicated<Fp32BitPrime>>; 3]) -> io::Error {
+            reconstruct_histogram::<Fp32BitPrime, _>(result_streams(shares))
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap_err()
+        }
+
+        let shares: [Vec<Replicated<Fp32BitPrime>>; 3] = (0..4_u128)
+            .map(Fp32BitPrime::truncate_from)
+            .share_with(&mut thread_rng());
+
+        let mut short = shares.clone();
+        short[2].pop();
+        let err = reconstruct(short).await;
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert!(
+            err.to_string().contains("different number of shares"),
+            "{err}"
+        );
+
+        let mut inconsistent = shares;
+        inconsistent[1][2] = Replicated::new(
+            inconsistent[1][2].left() + Fp32BitPrime::ONE,
+            inconsistent[1][2].right(),
+        );
+        let err = reconstruct(inconsistent).await;
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert!(err.to_string().starts_with("breakdown key 2:"), "{err}");
+    }
+
+    /// Serializes the result shares of every helper into a stream. Every stream arrives in chunks
+    /// that are not aligned with rows, and with a different chunk size.
+    fn result_streams(shares: [Vec<Replicated<Fp32BitPrime>>; 3]) -> [impl BytesStream; 3] {
+        let row_sz = <Replicated<Fp32BitPrime> as Serializable>::Size::USIZE;
+        let streams = zip(shares, [3, 7, 64]).map(|(shares, chunk_size)| {
+            let mut buf = vec![0u8; shares.len() * row_sz];
+            for (share, chunk) in zip(shares, buf.chunks_mut(row_sz)) {
+                share.serialize(GenericArray::from_mut_slice(chunk));
+            }
+            let chunks = buf
+                .chunks(chunk_size)
+                .map(|chunk| Ok::<_, BoxError>(Bytes::copy_from_slice(chunk)))
+                .collect::<Vec<_>>();
+            stream::iter(chunks)
+        });
+
+        streams
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| "three streams")
+            .unwrap()
+    }
+
+    #[test]
+    fn reconstruct_histogram_from_two_helpers() {
+        const BREAKDOWN_KEYS: u128 = 20;
+
+        let histogram = (0..BREAKDOWN_KEYS)
+            .map(|credit| Fp32BitPrime::truncate_from(credit * 7))
+            .collect::<Vec<_>>();
+        let shares: [Vec<Replicated<Fp32BitPrime>>; 3] =
+            histogram.into_iter().share_with(&mut thread_rng());
+        let expected: Vec<Fp32BitPrime> = shares.reconstruct();
+
+        for &missing in Role::all() {
+            let [a, b]: [&Vec<_>; 2] = zip(Role::all(), &shares)
+                .filter_map(|(&role, shares)| (role != missing).then_some(shares))
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap();
+            assert_eq!(
+                expected,
+                Vec::from_two(a, b, missing),
+                "{missing:?} is missing"
+            );
+        }
+    }
+
+    #[test]
+    fn malicious_shares_reconstruct_with_valid_macs() {
+        let mut rng = thread_rng();
+        let r = rng.gen::<Fp32BitPrime>();
+        let values = (0..10)
+            .map(|_| rng.gen::<Fp32BitPrime>())
+            .collect::<Vec<_>>();
+
+        let shares: [Vec<MaliciousReplicated<Fp32BitPrime>>; 3] = values
+            .iter()
+            .map(|&value| WithMacKey { value, r })
+            .share_with(&mut rng);
+        shares.validate(r);
+
+        let downgraded = shares.map(|shares| {
+            shares
+                .into_iter()
+                .map(|share| share.downgrade().access_without_downgrade())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(values, downgraded.reconstruct());
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion")]
+    fn malicious_shares_fail_with_another_key() {
+        let r = Fp31::truncate_from(5_u128);
+        let shares: [MaliciousReplicated<Fp31>; 3] = WithMacKey {
+            value: Fp31::truncate_from(3_u128),
+            r,
+        }
+        .share();
+        shares.validate(r + Fp31::ONE);
+    }
+}
@@ -7,7 +7,10 @@ use rand_core::{RngCore, SeedableRng};
 use tracing::{Instrument, Level, Span};
 
 use crate::{
-    helpers::{Gateway, GatewayConfig, InMemoryNetwork, Role, RoleAssignment},
+    helpers::{
+        Gateway, GatewayConfig, InMemoryNetwork, Role, RoleAssignment, StreamInterceptor,
+        TransportCallbacks,
+    },
     protocol::{
         context::{
             Context, MaliciousContext, SemiHonestContext, UpgradableContext, UpgradeContext,
@@ -27,7 +30,10 @@ use crate::{
     },
     telemetry::{stats::Metrics, StepStatsCsvExporter},
     test_fixture::{
-        logging, make_participants, metrics::MetricsHandle, sharing::ValidateMalicious, Reconstruct,
+        logging, make_participants,
+        metrics::MetricsHandle,
+        sharing::{ValidateConsistency, ValidateMalicious},
+        Reconstruct,
     },
 };
 
@@ -54,6 +60,9 @@ pub struct TestWorldConfig {
     pub role_assignment: Option<RoleAssignment>,
     /// Seed for random generators used in PRSS
     pub seed: u64,
+    /// If set, all records exchanged between helpers pass through it, so tests can simulate
+    /// a misbehaving helper.
+    pub stream_interceptor: Option<Arc<dyn StreamInterceptor>>,
 }
 
 impl Default for TestWorldConfig {
@@ -66,6 +75,7 @@ impl Default for TestWorldConfig {
             metrics_level: Level::DEBUG,
             role_assignment: None,
             seed: thread_rng().next_u64(),
+            stream_interceptor: None,
         }
     }
 }
@@ -82,6 +92,12 @@ impl TestWorldConfig {
         self.seed = seed;
         self
     }
+
+    #[must_use]
+    pub fn with_stream_interceptor(mut self, interceptor: Arc<dyn StreamInterceptor>) -> Self {
+        self.stream_interceptor = Some(interceptor);
+        self
+    }
 }
 
 impl Default for TestWorld {
@@ -100,7 +116,17 @@ impl TestWorld {
 
         let metrics_handle = MetricsHandle::new(config.metrics_level);
         let participants = make_participants(&mut StdRng::seed_from_u64(config.seed));
-        let network = InMemoryNetwork::default();
+        let network = match &config.stream_interceptor {
+            Some(interceptor) => InMemoryNetwork::with_interceptor(
+                [
+                    TransportCallbacks::default(),
+                    TransportCallbacks::default(),
+                    TransportCallbacks::default(),
+                ],
+                interceptor,
+            ),
+            None => InMemoryNetwork::default(),
+        };
         let role_assignment = config
             .role_assignment
             .unwrap_or_else(|| RoleAssignment::new(network.helper_identities()));
@@ -110,7 +136,7 @@ impl TestWorld {
             let transport = &network.transports[i];
             let role_assignment = role_assignment.clone();
             let gateway = Gateway::new(
-                QueryId,
+                QueryId::default(),
                 config.gateway_config,
                 role_assignment,
                 Arc::downgrade(transport),
@@ -162,6 +188,30 @@ impl TestWorld {
             .unwrap()
     }
 
+    /// Runs `f` against a fresh semi-honest context of each helper and collects the results,
+    /// indexed by [`Role`]. Useful for tests that need to inspect per-helper state, such as PRSS
+    /// or the gate, without writing the same assertion three times.
+    #[must_use]
+    pub fn for_each_role<T, F>(&self, f: F) -> [T; 3]
+    where
+        F: Fn(Role, &SemiHonestContext<'_>) -> T,
+    {
+        self.contexts().map(|ctx| f(ctx.role(), &ctx))
+    }
+
+    /// Checks that the shares in `result`, indexed by [`Role`], are consistent across helpers. Use
+    /// it on the output of [`Runner::semi_honest`] to catch helpers that diverged because of a
+    /// PRSS or step mismatch, before trying to reconstruct the result.
+    ///
+    /// # Panics
+    /// If any two helpers disagree on a share they both hold. The message points at the first
+    /// share that diverges.
+    pub fn assert_consistent<R: ValidateConsistency + ?Sized>(result: &R) {
+        if let Some(inconsistency) = result.first_inconsistency() {
+            panic!("helpers produced inconsistent shares {inconsistency}");
+        }
+    }
+
     #[must_use]
     pub fn metrics_snapshot(&self) -> Metrics {
         self.metrics_handle.snapshot()
@@ -335,3 +385,57 @@ impl Runner for TestWorld {
         output
     }
 }
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        ff::Fp32BitPrime,
+        helpers::Role,
+        protocol::{context::Context, prss::SharedRandomness},
+        secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
+        test_fixture::TestWorld,
+    };
+
+    /// Generates replicated shares of random values from PRSS. If `desync` is set, `H3` uses a
+    /// different gate for the share at that index, as if it had narrowed the context differently.
+    fn prss_shares(world: &TestWorld, desync: Option<u32>) -> [Vec<Replicated<Fp32BitPrime>>; 3] {
+        world.for_each_role(|role, ctx| {
+            (0..10_u32)
+                .map(|i| {
+                    if role == Role::H3 && desync == Some(i) {
+                        ctx.narrow("desync").prss().generate_replicated(i)
+                    } else {
+                        ctx.prss().generate_replicated(i)
+                    }
+                })
+                .collect()
+        })
+    }
+
+    #[tokio::test]
+    async fn consistent_shares() {
+        let world = TestWorld::default();
+        TestWorld::assert_consistent(&prss_shares(&world, None));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "helpers produced inconsistent shares at index 3")]
+    async fn detects_desynced_helper() {
+        let world = TestWorld::default();
+        TestWorld::assert_consistent(&prss_shares(&world, Some(3)));
+    }
+
+    #[tokio::test]
+    async fn for_each_role_visits_every_helper() {
+        let world = TestWorld::default();
+        let roles = world.for_each_role(|role, ctx| {
+            assert_eq!(role, ctx.role());
+            role
+        });
+
+        assert_eq!(&roles, Role::all());
+        assert_eq!(3, roles.into_iter().collect::<HashSet<_>>().len());
+    }
+}